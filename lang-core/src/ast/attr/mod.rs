@@ -16,6 +16,16 @@ common_enum! {
         NameValue(AstAttrMetaNameValue),
     }
 }
+impl AstAttrMeta {
+    /// `inline` in `#[inline(never)]`
+    pub fn name(&self) -> &Path {
+        match self {
+            AstAttrMeta::Path(path) => path,
+            AstAttrMeta::List(list) => &list.name,
+            AstAttrMeta::NameValue(nv) => &nv.name,
+        }
+    }
+}
 common_struct! {
     pub struct AstAttrMetaList {
         pub name: Path,
@@ -34,10 +44,20 @@ common_struct! {
         pub meta: AstAttrMeta,
     }
 }
+impl AstAttribute {
+    /// Attributes of the `shll` tool, like `#[shll::no_specialize]`, are only read by the
+    /// passes
+    pub fn is_shll(&self) -> bool {
+        let path = self.meta.name();
+        path.segments.len() > 1 && path.segments[0].as_str() == "shll"
+    }
+}
 
 pub trait AstAttributesExt {
     fn find_by_path(&self, path: &Path) -> Option<&AstAttrMeta>;
     fn find_by_name(&self, name: &str) -> Option<&AstAttrMeta>;
+    /// Whether `item` is listed in `#[name(..)]`, like `never` in `#[inline(never)]`
+    fn has_list_item(&self, name: &str, item: &str) -> bool;
 }
 impl AstAttributesExt for Vec<AstAttribute> {
     fn find_by_path(&self, path: &Path) -> Option<&AstAttrMeta> {
@@ -50,11 +70,16 @@ impl AstAttributesExt for Vec<AstAttribute> {
     }
     fn find_by_name(&self, name: &str) -> Option<&AstAttrMeta> {
         self.iter()
-            .find(|x| match &x.meta {
-                AstAttrMeta::NameValue(nv) => nv.name.last().as_str() == name,
-                AstAttrMeta::Path(p) => p.last().as_str() == name,
-                _ => false,
-            })
+            .find(|x| x.meta.name().last().as_str() == name)
             .map(|x| &x.meta)
     }
+    fn has_list_item(&self, name: &str, item: &str) -> bool {
+        self.iter().any(|x| match &x.meta {
+            AstAttrMeta::List(l) if l.name.last().as_str() == name => l
+                .items
+                .iter()
+                .any(|x| matches!(x, AstAttrMeta::Path(p) if p.last().as_str() == item)),
+            _ => false,
+        })
+    }
 }
@@ -27,12 +27,17 @@ common_enum! {
         If(ExprIf),
         Loop(ExprLoop),
         While(ExprWhile),
+        For(ExprFor),
+        Break(ExprBreak),
+        Continue(ExprContinue),
+        Return(ExprReturn),
         Invoke(ExprInvoke),
         BinOp(ExprBinOp),
         UnOp(ExprUnOp),
         Assign(ExprAssign),
         Select(ExprSelect),
         Index(ExprIndex),
+        Cast(ExprCast),
         Struct(ExprStruct),
         Structural(ExprStructural),
         Reference(ExprReference),
@@ -42,9 +47,13 @@ common_enum! {
         Let(ExprLet),
         Closure(ExprClosure),
         Array(ExprArray),
+        ArrayRepeat(ExprArrayRepeat),
         /// closured because it's conceptually a closure, not a real one
         Closured(ExprClosured),
         Paren(ExprParen),
+        Const(ExprConst),
+        Unsafe(ExprUnsafe),
+        Async(ExprAsync),
         Range(ExprRange),
 
         Splat(ExprSplat),
@@ -142,7 +142,7 @@ impl ExprBlock {
     }
     pub fn push_expr(&mut self, stmt: impl Into<BExpr>) {
         self.seal();
-        self.push_stmt(BlockStmt::Expr(
+        self.stmts.push(BlockStmt::Expr(
             BlockStmtExpr::new(stmt).with_semicolon(false),
         ));
     }
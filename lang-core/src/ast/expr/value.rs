@@ -76,6 +76,13 @@ common_struct! {
         pub index: BExpr,
     }
 }
+common_struct! {
+    /// `expr as ty`
+    pub struct ExprCast {
+        pub expr: BExpr,
+        pub ty: AstType,
+    }
+}
 
 common_struct! {
     pub struct ExprReference {
@@ -91,6 +98,9 @@ common_struct! {
 
 common_struct! {
     pub struct ExprMatch {
+        /// the value the patterns of the cases are matched against, `None` for a chain of
+        /// conditions
+        pub scrutinee: Option<BExpr>,
         pub cases: Vec<ExprMatchCase>,
     }
 }
@@ -110,12 +120,39 @@ common_struct! {
 }
 common_struct! {
     pub struct ExprWhile {
+        pub label: Option<Ident>,
         pub cond: BExpr,
         pub body: BExpr,
     }
 }
+common_struct! {
+    pub struct ExprFor {
+        pub label: Option<Ident>,
+        pub pat: BPattern,
+        pub iter: BExpr,
+        pub body: BExpr,
+    }
+}
+common_struct! {
+    pub struct ExprBreak {
+        pub label: Option<Ident>,
+        pub value: Option<BExpr>,
+    }
+}
+common_struct! {
+    pub struct ExprContinue {
+        pub label: Option<Ident>,
+    }
+}
+common_struct! {
+    pub struct ExprReturn {
+        pub value: Option<BExpr>,
+    }
+}
 common_struct! {
     pub struct ExprMatchCase {
+        pub pat: Option<BPattern>,
+        /// the guard of the case, `true` if it has none
         pub cond: BExpr,
         pub body: BExpr,
     }
@@ -198,6 +235,25 @@ common_struct! {
         pub expr: BExpr,
     }
 }
+common_struct! {
+    /// `const { .. }`, which must be known at compile time
+    pub struct ExprConst {
+        pub block: BExpr,
+    }
+}
+common_struct! {
+    /// `unsafe { .. }`
+    pub struct ExprUnsafe {
+        pub block: BExpr,
+    }
+}
+common_struct! {
+    /// `async { .. }`, or `async move { .. }`
+    pub struct ExprAsync {
+        pub movability: Option<bool>,
+        pub block: BExpr,
+    }
+}
 common_enum! {
     pub enum ExprRangeLimit {
         Inclusive,
@@ -244,6 +300,13 @@ common_struct! {
         pub values: Vec<AstExpr>,
     }
 }
+common_struct! {
+    /// `[value; len]`
+    pub struct ExprArrayRepeat {
+        pub value: BExpr,
+        pub len: BExpr,
+    }
+}
 common_struct! {
     /// To "splat" or expand an iterable.
     /// For example, in Python, `*a` will expand `a` into the arguments of a function
@@ -1,26 +1,34 @@
 use crate::ast::{
-    AstAttribute, AstType, BExpr, FunctionParam, FunctionParamReceiver, FunctionSignature,
-    ItemChunk, StructuralField, TypeBounds, TypeEnum, TypeFunction, TypeStruct, TypeStructural,
-    ValueFunction, Visibility,
+    AstAttribute, AstExpr, AstType, AstValue, BExpr, FunctionParam, FunctionParamReceiver,
+    FunctionSignature, GenericParam, ItemChunk, StructuralField, TypeBounds, TypeEnum,
+    TypeFunction, TypeStruct, TypeStructural, TypeType, ValueFunction, Visibility, WherePredicate,
 };
 use crate::common_struct;
 use crate::id::Ident;
 
 common_struct! {
     pub struct ItemDefStruct {
+        /// attributes like `#[derive(Debug, Clone)]`, kept for the printed code
+        pub attrs: Vec<AstAttribute>,
         pub visibility: Visibility,
         pub name: Ident,
+        /// lifetime params, like `a` in `struct Ref<'a>`
+        pub lifetimes: Vec<Ident>,
+        /// params of a generic struct like `struct Pair<T>`
+        pub generics_params: Vec<GenericParam>,
+        pub where_predicates: Vec<WherePredicate>,
         pub value: TypeStruct,
     }
 }
 impl ItemDefStruct {
     pub fn new(name: Ident, fields: Vec<StructuralField>) -> Self {
         Self {
+            attrs: vec![],
             visibility: Visibility::Public,
-            value: TypeStruct {
-                name: name.clone(),
-                fields,
-            },
+            lifetimes: vec![],
+            generics_params: vec![],
+            where_predicates: vec![],
+            value: TypeStruct::new(name.clone(), fields),
             name,
         }
     }
@@ -35,8 +43,13 @@ common_struct! {
 }
 common_struct! {
     pub struct ItemDefEnum {
+        pub attrs: Vec<AstAttribute>,
         pub visibility: Visibility,
         pub name: Ident,
+        /// lifetime params, like `a` in `enum Token<'a>`
+        pub lifetimes: Vec<Ident>,
+        pub generics_params: Vec<GenericParam>,
+        pub where_predicates: Vec<WherePredicate>,
         pub value: TypeEnum,
     }
 }
@@ -44,9 +57,33 @@ common_struct! {
     pub struct ItemDefType {
         pub visibility: Visibility,
         pub name: Ident,
+        /// params of a type function like `type Foo<T, const N: usize> = Type<{ .. }>`
+        pub generics_params: Vec<GenericParam>,
         pub value: AstType,
     }
 }
+impl ItemDefType {
+    /// The type function as a comptime function of its generic params that returns a type
+    pub fn to_type_function(&self) -> ValueFunction {
+        let body = match &self.value {
+            AstType::Expr(expr) => expr.clone(),
+            ty => AstExpr::value(AstValue::Type(ty.clone())).into(),
+        };
+        ValueFunction {
+            attrs: vec![],
+            sig: FunctionSignature {
+                name: Some(self.name.clone()),
+                receiver: None,
+                params: vec![],
+                lifetimes: vec![],
+                generics_params: self.generics_params.clone(),
+                where_predicates: vec![],
+                ret_ty: Some(AstType::Type(TypeType {})),
+            },
+            body,
+        }
+    }
+}
 common_struct! {
     pub struct ItemDefConst {
         pub visibility: Visibility,
@@ -103,6 +140,7 @@ impl ItemDefFunction {
     }
     pub fn _to_value(&self) -> ValueFunction {
         ValueFunction {
+            attrs: self.attrs.clone(),
             sig: self.sig.clone(),
             body: self.body.clone(),
         }
@@ -219,24 +219,27 @@ common_enum! {
 
 common_struct! {
     pub struct ItemImpl {
+        /// lifetime params, like `a` in `impl<'a> Foo<'a>`
+        pub lifetimes: Vec<Ident>,
+        pub generics_params: Vec<GenericParam>,
         pub trait_ty: Option<Locator>,
         pub self_ty: AstExpr,
+        pub where_predicates: Vec<WherePredicate>,
         pub items: ItemChunk,
     }
 }
 
 impl ItemImpl {
     pub fn new_ident(self_ty: Ident, items: ItemChunk) -> Self {
-        Self {
-            trait_ty: None,
-            self_ty: AstExpr::ident(self_ty).into(),
-            items,
-        }
+        Self::new(None, AstExpr::ident(self_ty), items)
     }
     pub fn new(trait_ty: Option<Locator>, self_ty: AstExpr, items: ItemChunk) -> Self {
         Self {
+            lifetimes: vec![],
+            generics_params: vec![],
             trait_ty,
             self_ty,
+            where_predicates: vec![],
             items,
         }
     }
@@ -0,0 +1,241 @@
+use std::fmt::{Display, Formatter};
+
+use common::*;
+use num_bigint::BigInt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ast::TypeInt;
+use crate::error::{Diagnostic, ErrorCode};
+use crate::ops::{BinOpKind, IntOverflow};
+use crate::utils::to_json::ToJson;
+
+/// A fixed width integer.
+///
+/// `ty` is `None` for unsuffixed literals: they take the type of the other operand,
+/// and are treated as `i64` otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ValueInt {
+    pub value: i128,
+    pub ty: Option<TypeInt>,
+}
+impl ValueInt {
+    pub fn new(value: i64) -> Self {
+        Self {
+            value: value as i128,
+            ty: None,
+        }
+    }
+    pub fn new_typed(value: i128, ty: TypeInt) -> Self {
+        assert_ne!(ty, TypeInt::BigInt, "BigInt is represented by ValueBigInt");
+        Self {
+            value,
+            ty: Some(ty),
+        }
+    }
+    /// the type used for arithmetic
+    pub fn int_ty(&self) -> TypeInt {
+        self.ty.unwrap_or(TypeInt::I64)
+    }
+    pub fn as_i64(&self) -> Option<i64> {
+        i64::try_from(self.value).ok()
+    }
+    /// the type of an operation on both, an error if both are typed differently
+    pub fn unify_ty(&self, rhs: &ValueInt) -> Result<Option<TypeInt>> {
+        match (self.ty, rhs.ty) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => {
+                bail!("mismatched integer types: {} and {}", lhs, rhs)
+            }
+            (lhs, rhs) => Ok(lhs.or(rhs)),
+        }
+    }
+    /// Computes `self op rhs` wrapped to the operand type, and whether it overflowed
+    pub fn overflowing_op(&self, op: BinOpKind, rhs: &ValueInt) -> Result<(ValueInt, bool)> {
+        if let BinOpKind::Shl | BinOpKind::Shr = op {
+            return Ok(self.overflowing_shift(op, rhs));
+        }
+        let ty = self.unify_ty(rhs)?;
+        let int_ty = ty.unwrap_or(TypeInt::I64);
+        let (raw, overflowed) = match op {
+            BinOpKind::Add => self.value.overflowing_add(rhs.value),
+            BinOpKind::Sub => self.value.overflowing_sub(rhs.value),
+            BinOpKind::Mul => self.value.overflowing_mul(rhs.value),
+            BinOpKind::Div | BinOpKind::Mod => {
+                if rhs.value == 0 {
+                    bail!(division_by_zero(op))
+                }
+                let overflowed = rhs.value == -1 && Some(self.value) == int_ty.min_value();
+                let raw = match op {
+                    BinOpKind::Div => self.value / rhs.value,
+                    _ => self.value % rhs.value,
+                };
+                (raw, overflowed)
+            }
+            BinOpKind::BitAnd => (self.value & rhs.value, false),
+            BinOpKind::BitOr => (self.value | rhs.value, false),
+            BinOpKind::BitXor => (self.value ^ rhs.value, false),
+            _ => bail!("Unsupported integer operation {}", op),
+        };
+        let value = int_ty.wrap(raw);
+        // a negative `u128` can't be wrapped within an `i128`
+        let overflowed = overflowed || value != raw || !int_ty.contains(value);
+        Ok((ValueInt { value, ty }, overflowed))
+    }
+    /// shifts keep the type of `self`, the amount may be of any integer type
+    fn overflowing_shift(&self, op: BinOpKind, rhs: &ValueInt) -> (ValueInt, bool) {
+        let ty = self.int_ty();
+        let bits = ty.bits().unwrap() as i128;
+        let overflowed = rhs.value < 0 || rhs.value >= bits;
+        // like `wrapping_shl`, the amount is taken modulo the width
+        let amount = rhs.value.rem_euclid(bits) as u32;
+        let raw = match op {
+            BinOpKind::Shl => self.value << amount,
+            _ => self.value >> amount,
+        };
+        let value = ValueInt {
+            value: ty.wrap(raw),
+            ty: self.ty,
+        };
+        (value, overflowed)
+    }
+    pub fn checked_op(&self, op: BinOpKind, rhs: &ValueInt) -> Result<Option<ValueInt>> {
+        let (value, overflowed) = self.overflowing_op(op, rhs)?;
+        Ok((!overflowed).then_some(value))
+    }
+    pub fn wrapping_op(&self, op: BinOpKind, rhs: &ValueInt) -> Result<ValueInt> {
+        Ok(self.overflowing_op(op, rhs)?.0)
+    }
+    /// `self op rhs` as compiled code would compute it under `overflow`
+    pub fn binop(&self, op: BinOpKind, rhs: &ValueInt, overflow: IntOverflow) -> Result<ValueInt> {
+        let (value, overflowed) = self.overflowing_op(op, rhs)?;
+        // `MIN / -1` panics in release builds too
+        let always_checked = matches!(op, BinOpKind::Div | BinOpKind::Mod);
+        if overflowed && (overflow == IntOverflow::Panic || always_checked) {
+            bail!(overflow_error(overflow_verb(op)))
+        }
+        Ok(value)
+    }
+    pub fn neg(&self, overflow: IntOverflow) -> Result<ValueInt> {
+        let ty = self.int_ty();
+        ensure!(
+            ty.is_signed(),
+            "cannot apply unary operator `-` to type `{}`",
+            ty
+        );
+        let value = ty.wrap(-self.value);
+        if value != -self.value && overflow == IntOverflow::Panic {
+            bail!(overflow_error("negate"))
+        }
+        Ok(ValueInt { value, ty: self.ty })
+    }
+    /// `self as ty`, truncating to the target width
+    pub fn cast(&self, ty: TypeInt) -> ValueInt {
+        ValueInt::new_typed(ty.wrap(self.value), ty)
+    }
+}
+fn overflow_verb(op: BinOpKind) -> &'static str {
+    match op {
+        BinOpKind::Add => "add",
+        BinOpKind::Sub => "subtract",
+        BinOpKind::Mul => "multiply",
+        BinOpKind::Div => "divide",
+        BinOpKind::Mod => "calculate the remainder",
+        BinOpKind::Shl => "shift left",
+        BinOpKind::Shr => "shift right",
+        _ => "compute",
+    }
+}
+fn overflow_error(verb: &str) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::ArithmeticError,
+        format!("attempt to {} with overflow", verb),
+    )
+    .with_label("overflows")
+}
+fn division_by_zero(op: BinOpKind) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::ArithmeticError,
+        format!("attempt to {} by zero", overflow_verb(op)),
+    )
+    .with_label("divisor is zero")
+}
+impl ToJson for ValueInt {
+    fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(json!(self.value))
+    }
+}
+impl Display for ValueInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.ty {
+            Some(ty) => write!(f, "{}{}", self.value, ty),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// An arbitrary precision integer, the value of `TypeInt::BigInt`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ValueBigInt {
+    pub value: BigInt,
+}
+impl ValueBigInt {
+    pub fn new(value: impl Into<BigInt>) -> Self {
+        Self {
+            value: value.into(),
+        }
+    }
+    pub fn binop(&self, op: BinOpKind, rhs: &ValueBigInt) -> Result<ValueBigInt> {
+        let value = match op {
+            BinOpKind::Add => &self.value + &rhs.value,
+            BinOpKind::Sub => &self.value - &rhs.value,
+            BinOpKind::Mul => &self.value * &rhs.value,
+            BinOpKind::Div | BinOpKind::Mod if rhs.value == BigInt::from(0) => {
+                bail!(division_by_zero(op))
+            }
+            BinOpKind::Div => &self.value / &rhs.value,
+            BinOpKind::Mod => &self.value % &rhs.value,
+            BinOpKind::BitAnd => &self.value & &rhs.value,
+            BinOpKind::BitOr => &self.value | &rhs.value,
+            BinOpKind::BitXor => &self.value ^ &rhs.value,
+            _ => bail!("Unsupported integer operation {}", op),
+        };
+        Ok(ValueBigInt { value })
+    }
+    pub fn shift(&self, op: BinOpKind, amount: &ValueInt) -> Result<ValueBigInt> {
+        let amount = usize::try_from(amount.value)
+            .ok()
+            .ok_or_else(|| overflow_error(overflow_verb(op)))?;
+        let value = match op {
+            BinOpKind::Shl => &self.value << amount,
+            BinOpKind::Shr => &self.value >> amount,
+            _ => bail!("Unsupported shift operation {}", op),
+        };
+        Ok(ValueBigInt { value })
+    }
+    pub fn neg(&self) -> ValueBigInt {
+        ValueBigInt {
+            value: -&self.value,
+        }
+    }
+    /// `self as ty`, keeping the low bits like casts between fixed width integers
+    pub fn cast(&self, ty: TypeInt) -> ValueInt {
+        let low = &self.value & BigInt::from(u128::MAX);
+        let low = u128::try_from(&low).expect("masked to 128 bits") as i128;
+        ValueInt::new_typed(ty.wrap(low), ty)
+    }
+}
+impl From<&ValueInt> for ValueBigInt {
+    fn from(value: &ValueInt) -> Self {
+        ValueBigInt::new(value.value)
+    }
+}
+impl ToJson for ValueBigInt {
+    fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(json!(self.value.to_string()))
+    }
+}
+impl Display for ValueBigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
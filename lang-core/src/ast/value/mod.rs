@@ -1,6 +1,8 @@
+mod int;
 mod ty;
 mod value;
 
+pub use int::*;
 pub use ty::*;
 pub use value::*;
 
@@ -17,6 +19,7 @@ pub type BValue = Box<AstValue>;
 common_enum! {
     pub enum AstValue {
         Int(ValueInt),
+        BigInt(ValueBigInt),
         Bool(ValueBool),
         Decimal(ValueDecimal),
         Char(ValueChar),
@@ -34,6 +37,7 @@ common_enum! {
         Escaped(ValueEscaped),
         Type(AstType),
         Struct(ValueStruct),
+        Variant(ValueVariant),
         Structural(ValueStructural),
         Function(ValueFunction),
         Tuple(ValueTuple),
@@ -57,6 +61,13 @@ impl AstValue {
     pub fn int(i: i64) -> AstValue {
         AstValue::Int(ValueInt::new(i))
     }
+    /// an integer of type `ty`, which must be in range
+    pub fn int_typed(value: i128, ty: TypeInt) -> AstValue {
+        match ty {
+            TypeInt::BigInt => AstValue::BigInt(ValueBigInt::new(value)),
+            _ => AstValue::Int(ValueInt::new_typed(value, ty)),
+        }
+    }
     pub fn unit() -> AstValue {
         AstValue::Unit(ValueUnit)
     }
@@ -91,11 +102,28 @@ impl AstValue {
             _ => None,
         }
     }
+    /// The type whose methods apply to the value, like `i64` for `1` or `Point` for a point
+    pub fn nominal_type(&self) -> Option<AstType> {
+        let ty = match self {
+            AstValue::Int(i) => TypePrimitive::Int(i.ty.unwrap_or(TypeInt::I64)),
+            AstValue::BigInt(_) => TypePrimitive::Int(TypeInt::BigInt),
+            AstValue::Bool(_) => TypePrimitive::Bool,
+            AstValue::Decimal(_) => TypePrimitive::f64(),
+            AstValue::Char(_) => TypePrimitive::Char,
+            AstValue::String(_) => TypePrimitive::String,
+            AstValue::List(_) => TypePrimitive::List,
+            AstValue::Struct(s) => return Some(AstType::Struct(s.ty.clone())),
+            AstValue::Variant(v) => return Some(AstType::Enum(v.ty.clone())),
+            _ => return None,
+        };
+        Some(AstType::Primitive(ty))
+    }
 }
 impl ToJson for AstValue {
     fn to_json(&self) -> common::Result<serde_json::Value> {
         match self {
             AstValue::Int(i) => i.to_json(),
+            AstValue::BigInt(i) => i.to_json(),
             AstValue::Bool(b) => b.to_json(),
             AstValue::Decimal(d) => d.to_json(),
             AstValue::Char(c) => c.to_json(),
@@ -105,6 +133,7 @@ impl ToJson for AstValue {
             AstValue::Null(n) => n.to_json(),
             AstValue::Undefined(u) => u.to_json(),
             AstValue::Struct(s) => s.to_json(),
+            AstValue::Variant(v) => v.to_json(),
             AstValue::Tuple(t) => t.to_json(),
             AstValue::None(n) => n.to_json(),
             AstValue::Some(s) => s.to_json(),
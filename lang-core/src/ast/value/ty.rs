@@ -27,6 +27,7 @@ common_enum! {
         Type(TypeType),
         Reference(TypeReference),
         Slice(TypeSlice),
+        TypeOf(TypeOf),
         Expr(BExpr),
         AnyBox(AnyBox),
     }
@@ -117,8 +118,10 @@ impl Display for AstType {
 }
 
 common_enum! {
-    #[derive(Copy)]
+    #[derive(Copy, Eq)]
     pub enum TypeInt {
+        I128,
+        U128,
         I64,
         U64,
         I32,
@@ -127,12 +130,96 @@ common_enum! {
         U16,
         I8,
         U8,
+        ISize,
+        USize,
         BigInt,
     }
 }
+impl TypeInt {
+    pub fn from_suffix(suffix: &str) -> Option<TypeInt> {
+        match suffix {
+            "i128" => Some(TypeInt::I128),
+            "u128" => Some(TypeInt::U128),
+            "i64" => Some(TypeInt::I64),
+            "u64" => Some(TypeInt::U64),
+            "i32" => Some(TypeInt::I32),
+            "u32" => Some(TypeInt::U32),
+            "i16" => Some(TypeInt::I16),
+            "u16" => Some(TypeInt::U16),
+            "i8" => Some(TypeInt::I8),
+            "u8" => Some(TypeInt::U8),
+            "isize" => Some(TypeInt::ISize),
+            "usize" => Some(TypeInt::USize),
+            _ => None,
+        }
+    }
+    pub fn is_signed(&self) -> bool {
+        !matches!(
+            self,
+            TypeInt::U128
+                | TypeInt::U64
+                | TypeInt::U32
+                | TypeInt::U16
+                | TypeInt::U8
+                | TypeInt::USize
+        )
+    }
+    /// width in bits, `None` for arbitrary precision
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            TypeInt::I128 | TypeInt::U128 => Some(128),
+            // assumes a 64-bit target, like the interpreter itself
+            TypeInt::I64 | TypeInt::U64 | TypeInt::ISize | TypeInt::USize => Some(64),
+            TypeInt::I32 | TypeInt::U32 => Some(32),
+            TypeInt::I16 | TypeInt::U16 => Some(16),
+            TypeInt::I8 | TypeInt::U8 => Some(8),
+            TypeInt::BigInt => None,
+        }
+    }
+    pub fn min_value(&self) -> Option<i128> {
+        let bits = self.bits()?;
+        if self.is_signed() {
+            Some(-(1i128 << (bits - 1)))
+        } else {
+            Some(0)
+        }
+    }
+    /// values are held in an `i128`, so `u128` is capped at `i128::MAX`
+    pub fn max_value(&self) -> Option<i128> {
+        let bits = self.bits()?;
+        if bits >= 128 {
+            Some(i128::MAX)
+        } else if self.is_signed() {
+            Some((1i128 << (bits - 1)) - 1)
+        } else {
+            Some((1i128 << bits) - 1)
+        }
+    }
+    pub fn contains(&self, value: i128) -> bool {
+        match (self.min_value(), self.max_value()) {
+            (Some(min), Some(max)) => min <= value && value <= max,
+            _ => true,
+        }
+    }
+    /// Truncates `value` to the width of the type in two's complement, like `as` does
+    pub fn wrap(&self, value: i128) -> i128 {
+        let bits = match self.bits() {
+            Some(bits) if bits < 128 => bits,
+            _ => return value,
+        };
+        let truncated = value & ((1i128 << bits) - 1);
+        if self.is_signed() && truncated >> (bits - 1) != 0 {
+            truncated - (1i128 << bits)
+        } else {
+            truncated
+        }
+    }
+}
 impl Display for TypeInt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            TypeInt::I128 => write!(f, "i128"),
+            TypeInt::U128 => write!(f, "u128"),
             TypeInt::I64 => write!(f, "i64"),
             TypeInt::U64 => write!(f, "u64"),
             TypeInt::I32 => write!(f, "i32"),
@@ -141,6 +228,8 @@ impl Display for TypeInt {
             TypeInt::U16 => write!(f, "u16"),
             TypeInt::I8 => write!(f, "i8"),
             TypeInt::U8 => write!(f, "u8"),
+            TypeInt::ISize => write!(f, "isize"),
+            TypeInt::USize => write!(f, "usize"),
             TypeInt::BigInt => write!(f, "bigint"),
         }
     }
@@ -188,6 +277,30 @@ impl TypePrimitive {
     pub fn bool() -> TypePrimitive {
         TypePrimitive::Bool
     }
+    /// The primitive type of a name like `i64` or `bool`
+    pub fn from_name(name: &str) -> Option<TypePrimitive> {
+        if let Some(int) = TypeInt::from_suffix(name) {
+            return Some(TypePrimitive::Int(int));
+        }
+        match name {
+            "f64" => Some(TypePrimitive::Decimal(DecimalType::F64)),
+            "f32" => Some(TypePrimitive::Decimal(DecimalType::F32)),
+            "bool" => Some(TypePrimitive::Bool),
+            "char" => Some(TypePrimitive::Char),
+            _ => None,
+        }
+    }
+    /// The name of the primitive type, as impls refer to it, like `i64` in `impl Foo for i64`
+    pub fn name(&self) -> String {
+        match self {
+            TypePrimitive::Int(int) => int.to_string(),
+            TypePrimitive::Decimal(decimal) => decimal.to_string(),
+            TypePrimitive::Bool => "bool".to_string(),
+            TypePrimitive::Char => "char".to_string(),
+            TypePrimitive::String => "String".to_string(),
+            TypePrimitive::List => "Vec".to_string(),
+        }
+    }
 }
 impl Display for TypePrimitive {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -221,10 +334,30 @@ impl StructuralField {
         Self { name, value }
     }
 }
+common_enum! {
+    pub enum StructKind {
+        /// `struct A { a: T }`
+        Named,
+        /// `struct A(T);`, whose fields are named `0`, `1`, ...
+        Tuple,
+        /// `struct A;`
+        Unit,
+    }
+}
 common_struct! {
     pub struct TypeStruct {
         pub name: Ident,
         pub fields: Vec<StructuralField>,
+        pub kind: StructKind,
+    }
+}
+impl TypeStruct {
+    pub fn new(name: Ident, fields: Vec<StructuralField>) -> Self {
+        Self {
+            name,
+            fields,
+            kind: StructKind::Named,
+        }
     }
 }
 common_struct! {
@@ -233,8 +366,15 @@ common_struct! {
         pub variants: Vec<EnumTypeVariant>,
     }
 }
+impl TypeEnum {
+    pub fn get_variant(&self, name: &Ident) -> Option<&EnumTypeVariant> {
+        self.variants.iter().find(|x| &x.name == name)
+    }
+}
 
 common_struct! {
+    /// The payload of a variant is `Any` for a unit variant like `A`, a `Tuple` for `A(T)`,
+    /// `Structural` for `A { a: T }`, or a named `Struct` the variant wraps
     pub struct EnumTypeVariant {
         pub name: Ident,
         pub value: AstType,
@@ -325,3 +465,10 @@ common_struct! {
         pub elem: BType,
     }
 }
+
+common_struct! {
+    /// `typeof(expr)`, the type of an expression
+    pub struct TypeOf {
+        pub expr: BExpr,
+    }
+}
@@ -8,8 +8,8 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::ast::{get_threadlocal_serializer, BExpr};
-use crate::ast::{AstType, AstValue, TypeBounds, TypeStruct};
+use crate::ast::{get_threadlocal_serializer, AstAttribute, BExpr};
+use crate::ast::{AstType, AstValue, BValue, TypeBounds, TypeEnum, TypeStruct, ValueInt};
 use crate::id::Ident;
 use crate::utils::to_json::ToJson;
 use crate::{common_enum, common_struct};
@@ -55,9 +55,6 @@ macro_rules! plain_value {
     };
 }
 
-plain_value! {
-    ValueInt: i64
-}
 plain_value! {
     ValueBool: bool
 }
@@ -318,7 +315,7 @@ impl Mul<ValueInt> for ValueOffset {
 
     fn mul(self, rhs: ValueInt) -> Self::Output {
         Self {
-            value: self.value * rhs.value,
+            value: self.value * rhs.value as i64,
         }
     }
 }
@@ -477,6 +474,44 @@ impl Display for ValueStruct {
         write!(f, "}}")
     }
 }
+common_struct! {
+    /// A variant of an enum like `Shape::Circle(1.0)`, whose payload is a tuple, the fields
+    /// of a struct variant as a structural, or unit
+    pub struct ValueVariant {
+        pub ty: TypeEnum,
+        pub name: Ident,
+        pub value: BValue,
+    }
+}
+impl ValueVariant {
+    pub fn new(ty: TypeEnum, name: Ident, value: AstValue) -> Self {
+        Self {
+            ty,
+            name,
+            value: value.into(),
+        }
+    }
+}
+impl ToJson for ValueVariant {
+    fn to_json(&self) -> Result<serde_json::Value> {
+        if self.value.is_unit() {
+            return Ok(json!(self.name.name));
+        }
+        let mut map = serde_json::Map::new();
+        map.insert(self.name.name.clone(), self.value.to_json()?);
+        Ok(json!(map))
+    }
+}
+impl Display for ValueVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}::{}", self.ty.name, self.name)?;
+        match &*self.value {
+            AstValue::Unit(_) => Ok(()),
+            AstValue::Structural(s) => write!(f, " {}", s),
+            value => write!(f, "{}", value),
+        }
+    }
+}
 common_struct! {
     pub struct ValueStructural {
         pub fields: Vec<ValueField>,
@@ -563,6 +598,15 @@ common_struct! {
     pub struct GenericParam {
         pub name: Ident,
         pub bounds: TypeBounds,
+        /// the type of a const param, like `usize` in `const N: usize`
+        pub const_ty: Option<AstType>,
+    }
+}
+common_struct! {
+    /// A predicate of a where clause, like `T: Clone + Debug`
+    pub struct WherePredicate {
+        pub ty: AstType,
+        pub bounds: TypeBounds,
     }
 }
 
@@ -571,7 +615,10 @@ common_struct! {
         pub name: Option<Ident>,
         pub receiver: Option<FunctionParamReceiver>,
         pub params: Vec<FunctionParam>,
+        /// lifetime params, like `a` in `fn foo<'a>`
+        pub lifetimes: Vec<Ident>,
         pub generics_params: Vec<GenericParam>,
+        pub where_predicates: Vec<WherePredicate>,
         pub ret_ty: Option<AstType>,
     }
 }
@@ -581,7 +628,9 @@ impl FunctionSignature {
             name: None,
             receiver: None,
             params: vec![],
+            lifetimes: vec![],
             generics_params: vec![],
+            where_predicates: vec![],
             ret_ty: None,
         }
     }
@@ -589,6 +638,8 @@ impl FunctionSignature {
 
 common_struct! {
     pub struct ValueFunction {
+        /// attributes of the function item, which steer the optimization of its calls
+        pub attrs: Vec<AstAttribute>,
         pub sig: FunctionSignature,
         pub body: BExpr,
     }
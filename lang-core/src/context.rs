@@ -1,6 +1,7 @@
 use crate::ast::{AstExpr, ExprClosured, Visibility};
-use crate::ast::{AstType, AstValue, ValueFunction};
-use crate::id::{Ident, Path};
+use crate::ast::{AstItem, ItemDefConst, ItemDefFunction, ItemDefTrait, ItemImpl};
+use crate::ast::{AstType, AstValue, FunctionParamReceiver, ValueFunction};
+use crate::id::{Ident, Locator, Path};
 use common::*;
 use dashmap::DashMap;
 use itertools::Itertools;
@@ -46,13 +47,65 @@ impl SharedValueSlot {
         self.with_storage(|x| x.closure = Some(closure));
     }
 }
+/// An item of an impl or trait, found by [`SharedScopedContext::lookup_assoc_item`]
+#[derive(Clone)]
+pub struct AssocItem {
+    pub item: AstItem,
+    /// the trait the item is implemented for, if not an inherent one
+    pub trait_name: Option<Ident>,
+    /// whether it's the default of the trait, not declared in the impl
+    pub is_default: bool,
+    /// the context of the impl, where the item is evaluated
+    pub ctx: SharedScopedContext,
+}
+/// A method found for a receiver type by [`SharedScopedContext::lookup_method`]
+#[derive(Clone)]
+pub struct MethodLookup {
+    pub func: ItemDefFunction,
+    pub trait_name: Option<Ident>,
+    pub ctx: SharedScopedContext,
+    /// how many references were stripped from the receiver type to find the method
+    pub derefs: usize,
+}
+impl MethodLookup {
+    /// whether the method takes `&self` or `&mut self`, so the receiver is referenced
+    pub fn by_ref(&self) -> bool {
+        matches!(
+            self.func.sig.receiver,
+            Some(
+                FunctionParamReceiver::Ref
+                    | FunctionParamReceiver::RefMut
+                    | FunctionParamReceiver::RefStatic
+                    | FunctionParamReceiver::RefMutStatic
+            )
+        )
+    }
+}
+
+/// The name impls are registered under for a type, like `Point` or `i64`
+pub fn impl_key(ty: &AstType) -> Option<Ident> {
+    match ty {
+        AstType::Primitive(p) => Some(Ident::new(p.name())),
+        AstType::Struct(s) => Some(s.name.clone()),
+        AstType::Enum(e) => Some(e.name.clone()),
+        AstType::Expr(expr) => match expr.as_ref() {
+            AstExpr::Locator(Locator::ParameterPath(path)) => Some(path.last().ident.clone()),
+            AstExpr::Locator(locator) => Some(locator.to_path().last().clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub struct ScopedContext {
     parent: Option<Weak<Self>>,
-    #[allow(dead_code)]
     ident: Ident,
     path: Path,
     storages: DashMap<Ident, SharedValueSlot>,
     childs: DashMap<Ident, Arc<Self>>,
+    traits: DashMap<Ident, ItemDefTrait>,
+    /// impl blocks by the [`impl_key`] of their self type
+    impls: DashMap<Ident, Vec<ItemImpl>>,
     buffer: Mutex<Vec<String>>,
     #[allow(dead_code)]
     visibility: Visibility,
@@ -67,12 +120,21 @@ impl ScopedContext {
             path: Path::root(),
             storages: Default::default(),
             childs: Default::default(),
+            traits: Default::default(),
+            impls: Default::default(),
             buffer: Mutex::new(vec![]),
             visibility: Visibility::Public,
             access_parent_locals: false,
         }
     }
 
+    pub fn ident(&self) -> &Ident {
+        &self.ident
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub fn insert_value(&self, key: impl Into<Ident>, value: AstValue) {
         self.storages
             .entry(key.into())
@@ -126,6 +188,8 @@ impl SharedScopedContext {
             path: self.path.with_ident(name.clone()),
             storages: Default::default(),
             childs: Default::default(),
+            traits: Default::default(),
+            impls: Default::default(),
             buffer: Mutex::new(vec![]),
             visibility,
             access_parent_locals,
@@ -184,9 +248,12 @@ impl SharedScopedContext {
                 .get_storage(key, self.access_parent_locals);
         }
 
-        let (paths, key) = key.segments.split_at(key.segments.len() - 1);
-        let this = self.get_module_recursive(Path::new(paths.to_owned()))?;
-        let value = this.storages.get(&key[0])?.value().clone();
+        let (paths, name) = key.segments.split_at(key.segments.len() - 1);
+        let Some(this) = self.get_module_recursive(Path::new(paths.to_owned())) else {
+            // modules and impls are registered where they are declared, look them up outwards
+            return self.get_parent()?.get_storage(key, access_local);
+        };
+        let value = this.storages.get(&name[0])?.value().clone();
         Some(value)
     }
     pub fn get_value(&self, key: impl Into<Path>) -> Option<AstValue> {
@@ -222,6 +289,111 @@ impl SharedScopedContext {
         let storage = self.get_storage(key, true)?;
         storage.ty()
     }
+    pub fn insert_trait(&self, def: ItemDefTrait) {
+        self.traits.insert(def.name.clone(), def);
+    }
+    pub fn get_trait(&self, name: &Ident) -> Option<ItemDefTrait> {
+        match self.traits.get(name) {
+            Some(def) => Some(def.clone()),
+            None => self.get_parent()?.get_trait(name),
+        }
+    }
+    /// Registers an impl block for the type it's for, in the scope it's declared in
+    pub fn insert_impl(&self, impl_: ItemImpl) -> Result<()> {
+        let key = impl_key(&AstType::expr(impl_.self_ty.clone()));
+        let Some(key) = key else {
+            bail!("Could not implement for {}", impl_.self_ty)
+        };
+        self.impls.entry(key).or_default().push(impl_);
+        Ok(())
+    }
+    /// The impls of the type `key` in this scope and its parents, innermost first
+    pub fn get_impls(&self, key: &Ident) -> Vec<(ItemImpl, Self)> {
+        let mut impls = vec![];
+        let mut this = Some(self.clone());
+        while let Some(ctx) = this {
+            if let Some(found) = ctx.impls.get(key) {
+                impls.extend(found.iter().map(|x| (x.clone(), ctx.clone())));
+            }
+            this = ctx.get_parent();
+        }
+        impls
+    }
+    /// Finds the item `name` of the type `key`: inherent impls come first, then trait impls,
+    /// then the defaults of the implemented traits
+    pub fn lookup_assoc_item(&self, key: &Ident, name: &Ident) -> Option<AssocItem> {
+        let impls = self.get_impls(key);
+        let (inherent, traits): (Vec<_>, Vec<_>) =
+            impls.into_iter().partition(|(x, _)| x.trait_ty.is_none());
+        for (impl_, ctx) in inherent.iter().chain(&traits) {
+            if let Some(item) = impl_.items.iter().find(|x| x.get_ident() == Some(name)) {
+                return Some(AssocItem {
+                    item: item.clone(),
+                    trait_name: impl_.trait_ty.as_ref().map(|x| x.to_path().last().clone()),
+                    is_default: false,
+                    ctx: ctx.clone(),
+                });
+            }
+        }
+        for (impl_, ctx) in &traits {
+            let Some(trait_ty) = &impl_.trait_ty else {
+                continue;
+            };
+            let trait_name = trait_ty.to_path().last().clone();
+            let Some(def) = ctx.get_trait(&trait_name) else {
+                continue;
+            };
+            let default = def.items.iter().find(|x| {
+                matches!(x, AstItem::DefFunction(_) | AstItem::DefConst(_))
+                    && x.get_ident() == Some(name)
+            });
+            if let Some(item) = default {
+                return Some(AssocItem {
+                    item: item.clone(),
+                    trait_name: Some(trait_name),
+                    is_default: true,
+                    ctx: ctx.clone(),
+                });
+            }
+        }
+        None
+    }
+    /// Finds the method `name` for a receiver of type `ty`, dereferencing it as needed.
+    /// Methods taking `&self` apply to values as well, see [`MethodLookup::by_ref`]
+    pub fn lookup_method(&self, ty: &AstType, name: &Ident) -> Option<MethodLookup> {
+        let mut ty = ty;
+        let mut derefs = 0;
+        loop {
+            if let Some(key) = impl_key(ty) {
+                if let Some(found) = self.lookup_assoc_item(&key, name) {
+                    let AstItem::DefFunction(func) = found.item else {
+                        return None;
+                    };
+                    return Some(MethodLookup {
+                        func,
+                        trait_name: found.trait_name,
+                        ctx: found.ctx,
+                        derefs,
+                    });
+                }
+            }
+            match ty {
+                AstType::Reference(r) => {
+                    ty = &r.ty;
+                    derefs += 1;
+                }
+                _ => return None,
+            }
+        }
+    }
+    /// Finds the associated const `name` of the type `key`, like `Point::ORIGIN`
+    pub fn lookup_assoc_const(&self, key: &Ident, name: &Ident) -> Option<(ItemDefConst, Self)> {
+        let found = self.lookup_assoc_item(key, name)?;
+        match found.item {
+            AstItem::DefConst(def) => Some((def, found.ctx)),
+            _ => None,
+        }
+    }
     pub fn root(&self) -> Self {
         self.get_parent()
             .map(|x| x.root())
@@ -252,6 +424,17 @@ impl SharedScopedContext {
             _ => Some(AstValue::expr(expr)),
         }
     }
+    /// whether `self` is `other` or one of its ancestors
+    pub fn is_ancestor_of(&self, other: &Self) -> bool {
+        let mut this = Some(other.clone());
+        while let Some(ctx) = this {
+            if &ctx == self {
+                return true;
+            }
+            this = ctx.get_parent();
+        }
+        false
+    }
     pub fn get_parent(&self) -> Option<Self> {
         match &self.parent {
             Some(parent) => match parent.upgrade() {
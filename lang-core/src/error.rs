@@ -1,9 +1,198 @@
-use crate::span::Span;
-use thiserror::Error;
-#[derive(Debug)]
-pub struct SyntaxError {}
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error("Syntax error: {0:?}")]
-    SyntaxError(Span, SyntaxError),
+use std::fmt::{Display, Formatter};
+
+use miette::{
+    GraphicalReportHandler, GraphicalTheme, LabeledSpan, MietteError, MietteSpanContents,
+    SourceCode, SourceSpan, SpanContents,
+};
+
+use crate::span::{with_threadlocal_source_map, FileInfo, SourceNode, Span};
+
+/// Stable codes of diagnostics, rendered as `shll::<stage>::<kind>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// syntax the parser does not handle yet
+    UnsupportedSyntax,
+    /// a literal that does not fit into its type
+    LiteralOutOfRange,
+    /// a name that is not defined in scope
+    UnresolvedName,
+    /// a value of the wrong kind, like invoking an integer
+    TypeMismatch,
+    /// a field defined with different types by the operands of type arithmetic
+    FieldConflict,
+    /// the type of an expression could not be inferred
+    InferenceFailed,
+    /// an expression the interpreter cannot evaluate
+    InterpretFailed,
+    /// overflow, division by zero and alike
+    ArithmeticError,
+    /// a statement or control flow the optimizer cannot handle
+    OptimizeFailed,
+}
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnsupportedSyntax => "shll::parse::unsupported",
+            Self::LiteralOutOfRange => "shll::parse::literal_out_of_range",
+            Self::UnresolvedName => "shll::resolve::unresolved_name",
+            Self::TypeMismatch => "shll::typing::mismatch",
+            Self::FieldConflict => "shll::typing::field_conflict",
+            Self::InferenceFailed => "shll::typing::inference_failed",
+            Self::InterpretFailed => "shll::interpret::failed",
+            Self::ArithmeticError => "shll::interpret::arithmetic",
+            Self::OptimizeFailed => "shll::optimize::failed",
+        }
+    }
+}
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A structured error of the parser, optimizer or interpreter.
+///
+/// It is raised through `eyre` like any other error, and can be found again with
+/// `report.downcast_ref::<Diagnostic>()` or rendered with [`render_report`].
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+    pub label: Option<String>,
+    pub help: Option<String>,
+    /// copied out of the source map, so the diagnostic renders on any thread
+    source: Option<FileInfo>,
+}
+impl Diagnostic {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            span: None,
+            label: None,
+            help: None,
+            source: None,
+        }
+    }
+    /// A name that is neither defined in scope nor a builtin
+    pub fn unresolved(name: impl Display) -> Self {
+        Self::new(
+            ErrorCode::UnresolvedName,
+            format!("cannot find `{}` in this scope", name),
+        )
+        .with_label("not found in this scope")
+    }
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.source = span
+            .file()
+            .and_then(|file| with_threadlocal_source_map(|map| map.get_file(file).cloned()));
+        self.span = Some(span);
+        self
+    }
+    /// Points at `node` if the parser recorded a span for it
+    pub fn with_node(self, node: &impl SourceNode) -> Self {
+        match node.span() {
+            Some(span) => self.with_span(span),
+            None => self,
+        }
+    }
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+    /// The proc macro input it points at, to report it there with `compile_error!`
+    pub fn tokens(&self) -> Option<proc_macro2::Span> {
+        with_threadlocal_source_map(|map| map.get_tokens(self.span?))
+    }
+    /// Renders the diagnostic with its source snippet, without colors
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, self)
+            .expect("writing to a String does not fail");
+        out
+    }
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+impl std::error::Error for Diagnostic {}
+impl miette::Diagnostic for Diagnostic {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(self.code))
+    }
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.help
+            .as_ref()
+            .map(|help| Box::new(help) as Box<dyn Display + 'a>)
+    }
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.source.as_ref().map(|source| source as &dyn SourceCode)
+    }
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        // without the source, miette could not resolve the label anyway
+        self.source.as_ref()?;
+        let Some(Span::File { lo, hi, .. }) = self.span else {
+            return None;
+        };
+        let label = LabeledSpan::new(self.label.clone(), lo as usize, (hi - lo) as usize);
+        Some(Box::new(std::iter::once(label)))
+    }
+}
+
+impl SourceCode for FileInfo {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError> {
+        let contents = self
+            .source
+            .read_span(span, context_lines_before, context_lines_after)?;
+        Ok(Box::new(MietteSpanContents::new_named(
+            self.file.display().to_string(),
+            contents.data(),
+            *contents.span(),
+            contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
+/// Points diagnostics raised without a span at the node being evaluated, e.g. an overflow
+/// deep in value arithmetic at the `a + b` that caused it
+pub trait DiagnosticContext<T> {
+    fn at_node(self, node: &impl SourceNode) -> eyre::Result<T>;
+}
+impl<T> DiagnosticContext<T> for eyre::Result<T> {
+    fn at_node(self, node: &impl SourceNode) -> eyre::Result<T> {
+        self.map_err(|report| match report.downcast::<Diagnostic>() {
+            Ok(diagnostic) if diagnostic.span.is_none() => diagnostic.with_node(node).into(),
+            Ok(diagnostic) => diagnostic.into(),
+            Err(report) => report,
+        })
+    }
+}
+
+/// Renders `report` for humans: the context chain, then the first [`Diagnostic`] in it with
+/// its source snippet. Reports without a diagnostic fall back to `{:?}`
+pub fn render_report(report: &eyre::Report) -> String {
+    let mut out = String::new();
+    for error in report.chain() {
+        if let Some(diagnostic) = error.downcast_ref::<Diagnostic>() {
+            out.push_str(&diagnostic.render());
+            return out;
+        }
+        out.push_str(&format!("{}\n", error));
+    }
+    format!("{:?}", report)
 }
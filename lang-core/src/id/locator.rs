@@ -27,7 +27,10 @@ impl Locator {
         match self {
             Self::Ident(ident) => ident.into(),
             Self::Path(path) => path.clone(),
-            _ => unreachable!(),
+            // the generic item itself, without its args
+            Self::ParameterPath(path) => {
+                Path::new(path.segments.iter().map(|x| x.ident.clone()).collect())
+            }
         }
     }
     pub fn parameter_path(path: ParameterPath) -> Self {
@@ -71,12 +71,18 @@ impl<'a> From<&'a Path> for Path {
 common_struct! {
     pub struct ParameterPathSegment {
         pub ident: Ident,
+        /// lifetime args, like `a` in `Cow<'a, str>`, which come before the other args
+        pub lifetimes: Vec<Ident>,
         pub args: Vec<AstType>,
     }
 }
 impl ParameterPathSegment {
     pub fn new(ident: Ident, args: Vec<AstType>) -> Self {
-        Self { ident, args }
+        Self {
+            ident,
+            lifetimes: vec![],
+            args,
+        }
     }
 }
 
@@ -102,9 +108,16 @@ impl Display for ParameterPath {
             }
             first = false;
             write!(f, "{}", seg.ident.name)?;
-            if !seg.args.is_empty() {
+            if !seg.lifetimes.is_empty() || !seg.args.is_empty() {
                 write!(f, "<")?;
                 let mut first = true;
+                for lifetime in &seg.lifetimes {
+                    if !first {
+                        write!(f, ", ")?;
+                    }
+                    first = false;
+                    write!(f, "'{}", lifetime.name)?;
+                }
                 for arg in &seg.args {
                     if !first {
                         write!(f, ", ")?;
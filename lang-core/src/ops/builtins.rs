@@ -1,10 +1,11 @@
 use crate::ast::*;
 use crate::context::SharedScopedContext;
 use crate::id::Ident;
-use crate::ops::BinOpKind;
+use crate::ops::{compare_literals, int_binop, BinOpKind, IntOverflow};
 use common::*;
 use itertools::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt::{Debug, Display, Formatter};
 use std::sync::Arc;
 
@@ -86,115 +87,141 @@ impl PartialEq for BuiltinFn {
     }
 }
 impl Eq for BuiltinFn {}
+/// Folds the arguments left to right with `op`, on integers of one type or on floats
 pub fn operate_on_literals(
     name: BinOpKind,
-    op_i64: impl Fn(&[i64]) -> i64 + Send + Sync + 'static,
-    op_f64: impl Fn(&[f64]) -> f64 + Send + Sync + 'static,
+    overflow: IntOverflow,
+    op_f64: impl Fn(f64, f64) -> f64 + Send + Sync + 'static,
 ) -> BuiltinFn {
     BuiltinFn::new(name, move |args, _ctx| {
-        let mut args_i64 = vec![];
-        let mut args_f64 = vec![];
-        for arg in args {
-            match arg {
-                AstValue::Int(x) => args_i64.push(x.value),
-                AstValue::Decimal(x) => args_f64.push(x.value),
-                _ => bail!("Does not support argument type {:?}", args),
-            }
-        }
-        if !args_i64.is_empty() && !args_f64.is_empty() {
+        let Some((first, rest)) = args.split_first() else {
             bail!("Does not support argument type {:?}", args)
-        }
-        if !args_i64.is_empty() {
-            return Ok(AstValue::int(op_i64(&args_i64)));
-        }
-        if !args_f64.is_empty() {
-            return Ok(AstValue::decimal(op_f64(&args_f64)));
-        }
-        bail!("Does not support argument type {:?}", args)
+        };
+        rest.iter()
+            .try_fold(first.clone(), |acc, arg| match (&acc, arg) {
+                (AstValue::Decimal(x), AstValue::Decimal(y)) => {
+                    Ok(AstValue::decimal(op_f64(x.value, y.value)))
+                }
+                _ => int_binop(name, &acc, arg, overflow),
+            })
+    })
+}
+/// Folds the arguments left to right with `op`, on integers of one type or on bools
+pub fn operate_on_integers(
+    name: BinOpKind,
+    overflow: IntOverflow,
+    op_bool: impl Fn(bool, bool) -> bool + Send + Sync + 'static,
+) -> BuiltinFn {
+    BuiltinFn::new(name, move |args, _ctx| {
+        let Some((first, rest)) = args.split_first() else {
+            bail!("Does not support argument type {:?}", args)
+        };
+        rest.iter()
+            .try_fold(first.clone(), |acc, arg| match (&acc, arg) {
+                (AstValue::Bool(x), AstValue::Bool(y)) => {
+                    Ok(AstValue::bool(op_bool(x.value, y.value)))
+                }
+                _ => int_binop(name, &acc, arg, overflow),
+            })
     })
 }
 pub fn binary_comparison_on_literals(
     name: BinOpKind,
-    op_i64: impl Fn(i64, i64) -> bool + Send + Sync + 'static,
-    op_f64: impl Fn(f64, f64) -> bool + Send + Sync + 'static,
+    op: impl Fn(Option<Ordering>) -> bool + Send + Sync + 'static,
 ) -> BuiltinFn {
     BuiltinFn::new(name, move |args, _ctx| {
         if args.len() != 2 {
             bail!("Argument expected 2, got: {:?}", args)
         }
-        let mut args_i64 = vec![];
-        let mut args_f64 = vec![];
-        for arg in args {
-            match arg {
-                AstValue::Int(x) => args_i64.push(x.value),
-                AstValue::Decimal(x) => args_f64.push(x.value),
-                _ => bail!("Does not support argument type {:?}", args),
-            }
-        }
-        if !args_i64.is_empty() && !args_f64.is_empty() {
-            bail!("Does not support argument type {:?}", args)
-        }
-        if !args_i64.is_empty() {
-            return Ok(AstValue::bool(op_i64(args_i64[0], args_i64[1])));
-        }
-        if !args_f64.is_empty() {
-            return Ok(AstValue::bool(op_f64(args_f64[0], args_f64[1])));
-        }
-
-        bail!("Does not support argument type {:?}", args)
+        let ordering = compare_literals(&args[0], &args[1])?;
+        Ok(AstValue::bool(op(ordering)))
     })
 }
-pub fn builtin_add() -> BuiltinFn {
-    operate_on_literals(
-        BinOpKind::Add,
-        |x| x.into_iter().sum(),
-        |x| x.into_iter().sum(),
-    )
-}
-pub fn builtin_sub() -> BuiltinFn {
-    operate_on_literals(
-        BinOpKind::Sub,
-        |x| {
-            x.into_iter()
-                .enumerate()
-                .map(|(i, &x)| if i > 0 { -x } else { x })
-                .sum()
-        },
-        |x| {
-            x.into_iter()
-                .enumerate()
-                .map(|(i, &x)| if i > 0 { -x } else { x })
-                .sum()
-        },
-    )
+pub fn builtin_add(overflow: IntOverflow) -> BuiltinFn {
+    operate_on_literals(BinOpKind::Add, overflow, |x, y| x + y)
+}
+pub fn builtin_sub(overflow: IntOverflow) -> BuiltinFn {
+    operate_on_literals(BinOpKind::Sub, overflow, |x, y| x - y)
 }
 
-pub fn builtin_mul() -> BuiltinFn {
-    operate_on_literals(
-        BinOpKind::Mul,
-        |x| x.into_iter().product(),
-        |x| x.into_iter().product(),
-    )
+pub fn builtin_mul(overflow: IntOverflow) -> BuiltinFn {
+    operate_on_literals(BinOpKind::Mul, overflow, |x, y| x * y)
+}
+pub fn builtin_div(overflow: IntOverflow) -> BuiltinFn {
+    operate_on_literals(BinOpKind::Div, overflow, |x, y| x / y)
+}
+pub fn builtin_mod(overflow: IntOverflow) -> BuiltinFn {
+    operate_on_literals(BinOpKind::Mod, overflow, |x, y| x % y)
+}
+pub fn builtin_bit_and() -> BuiltinFn {
+    operate_on_integers(BinOpKind::BitAnd, IntOverflow::Wrap, |x, y| x & y)
+}
+pub fn builtin_bit_or() -> BuiltinFn {
+    operate_on_integers(BinOpKind::BitOr, IntOverflow::Wrap, |x, y| x | y)
+}
+pub fn builtin_bit_xor() -> BuiltinFn {
+    operate_on_integers(BinOpKind::BitXor, IntOverflow::Wrap, |x, y| x ^ y)
+}
+pub fn builtin_shl(overflow: IntOverflow) -> BuiltinFn {
+    shift_integer(BinOpKind::Shl, overflow)
+}
+pub fn builtin_shr(overflow: IntOverflow) -> BuiltinFn {
+    shift_integer(BinOpKind::Shr, overflow)
+}
+fn shift_integer(name: BinOpKind, overflow: IntOverflow) -> BuiltinFn {
+    BuiltinFn::new(name, move |args, _ctx| {
+        if args.len() != 2 {
+            bail!("Argument expected 2, got: {:?}", args)
+        }
+        int_binop(name, &args[0], &args[1], overflow)
+    })
+}
+/// `&&` on evaluated arguments, the interpreter short-circuits before calling it
+pub fn builtin_and() -> BuiltinFn {
+    logical_on_bools(BinOpKind::And, |x, y| x && y)
+}
+/// `||` on evaluated arguments, the interpreter short-circuits before calling it
+pub fn builtin_or() -> BuiltinFn {
+    logical_on_bools(BinOpKind::Or, |x, y| x || y)
+}
+fn logical_on_bools(
+    name: BinOpKind,
+    op: impl Fn(bool, bool) -> bool + Send + Sync + 'static,
+) -> BuiltinFn {
+    BuiltinFn::new(name, move |args, _ctx| {
+        let Some((first, rest)) = args.split_first() else {
+            bail!("Does not support argument type {:?}", args)
+        };
+        rest.iter()
+            .try_fold(first.clone(), |acc, arg| match (&acc, arg) {
+                (AstValue::Bool(x), AstValue::Bool(y)) => Ok(AstValue::bool(op(x.value, y.value))),
+                _ => bail!("Expected bools for {}, got {:?} and {:?}", name, acc, arg),
+            })
+    })
 }
 
 pub fn builtin_gt() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Gt, |x, y| x > y, |x, y| x > y)
+    binary_comparison_on_literals(BinOpKind::Gt, |x| x == Some(Ordering::Greater))
 }
 
 pub fn builtin_ge() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Ge, |x, y| x >= y, |x, y| x >= y)
+    binary_comparison_on_literals(BinOpKind::Ge, |x| {
+        matches!(x, Some(Ordering::Greater | Ordering::Equal))
+    })
 }
 pub fn builtin_lt() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Lt, |x, y| x < y, |x, y| x < y)
+    binary_comparison_on_literals(BinOpKind::Lt, |x| x == Some(Ordering::Less))
 }
 pub fn builtin_le() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Le, |x, y| x <= y, |x, y| x <= y)
+    binary_comparison_on_literals(BinOpKind::Le, |x| {
+        matches!(x, Some(Ordering::Less | Ordering::Equal))
+    })
 }
 pub fn builtin_eq() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Eq, |x, y| x == y, |x, y| x == y)
+    binary_comparison_on_literals(BinOpKind::Eq, |x| x == Some(Ordering::Equal))
 }
 pub fn builtin_ne() -> BuiltinFn {
-    binary_comparison_on_literals(BinOpKind::Ne, |x, y| x != y, |x, y| x != y)
+    binary_comparison_on_literals(BinOpKind::Ne, |x| x != Some(Ordering::Equal))
 }
 
 pub fn builtin_print(se: Arc<dyn AstSerializer>) -> BuiltinFn {
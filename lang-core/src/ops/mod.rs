@@ -1,9 +1,11 @@
 use std::fmt::{Debug, Display, Formatter};
 mod builtins;
+mod num;
 
 use crate::common_enum;
 use crate::id::Ident;
 pub use builtins::*;
+pub use num::*;
 
 common_enum! {
     #[derive(Copy)]
@@ -25,6 +27,8 @@ common_enum! {
         BitOr,
         BitAnd,
         BitXor,
+        Shl,
+        Shr,
     }
 
 }
@@ -63,6 +67,8 @@ impl Display for BinOpKind {
             BinOpKind::BitOr => write!(f, "|"),
             BinOpKind::BitAnd => write!(f, "&"),
             BinOpKind::BitXor => write!(f, "^"),
+            BinOpKind::Shl => write!(f, "<<"),
+            BinOpKind::Shr => write!(f, ">>"),
         }
     }
 }
@@ -0,0 +1,121 @@
+use std::cmp::Ordering;
+
+use common::*;
+
+use crate::ast::{AstType, AstValue, DecimalType, TypeInt, TypePrimitive, ValueBigInt, ValueChar};
+use crate::ops::BinOpKind;
+
+/// What integer arithmetic does on overflow, like rustc's debug and release profiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IntOverflow {
+    /// `overflow-checks = true`, the default in debug builds
+    #[default]
+    Panic,
+    /// two's complement wrapping, the default in release builds
+    Wrap,
+}
+
+/// Applies `op` to two integer values, promoting unsuffixed integers to `BigInt` when needed
+pub fn int_binop(
+    op: BinOpKind,
+    lhs: &AstValue,
+    rhs: &AstValue,
+    overflow: IntOverflow,
+) -> Result<AstValue> {
+    match (lhs, rhs) {
+        (AstValue::BigInt(lhs), AstValue::Int(rhs))
+            if matches!(op, BinOpKind::Shl | BinOpKind::Shr) =>
+        {
+            Ok(AstValue::BigInt(lhs.shift(op, rhs)?))
+        }
+        (AstValue::Int(lhs), AstValue::Int(rhs)) => {
+            Ok(AstValue::Int(lhs.binop(op, rhs, overflow)?))
+        }
+        (AstValue::BigInt(lhs), AstValue::BigInt(rhs)) => Ok(AstValue::BigInt(lhs.binop(op, rhs)?)),
+        (AstValue::BigInt(lhs), AstValue::Int(rhs)) if rhs.ty.is_none() => {
+            Ok(AstValue::BigInt(lhs.binop(op, &rhs.into())?))
+        }
+        (AstValue::Int(lhs), AstValue::BigInt(rhs)) if lhs.ty.is_none() => {
+            Ok(AstValue::BigInt(ValueBigInt::from(lhs).binop(op, rhs)?))
+        }
+        _ => bail!("Does not support argument types {:?} {} {:?}", lhs, op, rhs),
+    }
+}
+
+/// Orders two literals of the same kind, `None` if they are unordered like `NaN`
+pub fn compare_literals(lhs: &AstValue, rhs: &AstValue) -> Result<Option<Ordering>> {
+    let ordering = match (lhs, rhs) {
+        (AstValue::Int(l), AstValue::Int(r)) => {
+            if let (Some(lt), Some(rt)) = (l.ty, r.ty) {
+                ensure!(lt == rt, "mismatched integer types: {} and {}", lt, rt);
+            }
+            Some(l.value.cmp(&r.value))
+        }
+        (AstValue::BigInt(l), AstValue::BigInt(r)) => Some(l.value.cmp(&r.value)),
+        (AstValue::BigInt(l), AstValue::Int(r)) if r.ty.is_none() => {
+            Some(l.value.cmp(&ValueBigInt::from(r).value))
+        }
+        (AstValue::Int(l), AstValue::BigInt(r)) if l.ty.is_none() => {
+            Some(ValueBigInt::from(l).value.cmp(&r.value))
+        }
+        (AstValue::Decimal(l), AstValue::Decimal(r)) => l.value.partial_cmp(&r.value),
+        (AstValue::Bool(l), AstValue::Bool(r)) => Some(l.value.cmp(&r.value)),
+        (AstValue::Char(l), AstValue::Char(r)) => Some(l.value.cmp(&r.value)),
+        (AstValue::String(l), AstValue::String(r)) => Some(l.value.cmp(&r.value)),
+        _ => bail!("Does not support comparing {:?} and {:?}", lhs, rhs),
+    };
+    Ok(ordering)
+}
+
+/// Evaluates `value as ty` with Rust's semantics: integers truncate, floats saturate
+pub fn cast_value(value: &AstValue, ty: &AstType) -> Result<AstValue> {
+    let AstType::Primitive(prim) = ty else {
+        bail!("non-primitive cast: {:?} as {}", value, ty)
+    };
+    let casted = match (value, prim) {
+        (AstValue::Int(i), TypePrimitive::Int(TypeInt::BigInt)) => {
+            AstValue::BigInt(ValueBigInt::from(i))
+        }
+        (AstValue::Int(i), TypePrimitive::Int(int)) => AstValue::Int(i.cast(*int)),
+        (AstValue::BigInt(i), TypePrimitive::Int(TypeInt::BigInt)) => AstValue::BigInt(i.clone()),
+        (AstValue::BigInt(i), TypePrimitive::Int(int)) => AstValue::Int(i.cast(*int)),
+        (AstValue::Int(i), TypePrimitive::Decimal(DecimalType::F64)) => {
+            AstValue::decimal(i.value as f64)
+        }
+        (AstValue::Int(i), TypePrimitive::Decimal(DecimalType::F32)) => {
+            AstValue::decimal(i.value as f32 as f64)
+        }
+        (AstValue::Decimal(d), TypePrimitive::Int(int)) if *int != TypeInt::BigInt => {
+            let (min, max) = (int.min_value().unwrap(), int.max_value().unwrap());
+            let value = if d.value.is_nan() {
+                0
+            } else {
+                // `as i128` saturates too, and every fixed width fits in i128
+                (d.value as i128).clamp(min, max)
+            };
+            AstValue::int_typed(value, *int)
+        }
+        (AstValue::Decimal(d), TypePrimitive::Decimal(DecimalType::F64)) => {
+            AstValue::decimal(d.value)
+        }
+        (AstValue::Decimal(d), TypePrimitive::Decimal(DecimalType::F32)) => {
+            AstValue::decimal(d.value as f32 as f64)
+        }
+        (AstValue::Bool(b), TypePrimitive::Int(int)) if *int != TypeInt::BigInt => {
+            AstValue::int_typed(b.value as i128, *int)
+        }
+        (AstValue::Char(c), TypePrimitive::Int(int)) if *int != TypeInt::BigInt => {
+            AstValue::int_typed(int.wrap(c.value as i128), *int)
+        }
+        (AstValue::Int(i), TypePrimitive::Char) if i.ty.unwrap_or(TypeInt::U8) == TypeInt::U8 => {
+            let byte = u8::try_from(i.value)
+                .with_context(|| format!("only u8 can be cast as char, got {}", i))?;
+            AstValue::Char(ValueChar::new(byte as char))
+        }
+        (AstValue::Bool(_), TypePrimitive::Bool) | (AstValue::Char(_), TypePrimitive::Char) => {
+            value.clone()
+        }
+        _ => bail!("non-primitive cast: {:?} as {}", value, ty),
+    };
+    Ok(casted)
+}
@@ -1,5 +1,4 @@
-use crate::ast::AstExpr;
-use crate::ast::AstType;
+use crate::ast::{AstExpr, AstType, AstValue, BExpr, ExprRangeLimit};
 use crate::id::{Ident, Locator};
 use crate::{common_enum, common_struct};
 pub type BPattern = Box<Pattern>;
@@ -14,6 +13,11 @@ common_enum! {
         Variant(PatternVariant),
         Type(PatternType),
         Wildcard(PatternWildcard),
+        Or(PatternOr),
+        Lit(PatternLit),
+        Range(PatternRange),
+        Slice(PatternSlice),
+        Rest(PatternRest),
     }
 }
 impl Pattern {
@@ -49,8 +53,10 @@ common_struct! {
 }
 common_struct! {
     pub struct PatternStruct {
-        pub name: Ident,
+        pub name: Locator,
         pub fields: Vec<PatternStructField>,
+        /// whether the remaining fields are skipped with `..`
+        pub has_rest: bool,
     }
 }
 common_struct! {
@@ -70,6 +76,7 @@ common_struct! {
     }
 }
 common_struct! {
+    /// a path like `State::Idle` naming a unit variant or constant
     pub struct PatternVariant {
         pub name: AstExpr, // TypeExpr
         pub pattern: Option<Box<Pattern>>,
@@ -94,10 +101,13 @@ impl PatternType {
 }
 
 common_struct! {
-    /// pattern like `mut x`
+    /// pattern like `mut x`, `ref x` or `x @ 1..=5`
     pub struct PatternIdent {
         pub ident: Ident,
         pub mutability: Option<bool>,
+        pub by_ref: bool,
+        /// the pattern after `@`, which the value must match as well
+        pub subpattern: Option<BPattern>,
     }
 }
 impl PatternIdent {
@@ -105,9 +115,41 @@ impl PatternIdent {
         Self {
             ident,
             mutability: None,
+            by_ref: false,
+            subpattern: None,
         }
     }
 }
 common_struct! {
     pub struct PatternWildcard {}
 }
+common_struct! {
+    /// `a | b`, matches if any of the patterns does
+    pub struct PatternOr {
+        pub patterns: Vec<Pattern>,
+    }
+}
+common_struct! {
+    /// a literal like `1`, `'a'` or `"str"`
+    pub struct PatternLit {
+        pub value: AstValue,
+    }
+}
+common_struct! {
+    /// `start..end`, `start..=end`, `start..` or `..=end`
+    pub struct PatternRange {
+        pub start: Option<BExpr>,
+        pub limit: ExprRangeLimit,
+        pub end: Option<BExpr>,
+    }
+}
+common_struct! {
+    /// `[first, .., last]`
+    pub struct PatternSlice {
+        pub patterns: Vec<Pattern>,
+    }
+}
+common_struct! {
+    /// `..` in a tuple or slice pattern, matching any number of elements
+    pub struct PatternRest {}
+}
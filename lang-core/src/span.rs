@@ -1,15 +1,183 @@
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 
+use crate::ast::{AstExpr, AstItem, AstType};
+use crate::pat::Pattern;
+
+/// Ids start at 1 and are not reused, so a stale span never resolves to another file
 pub type FileId = u64;
+/// Index of tokens without a source file in the [SourceMap]
+pub type TokensId = u64;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Span {
-    pub file: FileId,
-    pub lo: u32,
-    pub hi: u32,
+pub enum Span {
+    /// a byte range in a registered source file
+    File { file: FileId, lo: u32, hi: u32 },
+    /// tokens without a source file, like the input of a proc macro
+    Tokens(TokensId),
+}
+impl Span {
+    pub fn new(file: FileId, lo: u32, hi: u32) -> Self {
+        Self::File { file, lo, hi }
+    }
+    pub fn file(&self) -> Option<FileId> {
+        match self {
+            Self::File { file, .. } => Some(*file),
+            Self::Tokens(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FileInfo {
     pub file: PathBuf,
+    pub source: String,
+}
+impl FileInfo {
+    /// 1-based line and column of a byte offset
+    pub fn line_column(&self, offset: u32) -> (usize, usize) {
+        let before = self.source.get(..offset as usize).unwrap_or(&self.source);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+    pub fn text(&self, span: Span) -> Option<&str> {
+        let Span::File { lo, hi, .. } = span else {
+            return None;
+        };
+        self.source.get(lo as usize..hi as usize)
+    }
+}
+
+/// A position in a source file, displayed as `path:line:column`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// Source files, proc macro tokens and the spans the parser recorded for AST nodes.
+///
+/// Nodes are keyed by their kind and content hash, so the AST itself stays span-free. Equal
+/// nodes recorded at different spans are ambiguous and have none, rather than the wrong one.
+/// The parser clears the map before each parse, so it only describes the latest one.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: HashMap<FileId, FileInfo>,
+    last_file: FileId,
+    tokens: HashMap<TokensId, proc_macro2::Span>,
+    last_tokens: TokensId,
+    spans: HashMap<(TypeId, u64), Option<Span>>,
+}
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Forgets all files, tokens and spans. Ids keep counting up
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.tokens.clear();
+        self.spans.clear();
+    }
+    pub fn add_file(&mut self, file: PathBuf, source: String) -> FileId {
+        self.last_file += 1;
+        self.files.insert(self.last_file, FileInfo { file, source });
+        self.last_file
+    }
+    pub fn get_file(&self, file: FileId) -> Option<&FileInfo> {
+        self.files.get(&file)
+    }
+    pub fn add_tokens(&mut self, span: proc_macro2::Span) -> Span {
+        self.last_tokens += 1;
+        self.tokens.insert(self.last_tokens, span);
+        Span::Tokens(self.last_tokens)
+    }
+    /// The tokens of `span`, for proc macros to report errors at their input
+    pub fn get_tokens(&self, span: Span) -> Option<proc_macro2::Span> {
+        match span {
+            Span::Tokens(id) => self.tokens.get(&id).copied(),
+            Span::File { .. } => None,
+        }
+    }
+    pub fn record<T: Hash + 'static>(&mut self, node: &T, span: Span) {
+        self.spans
+            .entry(node_key(node))
+            .and_modify(|recorded| {
+                if *recorded != Some(span) {
+                    *recorded = None;
+                }
+            })
+            .or_insert(Some(span));
+    }
+    pub fn get_span<T: Hash + 'static>(&self, node: &T) -> Option<Span> {
+        self.spans.get(&node_key(node)).copied().flatten()
+    }
+    pub fn locate(&self, span: Span) -> Option<Location> {
+        let Span::File { file, lo, .. } = span else {
+            return None;
+        };
+        let file = self.get_file(file)?;
+        let (line, column) = file.line_column(lo);
+        Some(Location {
+            file: file.file.clone(),
+            line,
+            column,
+        })
+    }
+}
+fn node_key<T: Hash + 'static>(node: &T) -> (TypeId, u64) {
+    let mut hasher = DefaultHasher::new();
+    node.hash(&mut hasher);
+    (TypeId::of::<T>(), hasher.finish())
+}
+
+thread_local! {
+    static SOURCE_MAP: RefCell<SourceMap> = RefCell::new(SourceMap::new());
+}
+pub fn with_threadlocal_source_map<R>(f: impl FnOnce(&mut SourceMap) -> R) -> R {
+    SOURCE_MAP.with(|map| f(&mut map.borrow_mut()))
+}
+
+/// AST nodes the parser records spans for
+pub trait SourceNode: Hash + Debug + Sized + 'static {
+    fn span(&self) -> Option<Span> {
+        with_threadlocal_source_map(|map| map.get_span(self))
+    }
+    fn location(&self) -> Option<Location> {
+        let span = self.span()?;
+        with_threadlocal_source_map(|map| map.locate(span))
+    }
+    /// The node for error messages: its source text and location if known, `{:?}` otherwise
+    fn describe(&self) -> String {
+        let described = self.span().and_then(|span| {
+            with_threadlocal_source_map(|map| {
+                let text = map.get_file(span.file()?)?.text(span)?;
+                let location = map.locate(span)?;
+                let first_line = text.lines().next().unwrap_or_default();
+                let ellipsis = if first_line.len() < text.len() {
+                    " ..."
+                } else {
+                    ""
+                };
+                Some(format!("`{}{}` at {}", first_line, ellipsis, location))
+            })
+        });
+        described.unwrap_or_else(|| format!("{:?}", self))
+    }
 }
+impl SourceNode for AstExpr {}
+impl SourceNode for AstItem {}
+impl SourceNode for AstType {}
+impl SourceNode for Pattern {}
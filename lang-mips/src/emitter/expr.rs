@@ -92,7 +92,7 @@ impl MipsEmitter {
     ) -> Result<MipsEmitExprResult> {
         match value {
             AstValue::Int(i) => {
-                if i.value > i16::MAX as i64 {
+                if i.value > i16::MAX as i128 {
                     bail!("Value {} is too large for MIPS", i.value);
                 }
                 let ins = self.emit_load_immediate(i.value as i16);
@@ -1,10 +1,11 @@
 use eyre::Result;
 
-use lang_core::ast::AstItem;
+use lang_core::ast::{AstItem, AstModule};
 use lang_core::context::SharedScopedContext;
 
 use crate::emitter::expr::MipsEmitExprResult;
 use crate::emitter::MipsEmitter;
+use crate::instruction::MipsInstruction;
 
 impl MipsEmitter {
     pub fn emit_item(
@@ -18,4 +19,17 @@ impl MipsEmitter {
             _ => unimplemented!("emit_item: {:?}", item),
         }
     }
+    /// Emits the items of a module in order, see [`crate::pipeline::optimize_module`] for
+    /// lowering its generic items first
+    pub fn emit_module(
+        &mut self,
+        module: &AstModule,
+        ctx: &SharedScopedContext,
+    ) -> Result<Vec<MipsInstruction>> {
+        let mut instructions = vec![];
+        for item in &module.items {
+            instructions.extend(self.emit_item(item, ctx)?.instructions);
+        }
+        Ok(instructions)
+    }
 }
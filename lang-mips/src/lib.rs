@@ -1,3 +1,4 @@
 pub mod emitter;
 pub mod instruction;
+pub mod pipeline;
 pub mod storage;
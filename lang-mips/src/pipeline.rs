@@ -0,0 +1,19 @@
+use std::sync::Arc;
+
+use eyre::Result;
+
+use lang_core::ast::{AstModule, AstSerializer};
+use lang_core::context::SharedScopedContext;
+use lang_optimize::pass::{OptLevel, PassManager};
+
+/// Optimizes a module at `level` before it's emitted. MIPS has no generics, so generic
+/// items are always instantiated first
+pub fn optimize_module(
+    serializer: Arc<dyn AstSerializer>,
+    module: AstModule,
+    level: OptLevel,
+    ctx: &SharedScopedContext,
+) -> Result<AstModule> {
+    let mut manager = PassManager::with_level(serializer, level)?.with_monomorphize(true);
+    manager.optimize_module(module, ctx, true)
+}
@@ -4,6 +4,9 @@ use lang_core::context::SharedScopedContext;
 
 use lang_mips::emitter::MipsEmitter;
 use lang_mips::instruction::MipsInstruction;
+use lang_mips::pipeline::optimize_module;
+use lang_optimize::pass::OptLevel;
+use rust_lang::parser::RustParser;
 use rust_lang::printer::RustPrinter;
 use rust_lang::shll_parse_expr;
 use std::sync::Arc;
@@ -105,3 +108,37 @@ fn test_mips_emit_func() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_mips_emit_generic_module() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let module = match RustParser::new().deserialize_item(
+        "mod m {
+    fn one<T>() -> i64 {
+        1
+    }
+    fn main() -> i64 {
+        one::<i64>() + 2
+    }
+}",
+    )? {
+        AstItem::Module(module) => module,
+        item => bail!("Expected a module, got {}", item),
+    };
+    let ctx = SharedScopedContext::new();
+    let module = optimize_module(Arc::new(RustPrinter::new()), module, OptLevel::O0, &ctx)?;
+
+    let instructions = MipsEmitter::new().emit_module(&module, &ctx)?;
+    let labels = instructions
+        .iter()
+        .filter_map(|x| match x {
+            MipsInstruction::Label { name } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(labels.contains(&"one_i64"), "{:?}", labels);
+    assert!(labels.contains(&"main"), "{:?}", labels);
+    assert!(!labels.contains(&"one"), "{:?}", labels);
+    Ok(())
+}
@@ -4,7 +4,7 @@ use itertools::Itertools;
 use lang_core::ast::{AstDeserializer, AstSerializer};
 use lang_core::context::SharedScopedContext;
 use lang_optimize::interpreter::Interpreter;
-use lang_optimize::pass::load_optimizers;
+use lang_optimize::pass::{OptLevel, PassManager};
 use rust_lang::parser::RustParser;
 use rust_lang::printer::rustfmt::format_code;
 use rust_lang::printer::RustPrinter;
@@ -36,10 +36,8 @@ fn main() -> Result<()> {
         let file_content = std::fs::read_to_string(file_in)?;
         let mut node = rust_parser.deserialize_node(&file_content)?;
         let ctx = SharedScopedContext::new();
-        let optimizers = load_optimizers(rust_printer.clone() as _);
-        for optimizer in optimizers {
-            node = optimizer.optimize_tree(node, &ctx)?;
-        }
+        let mut manager = PassManager::with_level(rust_printer.clone() as _, OptLevel::O2)?;
+        node = manager.optimize_tree(node, &ctx)?;
         let code = rust_printer.serialize_node(&node)?;
         writeln!(&mut file_out, "{}", code)?;
         let code = format_code(&code)?;
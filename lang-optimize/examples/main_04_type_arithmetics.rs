@@ -1,24 +1,27 @@
-use rust_lang::t;
+use rust_macro::t;
 use std::fmt::Display;
 
 fn print(i: impl Display) {
     println!("{}", i)
 }
-type Int = i64;
 
-type FooUnnamedStruct = t! {
-    struct {
-        a: Int,
-        b: Int,
+t! {
+    type Int = i64;
+
+    type FooUnnamedStruct = t! {
+        struct {
+            a: Int,
+            b: Int,
+        }
+    };
+    struct BarNamedStruct {
+        c: Int,
+        d: Int,
     }
-};
-struct BarNamedStruct {
-    c: Int,
-    d: Int,
+    type FooPlusBar = t! {
+         FooUnnamedStruct + BarNamedStruct
+    };
 }
-type FooPlusBar = t! {
-     FooUnnamedStruct + BarNamedStruct
-};
 
 fn main() {
     FooPlusBar {
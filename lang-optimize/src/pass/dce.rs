@@ -0,0 +1,750 @@
+use crate::pass::OptimizePass;
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::id::{Ident, Locator};
+use lang_core::ops::{BinOpKind, UnOpKind};
+use lang_core::pat::{Pattern, PatternOr, PatternSlice, PatternTuple};
+use std::collections::HashSet;
+
+/// Collects identifiers an AST fragment may refer to.
+///
+/// Opaque nodes (raw macros and other `Any` payloads) are kept as their debug text,
+/// and an identifier counts as used if it appears in them as a whole word.
+///
+/// It also records which bindings are written to, and whether the fragment contains
+/// `break`, `continue` or `return`.
+#[derive(Default)]
+pub struct UsageCollector {
+    idents: HashSet<Ident>,
+    opaque: Vec<String>,
+    assigned: HashSet<Ident>,
+    /// bindings that may be written behind our back: borrowed mutably or assigned in a closure
+    escaped: HashSet<Ident>,
+    loop_control: bool,
+    early_return: bool,
+}
+impl UsageCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn is_used(&self, ident: &Ident) -> bool {
+        self.idents.contains(ident)
+            || self
+                .opaque
+                .iter()
+                .any(|raw| contains_word(raw, ident.as_str()))
+    }
+    pub fn is_assigned(&self, ident: &Ident) -> bool {
+        self.assigned.contains(ident) || self.is_escaped(ident)
+    }
+    pub fn is_escaped(&self, ident: &Ident) -> bool {
+        self.escaped.contains(ident)
+            || self
+                .opaque
+                .iter()
+                .any(|raw| contains_word(raw, ident.as_str()))
+    }
+    pub fn assigned(&self) -> impl Iterator<Item = &Ident> {
+        self.assigned.iter().chain(self.escaped.iter())
+    }
+    /// `return` leaves the loop as well
+    pub fn has_loop_control(&self) -> bool {
+        self.loop_control
+            || self.has_return()
+            || self
+                .opaque
+                .iter()
+                .any(|raw| contains_word(raw, "break") || contains_word(raw, "continue"))
+    }
+    pub fn has_return(&self) -> bool {
+        self.early_return || self.opaque.iter().any(|raw| contains_word(raw, "return"))
+    }
+    pub fn forget(&mut self, ident: &Ident) {
+        self.idents.remove(ident);
+    }
+    pub fn extend(&mut self, other: &UsageCollector) {
+        self.idents.extend(other.idents.iter().cloned());
+        self.opaque.extend(other.opaque.iter().cloned());
+        self.assigned.extend(other.assigned.iter().cloned());
+        self.escaped.extend(other.escaped.iter().cloned());
+        self.loop_control |= other.loop_control;
+        self.early_return |= other.early_return;
+    }
+    fn collect_locator(&mut self, locator: &Locator) {
+        match locator {
+            Locator::Ident(ident) => {
+                self.idents.insert(ident.clone());
+            }
+            Locator::Path(path) => self.idents.extend(path.segments.iter().cloned()),
+            Locator::ParameterPath(path) => {
+                self.idents
+                    .extend(path.segments.iter().map(|x| x.ident.clone()));
+            }
+        }
+    }
+    /// The names a pattern refers to, like the bounds of a range or the struct it destructures
+    pub fn collect_pattern(&mut self, pat: &Pattern) {
+        match pat {
+            Pattern::Ident(ident) => {
+                if let Some(pat) = &ident.subpattern {
+                    self.collect_pattern(pat);
+                }
+            }
+            Pattern::Tuple(PatternTuple { patterns })
+            | Pattern::Slice(PatternSlice { patterns })
+            | Pattern::Or(PatternOr { patterns }) => {
+                patterns.iter().for_each(|x| self.collect_pattern(x))
+            }
+            Pattern::TupleStruct(t) => {
+                self.collect_locator(&t.name);
+                t.patterns.iter().for_each(|x| self.collect_pattern(x));
+            }
+            Pattern::Struct(s) => {
+                self.collect_locator(&s.name);
+                s.fields
+                    .iter()
+                    .filter_map(|x| x.rename.as_ref())
+                    .for_each(|x| self.collect_pattern(x));
+            }
+            Pattern::Structural(s) => s
+                .fields
+                .iter()
+                .filter_map(|x| x.rename.as_ref())
+                .for_each(|x| self.collect_pattern(x)),
+            Pattern::Box(b) => self.collect_pattern(&b.pattern),
+            Pattern::Variant(v) => {
+                self.collect_expr(&v.name);
+                if let Some(pat) = &v.pattern {
+                    self.collect_pattern(pat);
+                }
+            }
+            Pattern::Type(t) => self.collect_pattern(&t.pat),
+            Pattern::Range(r) => {
+                for x in [&r.start, &r.end].into_iter().flatten() {
+                    self.collect_expr(x);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Rest(_) | Pattern::Lit(_) => {}
+        }
+    }
+    fn collect_opaque(&mut self, any: &impl std::fmt::Debug) {
+        self.opaque.push(format!("{:?}", any));
+    }
+    pub fn collect_expr(&mut self, expr: &AstExpr) {
+        match expr {
+            AstExpr::Id(_) => {}
+            AstExpr::Locator(locator) => self.collect_locator(locator),
+            AstExpr::Value(value) => self.collect_value(value),
+            AstExpr::Block(block) => self.collect_block(block),
+            AstExpr::Match(m) => {
+                if let Some(scrutinee) = &m.scrutinee {
+                    self.collect_expr(scrutinee);
+                }
+                for case in &m.cases {
+                    if let Some(pat) = &case.pat {
+                        self.collect_pattern(pat);
+                    }
+                    self.collect_expr(&case.cond);
+                    self.collect_expr(&case.body);
+                }
+            }
+            AstExpr::If(if_) => {
+                self.collect_expr(&if_.cond);
+                self.collect_expr(&if_.then);
+                if let Some(elze) = &if_.elze {
+                    self.collect_expr(elze);
+                }
+            }
+            AstExpr::Loop(l) => self.collect_expr(&l.body),
+            AstExpr::While(w) => {
+                self.collect_expr(&w.cond);
+                self.collect_expr(&w.body);
+            }
+            AstExpr::For(f) => {
+                self.collect_expr(&f.iter);
+                self.collect_expr(&f.body);
+            }
+            AstExpr::Break(b) => {
+                self.loop_control = true;
+                if let Some(value) = &b.value {
+                    self.collect_expr(value);
+                }
+            }
+            AstExpr::Continue(_) => self.loop_control = true,
+            AstExpr::Return(r) => {
+                self.early_return = true;
+                if let Some(value) = &r.value {
+                    self.collect_expr(value);
+                }
+            }
+            AstExpr::Invoke(invoke) => {
+                match &invoke.target {
+                    ExprInvokeTarget::Function(locator) => self.collect_locator(locator),
+                    ExprInvokeTarget::Type(_) => {}
+                    ExprInvokeTarget::Method(select) => self.collect_expr(&select.obj),
+                    ExprInvokeTarget::Closure(func) => self.collect_expr(&func.body),
+                    ExprInvokeTarget::BinOp(_) => {}
+                    ExprInvokeTarget::Expr(expr) => self.collect_expr(expr),
+                }
+                for arg in &invoke.args {
+                    self.collect_expr(arg);
+                }
+            }
+            AstExpr::BinOp(op) => {
+                self.collect_expr(&op.lhs);
+                self.collect_expr(&op.rhs);
+            }
+            AstExpr::UnOp(op) => self.collect_expr(&op.val),
+            AstExpr::Assign(assign) => {
+                if let Some(root) = place_root(&assign.target) {
+                    self.assigned.insert(root.clone());
+                }
+                self.collect_expr(&assign.target);
+                self.collect_expr(&assign.value);
+            }
+            AstExpr::Select(select) => self.collect_expr(&select.obj),
+            AstExpr::Cast(cast) => self.collect_expr(&cast.expr),
+            AstExpr::Index(index) => {
+                self.collect_expr(&index.obj);
+                self.collect_expr(&index.index);
+            }
+            AstExpr::Struct(s) => {
+                self.collect_expr(&s.name);
+                self.collect_fields(&s.fields);
+            }
+            AstExpr::Structural(s) => self.collect_fields(&s.fields),
+            AstExpr::Reference(r) => {
+                if r.mutable == Some(true) {
+                    if let Some(root) = place_root(&r.referee) {
+                        self.escaped.insert(root.clone());
+                    }
+                }
+                self.collect_expr(&r.referee)
+            }
+            AstExpr::Dereference(d) => self.collect_expr(&d.referee),
+            AstExpr::Tuple(t) => t.values.iter().for_each(|x| self.collect_expr(x)),
+            AstExpr::Try(t) => self.collect_expr(&t.expr),
+            AstExpr::Let(l) => self.collect_expr(&l.expr),
+            AstExpr::Closure(c) => {
+                let mut body = UsageCollector::new();
+                body.collect_expr(&c.body);
+                self.escaped.extend(body.assigned.iter().cloned());
+                self.extend(&body);
+            }
+            AstExpr::Array(a) => a.values.iter().for_each(|x| self.collect_expr(x)),
+            AstExpr::ArrayRepeat(a) => {
+                self.collect_expr(&a.value);
+                self.collect_expr(&a.len);
+            }
+            AstExpr::Closured(c) => self.collect_expr(&c.expr),
+            AstExpr::Paren(p) => self.collect_expr(&p.expr),
+            AstExpr::Const(c) => self.collect_expr(&c.block),
+            AstExpr::Unsafe(u) => self.collect_expr(&u.block),
+            AstExpr::Async(a) => self.collect_expr(&a.block),
+            AstExpr::Range(r) => {
+                for x in [&r.start, &r.end, &r.step].into_iter().flatten() {
+                    self.collect_expr(x);
+                }
+            }
+            AstExpr::Splat(s) => self.collect_expr(&s.iter),
+            AstExpr::SplatDict(s) => self.collect_expr(&s.dict),
+            AstExpr::Item(item) => self.collect_item(item),
+            AstExpr::Any(any) => self.collect_opaque(any),
+        }
+    }
+    fn collect_fields(&mut self, fields: &[ExprField]) {
+        for field in fields {
+            match &field.value {
+                Some(value) => self.collect_expr(value),
+                // shorthand `Foo { a }` refers to the binding `a`
+                None => {
+                    self.idents.insert(field.name.clone());
+                }
+            }
+        }
+    }
+    pub fn collect_value(&mut self, value: &AstValue) {
+        match value {
+            AstValue::Function(func) => self.collect_expr(&func.body),
+            AstValue::Expr(expr) => self.collect_expr(expr),
+            AstValue::Type(AstType::Expr(expr)) => self.collect_expr(expr),
+            AstValue::Struct(s) => s
+                .structural
+                .fields
+                .iter()
+                .for_each(|x| self.collect_value(&x.value)),
+            // printed as `Enum::Variant`, so the enum stays
+            AstValue::Variant(v) => {
+                self.idents.insert(v.ty.name.clone());
+                self.collect_value(&v.value);
+            }
+            AstValue::Structural(s) => s.fields.iter().for_each(|x| self.collect_value(&x.value)),
+            AstValue::Tuple(t) => t.values.iter().for_each(|x| self.collect_value(x)),
+            AstValue::List(l) => l.values.iter().for_each(|x| self.collect_value(x)),
+            AstValue::Some(s) => self.collect_value(&s.value),
+            AstValue::Option(o) => {
+                if let Some(value) = &o.value {
+                    self.collect_value(value)
+                }
+            }
+            AstValue::Any(any) => self.collect_opaque(any),
+            _ => {}
+        }
+    }
+    pub fn collect_block(&mut self, block: &ExprBlock) {
+        for stmt in &block.stmts {
+            self.collect_stmt(stmt);
+        }
+    }
+    pub fn collect_stmt(&mut self, stmt: &BlockStmt) {
+        match stmt {
+            BlockStmt::Item(item) => self.collect_item(item),
+            BlockStmt::Let(let_) => {
+                self.collect_pattern(&let_.pat);
+                for x in [&let_.init, &let_.diverge].into_iter().flatten() {
+                    self.collect_expr(x);
+                }
+            }
+            BlockStmt::Expr(expr) => self.collect_expr(&expr.expr),
+            BlockStmt::Noop => {}
+            BlockStmt::Any(any) => self.collect_opaque(any),
+        }
+    }
+    pub fn collect_item(&mut self, item: &AstItem) {
+        match item {
+            AstItem::Module(m) => m.items.iter().for_each(|x| self.collect_item(x)),
+            AstItem::DefFunction(f) => self.collect_expr(&f.body),
+            AstItem::DefConst(c) => self.collect_expr(&c.value),
+            AstItem::DefStatic(s) => self.collect_expr(&s.value),
+            AstItem::Impl(i) => i.items.iter().for_each(|x| self.collect_item(x)),
+            AstItem::DefTrait(t) => t.items.iter().for_each(|x| self.collect_item(x)),
+            AstItem::Expr(expr) => self.collect_expr(expr),
+            AstItem::Any(any) => self.collect_opaque(any),
+            _ => {}
+        }
+    }
+}
+
+/// The binding a place expression like `a.b[i]` writes to
+pub fn place_root(expr: &AstExpr) -> Option<&Ident> {
+    match expr {
+        AstExpr::Locator(Locator::Ident(ident)) => Some(ident),
+        AstExpr::Select(select) => place_root(&select.obj),
+        AstExpr::Index(index) => place_root(&index.obj),
+        AstExpr::Paren(paren) => place_root(&paren.expr),
+        AstExpr::Dereference(deref) => place_root(&deref.referee),
+        _ => None,
+    }
+}
+
+fn contains_word(haystack: &str, word: &str) -> bool {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+    haystack.match_indices(word).any(|(i, _)| {
+        let before = haystack[..i].chars().next_back();
+        let after = haystack[i + word.len()..].chars().next();
+        !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+    })
+}
+
+pub fn collect_pattern_idents(pat: &Pattern, idents: &mut Vec<Ident>) {
+    match pat {
+        Pattern::Ident(ident) => {
+            idents.push(ident.ident.clone());
+            if let Some(pat) = &ident.subpattern {
+                collect_pattern_idents(pat, idents)
+            }
+        }
+        Pattern::Tuple(t) => t
+            .patterns
+            .iter()
+            .for_each(|x| collect_pattern_idents(x, idents)),
+        Pattern::TupleStruct(t) => t
+            .patterns
+            .iter()
+            .for_each(|x| collect_pattern_idents(x, idents)),
+        Pattern::Struct(s) => s.fields.iter().for_each(|x| match &x.rename {
+            Some(rename) => collect_pattern_idents(rename, idents),
+            None => idents.push(x.name.clone()),
+        }),
+        Pattern::Structural(s) => s.fields.iter().for_each(|x| match &x.rename {
+            Some(rename) => collect_pattern_idents(rename, idents),
+            None => idents.push(x.name.clone()),
+        }),
+        Pattern::Box(b) => collect_pattern_idents(&b.pattern, idents),
+        Pattern::Variant(v) => {
+            if let Some(pattern) = &v.pattern {
+                collect_pattern_idents(pattern, idents)
+            }
+        }
+        Pattern::Type(t) => collect_pattern_idents(&t.pat, idents),
+        // every alternative binds the same names
+        Pattern::Or(o) => {
+            if let Some(first) = o.patterns.first() {
+                collect_pattern_idents(first, idents)
+            }
+        }
+        Pattern::Slice(s) => s
+            .patterns
+            .iter()
+            .for_each(|x| collect_pattern_idents(x, idents)),
+        Pattern::Wildcard(_) | Pattern::Rest(_) | Pattern::Lit(_) | Pattern::Range(_) => {}
+    }
+}
+
+/// Operators that panic on some operands, like overflowing `+` or `/` by zero
+fn is_trapping_binop(kind: &BinOpKind) -> bool {
+    matches!(
+        kind,
+        BinOpKind::Add
+            | BinOpKind::Sub
+            | BinOpKind::Mul
+            | BinOpKind::Div
+            | BinOpKind::Mod
+            | BinOpKind::Shl
+            | BinOpKind::Shr
+    )
+}
+/// Whether evaluating the expression can be skipped without changing behaviour.
+///
+/// Removing an expression that can panic would remove the panic too
+pub fn is_pure_expr(expr: &AstExpr) -> bool {
+    is_effect_free(expr, false)
+}
+/// Whether the expression has no side effects, though it may panic. Fine for expressions
+/// that are evaluated at compile time anyway, where a panic fails the evaluation
+pub fn is_effect_free_expr(expr: &AstExpr) -> bool {
+    is_effect_free(expr, true)
+}
+fn is_effect_free(expr: &AstExpr, allow_panic: bool) -> bool {
+    let free = |x: &AstExpr| is_effect_free(x, allow_panic);
+    match expr {
+        AstExpr::Locator(_) | AstExpr::Value(_) | AstExpr::Closure(_) => true,
+        AstExpr::BinOp(op) if !allow_panic && is_trapping_binop(&op.kind) => false,
+        AstExpr::BinOp(op) => free(&op.lhs) && free(&op.rhs),
+        // `-i64::MIN` overflows
+        AstExpr::UnOp(op) if !allow_panic && op.op == UnOpKind::Neg => false,
+        AstExpr::UnOp(op) => free(&op.val),
+        AstExpr::Paren(p) => free(&p.expr),
+        // `as` wraps or saturates, it never panics
+        AstExpr::Cast(c) => free(&c.expr),
+        AstExpr::Select(s) => free(&s.obj),
+        AstExpr::Reference(r) => free(&r.referee),
+        AstExpr::Tuple(t) => t.values.iter().all(free),
+        AstExpr::Array(a) => a.values.iter().all(free),
+        AstExpr::ArrayRepeat(a) => free(&a.value) && free(&a.len),
+        AstExpr::Struct(s) => s.fields.iter().all(|x| x.value.iter().all(|x| free(x))),
+        AstExpr::Structural(s) => s.fields.iter().all(|x| x.value.iter().all(|x| free(x))),
+        AstExpr::Range(r) => [&r.start, &r.end, &r.step]
+            .into_iter()
+            .flatten()
+            .all(|x| free(x)),
+        _ => false,
+    }
+}
+
+fn as_literal_bool(expr: &AstExpr) -> Option<bool> {
+    match expr {
+        AstExpr::Value(value) => match value.as_ref() {
+            AstValue::Bool(b) => Some(b.value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Functions with these attributes are called from outside the module, by the test harness or
+/// through their symbol, or only exist in some builds
+const ROOT_ATTRS: &[&str] = &["test", "cfg", "no_mangle", "export_name"];
+/// Whether a function is kept even if nothing in its module calls it
+fn is_root_function(func: &ItemDefFunction) -> bool {
+    func.visibility == Visibility::Public
+        || func.name.as_str() == "main"
+        || ROOT_ATTRS
+            .iter()
+            .any(|x| func.attrs.find_by_name(x).is_some())
+}
+
+/// Computes the functions reachable from `roots`, given the usages of every function body
+pub fn reachable_functions(
+    roots: &UsageCollector,
+    functions: &[(Ident, UsageCollector)],
+) -> HashSet<Ident> {
+    let mut reachable: HashSet<Ident> = HashSet::new();
+    loop {
+        let mut changed = false;
+        for (name, _) in functions {
+            if reachable.contains(name) {
+                continue;
+            }
+            let used = roots.is_used(name)
+                || functions
+                    .iter()
+                    .any(|(other, usage)| reachable.contains(other) && usage.is_used(name));
+            if used {
+                reachable.insert(name.clone());
+                changed = true;
+            }
+        }
+        if !changed {
+            return reachable;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DeadCodeEliminationPass;
+impl DeadCodeEliminationPass {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// removes unused function items, unused pure let bindings and pure statements without value
+    pub fn prune_block(&self, mut block: ExprBlock) -> ExprBlock {
+        // items in a block are visible to the whole block, so check them first
+        let mut roots = UsageCollector::new();
+        let mut functions = vec![];
+        for stmt in &block.stmts {
+            let func = match stmt {
+                BlockStmt::Item(item) => item.as_function(),
+                _ => None,
+            };
+            match func {
+                Some(func) => {
+                    let mut usage = UsageCollector::new();
+                    usage.collect_expr(&func.body);
+                    functions.push((func.name.clone(), usage));
+                }
+                None => roots.collect_stmt(stmt),
+            }
+        }
+        let reachable = reachable_functions(&roots, &functions);
+        block.stmts.retain(|stmt| match stmt {
+            BlockStmt::Item(item) => match item.as_function() {
+                Some(func) => reachable.contains(&func.name),
+                None => true,
+            },
+            _ => true,
+        });
+
+        let tail = block.last_expr().is_some();
+        let count = block.stmts.len();
+        let mut used = UsageCollector::new();
+        let mut stmts = vec![];
+        for (i, stmt) in block.stmts.into_iter().enumerate().rev() {
+            let is_tail = tail && i + 1 == count;
+            match &stmt {
+                BlockStmt::Let(let_) => {
+                    let mut idents = vec![];
+                    collect_pattern_idents(&let_.pat, &mut idents);
+                    let removable = idents.iter().all(|x| !used.is_used(x))
+                        && let_.init.iter().all(is_pure_expr)
+                        && let_.diverge.is_none();
+                    if removable {
+                        debug!("Removing unused binding {:?}", idents);
+                        continue;
+                    }
+                    idents.iter().for_each(|x| used.forget(x));
+                }
+                BlockStmt::Expr(expr) if !is_tail && is_pure_expr(&expr.expr) => continue,
+                BlockStmt::Noop => continue,
+                _ => {}
+            }
+            used.collect_stmt(&stmt);
+            stmts.push(stmt);
+        }
+        stmts.reverse();
+        block.stmts = stmts;
+        block
+    }
+
+    pub fn eliminate_if(&self, if_: ExprIf) -> AstExpr {
+        let cond = self.eliminate_expr(*if_.cond);
+        match as_literal_bool(&cond) {
+            Some(true) => self.eliminate_expr(*if_.then),
+            Some(false) => match if_.elze {
+                Some(elze) => self.eliminate_expr(*elze),
+                None => AstExpr::unit(),
+            },
+            None => AstExpr::If(ExprIf {
+                cond: cond.into(),
+                then: self.eliminate_expr(*if_.then).into(),
+                elze: if_.elze.map(|x| self.eliminate_expr(*x).into()),
+            }),
+        }
+    }
+    pub fn eliminate_match(&self, mut match_: ExprMatch) -> AstExpr {
+        // a pattern case is only taken if its pattern matches, whatever its guard
+        if match_.scrutinee.is_some() {
+            match_.cases = match_
+                .cases
+                .into_iter()
+                .map(|mut case| {
+                    case.cond = self.eliminate_expr(*case.cond).into();
+                    case.body = self.eliminate_expr(*case.body).into();
+                    case
+                })
+                .collect();
+            return AstExpr::Match(match_);
+        }
+        let mut cases = vec![];
+        for case in match_.cases {
+            let cond = self.eliminate_expr(*case.cond);
+            let literal = as_literal_bool(&cond);
+            if literal == Some(false) {
+                continue;
+            }
+            let body = self.eliminate_expr(*case.body);
+            if literal == Some(true) && cases.is_empty() {
+                return body;
+            }
+            cases.push(ExprMatchCase {
+                pat: None,
+                cond: cond.into(),
+                body: body.into(),
+            });
+            if literal == Some(true) {
+                break;
+            }
+        }
+        if cases.is_empty() {
+            return AstExpr::unit();
+        }
+        AstExpr::Match(ExprMatch {
+            scrutinee: None,
+            cases,
+        })
+    }
+
+    /// recursively eliminates dead code in an expression, without the need of a context
+    pub fn eliminate_expr(&self, expr: AstExpr) -> AstExpr {
+        match expr {
+            AstExpr::Block(block) => self.eliminate_block(block),
+            AstExpr::If(if_) => self.eliminate_if(if_),
+            AstExpr::Match(match_) => self.eliminate_match(match_),
+            AstExpr::Loop(mut l) => {
+                l.body = self.eliminate_expr(*l.body).into();
+                AstExpr::Loop(l)
+            }
+            AstExpr::While(mut w) => {
+                w.cond = self.eliminate_expr(*w.cond).into();
+                w.body = self.eliminate_expr(*w.body).into();
+                AstExpr::While(w)
+            }
+            AstExpr::For(mut f) => {
+                f.body = self.eliminate_expr(*f.body).into();
+                AstExpr::For(f)
+            }
+            AstExpr::Invoke(mut invoke) => {
+                invoke.args = invoke
+                    .args
+                    .into_iter()
+                    .map(|x| self.eliminate_expr(x))
+                    .collect();
+                AstExpr::Invoke(invoke)
+            }
+            AstExpr::Closure(mut c) => {
+                c.body = self.eliminate_expr(*c.body).into();
+                AstExpr::Closure(c)
+            }
+            AstExpr::Paren(mut p) => {
+                p.expr = self.eliminate_expr(*p.expr).into();
+                AstExpr::Paren(p)
+            }
+            AstExpr::Assign(mut a) => {
+                a.value = self.eliminate_expr(*a.value).into();
+                AstExpr::Assign(a)
+            }
+            _ => expr,
+        }
+    }
+    fn eliminate_block(&self, block: ExprBlock) -> AstExpr {
+        let stmts = block
+            .stmts
+            .into_iter()
+            .map(|stmt| self.eliminate_stmt(stmt))
+            .collect();
+        AstExpr::block(self.prune_block(ExprBlock::new_stmts(stmts)))
+    }
+    fn eliminate_stmt(&self, stmt: BlockStmt) -> BlockStmt {
+        match stmt {
+            BlockStmt::Expr(mut expr) => {
+                expr.expr = self.eliminate_expr(*expr.expr).into();
+                BlockStmt::Expr(expr)
+            }
+            BlockStmt::Let(mut let_) => {
+                let_.init = let_.init.map(|x| self.eliminate_expr(x));
+                BlockStmt::Let(let_)
+            }
+            BlockStmt::Item(item) => BlockStmt::item(self.eliminate_item(*item)),
+            _ => stmt,
+        }
+    }
+    pub fn eliminate_item(&self, item: AstItem) -> AstItem {
+        match item {
+            AstItem::DefFunction(mut func) => {
+                func.body = self.eliminate_expr(*func.body).into();
+                AstItem::DefFunction(func)
+            }
+            AstItem::Impl(mut impl_) => {
+                impl_.items = impl_
+                    .items
+                    .into_iter()
+                    .map(|x| self.eliminate_item(x))
+                    .collect();
+                AstItem::Impl(impl_)
+            }
+            _ => item,
+        }
+    }
+    /// removes private functions that are not reachable from the rest of the module
+    pub fn prune_module(&self, mut module: AstModule) -> AstModule {
+        let mut roots = UsageCollector::new();
+        let mut functions = vec![];
+        for item in &module.items {
+            match item {
+                AstItem::DefFunction(func) if !is_root_function(func) => {
+                    let mut usage = UsageCollector::new();
+                    usage.collect_expr(&func.body);
+                    functions.push((func.name.clone(), usage));
+                }
+                _ => roots.collect_item(item),
+            }
+        }
+        let reachable = reachable_functions(&roots, &functions);
+        module.items.retain(|item| match item {
+            AstItem::DefFunction(func) => {
+                let keep = !functions.iter().any(|(name, _)| name == &func.name)
+                    || reachable.contains(&func.name);
+                if !keep {
+                    debug!("Removing unused function {}", func.name);
+                }
+                keep
+            }
+            _ => true,
+        });
+        module
+    }
+}
+
+impl OptimizePass for DeadCodeEliminationPass {
+    fn name(&self) -> &str {
+        "dce"
+    }
+    fn optimize_item(&self, item: AstItem, _ctx: &SharedScopedContext) -> Result<AstItem> {
+        Ok(self.eliminate_item(item))
+    }
+    fn optimize_block(&self, block: ExprBlock, _ctx: &SharedScopedContext) -> Result<ExprBlock> {
+        Ok(self.prune_block(block))
+    }
+    fn optimize_module(&self, module: AstModule, _ctx: &SharedScopedContext) -> Result<AstModule> {
+        Ok(self.prune_module(module))
+    }
+    fn evaluate_condition(&self, expr: AstExpr, _ctx: &SharedScopedContext) -> Result<ControlFlow> {
+        match as_literal_bool(&expr) {
+            Some(true) => Ok(ControlFlow::IntoAndBreak(None)),
+            Some(false) => Ok(ControlFlow::Continue),
+            None => Ok(ControlFlow::Into),
+        }
+    }
+}
@@ -1,10 +1,21 @@
-use crate::pass::OptimizePass;
+use crate::pass::{OptimizePass, UsageCollector};
 use common::*;
 use lang_core::ast::*;
 use lang_core::context::SharedScopedContext;
 use lang_core::id::Locator;
 use std::sync::Arc;
 
+/// Whether a function body returns early, so it can't be pasted into its caller
+pub fn has_return(body: &AstExpr) -> bool {
+    let mut usage = UsageCollector::new();
+    usage.collect_expr(body);
+    usage.has_return()
+}
+/// `#[inline(never)]` keeps the calls of a function as they are
+pub fn is_inline_never(func: &ValueFunction) -> bool {
+    func.attrs.has_list_item("inline", "never")
+}
+
 pub struct InlinePass {
     pub serializer: Arc<dyn AstSerializer>,
 }
@@ -33,7 +44,13 @@ impl InlinePass {
                             invoke.target = Locator::ident(name.clone()).into();
                             return Ok(AstExpr::Invoke(invoke.into()));
                         }
-                        _ if invoke.args.is_empty() => return Ok(func.body.get()),
+                        // a `return` in the body would leave the caller instead
+                        _ if invoke.args.is_empty()
+                            && !has_return(&func.body)
+                            && !is_inline_never(func) =>
+                        {
+                            return Ok(func.body.get())
+                        }
                         _ => {}
                     };
                 }
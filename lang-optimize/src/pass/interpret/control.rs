@@ -0,0 +1,332 @@
+use std::fmt::{Display, Formatter};
+
+use common::*;
+
+use lang_core::ast::{
+    AstExpr, AstValue, ControlFlow, ExprBreak, ExprContinue, ExprFor, ExprIf, ExprLoop, ExprMatch,
+    ExprRange, ExprRangeLimit, ExprReturn, ExprWhile, TypeInt, ValueInt, Visibility,
+};
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::Ident;
+use lang_core::ops::BinOpKind;
+use lang_core::span::SourceNode;
+
+use crate::pass::interpret::expected_bool;
+use crate::pass::InterpreterPass;
+
+/// A `break`, `continue` or `return` leaving the expressions being interpreted.
+///
+/// It unwinds through `?` up to the loop or the call it targets, which turns it back into
+/// the [`ControlFlow`] it carries; it only surfaces as an error when nothing catches it.
+#[derive(Debug)]
+pub struct Unwind {
+    /// the loop targeted by `break 'label` and `continue 'label`
+    pub label: Option<Ident>,
+    /// `Break`, `Continue` or `Return`, with the evaluated value
+    pub flow: ControlFlow,
+}
+impl Unwind {
+    fn new(label: Option<Ident>, flow: ControlFlow) -> Self {
+        Self { label, flow }
+    }
+}
+impl Display for Unwind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match &self.flow {
+            ControlFlow::Return(_) => return f.write_str("return outside of a function"),
+            ControlFlow::Continue => "continue",
+            _ => "break",
+        };
+        match &self.label {
+            Some(label) => write!(f, "{} '{} outside of a loop", kind, label),
+            None => write!(f, "{} outside of a loop", kind),
+        }
+    }
+}
+impl std::error::Error for Unwind {}
+
+/// The value carried by `break` or `return`, unit if there is none
+fn flow_value(value: Option<AstExpr>) -> AstValue {
+    match value {
+        Some(AstExpr::Value(value)) => *value,
+        Some(expr) => AstValue::expr(expr),
+        None => AstValue::unit(),
+    }
+}
+
+/// A bounded integer range with its bounds evaluated, `start..end` stepping by `step`
+#[derive(Debug, Clone, Copy)]
+pub struct IntRange {
+    pub start: i128,
+    /// exclusive, `a..=b` ends at `b + 1`
+    pub end: i128,
+    pub step: i128,
+    pub ty: Option<TypeInt>,
+}
+impl IntRange {
+    /// how many values the range yields, without producing them
+    pub fn trip_count(&self) -> u128 {
+        if self.end <= self.start {
+            return 0;
+        }
+        (self.end - self.start - 1) as u128 / self.step as u128 + 1
+    }
+    pub fn values(self) -> impl Iterator<Item = AstValue> {
+        let Self {
+            start,
+            end,
+            step,
+            ty,
+        } = self;
+        std::iter::successors(Some(start), move |x| x.checked_add(step))
+            .take_while(move |x| *x < end)
+            .map(move |value| AstValue::Int(ValueInt { value, ty }))
+    }
+}
+
+impl InterpreterPass {
+    /// Returns the `break` or `continue` of the loop with `label`, otherwise keeps unwinding
+    fn catch_loop_control(&self, err: Error, label: &Option<Ident>) -> Result<ControlFlow> {
+        let unwind = err.downcast::<Unwind>()?;
+        match (&unwind.flow, &unwind.label) {
+            (ControlFlow::Return(_), _) => Err(unwind.into()),
+            (_, Some(target)) if Some(target) != label.as_ref() => Err(unwind.into()),
+            _ => Ok(unwind.flow),
+        }
+    }
+    /// Counts the iterations of a loop, failing instead of hanging on one that doesn't end
+    fn check_loop_fuel(
+        &self,
+        iterations: &mut usize,
+        node: impl FnOnce() -> AstExpr,
+    ) -> Result<()> {
+        *iterations += 1;
+        if *iterations <= self.loop_iteration_limit {
+            return Ok(());
+        }
+        let node = node();
+        bail!(Diagnostic::new(
+            ErrorCode::InterpretFailed,
+            format!(
+                "{} did not finish within {} iterations",
+                node.describe(),
+                self.loop_iteration_limit
+            )
+        )
+        .with_node(&node)
+        .with_label("this loop runs too long to be evaluated at compile time")
+        .with_help("make sure the loop terminates, or keep it out of compile time evaluation"))
+    }
+    fn interpret_bool(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<bool> {
+        match self.interpret_expr(expr, ctx)? {
+            AstValue::Bool(b) => Ok(b.value),
+            value => bail!(expected_bool(expr, &value)),
+        }
+    }
+    /// `&&` and `||`, evaluating operands only until the result is known
+    pub fn interpret_logical(
+        &self,
+        op: BinOpKind,
+        args: &[AstExpr],
+        ctx: &SharedScopedContext,
+    ) -> Result<AstValue> {
+        let short_circuit = match op {
+            BinOpKind::And => false,
+            BinOpKind::Or => true,
+            _ => bail!("Expected logical operator, got {}", op),
+        };
+        for arg in args {
+            if self.interpret_bool(arg, ctx)? == short_circuit {
+                return Ok(AstValue::bool(short_circuit));
+            }
+        }
+        Ok(AstValue::bool(!short_circuit))
+    }
+    pub fn interpret_loop(&self, node: &ExprLoop, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let mut iterations = 0;
+        loop {
+            self.check_loop_fuel(&mut iterations, || AstExpr::Loop(node.clone()))?;
+            let Err(err) = self.interpret_expr(&node.body, ctx) else {
+                continue;
+            };
+            match self.catch_loop_control(err, &node.label)? {
+                ControlFlow::Break(value) => return Ok(flow_value(value)),
+                _ => continue,
+            }
+        }
+    }
+    pub fn interpret_while(&self, node: &ExprWhile, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let mut iterations = 0;
+        while self.interpret_bool(&node.cond, ctx)? {
+            self.check_loop_fuel(&mut iterations, || AstExpr::While(node.clone()))?;
+            let Err(err) = self.interpret_expr(&node.body, ctx) else {
+                continue;
+            };
+            match self.catch_loop_control(err, &node.label)? {
+                ControlFlow::Break(Some(_)) => bail!(Diagnostic::new(
+                    ErrorCode::InterpretFailed,
+                    "`break` with value is only allowed in `loop`"
+                )
+                .with_node(&AstExpr::While(node.clone()))
+                .with_label("this `while` breaks with a value")),
+                ControlFlow::Break(None) => break,
+                _ => continue,
+            }
+        }
+        Ok(AstValue::unit())
+    }
+    fn interpret_int(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<ValueInt> {
+        match self.interpret_expr(expr, ctx)? {
+            AstValue::Int(i) => Ok(i),
+            value => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Expected integer in {}", expr.describe())
+            )
+            .with_node(expr)
+            .with_label(format!("expected integer, found `{}`", value))),
+        }
+    }
+    /// Evaluates the bounds of an integer range
+    pub fn interpret_range(&self, node: &ExprRange, ctx: &SharedScopedContext) -> Result<IntRange> {
+        let start = match &node.start {
+            Some(start) => self.interpret_int(start, ctx)?,
+            None => ValueInt::new(0),
+        };
+        let Some(end) = &node.end else {
+            bail!(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                "Unbounded range is not supported"
+            )
+            .with_node(&AstExpr::Range(node.clone()))
+            .with_label("this range has no end"))
+        };
+        let end = self.interpret_int(end, ctx)?;
+        let ty = start.unify_ty(&end)?;
+        let end = match node.limit {
+            ExprRangeLimit::Inclusive => end.value + 1,
+            ExprRangeLimit::Exclusive => end.value,
+        };
+        let step = match &node.step {
+            Some(step) => self.interpret_int(step, ctx)?.value,
+            None => 1,
+        };
+        if step <= 0 {
+            bail!(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                format!("Range step must be positive, got {}", step)
+            )
+            .with_node(&AstExpr::Range(node.clone()))
+            .with_label("this range never ends"))
+        }
+        Ok(IntRange {
+            start: start.value,
+            end,
+            step,
+            ty,
+        })
+    }
+    pub fn interpret_for(&self, node: &ExprFor, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let values: Box<dyn Iterator<Item = AstValue>> = match &*node.iter {
+            AstExpr::Range(range) => Box::new(self.interpret_range(range, ctx)?.values()),
+            iter => match self.interpret_expr(iter, ctx)? {
+                AstValue::List(list) => Box::new(list.values.into_iter()),
+                value => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot iterate over {}", iter.describe())
+                )
+                .with_node(iter)
+                .with_label(format!("expected range or list, found `{}`", value))),
+            },
+        };
+        for value in values {
+            let ctx = ctx.child(Ident::new("__for__"), Visibility::Private, true);
+            self.interpret_irrefutable(&node.pat, &value, &ctx)?;
+            let Err(err) = self.interpret_expr(&node.body, &ctx) else {
+                continue;
+            };
+            match self.catch_loop_control(err, &node.label)? {
+                ControlFlow::Break(Some(_)) => bail!(Diagnostic::new(
+                    ErrorCode::InterpretFailed,
+                    "`break` with value is only allowed in `loop`"
+                )
+                .with_node(&AstExpr::For(node.clone()))
+                .with_label("this `for` breaks with a value")),
+                ControlFlow::Break(None) => break,
+                _ => continue,
+            }
+        }
+        Ok(AstValue::unit())
+    }
+    pub fn interpret_break(&self, node: &ExprBreak, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let value = match &node.value {
+            Some(value) => Some(AstExpr::value(self.interpret_expr(value, ctx)?)),
+            None => None,
+        };
+        Err(Unwind::new(node.label.clone(), ControlFlow::Break(value)).into())
+    }
+    pub fn interpret_continue(
+        &self,
+        node: &ExprContinue,
+        _ctx: &SharedScopedContext,
+    ) -> Result<AstValue> {
+        Err(Unwind::new(node.label.clone(), ControlFlow::Continue).into())
+    }
+    pub fn interpret_return(
+        &self,
+        node: &ExprReturn,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstValue> {
+        let value = match &node.value {
+            Some(value) => Some(AstExpr::value(self.interpret_expr(value, ctx)?)),
+            None => None,
+        };
+        Err(Unwind::new(None, ControlFlow::Return(value)).into())
+    }
+    /// Evaluates the body of a function, where `return` ends the call with its value
+    pub fn interpret_body(&self, body: &AstExpr, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let err = match self.interpret_expr(body, ctx) {
+            Err(err) => err,
+            value => return value,
+        };
+        let unwind = err.downcast::<Unwind>()?;
+        match unwind.flow {
+            ControlFlow::Return(value) => Ok(flow_value(value)),
+            _ => Err(unwind.into()),
+        }
+    }
+    /// `match` takes the first case whose pattern matches the scrutinee and whose guard holds
+    pub fn interpret_match(&self, node: &ExprMatch, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let Some(scrutinee) = &node.scrutinee else {
+            return self.interpret_cond(node, ctx);
+        };
+        let value = self.interpret_expr(scrutinee, ctx)?;
+        for case in &node.cases {
+            let ctx = ctx.child(Ident::new("__match__"), Visibility::Private, true);
+            if let Some(pat) = &case.pat {
+                if !self.interpret_pattern(pat, &value, &ctx)? {
+                    continue;
+                }
+            }
+            if self.interpret_bool(&case.cond, &ctx)? {
+                return self.interpret_expr(&case.body, &ctx);
+            }
+        }
+        let node = AstExpr::Match(node.clone());
+        bail!(Diagnostic::new(
+            ErrorCode::InterpretFailed,
+            format!("No case of {} matches {}", node.describe(), value)
+        )
+        .with_node(&node)
+        .with_label(format!("`{}` is not covered", value)))
+    }
+    pub fn interpret_if(&self, node: &ExprIf, ctx: &SharedScopedContext) -> Result<AstValue> {
+        if self.interpret_bool(&node.cond, ctx)? {
+            self.interpret_expr(&node.then, ctx)
+        } else if let Some(elze) = &node.elze {
+            self.interpret_expr(elze, ctx)
+        } else {
+            Ok(AstValue::unit())
+        }
+    }
+}
@@ -0,0 +1,1305 @@
+use crate::pass::interpret::literal_out_of_range;
+use crate::pass::typeck::{mismatch, no_field, substitute, tuple_element_types, type_name};
+use crate::pass::InterpreterPass;
+use common::*;
+use itertools::Itertools;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator, Path};
+use lang_core::ops::{BinOpKind, UnOpKind};
+use lang_core::pat::{Pattern, PatternIdent, PatternStruct, PatternStructural};
+use lang_core::span::SourceNode;
+use std::collections::HashMap;
+
+/// A type yet to be solved, only alive during [`TypeInference`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TypeVar(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VarKind {
+    Any,
+    /// of an unsuffixed integer literal, `i64` unless unified with another integer type
+    Int,
+    /// of a float literal, `f64` unless unified with another float type
+    Float,
+}
+
+/// Constraint-based inference in the style of Hindley–Milner: every expression gets a type,
+/// possibly with type variables in it, and the constraints between them are solved by
+/// unification as they are generated.
+///
+/// Let bindings are monomorphic, like in Rust, while generic functions are instantiated
+/// with fresh variables at every use.
+struct TypeInference<'a> {
+    pass: &'a InterpreterPass,
+    solutions: Vec<Option<AstType>>,
+    kinds: Vec<VarKind>,
+    /// the unsuffixed integer literals, checked against the types they are solved to
+    literals: Vec<(i128, AstType, AstExpr)>,
+    /// the let bindings, recorded with their solved types when done
+    bindings: Vec<(SharedScopedContext, Ident, AstType)>,
+    /// the result of each enclosing loop, `None` for `while` and `for`
+    loops: Vec<Option<AstType>>,
+    /// the result of each enclosing function and closure, for `return`
+    returns: Vec<AstType>,
+}
+impl<'a> TypeInference<'a> {
+    fn new(pass: &'a InterpreterPass) -> Self {
+        Self {
+            pass,
+            solutions: vec![],
+            kinds: vec![],
+            literals: vec![],
+            bindings: vec![],
+            loops: vec![],
+            returns: vec![],
+        }
+    }
+    fn fresh(&mut self, kind: VarKind) -> AstType {
+        self.solutions.push(None);
+        self.kinds.push(kind);
+        AstType::any_box(TypeVar(self.solutions.len() - 1))
+    }
+    fn var_of(ty: &AstType) -> Option<usize> {
+        match ty {
+            AstType::AnyBox(any) => any.downcast_ref::<TypeVar>().map(|x| x.0),
+            _ => None,
+        }
+    }
+    /// `ty` with the variable at its top replaced by its solution
+    fn shallow(&self, ty: &AstType) -> AstType {
+        let mut ty = ty.clone();
+        while let Some(solution) = Self::var_of(&ty).and_then(|x| self.solutions[x].clone()) {
+            ty = solution;
+        }
+        ty
+    }
+    /// `ty` with all variables replaced by their solutions, or their defaults if unsolved
+    fn apply(&self, ty: &AstType) -> AstType {
+        let ty = self.shallow(ty);
+        if let Some(var) = Self::var_of(&ty) {
+            return match self.kinds[var] {
+                VarKind::Any => AstType::unknown(),
+                VarKind::Int => AstType::Primitive(TypePrimitive::i64()),
+                VarKind::Float => AstType::Primitive(TypePrimitive::f64()),
+            };
+        }
+        match ty {
+            AstType::Reference(mut r) => {
+                r.ty = self.apply(&r.ty).into();
+                AstType::Reference(r)
+            }
+            AstType::Tuple(t) => AstType::Tuple(TypeTuple {
+                types: t.types.iter().map(|x| self.apply(x)).collect(),
+            }),
+            AstType::Slice(s) => AstType::Slice(TypeSlice {
+                elem: self.apply(&s.elem).into(),
+            }),
+            AstType::Vec(v) => AstType::Vec(TypeVec {
+                ty: self.apply(&v.ty).into(),
+            }),
+            AstType::Function(f) => AstType::Function(
+                TypeFunction {
+                    params: f.params.iter().map(|x| self.apply(x)).collect(),
+                    generics_params: f.generics_params.clone(),
+                    ret_ty: f.ret_ty.as_ref().map(|x| self.apply(x).into()),
+                }
+                .into(),
+            ),
+            ty => ty,
+        }
+    }
+    fn occurs(&self, var: usize, ty: &AstType) -> bool {
+        let ty = self.shallow(ty);
+        if Self::var_of(&ty) == Some(var) {
+            return true;
+        }
+        match &ty {
+            AstType::Reference(r) => self.occurs(var, &r.ty),
+            AstType::Tuple(t) => t.types.iter().any(|x| self.occurs(var, x)),
+            AstType::Slice(s) => self.occurs(var, &s.elem),
+            AstType::Vec(v) => self.occurs(var, &v.ty),
+            AstType::Function(f) => {
+                f.params.iter().any(|x| self.occurs(var, x))
+                    || f.ret_ty.as_ref().map_or(false, |x| self.occurs(var, x))
+            }
+            _ => false,
+        }
+    }
+    fn bind(&mut self, var: usize, ty: AstType) -> bool {
+        if let Some(other) = Self::var_of(&ty) {
+            let kind = match (self.kinds[var], self.kinds[other]) {
+                (VarKind::Any, kind) | (kind, VarKind::Any) => kind,
+                (a, b) if a == b => a,
+                _ => return false,
+            };
+            self.kinds[other] = kind;
+        } else {
+            let fits = match (self.kinds[var], &ty) {
+                (VarKind::Any, _) => !self.occurs(var, &ty),
+                (VarKind::Int, AstType::Primitive(TypePrimitive::Int(_))) => true,
+                (VarKind::Float, AstType::Primitive(TypePrimitive::Decimal(_))) => true,
+                _ => false,
+            };
+            if !fits {
+                return false;
+            }
+        }
+        self.solutions[var] = Some(ty);
+        true
+    }
+    fn unify(&mut self, a: &AstType, b: &AstType) -> bool {
+        let a = self.shallow(a);
+        let b = self.shallow(b);
+        match (Self::var_of(&a), Self::var_of(&b)) {
+            (Some(x), Some(y)) if x == y => return true,
+            (Some(x), _) => return self.bind(x, b),
+            (_, Some(y)) => return self.bind(y, a),
+            _ => {}
+        }
+        match (&a, &b) {
+            (AstType::Unknown(_) | AstType::Any(_) | AstType::Nothing(_), _)
+            | (_, AstType::Unknown(_) | AstType::Any(_) | AstType::Nothing(_)) => true,
+            // trait bounds are not resolved yet
+            (AstType::ImplTraits(_) | AstType::TypeBounds(_), _) => true,
+            // `&mut T` coerces to `&T`, not the other way around
+            (AstType::Reference(x), AstType::Reference(y)) => {
+                (x.mutability != Some(true) || y.mutability == Some(true))
+                    && self.unify(&x.ty, &y.ty)
+            }
+            (AstType::Tuple(x), AstType::Tuple(y)) => {
+                x.types.len() == y.types.len()
+                    && x.types.iter().zip(&y.types).all(|(x, y)| self.unify(x, y))
+            }
+            (AstType::Slice(x), AstType::Slice(y)) => self.unify(&x.elem, &y.elem),
+            (AstType::Vec(x), AstType::Vec(y)) => self.unify(&x.ty, &y.ty),
+            (AstType::Function(x), AstType::Function(y)) => {
+                let unit = AstType::unit();
+                x.params.len() == y.params.len()
+                    && x.params
+                        .iter()
+                        .zip(&y.params)
+                        .all(|(x, y)| self.unify(x, y))
+                    && self.unify(
+                        x.ret_ty.as_deref().unwrap_or(&unit),
+                        y.ret_ty.as_deref().unwrap_or(&unit),
+                    )
+            }
+            (AstType::Struct(x), AstType::Struct(y)) => x.name == y.name,
+            // a struct or enum and its name
+            (
+                AstType::Struct(TypeStruct { name, .. }) | AstType::Enum(TypeEnum { name, .. }),
+                AstType::Expr(expr),
+            )
+            | (
+                AstType::Expr(expr),
+                AstType::Struct(TypeStruct { name, .. }) | AstType::Enum(TypeEnum { name, .. }),
+            ) => {
+                matches!(expr.as_ref(), AstExpr::Locator(locator) if locator.to_path().last() == name)
+            }
+            (AstType::Enum(x), AstType::Enum(y)) => x.name == y.name,
+            (AstType::Expr(x), AstType::Expr(y)) => match (x.as_ref(), y.as_ref()) {
+                (
+                    AstExpr::Locator(Locator::ParameterPath(x)),
+                    AstExpr::Locator(Locator::ParameterPath(y)),
+                ) => {
+                    x.segments.len() == y.segments.len()
+                        && x.segments.iter().zip(&y.segments).all(|(x, y)| {
+                            x.ident == y.ident
+                                && x.args.len() == y.args.len()
+                                && x.args.iter().zip(&y.args).all(|(x, y)| self.unify(x, y))
+                        })
+                }
+                _ => x == y,
+            },
+            _ => a == b,
+        }
+    }
+    fn expect(&mut self, expected: &AstType, found: &AstType, node: &AstExpr) -> Result<()> {
+        if !self.unify(expected, found) {
+            bail!(mismatch(&self.apply(expected), &self.apply(found), node))
+        }
+        Ok(())
+    }
+    /// Records the solved types of the let bindings, so later passes can read them back
+    fn finish(&mut self) {
+        for (ctx, name, ty) in std::mem::take(&mut self.bindings) {
+            ctx.insert_type(name, self.apply(&ty));
+        }
+    }
+    /// Checks that the unsuffixed integer literals fit in the types they were solved to
+    fn check_literals(&self) -> Result<()> {
+        for (value, ty, node) in &self.literals {
+            let AstType::Primitive(TypePrimitive::Int(int)) = self.apply(ty) else {
+                continue;
+            };
+            if *value < 0 && !int.is_signed() {
+                bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot negate unsigned {}", int)
+                )
+                .with_node(node)
+                .with_label(format!("`{}` cannot be negative", int)))
+            }
+            if !int.contains(*value) {
+                bail!(literal_out_of_range(*value, int).with_node(node))
+            }
+        }
+        Ok(())
+    }
+
+    /// The type of a function declared in `ctx`, with its generic params replaced by
+    /// `type_args` or else by fresh variables
+    fn instantiate(
+        &mut self,
+        sig: &FunctionSignature,
+        type_args: &[AstType],
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let mut subst = HashMap::new();
+        let generics = sig.generics_params.iter().filter(|x| x.const_ty.is_none());
+        for (i, param) in generics.enumerate() {
+            let ty = match type_args.get(i) {
+                Some(ty) => ty.clone(),
+                None => self.fresh(VarKind::Any),
+            };
+            subst.insert(param.name.clone(), ty);
+        }
+        let mut params = vec![];
+        for param in &sig.params {
+            let ty = self.pass.resolve_type(&param.ty, ctx)?;
+            params.push(substitute(&ty, &subst));
+        }
+        let ret_ty = match &sig.ret_ty {
+            Some(ty) => substitute(&self.pass.resolve_type(ty, ctx)?, &subst),
+            None => AstType::unit(),
+        };
+        Ok(AstType::Function(
+            TypeFunction {
+                params,
+                generics_params: vec![],
+                ret_ty: Some(ret_ty.into()),
+            }
+            .into(),
+        ))
+    }
+    /// A type as written in an annotation, `_` standing for a fresh variable
+    fn annotation(&mut self, ty: &AstType, ctx: &SharedScopedContext) -> Result<AstType> {
+        let ty = self.pass.resolve_type(ty, ctx)?;
+        Ok(self.freshen(&ty))
+    }
+    fn freshen(&mut self, ty: &AstType) -> AstType {
+        match ty {
+            AstType::Unknown(_) => self.fresh(VarKind::Any),
+            AstType::Reference(r) => {
+                let mut r = r.clone();
+                r.ty = self.freshen(&r.ty).into();
+                AstType::Reference(r)
+            }
+            AstType::Tuple(t) => AstType::Tuple(TypeTuple {
+                types: t.types.iter().map(|x| self.freshen(x)).collect(),
+            }),
+            AstType::Slice(s) => AstType::Slice(TypeSlice {
+                elem: self.freshen(&s.elem).into(),
+            }),
+            AstType::Vec(v) => AstType::Vec(TypeVec {
+                ty: self.freshen(&v.ty).into(),
+            }),
+            _ => ty.clone(),
+        }
+    }
+
+    /// An unsuffixed integer literal, its range is checked once its type is solved
+    fn literal(&mut self, value: i128, node: &AstExpr) -> AstType {
+        let ty = self.fresh(VarKind::Int);
+        self.literals.push((value, ty.clone(), node.clone()));
+        ty
+    }
+    fn infer_value(&mut self, value: &AstValue, ctx: &SharedScopedContext) -> Result<AstType> {
+        let ty = match value {
+            AstValue::Int(i) => match i.ty {
+                Some(ty) => AstType::Primitive(TypePrimitive::Int(ty)),
+                None => self.fresh(VarKind::Int),
+            },
+            AstValue::BigInt(_) => AstType::Primitive(TypePrimitive::Int(TypeInt::BigInt)),
+            AstValue::Decimal(_) => self.fresh(VarKind::Float),
+            AstValue::Bool(_) => AstType::bool(),
+            AstValue::Char(_) => AstType::Primitive(TypePrimitive::Char),
+            AstValue::String(s) if s.owned => AstType::Primitive(TypePrimitive::String),
+            AstValue::String(_) => AstType::Reference(
+                TypeReference {
+                    ty: AstType::ident("str".into()).into(),
+                    mutability: None,
+                    lifetime: Some(Ident::new("static")),
+                }
+                .into(),
+            ),
+            AstValue::List(_) => AstType::Primitive(TypePrimitive::List),
+            AstValue::Unit(_) => AstType::unit(),
+            AstValue::Type(_) => AstType::Type(TypeType),
+            AstValue::Tuple(tuple) => AstType::Tuple(TypeTuple {
+                types: tuple
+                    .values
+                    .iter()
+                    .map(|x| self.infer_value(x, ctx))
+                    .try_collect()?,
+            }),
+            AstValue::Struct(s) => AstType::ident(s.ty.name.clone()),
+            AstValue::Variant(v) => AstType::ident(v.ty.name.clone()),
+            AstValue::Function(f) => self.instantiate(&f.sig, &[], ctx)?,
+            AstValue::Expr(expr) => self.infer(expr, ctx)?,
+            _ => AstType::unknown(),
+        };
+        Ok(ty)
+    }
+    /// A constructor named by `path`, a function for a tuple struct or tuple variant, or the
+    /// type itself for a unit struct or unit variant
+    fn constructor_type(&self, path: &Path, ctx: &SharedScopedContext) -> Option<AstType> {
+        let (name, params) = match self.pass.lookup_variant(path, ctx) {
+            Some((ty, variant)) => match variant.value {
+                AstType::Any(_) => (ty.name, None),
+                AstType::Tuple(tuple) => (ty.name, Some(tuple.types)),
+                _ => return None,
+            },
+            None => {
+                let [name] = path.segments.as_slice() else {
+                    return None;
+                };
+                let Some(AstValue::Type(AstType::Struct(ty))) = ctx.get_value(name.clone()) else {
+                    return None;
+                };
+                match ty.kind {
+                    StructKind::Named => return None,
+                    StructKind::Tuple => {
+                        let params = ty.fields.into_iter().map(|x| x.value).collect();
+                        (ty.name, Some(params))
+                    }
+                    StructKind::Unit => (ty.name, None),
+                }
+            }
+        };
+        let ty = AstType::ident(name);
+        let Some(params) = params else {
+            return Some(ty);
+        };
+        Some(AstType::Function(
+            TypeFunction {
+                params,
+                generics_params: vec![],
+                ret_ty: Some(ty.into()),
+            }
+            .into(),
+        ))
+    }
+    fn infer_locator(
+        &mut self,
+        locator: &Locator,
+        node: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let path = locator.to_path();
+        if let Some(ty) = ctx.get_type(path.clone()) {
+            return Ok(ty);
+        }
+        if let Some(ty) = self.constructor_type(&path, ctx) {
+            return Ok(ty);
+        }
+        if let Some((f, closure)) = lookup_function(&path, ctx) {
+            let mut type_args = vec![];
+            if let Locator::ParameterPath(path) = locator {
+                for arg in &path.last().args {
+                    type_args.push(self.annotation(arg, ctx)?);
+                }
+            }
+            return self.instantiate(&f.sig, &type_args, &closure);
+        }
+        match ctx.get_value(path) {
+            Some(value) => self.infer_value(&value, ctx),
+            None => bail!(Diagnostic::unresolved(locator)
+                .with_node(node)
+                .with_label("not found in this scope")),
+        }
+    }
+    fn infer_block(&mut self, block: &ExprBlock, ctx: &SharedScopedContext) -> Result<AstType> {
+        // items in a block are visible to the whole block, so declare them first
+        for stmt in &block.stmts {
+            if let BlockStmt::Item(item) = stmt {
+                self.pass.interpret_item(item, ctx)?;
+            }
+        }
+        for stmt in block.first_stmts() {
+            match stmt {
+                BlockStmt::Let(let_) => self.infer_let(let_, ctx)?,
+                BlockStmt::Expr(expr) => {
+                    self.infer(&expr.expr, ctx)?;
+                }
+                BlockStmt::Item(item) => self.check_item(item, ctx)?,
+                BlockStmt::Noop | BlockStmt::Any(_) => {}
+            }
+        }
+        match (block.last_expr(), block.stmts.last()) {
+            (Some(expr), _) => self.infer(expr, ctx),
+            // a block ending in `break;`, `continue;` or `return;` never completes
+            (None, Some(BlockStmt::Expr(stmt)))
+                if matches!(
+                    stmt.expr.as_ref(),
+                    AstExpr::Break(_) | AstExpr::Continue(_) | AstExpr::Return(_)
+                ) =>
+            {
+                Ok(AstType::Nothing(TypeNothing))
+            }
+            (None, _) => Ok(AstType::unit()),
+        }
+    }
+    /// The body of a function item is inferred on its own, its lets are not those of the
+    /// enclosing function
+    fn check_item(&self, item: &AstItem, ctx: &SharedScopedContext) -> Result<()> {
+        if let AstItem::DefFunction(def) = item {
+            self.pass.infer_function_body(&def._to_value(), ctx)?;
+        }
+        Ok(())
+    }
+    fn infer_let(&mut self, let_: &StmtLet, ctx: &SharedScopedContext) -> Result<()> {
+        let ty = match &let_.pat {
+            Pattern::Type(pat) => self.annotation(&pat.ty, ctx)?,
+            _ => self.fresh(VarKind::Any),
+        };
+        if let Some(init) = &let_.init {
+            let found = self.infer(init, ctx)?;
+            self.expect(&ty, &found, init)?;
+        }
+        if let Some(diverge) = &let_.diverge {
+            self.infer(diverge, ctx)?;
+        }
+        self.bind_pattern(&let_.pat, ty, ctx)
+    }
+    fn bind_pattern(
+        &mut self,
+        pat: &Pattern,
+        ty: AstType,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        match pat {
+            Pattern::Ident(ident) => {
+                if let Some(pat) = &ident.subpattern {
+                    self.bind_pattern(pat, ty.clone(), ctx)?;
+                }
+                ctx.insert_type(ident.ident.clone(), ty.clone());
+                self.bindings.push((ctx.clone(), ident.ident.clone(), ty));
+            }
+            // the annotation was unified with `ty` already
+            Pattern::Type(pat) => self.bind_pattern(&pat.pat, ty, ctx)?,
+            // a `..` leaves the length open, so only a tuple known already gives the types
+            Pattern::Tuple(tuple)
+                if tuple.patterns.iter().any(|x| matches!(x, Pattern::Rest(_))) =>
+            {
+                let types = match self.apply(&ty) {
+                    AstType::Tuple(t) => tuple_element_types(&tuple.patterns, t.types),
+                    _ => None,
+                };
+                for (i, pat) in tuple.patterns.iter().enumerate() {
+                    let ty = match &types {
+                        Some(types) => types[i].clone(),
+                        None => self.fresh(VarKind::Any),
+                    };
+                    self.bind_pattern(pat, ty, ctx)?;
+                }
+            }
+            Pattern::Tuple(tuple) => {
+                let types = tuple
+                    .patterns
+                    .iter()
+                    .map(|_| self.fresh(VarKind::Any))
+                    .collect::<Vec<_>>();
+                let tuple_ty = AstType::Tuple(TypeTuple {
+                    types: types.clone(),
+                });
+                if !self.unify(&tuple_ty, &ty) {
+                    bail!(Diagnostic::new(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "Expected a tuple of {}, found {}",
+                            tuple.patterns.len(),
+                            self.apply(&ty)
+                        )
+                    ))
+                }
+                for (pat, ty) in tuple.patterns.iter().zip(types) {
+                    self.bind_pattern(pat, ty, ctx)?;
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Rest(_) => {}
+            Pattern::Or(or) => {
+                for pat in &or.patterns {
+                    self.bind_pattern(pat, ty.clone(), ctx)?;
+                }
+            }
+            Pattern::Lit(lit) => {
+                let node = AstExpr::value(lit.value.clone());
+                let found = self.infer(&node, ctx)?;
+                self.expect(&ty, &found, &node)?;
+            }
+            Pattern::Range(range) => {
+                for bound in [&range.start, &range.end].into_iter().flatten() {
+                    let found = self.infer(bound, ctx)?;
+                    self.expect(&ty, &found, bound)?;
+                }
+            }
+            // slices are not typed yet
+            Pattern::Slice(slice) => {
+                for pat in &slice.patterns {
+                    let ty = self.fresh(VarKind::Any);
+                    self.bind_pattern(pat, ty, ctx)?;
+                }
+            }
+            // the fields of struct and variant patterns are not typed yet
+            Pattern::Struct(PatternStruct { fields, .. })
+            | Pattern::Structural(PatternStructural { fields }) => {
+                for field in fields {
+                    let ty = self.fresh(VarKind::Any);
+                    match &field.rename {
+                        Some(pat) => self.bind_pattern(pat, ty, ctx)?,
+                        None => self.bind_pattern(
+                            &Pattern::Ident(PatternIdent::new(field.name.clone())),
+                            ty,
+                            ctx,
+                        )?,
+                    }
+                }
+            }
+            Pattern::TupleStruct(s) => {
+                for pat in &s.patterns {
+                    let ty = self.fresh(VarKind::Any);
+                    self.bind_pattern(pat, ty, ctx)?;
+                }
+            }
+            Pattern::Box(b) => {
+                let ty = self.fresh(VarKind::Any);
+                self.bind_pattern(&b.pattern, ty, ctx)?;
+            }
+            Pattern::Variant(v) => {
+                if let Some(pat) = &v.pattern {
+                    let ty = self.fresh(VarKind::Any);
+                    self.bind_pattern(pat, ty, ctx)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn infer_binop(
+        &mut self,
+        kind: BinOpKind,
+        lhs: &AstExpr,
+        rhs: &AstExpr,
+        node: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let lhs_ty = self.infer(lhs, ctx)?;
+        let rhs_ty = self.infer(rhs, ctx)?;
+        match kind {
+            BinOpKind::And | BinOpKind::Or => {
+                self.expect(&AstType::bool(), &lhs_ty, lhs)?;
+                self.expect(&AstType::bool(), &rhs_ty, rhs)?;
+                return Ok(AstType::bool());
+            }
+            BinOpKind::Shl | BinOpKind::Shr => return Ok(lhs_ty),
+            _ => {}
+        }
+        if !self.unify(&lhs_ty, &rhs_ty) {
+            let lhs_ty = type_name(&self.apply(&lhs_ty));
+            let rhs_ty = type_name(&self.apply(&rhs_ty));
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Expected same types, got {} and {}", lhs_ty, rhs_ty)
+            )
+            .with_node(node)
+            .with_label(format!("`{}` and `{}` do not match", lhs_ty, rhs_ty)))
+        }
+        if kind.is_ret_bool() {
+            return Ok(AstType::bool());
+        }
+        Ok(lhs_ty)
+    }
+    fn infer_invoke(
+        &mut self,
+        invoke: &ExprInvoke,
+        node: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let mut args = invoke.args.as_slice();
+        let callee = match &invoke.target {
+            // takes any args
+            ExprInvokeTarget::Function(Locator::Ident(ident)) if ident.as_str() == "print" => {
+                for arg in args {
+                    self.infer(arg, ctx)?;
+                }
+                return Ok(AstType::unit());
+            }
+            ExprInvokeTarget::Function(locator) => {
+                let path = locator.to_path();
+                if ctx.get_type(path.clone()).is_none() {
+                    match lookup_function(&path, ctx) {
+                        // a method called by its path, like `Foo::get(&foo)`
+                        Some((f, _))
+                            if f.sig.receiver.is_some() && args.len() == f.params.len() + 1 =>
+                        {
+                            self.infer(&args[0], ctx)?;
+                            args = &args[1..];
+                        }
+                        // like `Vec::new`, functions from outside the module
+                        None if path.segments.len() > 1
+                            && ctx.get_value(path.clone()).is_none()
+                            && self.constructor_type(&path, ctx).is_none() =>
+                        {
+                            for arg in args {
+                                self.infer(arg, ctx)?;
+                            }
+                            return Ok(AstType::unknown());
+                        }
+                        _ => {}
+                    }
+                }
+                self.infer_locator(locator, node, ctx)?
+            }
+            ExprInvokeTarget::Method(select) => {
+                let obj = self.infer(&select.obj, ctx)?;
+                let obj = self.apply(&obj);
+                let name = match obj.unwrap_reference() {
+                    AstType::Struct(s) => Some(s.name.clone()),
+                    AstType::Enum(e) => Some(e.name.clone()),
+                    AstType::Expr(expr) => match expr.as_ref() {
+                        AstExpr::Locator(locator) => Some(locator.to_path().last().clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let method = name
+                    .and_then(|x| lookup_function(&Path::new(vec![x, select.field.clone()]), ctx));
+                match method {
+                    Some((f, closure)) => self.instantiate(&f.sig, &[], &closure)?,
+                    // methods of types from outside, or of traits
+                    None => AstType::unknown(),
+                }
+            }
+            ExprInvokeTarget::BinOp(kind) => match args {
+                [lhs, rhs] => return self.infer_binop(*kind, lhs, rhs, node, ctx),
+                _ => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Operator {} takes 2 arguments", kind)
+                )
+                .with_node(node)),
+            },
+            ExprInvokeTarget::Closure(func) => self.instantiate(&func.sig, &[], ctx)?,
+            ExprInvokeTarget::Expr(expr) => self.infer(expr, ctx)?,
+            ExprInvokeTarget::Type(_) => AstType::unknown(),
+        };
+        let exprs = args;
+        let args: Vec<_> = exprs.iter().map(|x| self.infer(x, ctx)).try_collect()?;
+        match self.shallow(&callee) {
+            AstType::Function(f) => {
+                if f.params.len() != args.len() {
+                    bail!(Diagnostic::new(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "Function takes {} arguments but {} were supplied",
+                            f.params.len(),
+                            args.len()
+                        )
+                    )
+                    .with_node(node)
+                    .with_label(format!("expected {} arguments", f.params.len())))
+                }
+                for ((param, arg), expr) in f.params.iter().zip(&args).zip(exprs) {
+                    self.expect(param, arg, expr)?;
+                }
+                Ok(f.ret_ty.map(|x| *x).unwrap_or(AstType::unit()))
+            }
+            // a closure whose type is not known yet, like a param
+            callee if Self::var_of(&callee).is_some() => {
+                let ret = self.fresh(VarKind::Any);
+                let func = AstType::Function(
+                    TypeFunction {
+                        params: args,
+                        generics_params: vec![],
+                        ret_ty: Some(ret.clone().into()),
+                    }
+                    .into(),
+                );
+                self.expect(&callee, &func, node)?;
+                Ok(ret)
+            }
+            _ => Ok(AstType::unknown()),
+        }
+    }
+    fn infer_select(
+        &mut self,
+        select: &ExprSelect,
+        node: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let mut ty = self.infer(&select.obj, ctx)?;
+        ty = self.shallow(&ty);
+        // fields are selected through references
+        while let AstType::Reference(r) = ty {
+            ty = self.shallow(&r.ty);
+        }
+        if let AstType::Expr(expr) = &ty {
+            if let AstExpr::Locator(locator) = expr.as_ref() {
+                if let Some(AstValue::Type(found)) = ctx.get_value(locator.to_path()) {
+                    ty = found;
+                }
+            }
+        }
+        let found = match &ty {
+            AstType::Struct(TypeStruct { fields, .. })
+            | AstType::Structural(TypeStructural { fields }) => fields
+                .iter()
+                .find(|x| x.name == select.field)
+                .map(|x| x.value.clone()),
+            AstType::Tuple(tuple) => select
+                .field
+                .as_str()
+                .parse::<usize>()
+                .ok()
+                .and_then(|x| tuple.types.get(x).cloned()),
+            AstType::Primitive(_) | AstType::Unit(_) => None,
+            _ => return Ok(AstType::unknown()),
+        };
+        match found {
+            Some(found) => self.annotation(&found, ctx),
+            None => bail!(no_field(&self.apply(&ty), &select.field, node)),
+        }
+    }
+    fn infer_struct(
+        &mut self,
+        s: &ExprStruct,
+        node: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let AstExpr::Locator(locator) = &*s.name else {
+            return Ok(AstType::unknown());
+        };
+        let path = locator.to_path();
+        // a struct variant is of its enum
+        let (name, fields, ty) = match self.pass.lookup_variant(&path, ctx) {
+            Some((ty, variant)) => {
+                let AstType::Structural(payload) = variant.value else {
+                    bail!(Diagnostic::new(
+                        ErrorCode::TypeMismatch,
+                        format!("Variant {} has no named fields", path)
+                    )
+                    .with_node(&*s.name)
+                    .with_label("not a struct variant"))
+                };
+                (path.to_string(), payload.fields, AstType::ident(ty.name))
+            }
+            None => match ctx.get_value(path.clone()) {
+                Some(AstValue::Type(AstType::Struct(def))) => {
+                    let ty = AstType::ident(def.name.clone());
+                    (def.name.to_string(), def.fields, ty)
+                }
+                Some(AstValue::Type(found)) => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Expected struct, found {}", type_name(&found))
+                )
+                .with_node(&*s.name)
+                .with_label("not a struct")),
+                // like `std::ops::Range`, structs from outside the module
+                _ if path.segments.len() > 1 => {
+                    for field in &s.fields {
+                        if let Some(value) = &field.value {
+                            self.infer(value, ctx)?;
+                        }
+                    }
+                    return Ok(AstType::expr(*s.name.clone()));
+                }
+                _ => bail!(Diagnostic::unresolved(&s.name)
+                    .with_node(&*s.name)
+                    .with_label("not a struct in this scope")),
+            },
+        };
+        for field in &s.fields {
+            let Some(declared) = fields.iter().find(|x| x.name == field.name) else {
+                bail!(Diagnostic::new(
+                    ErrorCode::UnresolvedName,
+                    format!("Struct {} has no field named `{}`", name, field.name)
+                )
+                .with_node(field.value.as_ref().unwrap_or(node))
+                .with_label("unknown field"))
+            };
+            let declared = self.annotation(&declared.value, ctx)?;
+            let value = field
+                .value
+                .clone()
+                .unwrap_or_else(|| AstExpr::ident(field.name.clone()));
+            let found = self.infer(&value, ctx)?;
+            self.expect(&declared, &found, &value)?;
+        }
+        let missing = fields
+            .iter()
+            .filter(|x| !s.fields.iter().any(|f| f.name == x.name))
+            .map(|x| format!("`{}`", x.name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Missing fields {} in initializer of {}",
+                    missing.join(", "),
+                    name
+                )
+            )
+            .with_node(node)
+            .with_label(format!("missing {}", missing.join(", "))))
+        }
+        Ok(ty)
+    }
+    fn infer_closure(
+        &mut self,
+        closure: &ExprClosure,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let sub = ctx.child(Ident::new("__closure__"), Visibility::Private, true);
+        let mut params = vec![];
+        for param in &closure.params {
+            let ty = match param {
+                Pattern::Type(pat) => self.annotation(&pat.ty, ctx)?,
+                _ => self.fresh(VarKind::Any),
+            };
+            self.bind_pattern(param, ty.clone(), &sub)?;
+            params.push(ty);
+        }
+        let ret_ty = match &closure.ret_ty {
+            Some(ty) => self.annotation(ty, ctx)?,
+            None => self.fresh(VarKind::Any),
+        };
+        self.returns.push(ret_ty.clone());
+        let body = self.infer(&closure.body, &sub);
+        self.returns.pop();
+        self.expect(&ret_ty, &body?, &closure.body)?;
+        Ok(AstType::Function(
+            TypeFunction {
+                params,
+                generics_params: vec![],
+                ret_ty: Some(ret_ty.into()),
+            }
+            .into(),
+        ))
+    }
+    /// The cases of a `match` unify with each other, the patterns with the scrutinee
+    fn infer_match(&mut self, match_: &ExprMatch, ctx: &SharedScopedContext) -> Result<AstType> {
+        let scrutinee = match &match_.scrutinee {
+            Some(scrutinee) => self.infer(scrutinee, ctx)?,
+            None => self.fresh(VarKind::Any),
+        };
+        let result = self.fresh(VarKind::Any);
+        for case in &match_.cases {
+            let sub = ctx.child(Ident::new("__match__"), Visibility::Private, true);
+            if let Some(pat) = &case.pat {
+                self.bind_pattern(pat, scrutinee.clone(), &sub)?;
+            }
+            let cond = self.infer(&case.cond, &sub)?;
+            self.expect(&AstType::bool(), &cond, &case.cond)?;
+            let found = self.infer(&case.body, &sub)?;
+            self.expect(&result, &found, &case.body)?;
+        }
+        Ok(result)
+    }
+    fn infer_loop(
+        &mut self,
+        result: Option<AstType>,
+        body: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        self.loops.push(result);
+        let ty = self.infer(body, ctx);
+        self.loops.pop();
+        ty.map(|_| ())
+    }
+    fn infer(&mut self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<AstType> {
+        let ty = match expr {
+            AstExpr::Locator(locator) => self.infer_locator(locator, expr, ctx)?,
+            AstExpr::Value(value) => match value.as_ref() {
+                AstValue::Int(ValueInt { value, ty: None }) => self.literal(*value, expr),
+                value => self.infer_value(value, ctx)?,
+            },
+            AstExpr::Block(block) => {
+                let sub = ctx.child(Ident::new("__block__"), Visibility::Private, true);
+                self.infer_block(block, &sub)?
+            }
+            AstExpr::If(if_) => {
+                let cond = self.infer(&if_.cond, ctx)?;
+                self.expect(&AstType::bool(), &cond, &if_.cond)?;
+                let then = self.infer(&if_.then, ctx)?;
+                match &if_.elze {
+                    Some(elze) => {
+                        let found = self.infer(elze, ctx)?;
+                        self.expect(&then, &found, elze)?;
+                        then
+                    }
+                    None => {
+                        self.expect(&AstType::unit(), &then, &if_.then)?;
+                        AstType::unit()
+                    }
+                }
+            }
+            AstExpr::Let(let_) => {
+                let ty = self.infer(&let_.expr, ctx)?;
+                self.bind_pattern(&let_.pat, ty, ctx)?;
+                AstType::bool()
+            }
+            AstExpr::Loop(loop_) => {
+                let result = self.fresh(VarKind::Any);
+                self.infer_loop(Some(result.clone()), &loop_.body, ctx)?;
+                result
+            }
+            AstExpr::While(while_) => {
+                let cond = self.infer(&while_.cond, ctx)?;
+                self.expect(&AstType::bool(), &cond, &while_.cond)?;
+                self.infer_loop(None, &while_.body, ctx)?;
+                AstType::unit()
+            }
+            AstExpr::For(for_) => {
+                let elem = match for_.iter.as_ref() {
+                    AstExpr::Range(range) => {
+                        let elem = self.fresh(VarKind::Any);
+                        for bound in range.start.iter().chain(&range.end) {
+                            let found = self.infer(bound, ctx)?;
+                            self.expect(&elem, &found, bound)?;
+                        }
+                        elem
+                    }
+                    iter => {
+                        self.infer(iter, ctx)?;
+                        self.fresh(VarKind::Any)
+                    }
+                };
+                let sub = ctx.child(Ident::new("__for__"), Visibility::Private, true);
+                self.bind_pattern(&for_.pat, elem, &sub)?;
+                self.infer_loop(None, &for_.body, &sub)?;
+                AstType::unit()
+            }
+            AstExpr::Break(break_) => {
+                let found = match &break_.value {
+                    Some(value) => self.infer(value, ctx)?,
+                    None => AstType::unit(),
+                };
+                if let Some(Some(result)) = self.loops.last().cloned() {
+                    let node = break_.value.as_deref().unwrap_or(expr);
+                    self.expect(&result, &found, node)?;
+                }
+                AstType::Nothing(TypeNothing)
+            }
+            AstExpr::Continue(_) => AstType::Nothing(TypeNothing),
+            AstExpr::Return(return_) => {
+                let found = match &return_.value {
+                    Some(value) => self.infer(value, ctx)?,
+                    None => AstType::unit(),
+                };
+                if let Some(result) = self.returns.last().cloned() {
+                    let node = return_.value.as_deref().unwrap_or(expr);
+                    self.expect(&result, &found, node)?;
+                }
+                AstType::Nothing(TypeNothing)
+            }
+            AstExpr::Match(match_) => self.infer_match(match_, ctx)?,
+            AstExpr::Invoke(invoke) => self.infer_invoke(invoke, expr, ctx)?,
+            AstExpr::BinOp(op) => self.infer_binop(op.kind, &op.lhs, &op.rhs, expr, ctx)?,
+            AstExpr::UnOp(op) => match op.op {
+                UnOpKind::Neg => match op.val.as_ref() {
+                    AstExpr::Value(value) => match value.as_ref() {
+                        AstValue::Int(ValueInt { value, ty: None }) => self.literal(-value, expr),
+                        _ => self.infer(&op.val, ctx)?,
+                    },
+                    _ => self.infer(&op.val, ctx)?,
+                },
+                UnOpKind::Not => self.infer(&op.val, ctx)?,
+                UnOpKind::Deref => {
+                    let referee = self.infer(&op.val, ctx)?;
+                    self.infer_deref(referee)
+                }
+                UnOpKind::Any(_) => AstType::unknown(),
+            },
+            AstExpr::Dereference(deref) => {
+                let referee = self.infer(&deref.referee, ctx)?;
+                self.infer_deref(referee)
+            }
+            AstExpr::Reference(reference) => AstType::Reference(
+                TypeReference {
+                    ty: self.infer(&reference.referee, ctx)?.into(),
+                    mutability: reference.mutable.filter(|x| *x),
+                    lifetime: None,
+                }
+                .into(),
+            ),
+            AstExpr::Assign(assign) => {
+                let target = self.infer(&assign.target, ctx)?;
+                let value = self.infer(&assign.value, ctx)?;
+                self.expect(&target, &value, &assign.value)?;
+                AstType::unit()
+            }
+            AstExpr::Select(select) => self.infer_select(select, expr, ctx)?,
+            AstExpr::Index(index) => {
+                let obj = self.infer(&index.obj, ctx)?;
+                self.infer(&index.index, ctx)?;
+                match self.apply(&obj).unwrap_reference() {
+                    AstType::Slice(s) => *s.elem.clone(),
+                    AstType::Vec(v) => *v.ty.clone(),
+                    AstType::Expr(e) => match e.as_ref() {
+                        AstExpr::Locator(Locator::ParameterPath(path))
+                            if path.last().ident.as_str() == "Vec" =>
+                        {
+                            path.last()
+                                .args
+                                .first()
+                                .cloned()
+                                .unwrap_or(AstType::unknown())
+                        }
+                        _ => AstType::unknown(),
+                    },
+                    _ => AstType::unknown(),
+                }
+            }
+            AstExpr::Cast(cast) => {
+                self.infer(&cast.expr, ctx)?;
+                self.annotation(&cast.ty, ctx)?
+            }
+            AstExpr::Struct(s) => self.infer_struct(s, expr, ctx)?,
+            AstExpr::Tuple(tuple) => AstType::Tuple(TypeTuple {
+                types: tuple
+                    .values
+                    .iter()
+                    .map(|x| self.infer(x, ctx))
+                    .try_collect()?,
+            }),
+            AstExpr::Array(array) => {
+                let elem = self.fresh(VarKind::Any);
+                for value in &array.values {
+                    let found = self.infer(value, ctx)?;
+                    self.expect(&elem, &found, value)?;
+                }
+                AstType::unknown()
+            }
+            AstExpr::ArrayRepeat(repeat) => {
+                self.infer(&repeat.value, ctx)?;
+                let len = self.infer(&repeat.len, ctx)?;
+                let usize = AstType::Primitive(TypePrimitive::Int(TypeInt::USize));
+                self.expect(&usize, &len, &repeat.len)?;
+                AstType::unknown()
+            }
+            AstExpr::Closure(closure) => self.infer_closure(closure, ctx)?,
+            AstExpr::Closured(closured) => self.infer(&closured.expr, &closured.ctx)?,
+            AstExpr::Paren(paren) => self.infer(&paren.expr, ctx)?,
+            AstExpr::Const(const_) => self.infer(&const_.block, ctx)?,
+            AstExpr::Unsafe(unsafe_) => self.infer(&unsafe_.block, ctx)?,
+            AstExpr::Async(async_) => {
+                self.infer(&async_.block, ctx)?;
+                AstType::unknown()
+            }
+            AstExpr::Item(item) => {
+                self.pass.interpret_item(item, ctx)?;
+                self.check_item(item, ctx)?;
+                AstType::unit()
+            }
+            AstExpr::Try(_)
+            | AstExpr::Range(_)
+            | AstExpr::Structural(_)
+            | AstExpr::Splat(_)
+            | AstExpr::SplatDict(_)
+            | AstExpr::Id(_)
+            | AstExpr::Any(_) => AstType::unknown(),
+        };
+        Ok(ty)
+    }
+    fn infer_deref(&mut self, referee: AstType) -> AstType {
+        match self.shallow(&referee) {
+            AstType::Reference(r) => *r.ty,
+            referee if Self::var_of(&referee).is_some() => {
+                let ty = self.fresh(VarKind::Any);
+                self.unify(&referee, &AstType::reference(ty.clone()));
+                ty
+            }
+            // like `Box<T>`, dereferenced through a trait
+            _ => AstType::unknown(),
+        }
+    }
+}
+
+/// A function named `path`, with the scope it was declared in if it was declared with one
+fn lookup_function(
+    path: &Path,
+    ctx: &SharedScopedContext,
+) -> Option<(ValueFunction, SharedScopedContext)> {
+    let (expr, closure) = match ctx.get_expr_with_ctx(path.clone())? {
+        AstExpr::Closured(closured) => (closured.expr.get(), closured.ctx),
+        expr => (expr, ctx.clone()),
+    };
+    match expr {
+        AstExpr::Value(value) => match *value {
+            AstValue::Function(func) => Some((func, closure)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl InterpreterPass {
+    /// Infers the type of `expr`, solving the types of the untyped lets and closures in it.
+    ///
+    /// The types of the let bindings are inserted into their scopes with
+    /// [`SharedScopedContext::insert_type`], for passes and printers to emit explicitly.
+    pub fn infer_expr(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<AstType> {
+        let mut inference = TypeInference::new(self);
+        let ty = inference.infer(expr, ctx);
+        inference.finish();
+        let ty = ty?;
+        inference.check_literals()?;
+        if let Some(var) = TypeInference::var_of(&inference.shallow(&ty)) {
+            if inference.kinds[var] == VarKind::Any {
+                bail!(Diagnostic::new(
+                    ErrorCode::InferenceFailed,
+                    format!("Could not infer type of {}", expr.describe())
+                )
+                .with_node(expr)
+                .with_label("type unknown")
+                .with_help("consider adding a type annotation"))
+            }
+        }
+        Ok(inference.apply(&ty))
+    }
+    /// Checks `expr` against the type it is expected to have, which also types unsuffixed
+    /// literals, like `1` as `u8` in `let a: u8 = 1;`
+    pub fn check_expr(
+        &self,
+        expr: &AstExpr,
+        expected: &AstType,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        let mut inference = TypeInference::new(self);
+        let checked = inference.annotation(expected, ctx).and_then(|expected| {
+            let found = inference.infer(expr, ctx)?;
+            inference.expect(&expected, &found, expr)
+        });
+        inference.finish();
+        checked?;
+        inference.check_literals()
+    }
+    /// Infers the statements of `block` in `ctx` itself rather than a scope of its own, so the
+    /// types of its lets can be read back from `ctx`
+    pub fn infer_block(&self, block: &ExprBlock, ctx: &SharedScopedContext) -> Result<AstType> {
+        let mut inference = TypeInference::new(self);
+        let ty = inference.infer_block(block, ctx);
+        inference.finish();
+        let ty = ty?;
+        inference.check_literals()?;
+        Ok(inference.apply(&ty))
+    }
+    /// Infers the body of a function declared in `ctx` against its signature, with its generic
+    /// params opaque
+    pub fn infer_function_body(
+        &self,
+        func: &ValueFunction,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let sub = ctx.child(Ident::new("__func__"), Visibility::Private, false);
+        for param in &func.generics_params {
+            match &param.const_ty {
+                Some(ty) => sub.insert_type(param.name.clone(), self.resolve_type(ty, ctx)?),
+                None => sub.insert_value(
+                    param.name.clone(),
+                    AstValue::Type(AstType::ident(param.name.clone())),
+                ),
+            }
+        }
+        if let Some(receiver) = &func.receiver {
+            let self_ty = self.resolve_type(&AstType::ident("Self".into()), ctx)?;
+            let ty = match receiver {
+                FunctionParamReceiver::Implicit
+                | FunctionParamReceiver::Value
+                | FunctionParamReceiver::MutValue => self_ty,
+                FunctionParamReceiver::Ref | FunctionParamReceiver::RefStatic => {
+                    AstType::reference(self_ty)
+                }
+                FunctionParamReceiver::RefMut | FunctionParamReceiver::RefMutStatic => {
+                    AstType::Reference(
+                        TypeReference {
+                            ty: self_ty.into(),
+                            mutability: Some(true),
+                            lifetime: None,
+                        }
+                        .into(),
+                    )
+                }
+            };
+            sub.insert_type("self", ty);
+        }
+        for param in &func.params {
+            sub.insert_type(param.name.clone(), self.resolve_type(&param.ty, &sub)?);
+        }
+        let ret_ty = match &func.ret_ty {
+            Some(ty) => self.resolve_type(ty, &sub)?,
+            None => AstType::unit(),
+        };
+        let mut inference = TypeInference::new(self);
+        inference.returns.push(ret_ty.clone());
+        let ty = inference
+            .infer(&func.body, &sub)
+            .and_then(|ty| inference.expect(&ret_ty, &ty, &func.body).map(|_| ty));
+        inference.finish();
+        let ty = ty?;
+        inference.check_literals()?;
+        Ok(inference.apply(&ty))
+    }
+    /// Resolves the names in a type to what they were declared as, evaluating type functions.
+    ///
+    /// Structs and enums stay nominal, named as they were declared, while aliases are
+    /// resolved.
+    pub fn resolve_type(&self, ty: &AstType, ctx: &SharedScopedContext) -> Result<AstType> {
+        let resolved = match ty {
+            AstType::Expr(expr) => match expr.as_ref() {
+                AstExpr::Locator(Locator::ParameterPath(path)) => {
+                    match ctx.get_value(Locator::ParameterPath(path.clone()).to_path()) {
+                        Some(AstValue::Function(_)) => self.interpret_parameter_path(path, ctx)?,
+                        // like `Vec<T>`, compared by name and args
+                        _ => ty.clone(),
+                    }
+                }
+                AstExpr::Locator(locator) => match ctx.get_value(locator.to_path()) {
+                    Some(AstValue::Type(AstType::Struct(s))) => AstType::ident(s.name),
+                    Some(AstValue::Type(AstType::Enum(e))) => AstType::ident(e.name),
+                    Some(AstValue::Type(found)) if &found != ty => {
+                        self.resolve_type(&found, ctx)?
+                    }
+                    Some(AstValue::Type(found)) => found,
+                    _ if matches!(locator, Locator::Ident(ident) if ident.as_str() == "String") => {
+                        AstType::Primitive(TypePrimitive::String)
+                    }
+                    _ => ty.clone(),
+                },
+                _ => self.evaluate_type_value(ty, ctx)?,
+            },
+            AstType::Reference(r) => {
+                let mut r = r.clone();
+                r.ty = self.resolve_type(&r.ty, ctx)?.into();
+                AstType::Reference(r)
+            }
+            AstType::Tuple(t) => AstType::Tuple(TypeTuple {
+                types: t
+                    .types
+                    .iter()
+                    .map(|x| self.resolve_type(x, ctx))
+                    .try_collect()?,
+            }),
+            AstType::Slice(s) => AstType::Slice(TypeSlice {
+                elem: self.resolve_type(&s.elem, ctx)?.into(),
+            }),
+            AstType::Vec(v) => AstType::Vec(TypeVec {
+                ty: self.resolve_type(&v.ty, ctx)?.into(),
+            }),
+            AstType::TypeOf(_) => self.evaluate_type_value(ty, ctx)?,
+            _ => ty.clone(),
+        };
+        Ok(resolved)
+    }
+}
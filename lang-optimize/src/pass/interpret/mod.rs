@@ -1,29 +1,159 @@
+mod control;
+mod infer;
+mod pattern;
 mod typing;
 
+pub use control::{IntRange, Unwind};
+
 use crate::pass::{FoldOptimizer, OptimizePass};
 use common::*;
 use itertools::Itertools;
 use lang_core::ast::*;
-use lang_core::context::SharedScopedContext;
+use lang_core::context::{impl_key, SharedScopedContext};
 use lang_core::ctx::{Context, ValueSystem};
-use lang_core::id::{Ident, Locator};
+use lang_core::error::{Diagnostic, DiagnosticContext, ErrorCode};
+use lang_core::id::{Ident, Locator, Path};
 use lang_core::ops::*;
+use lang_core::span::SourceNode;
 use lang_core::utils::conv::TryConv;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+
+fn not_invokable(invoke: &ExprInvoke) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::TypeMismatch,
+        format!(
+            "Could not invoke {}",
+            AstExpr::Invoke(invoke.clone()).describe()
+        ),
+    )
+    .with_node(&AstExpr::Invoke(invoke.clone()))
+    .with_label("not a function")
+    .with_help("only functions, operators and builtins can be invoked")
+}
+fn expected_bool(cond: &AstExpr, value: &AstValue) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::TypeMismatch,
+        format!("Mismatched types in {}", cond.describe()),
+    )
+    .with_node(cond)
+    .with_label(format!("expected `bool`, found `{}`", value))
+}
+/// whether `expr` can be assigned to, like `a`, `a.b` or `a[0]`
+fn is_place(expr: &AstExpr) -> bool {
+    match expr {
+        AstExpr::Locator(_) => true,
+        AstExpr::Select(s) => is_place(&s.obj),
+        AstExpr::Index(i) => is_place(&i.obj),
+        AstExpr::Paren(p) => is_place(&p.expr),
+        _ => false,
+    }
+}
+/// An unsuffixed integer takes the integer type it is bound to, like in `let x: u8 = 255`
+pub(crate) fn coerce_untyped_int(value: &AstValue, ty: &AstType) -> Result<AstValue> {
+    let (AstValue::Int(int), AstType::Primitive(TypePrimitive::Int(target))) = (value, ty) else {
+        return Ok(value.clone());
+    };
+    if int.ty.is_some() {
+        return Ok(value.clone());
+    }
+    if !target.contains(int.value) {
+        bail!(literal_out_of_range(int.value, *target))
+    }
+    Ok(AstValue::int_typed(int.value, *target))
+}
+pub(crate) fn literal_out_of_range(value: i128, target: TypeInt) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::LiteralOutOfRange,
+        format!("literal out of range for {}: {}", target, value),
+    )
+    .with_label(format!("does not fit into `{}`", target))
+    .with_help(format!(
+        "the range of `{}` is `{}..={}`",
+        target,
+        target.min_value().unwrap(),
+        target.max_value().unwrap()
+    ))
+}
+fn unsupported_node(node: &impl SourceNode) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::InterpretFailed,
+        format!("Failed to interpret {}", node.describe()),
+    )
+    .with_node(node)
+    .with_label("the interpreter cannot evaluate this")
+}
+fn expected_indexable(node: &AstExpr, value: &AstValue) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::TypeMismatch,
+        format!("Cannot index into {}", node.describe()),
+    )
+    .with_node(node)
+    .with_label(format!("expected list or tuple, found `{}`", value))
+}
+fn expected_structural(node: &AstExpr, value: &AstValue) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::TypeMismatch,
+        format!("No fields on {}", node.describe()),
+    )
+    .with_node(node)
+    .with_label(format!("expected struct, found `{}`", value))
+}
+fn missing_field(node: &AstExpr, field: &Ident) -> Diagnostic {
+    Diagnostic::new(ErrorCode::UnresolvedName, format!("No field `{}`", field))
+        .with_node(node)
+        .with_label("unknown field")
+}
+fn out_of_bounds(node: &AstExpr, index: usize, len: usize) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::InterpretFailed,
+        format!("Index {} out of bounds for length {}", index, len),
+    )
+    .with_node(node)
+    .with_label("index out of bounds")
+}
 
 #[derive(Clone)]
 pub struct InterpreterPass {
     pub serializer: Arc<dyn AstSerializer>,
     pub ignore_missing_items: bool,
+    pub overflow: IntOverflow,
+    /// how deep type functions may instantiate each other
+    type_depth_limit: usize,
+    /// how many iterations a loop may run before it's assumed not to terminate
+    loop_iteration_limit: usize,
+    type_depth: Arc<AtomicUsize>,
+    /// instances of type functions, shared by the clones of the pass
+    type_instances: Arc<Mutex<HashMap<String, AstType>>>,
 }
 
 impl InterpreterPass {
+    pub const DEFAULT_TYPE_DEPTH_LIMIT: usize = 64;
+    pub const DEFAULT_LOOP_ITERATION_LIMIT: usize = 1_000_000;
     pub fn new(serializer: Arc<dyn AstSerializer>) -> Self {
         Self {
             serializer,
             ignore_missing_items: false,
+            overflow: IntOverflow::default(),
+            type_depth_limit: Self::DEFAULT_TYPE_DEPTH_LIMIT,
+            loop_iteration_limit: Self::DEFAULT_LOOP_ITERATION_LIMIT,
+            type_depth: Default::default(),
+            type_instances: Default::default(),
         }
     }
+    pub fn with_overflow(mut self, overflow: IntOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+    pub fn with_type_depth_limit(mut self, limit: usize) -> Self {
+        self.type_depth_limit = limit;
+        self
+    }
+    pub fn with_loop_iteration_limit(mut self, limit: usize) -> Self {
+        self.loop_iteration_limit = limit;
+        self
+    }
 
     pub fn interpret_items(&self, node: &ItemChunk, ctx: &SharedScopedContext) -> Result<AstValue> {
         let result: Vec<_> = node
@@ -49,20 +179,31 @@ impl InterpreterPass {
                     ctx,
                 )
             }
-            ExprInvokeTarget::Method(select) => match select.field.as_str() {
-                "to_string" => match &select.obj.get() {
-                    AstExpr::Value(value) => match value.as_ref() {
-                        AstValue::String(obj) => {
-                            let mut obj = obj.clone();
-                            obj.owned = true;
-                            Ok(AstValue::String(obj))
-                        }
-                        _ => bail!("Expected string for {:?}", select),
+            ExprInvokeTarget::Function(locator) => {
+                if let Some((ty, variant)) = self.lookup_variant(&locator.to_path(), ctx) {
+                    let args = self.interpret_values(&node.args, ctx)?;
+                    return self.construct_tuple_variant(ty, variant, args, node);
+                }
+                let func = self.interpret_expr(&AstExpr::Locator(locator.clone()), ctx)?;
+                self.interpret_invoke(
+                    &ExprInvoke {
+                        target: ExprInvokeTarget::expr(AstExpr::value(func).into()),
+                        args: node.args.clone(),
                     },
-                    _ => bail!("Expected struct for {:?}", select),
-                },
-                x => bail!("Could not invoke {:?}", x),
-            },
+                    ctx,
+                )
+            }
+            ExprInvokeTarget::Method(select) => self.interpret_method_call(node, select, ctx),
+            ExprInvokeTarget::Type(AstType::Struct(ty)) if ty.kind == StructKind::Tuple => {
+                let args = self.interpret_values(&node.args, ctx)?;
+                self.construct_tuple_struct(ty, args, node)
+                    .map(AstValue::Struct)
+            }
+            ExprInvokeTarget::Closure(func) => {
+                let args = self.interpret_values(&node.args, ctx)?;
+                self.interpret_call(func, args, node, ctx)
+                    .map(|(ret, _)| ret)
+            }
             ExprInvokeTarget::Expr(e) => match e.as_ref() {
                 AstExpr::Value(value) => match value.as_ref() {
                     AstValue::BinOpKind(kind) => {
@@ -73,7 +214,7 @@ impl InterpreterPass {
                         let arg = self.interpret_expr(&node.args[0].get(), ctx)?;
                         self.interpret_invoke_unop(func.clone(), arg, ctx)
                     }
-                    _ => bail!("Could not invoke {}", node),
+                    _ => bail!(not_invokable(node)),
                 },
 
                 AstExpr::Any(any) => {
@@ -81,19 +222,232 @@ impl InterpreterPass {
                         let args = self.interpret_args(&node.args, ctx)?;
                         exp.invoke(&args, ctx)
                     } else {
-                        bail!("Could not invoke {:?}", node)
+                        bail!(not_invokable(node))
                     }
                 }
-                _ => bail!("Could not invoke {:?}", node),
+                _ => bail!(not_invokable(node)),
             },
-            kind => bail!("Could not invoke {:?}", kind),
+            _ => bail!(not_invokable(node)),
         }
     }
+    /// `x.method(..)`, looked up in the impls of the type of `x`
+    fn interpret_method_call(
+        &self,
+        node: &ExprInvoke,
+        select: &ExprSelect,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstValue> {
+        let obj = self.interpret_expr(&select.obj, ctx)?;
+        let found = obj
+            .nominal_type()
+            .and_then(|ty| ctx.lookup_method(&ty, &select.field));
+        let Some(found) = found else {
+            return match (select.field.as_str(), obj) {
+                ("to_string", AstValue::String(mut obj)) => {
+                    obj.owned = true;
+                    Ok(AstValue::String(obj))
+                }
+                (_, obj) => bail!(Diagnostic::new(
+                    ErrorCode::InterpretFailed,
+                    format!("Could not invoke method {}", select.field)
+                )
+                .with_node(&AstExpr::Invoke(node.clone()))
+                .with_label(format!("no method `{}` for `{}`", select.field, obj))),
+            };
+        };
+        let scope = found
+            .ctx
+            .child(Ident::new("__impl__"), Visibility::Private, true);
+        if let Some(ty) = obj.nominal_type() {
+            scope.insert_value("Self", AstValue::Type(ty));
+        }
+        let mut args = vec![obj];
+        args.extend(self.interpret_values(&node.args, ctx)?);
+        let func = found.func._to_value();
+        let (ret, call) = self.interpret_call(&func, args, node, &scope)?;
+        // `&mut self` methods change the receiver in place
+        if let Some(FunctionParamReceiver::RefMut) = func.sig.receiver {
+            let this = call.get_value(Ident::new("self"));
+            if let (Some(this), true) = (this, is_place(&select.obj)) {
+                self.assign_value(&select.obj, this, ctx)?;
+            }
+        }
+        Ok(ret)
+    }
+    fn interpret_values(
+        &self,
+        args: &[AstExpr],
+        ctx: &SharedScopedContext,
+    ) -> Result<Vec<AstValue>> {
+        args.iter()
+            .map(|x| self.interpret_expr(x, ctx))
+            .try_collect()
+    }
+    /// Calls `func` in a child of `ctx`, returning the result and the context of the call.
+    /// A receiver takes the first of `args` as `self`
+    pub fn interpret_call(
+        &self,
+        func: &ValueFunction,
+        args: Vec<AstValue>,
+        node: &ExprInvoke,
+        ctx: &SharedScopedContext,
+    ) -> Result<(AstValue, SharedScopedContext)> {
+        let call = ctx.child(Ident::new("__call__"), Visibility::Private, true);
+        let mut names = vec![];
+        if func.sig.receiver.is_some() {
+            names.push(Ident::new("self"));
+        }
+        names.extend(func.sig.params.iter().map(|x| x.name.clone()));
+        let offset = names.len() - func.sig.params.len();
+        if names.len() != args.len() {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Function takes {} arguments but {} were supplied",
+                    names.len(),
+                    args.len()
+                )
+            )
+            .with_node(&AstExpr::Invoke(node.clone())))
+        }
+        for (i, (name, arg)) in names.into_iter().zip(args).enumerate() {
+            let arg = match i.checked_sub(offset) {
+                Some(i) => coerce_untyped_int(&arg, &func.sig.params[i].ty)?,
+                None => arg,
+            };
+            call.insert_value(name, arg);
+        }
+        let ret = self.interpret_body(&func.body, &call)?;
+        Ok((ret, call))
+    }
+    /// `P(a, b)` of a tuple struct, whose fields are named by their index
+    fn construct_tuple_struct(
+        &self,
+        ty: &TypeStruct,
+        args: Vec<AstValue>,
+        node: &ExprInvoke,
+    ) -> Result<ValueStruct> {
+        if ty.fields.len() != args.len() {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "{} takes {} fields but {} were supplied",
+                    ty.name,
+                    ty.fields.len(),
+                    args.len()
+                )
+            )
+            .with_node(&AstExpr::Invoke(node.clone())))
+        }
+        let fields = ty
+            .fields
+            .iter()
+            .zip(args)
+            .map(|(field, value)| ValueField {
+                name: field.name.clone(),
+                value,
+            })
+            .collect();
+        Ok(ValueStruct::new(ty.clone(), fields))
+    }
+    /// `Enum::Variant(a, b)` of a tuple variant
+    fn construct_tuple_variant(
+        &self,
+        ty: TypeEnum,
+        variant: EnumTypeVariant,
+        args: Vec<AstValue>,
+        node: &ExprInvoke,
+    ) -> Result<AstValue> {
+        let AstType::Tuple(payload) = &variant.value else {
+            bail!(not_invokable(node))
+        };
+        if payload.types.len() != args.len() {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "{}::{} takes {} fields but {} were supplied",
+                    ty.name,
+                    variant.name,
+                    payload.types.len(),
+                    args.len()
+                )
+            )
+            .with_node(&AstExpr::Invoke(node.clone())))
+        }
+        let value = AstValue::Tuple(ValueTuple::new(args));
+        let variant = ValueVariant::new(ty, variant.name, value);
+        Ok(AstValue::Variant(variant))
+    }
+    fn lookup_variant_expr(
+        &self,
+        expr: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Option<(TypeEnum, EnumTypeVariant)> {
+        match expr {
+            AstExpr::Locator(locator) => self.lookup_variant(&locator.to_path(), ctx),
+            _ => None,
+        }
+    }
+    /// `Enum::Variant`, when `Enum` names an enum with that variant
+    pub fn lookup_variant(
+        &self,
+        path: &Path,
+        ctx: &SharedScopedContext,
+    ) -> Option<(TypeEnum, EnumTypeVariant)> {
+        let [ty, name] = path.segments.as_slice() else {
+            return None;
+        };
+        let Some(AstValue::Type(AstType::Enum(ty))) = ctx.get_value_recursive(ty.clone()) else {
+            return None;
+        };
+        let variant = ty.get_variant(name)?.clone();
+        Some((ty, variant))
+    }
+    /// `Type::item`, an associated function or const found through the impls of `Type`
+    fn interpret_assoc_path(&self, path: &Path, ctx: &SharedScopedContext) -> Result<AstValue> {
+        // a unit variant is a value of its own
+        if let Some((ty, variant)) = self.lookup_variant(path, ctx) {
+            if let AstType::Any(_) = variant.value {
+                let value = ValueVariant::new(ty, variant.name, AstValue::unit());
+                return Ok(AstValue::Variant(value));
+            }
+        }
+        let [ty, name] = path.segments.as_slice() else {
+            bail!(Diagnostic::unresolved(path))
+        };
+        // `Self` and aliases name the type they stand for
+        let key = match ctx.get_value(ty.clone()) {
+            Some(AstValue::Type(ty)) => impl_key(&ty),
+            _ => None,
+        };
+        let key = key.unwrap_or_else(|| ty.clone());
+        let Some(found) = ctx.lookup_assoc_item(&key, name) else {
+            bail!(Diagnostic::unresolved(path))
+        };
+        match found.item {
+            AstItem::DefFunction(def) => Ok(AstValue::Function(def._to_value())),
+            AstItem::DefConst(def) => self.interpret_expr(&def.value, &found.ctx),
+            _ => bail!(Diagnostic::unresolved(path)),
+        }
+    }
+    pub fn interpret_def_trait(&self, def: &ItemDefTrait, ctx: &SharedScopedContext) -> Result<()> {
+        ctx.insert_trait(def.clone());
+        Ok(())
+    }
+    pub fn interpret_impl(&self, impl_: &ItemImpl, ctx: &SharedScopedContext) -> Result<()> {
+        ctx.insert_impl(impl_.clone())
+    }
     pub fn interpret_import(&self, _node: &ItemImport, _ctx: &SharedScopedContext) -> Result<()> {
         Ok(())
     }
     pub fn interpret_block(&self, node: &ExprBlock, ctx: &SharedScopedContext) -> Result<AstValue> {
         let ctx = ctx.child(Ident::new("__block__"), Visibility::Private, true);
+        // items are visible to the whole block
+        for stmt in &node.stmts {
+            if let BlockStmt::Item(item) = stmt {
+                self.interpret_item(item, &ctx)?;
+            }
+        }
         for stmt in node.first_stmts() {
             self.interpret_stmt(&stmt, &ctx)?;
         }
@@ -115,9 +469,7 @@ impl InterpreterPass {
                         continue;
                     }
                 }
-                _ => {
-                    bail!("Failed to interpret {:?} => {:?}", case.cond, interpret)
-                }
+                _ => bail!(expected_bool(&case.cond, &interpret)),
             }
         }
         Ok(AstValue::unit())
@@ -142,9 +494,9 @@ impl InterpreterPass {
     ) -> Result<AstValue> {
         match ident.as_str() {
             // TODO: can we remove these?
-            "+" if resolve => Ok(AstValue::any(builtin_add())),
-            "-" if resolve => Ok(AstValue::any(builtin_sub())),
-            "*" if resolve => Ok(AstValue::any(builtin_mul())),
+            "+" if resolve => Ok(AstValue::any(builtin_add(self.overflow))),
+            "-" if resolve => Ok(AstValue::any(builtin_sub(self.overflow))),
+            "*" if resolve => Ok(AstValue::any(builtin_mul(self.overflow))),
             ">" if resolve => Ok(AstValue::any(builtin_gt())),
             ">=" if resolve => Ok(AstValue::any(builtin_ge())),
             "==" if resolve => Ok(AstValue::any(builtin_eq())),
@@ -161,52 +513,90 @@ impl InterpreterPass {
             _ => {
                 info!("Get value recursive {:?}", ident);
                 ctx.print_values()?;
-                ctx.get_value_recursive(ident)
-                    .with_context(|| format!("could not find {:?} in context", ident.name))
+                if let Some(value) = ctx.get_value_recursive(ident) {
+                    return Ok(match value {
+                        // a unit struct is its only value
+                        AstValue::Type(AstType::Struct(ty)) if ty.kind == StructKind::Unit => {
+                            AstValue::Struct(ValueStruct::new(ty, vec![]))
+                        }
+                        value => value,
+                    });
+                }
+                // types are values in comptime blocks, like `bool` in `T.a = bool`
+                match TypePrimitive::from_name(ident.as_str()) {
+                    Some(ty) => Ok(AstValue::Type(AstType::Primitive(ty))),
+                    None => Err(Diagnostic::unresolved(ident).into()),
+                }
             }
         }
     }
+    /// `-`, `|` and `&` of types are type arithmetic, otherwise they are `builtin`
+    fn type_op_or(&self, op: BinOpKind, builtin: BuiltinFn) -> BuiltinFn {
+        let this = self.clone();
+        BuiltinFn::new(op, move |args, ctx| {
+            if !args.iter().any(|x| matches!(x, AstValue::Type(_))) {
+                return builtin.invoke(args, ctx);
+            }
+            let types = this.interpret_type_args(args, ctx)?;
+            let ty = match op {
+                BinOpKind::Sub => this.subtract_types(&types)?,
+                BinOpKind::BitOr => this.union_types(&types)?,
+                BinOpKind::BitAnd => this.intersect_types(&types)?,
+                _ => unreachable!("{} is not a type operator", op),
+            };
+            Ok(AstValue::Type(ty))
+        })
+    }
+    fn interpret_type_args(
+        &self,
+        args: &[AstValue],
+        ctx: &SharedScopedContext,
+    ) -> Result<Vec<AstType>> {
+        args.iter()
+            .map(|x| {
+                // trait bounds are compared by path, as they are written
+                if let AstValue::Type(ty @ AstType::ImplTraits(_)) = x {
+                    return Ok(ty.clone());
+                }
+                let value = self.interpret_value(x, ctx, true)?;
+                match value {
+                    AstValue::Type(ty) => Ok(ty),
+                    _ => bail!(Diagnostic::new(
+                        ErrorCode::TypeMismatch,
+                        format!("Expected type, found `{}`", value)
+                    )
+                    .with_label("expected a type argument")),
+                }
+            })
+            .try_collect()
+    }
     pub fn lookup_bin_op_kind(&self, op: BinOpKind) -> Result<BuiltinFn> {
         match op {
-            BinOpKind::Add => Ok(builtin_add()),
+            BinOpKind::Add => Ok(builtin_add(self.overflow)),
             BinOpKind::AddTrait => {
                 let this = self.clone();
                 Ok(BuiltinFn::new(op, move |args, value| {
-                    let args: Vec<_> = args
-                        .into_iter()
-                        .map(|x| {
-                            let value = this.interpret_value(x, value, true)?;
-                            match value {
-                                AstValue::Type(AstType::ImplTraits(impls)) => Ok(impls.bounds),
-                                _ => bail!("Expected impl Traits, got {:?}", value),
-                            }
-                        })
-                        .try_collect()?;
-                    Ok(AstType::ImplTraits(ImplTraits {
-                        bounds: TypeBounds {
-                            bounds: args.into_iter().flat_map(|x| x.bounds).collect(),
-                        },
-                    })
-                    .into())
+                    let types = this.interpret_type_args(args, value)?;
+                    this.add_types(&types).map(AstValue::Type)
                 }))
             }
-            BinOpKind::Sub => Ok(builtin_sub()),
-            BinOpKind::Mul => Ok(builtin_mul()),
-            // BinOpKind::Div => Ok(builtin_div()),
-            // BinOpKind::Mod => Ok(builtin_mod()),
+            BinOpKind::Sub => Ok(self.type_op_or(op, builtin_sub(self.overflow))),
+            BinOpKind::Mul => Ok(builtin_mul(self.overflow)),
+            BinOpKind::Div => Ok(builtin_div(self.overflow)),
+            BinOpKind::Mod => Ok(builtin_mod(self.overflow)),
             BinOpKind::Gt => Ok(builtin_gt()),
             BinOpKind::Lt => Ok(builtin_lt()),
             BinOpKind::Ge => Ok(builtin_ge()),
             BinOpKind::Le => Ok(builtin_le()),
             BinOpKind::Eq => Ok(builtin_eq()),
             BinOpKind::Ne => Ok(builtin_ne()),
-            // BinOpKind::LogicalOr => {}
-            // BinOpKind::LogicalAnd => {}
-            // BinOpKind::BitOr => {}
-            // BinOpKind::BitAnd => {}
-            // BinOpKind::BitXor => {}
-            // BinOpKind::Any(_) => {}
-            _ => bail!("Could not process {:?}", op),
+            BinOpKind::Or => Ok(builtin_or()),
+            BinOpKind::And => Ok(builtin_and()),
+            BinOpKind::BitOr => Ok(self.type_op_or(op, builtin_bit_or())),
+            BinOpKind::BitAnd => Ok(self.type_op_or(op, builtin_bit_and())),
+            BinOpKind::BitXor => Ok(builtin_bit_xor()),
+            BinOpKind::Shl => Ok(builtin_shl(self.overflow)),
+            BinOpKind::Shr => Ok(builtin_shr(self.overflow)),
         }
     }
 
@@ -232,6 +622,12 @@ impl InterpreterPass {
         Ok(())
     }
     pub fn interpret_def_type(&self, def: &ItemDefType, ctx: &SharedScopedContext) -> Result<()> {
+        if !def.generics_params.is_empty() {
+            // instantiated on use, see `interpret_parameter_path`
+            let func = def.to_type_function();
+            ctx.insert_value_with_ctx(def.name.clone(), AstValue::Function(func));
+            return Ok(());
+        }
         ctx.insert_value_with_ctx(def.name.clone(), AstValue::Type(def.value.clone()));
         Ok(())
     }
@@ -256,11 +652,32 @@ impl InterpreterPass {
         node: &ExprStruct,
         ctx: &SharedScopedContext,
     ) -> Result<ValueStruct> {
-        let value: AstValue = self.interpret_expr(&node.name.get(), ctx)?.try_conv()?;
-        let ty: AstType = value.try_conv()?;
+        let ty = self.interpret_type_expr(&node.name.get(), ctx)?;
         let struct_ = ty.try_conv()?;
-        let fields: Vec<_> = node
-            .fields
+        let fields = self.interpret_field_values(node, ctx)?;
+        Ok(ValueStruct {
+            ty: struct_,
+            structural: ValueStructural { fields },
+        })
+    }
+    /// `Enum::Variant { a: 1 }` of a struct variant
+    fn interpret_struct_variant(
+        &self,
+        node: &ExprStruct,
+        ty: TypeEnum,
+        variant: EnumTypeVariant,
+        ctx: &SharedScopedContext,
+    ) -> Result<ValueVariant> {
+        let fields = self.interpret_field_values(node, ctx)?;
+        let value = AstValue::Structural(ValueStructural::new(fields));
+        Ok(ValueVariant::new(ty, variant.name, value))
+    }
+    fn interpret_field_values(
+        &self,
+        node: &ExprStruct,
+        ctx: &SharedScopedContext,
+    ) -> Result<Vec<ValueField>> {
+        node.fields
             .iter()
             .map(|x| {
                 Ok::<_, Error>(ValueField {
@@ -272,11 +689,7 @@ impl InterpreterPass {
                     },
                 })
             })
-            .try_collect()?;
-        Ok(ValueStruct {
-            ty: struct_,
-            structural: ValueStructural { fields },
-        })
+            .try_collect()
     }
     pub fn interpret_struct_value(
         &self,
@@ -301,20 +714,148 @@ impl InterpreterPass {
     }
     pub fn interpret_select(&self, s: &ExprSelect, ctx: &SharedScopedContext) -> Result<AstValue> {
         let obj0 = self.interpret_expr(&s.obj.get(), ctx)?;
-        let obj = obj0.as_structural().with_context(|| {
-            format!(
-                "Expected structural type, got {}",
-                self.serializer.serialize_value(&obj0).unwrap()
+        let obj = obj0
+            .as_structural()
+            .ok_or_else(|| expected_structural(&s.obj.get(), &obj0))?;
+        let value = obj
+            .get_field(&s.field)
+            .ok_or_else(|| missing_field(&AstExpr::Select(s.clone()), &s.field))?;
+        Ok(value.value.clone())
+    }
+    pub fn interpret_array(
+        &self,
+        node: &ExprArray,
+        ctx: &SharedScopedContext,
+    ) -> Result<ValueList> {
+        let values: Vec<_> = node
+            .values
+            .iter()
+            .map(|x| self.interpret_expr(x, ctx))
+            .try_collect()?;
+        Ok(ValueList { values })
+    }
+    pub fn interpret_expr_tuple(
+        &self,
+        node: &ExprTuple,
+        ctx: &SharedScopedContext,
+    ) -> Result<ValueTuple> {
+        let values: Vec<_> = node
+            .values
+            .iter()
+            .map(|x| self.interpret_expr(x, ctx))
+            .try_collect()?;
+        Ok(ValueTuple { values })
+    }
+    /// `[value; len]`, the value evaluated once and cloned
+    pub fn interpret_array_repeat(
+        &self,
+        node: &ExprArrayRepeat,
+        ctx: &SharedScopedContext,
+    ) -> Result<ValueList> {
+        let value = self.interpret_expr(&node.value, ctx)?;
+        let len = self.interpret_index_key(&node.len, ctx)?;
+        Ok(ValueList {
+            values: vec![value; len],
+        })
+    }
+    fn interpret_index_key(&self, index: &AstExpr, ctx: &SharedScopedContext) -> Result<usize> {
+        match self.interpret_expr(index, ctx)? {
+            AstValue::Int(i) if i.value >= 0 => Ok(i.value as usize),
+            value => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Invalid index {}", index.describe())
             )
-        })?;
-        let value = obj.get_field(&s.field).with_context(|| {
-            format!(
-                "Could not find field {} in {}",
-                s.field,
-                self.serializer.serialize_value(&obj0).unwrap()
+            .with_node(index)
+            .with_label(format!("expected non-negative integer, found `{}`", value))),
+        }
+    }
+    pub fn interpret_index(&self, node: &ExprIndex, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let obj = self.interpret_expr(&node.obj, ctx)?;
+        if let AstValue::Type(ty) = &obj {
+            let ty = self.evaluate_type_value(ty, ctx)?;
+            let index = self.interpret_expr(&node.index, ctx)?;
+            return self.index_type(&ty, &index).map(AstValue::Type);
+        }
+        let index = self.interpret_index_key(&node.index, ctx)?;
+        let values = match &obj {
+            AstValue::List(list) => &list.values,
+            AstValue::Tuple(tuple) => &tuple.values,
+            _ => bail!(expected_indexable(&node.obj, &obj)),
+        };
+        let Some(value) = values.get(index) else {
+            bail!(out_of_bounds(
+                &AstExpr::Index(node.clone()),
+                index,
+                values.len()
+            ))
+        };
+        Ok(value.clone())
+    }
+    pub fn interpret_assign(
+        &self,
+        node: &ExprAssign,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstValue> {
+        let value = self.interpret_expr(&node.value, ctx)?;
+        self.assign_value(&node.target, value, ctx)?;
+        Ok(AstValue::unit())
+    }
+    /// Writes `value` to the place `target` refers to, through its value slot
+    pub fn assign_value(
+        &self,
+        target: &AstExpr,
+        value: AstValue,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        match target {
+            AstExpr::Locator(locator) => {
+                let slot = ctx
+                    .get_storage(locator.to_path(), true)
+                    .ok_or_else(|| Diagnostic::unresolved(locator).with_node(target))?;
+                slot.set_value(value);
+            }
+            AstExpr::Index(index) => {
+                let mut obj = self.interpret_expr(&index.obj, ctx)?;
+                let i = self.interpret_index_key(&index.index, ctx)?;
+                let values = match &mut obj {
+                    AstValue::List(list) => &mut list.values,
+                    AstValue::Tuple(tuple) => &mut tuple.values,
+                    _ => bail!(expected_indexable(&index.obj, &obj)),
+                };
+                let len = values.len();
+                *values
+                    .get_mut(i)
+                    .ok_or_else(|| out_of_bounds(target, i, len))? = value;
+                self.assign_value(&index.obj, obj, ctx)?;
+            }
+            AstExpr::Select(select) => {
+                let mut obj = self.interpret_expr(&select.obj, ctx)?;
+                if let AstValue::Type(ty) = &mut obj {
+                    self.assign_type_field(ty, &select.field, value)?;
+                    return self.assign_value(&select.obj, obj, ctx);
+                }
+                let fields = match &mut obj {
+                    AstValue::Struct(s) => &mut s.structural.fields,
+                    AstValue::Structural(s) => &mut s.fields,
+                    _ => bail!(expected_structural(&select.obj, &obj)),
+                };
+                let field = fields
+                    .iter_mut()
+                    .find(|x| x.name == select.field)
+                    .ok_or_else(|| missing_field(target, &select.field))?;
+                field.value = value;
+                self.assign_value(&select.obj, obj, ctx)?;
+            }
+            AstExpr::Paren(paren) => self.assign_value(&paren.expr, value, ctx)?,
+            _ => bail!(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                format!("Cannot assign to {}", target.describe())
             )
-        })?;
-        Ok(value.value.clone())
+            .with_node(target)
+            .with_label("cannot be assigned to")
+            .with_help("only variables, indices, fields and their parentheses can be assigned")),
+        }
+        Ok(())
     }
     pub fn interpret_tuple(
         &self,
@@ -368,7 +909,9 @@ impl InterpreterPass {
             name: node.sig.name.clone(),
             receiver: None,
             params,
+            lifetimes: node.lifetimes.clone(),
             generics_params: node.generics_params.clone(),
+            where_predicates: node.where_predicates.clone(),
             ret_ty: if let Some(ret_ty) = &node.sig.ret_ty {
                 Some(self.interpret_type(ret_ty, &sub)?)
             } else {
@@ -377,6 +920,7 @@ impl InterpreterPass {
         };
 
         Ok(ValueFunction {
+            attrs: node.attrs.clone(),
             sig,
             body: node.body.clone(),
         })
@@ -424,6 +968,10 @@ impl InterpreterPass {
         binop: &ExprBinOp,
         ctx: &SharedScopedContext,
     ) -> Result<AstValue> {
+        if let BinOpKind::And | BinOpKind::Or = binop.kind {
+            let args = [binop.lhs.get(), binop.rhs.get()];
+            return self.interpret_logical(binop.kind, &args, ctx);
+        }
         let builtin_fn = self.lookup_bin_op_kind(binop.kind.clone())?;
         let lhs = self.interpret_expr(&binop.lhs.get(), ctx)?;
         let rhs = self.interpret_expr(&binop.rhs.get(), ctx)?;
@@ -435,6 +983,10 @@ impl InterpreterPass {
         args: &[AstExpr],
         ctx: &SharedScopedContext,
     ) -> Result<AstValue> {
+        if let BinOpKind::And | BinOpKind::Or = op {
+            let args: Vec<_> = args.iter().map(|x| x.get()).collect();
+            return self.interpret_logical(op, &args, ctx);
+        }
         let builtin_fn = self.lookup_bin_op_kind(op)?;
         let args = self.interpret_args(args, ctx)?;
         builtin_fn.invoke(&args, ctx)
@@ -447,7 +999,8 @@ impl InterpreterPass {
     ) -> Result<AstValue> {
         match op {
             UnOpKind::Neg => match arg {
-                AstValue::Int(val) => Ok(AstValue::Int(ValueInt::new(-val.value))),
+                AstValue::Int(val) => Ok(AstValue::Int(val.neg(self.overflow)?)),
+                AstValue::BigInt(val) => Ok(AstValue::BigInt(val.neg())),
                 AstValue::Decimal(val) => Ok(AstValue::Decimal(ValueDecimal::new(-val.value))),
                 _ => bail!("Failed to interpret {:?}", op),
             },
@@ -464,21 +1017,57 @@ impl InterpreterPass {
         ctx: &SharedScopedContext,
         resolve: bool,
     ) -> Result<AstValue> {
-        match node {
+        let result = match node {
             AstExpr::Locator(Locator::Ident(n)) => self.interpret_ident(n, ctx, resolve),
-            AstExpr::Locator(n) => ctx
-                .get_value_recursive(n.to_path())
-                .with_context(|| format!("could not find {:?} in context", n)),
+            AstExpr::Locator(Locator::ParameterPath(n)) => {
+                self.interpret_parameter_path(n, ctx).map(AstValue::Type)
+            }
+            AstExpr::Locator(n) => match ctx.get_value_recursive(n.to_path()) {
+                Some(value) => Ok(value),
+                None => self.interpret_assoc_path(&n.to_path(), ctx),
+            },
             AstExpr::Value(n) => self.interpret_value(n, ctx, resolve),
             AstExpr::Block(n) => self.interpret_block(n, ctx),
-            AstExpr::Match(c) => self.interpret_cond(c, ctx),
+            AstExpr::Match(c) => self.interpret_match(c, ctx),
             AstExpr::Invoke(invoke) => self.interpret_invoke(invoke, ctx),
             AstExpr::BinOp(op) => self.interpret_binop(op, ctx),
             AstExpr::Any(n) => Ok(AstValue::Any(n.clone())),
             AstExpr::Select(s) => self.interpret_select(s, ctx),
-            AstExpr::Struct(s) => self.interpret_struct_expr(s, ctx).map(AstValue::Struct),
-            _ => bail!("Failed to interpret {:?}", node),
-        }
+            AstExpr::Struct(s) => match self.lookup_variant_expr(&s.name, ctx) {
+                Some((ty, variant)) => self
+                    .interpret_struct_variant(s, ty, variant, ctx)
+                    .map(AstValue::Variant),
+                None => self.interpret_struct_expr(s, ctx).map(AstValue::Struct),
+            },
+            AstExpr::If(n) => self.interpret_if(n, ctx),
+            AstExpr::Loop(n) => self.interpret_loop(n, ctx),
+            AstExpr::While(n) => self.interpret_while(n, ctx),
+            AstExpr::For(n) => self.interpret_for(n, ctx),
+            AstExpr::Break(n) => self.interpret_break(n, ctx),
+            AstExpr::Continue(n) => self.interpret_continue(n, ctx),
+            AstExpr::Return(n) => self.interpret_return(n, ctx),
+            AstExpr::Assign(n) => self.interpret_assign(n, ctx),
+            AstExpr::Index(n) => self.interpret_index(n, ctx),
+            AstExpr::Array(n) => self.interpret_array(n, ctx).map(AstValue::List),
+            AstExpr::ArrayRepeat(n) => self.interpret_array_repeat(n, ctx).map(AstValue::List),
+            AstExpr::Tuple(n) => self.interpret_expr_tuple(n, ctx).map(AstValue::Tuple),
+            AstExpr::Paren(n) => self.interpret_expr(&n.expr, ctx),
+            // values have no address, `&x` and `*x` are `x` itself
+            AstExpr::Reference(n) => self.interpret_expr(&n.referee, ctx),
+            AstExpr::Dereference(n) => self.interpret_expr(&n.referee, ctx),
+            AstExpr::Const(n) => self.interpret_expr(&n.block, ctx),
+            AstExpr::Unsafe(n) => self.interpret_expr(&n.block, ctx),
+            AstExpr::Cast(n) => {
+                let value = self.interpret_expr(&n.expr, ctx)?;
+                cast_value(&value, &n.ty)
+            }
+            AstExpr::UnOp(op) => {
+                let arg = self.interpret_expr(&op.val, ctx)?;
+                self.interpret_invoke_unop(op.op.clone(), arg, ctx)
+            }
+            _ => bail!(unsupported_node(node)),
+        };
+        result.at_node(node)
     }
     pub fn interpret_expr(&self, node: &AstExpr, ctx: &SharedScopedContext) -> Result<AstValue> {
         self.interpret_expr_common(node, ctx, true)
@@ -502,27 +1091,38 @@ impl InterpreterPass {
             AstItem::DefType(n) => self.interpret_def_type(n, ctx).map(|_| AstValue::unit()),
             AstItem::DefConst(n) => self.interpret_def_const(n, ctx).map(|_| AstValue::unit()),
             AstItem::Import(n) => self.interpret_import(n, ctx).map(|_| AstValue::unit()),
+            AstItem::DefTrait(n) => self.interpret_def_trait(n, ctx).map(|_| AstValue::unit()),
+            AstItem::Impl(n) => self.interpret_impl(n, ctx).map(|_| AstValue::unit()),
 
             AstItem::Any(n) => Ok(AstValue::Any(n.clone())),
-            _ => bail!("Failed to interpret {:?}", node),
+            _ => bail!(unsupported_node(node)),
         }
     }
 
     pub fn interpret_let(&self, node: &StmtLet, ctx: &SharedScopedContext) -> Result<AstValue> {
-        if let Some(init) = &node.init {
-            let value = self.interpret_expr(&init, ctx)?;
-            ctx.insert_value(
-                node.pat.as_ident().context("Only supports ident")?.as_str(),
-                value.clone(),
-            );
-            Ok(value)
-        } else {
+        let Some(init) = &node.init else {
             ctx.insert_value(
                 node.pat.as_ident().context("Only supports ident")?.as_str(),
                 AstValue::undefined(),
             );
-            Ok(AstValue::unit())
+            return Ok(AstValue::unit());
+        };
+        let value = self.interpret_expr(&init, ctx)?;
+        let Some(diverge) = &node.diverge else {
+            self.interpret_irrefutable(&node.pat, &value, ctx)?;
+            return Ok(value);
+        };
+        if !self.interpret_pattern(&node.pat, &value, ctx)? {
+            // the `else` block leaves through `return`, `break` or `continue`
+            self.interpret_expr(diverge, ctx)?;
+            bail!(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                "`else` block of `let...else` did not diverge"
+            )
+            .with_node(diverge)
+            .with_label("expected to leave through `return`, `break` or `continue`"));
         }
+        Ok(value)
     }
 
     pub fn interpret_stmt(
@@ -545,6 +1145,7 @@ impl InterpreterPass {
                 )
             }
             BlockStmt::Item(_) => Ok(None),
+            BlockStmt::Noop => Ok(None),
             _ => bail!("Failed to interpret {:?}", node),
         }
     }
@@ -562,6 +1163,9 @@ impl OptimizePass for InterpreterPass {
     fn name(&self) -> &str {
         "interpreter"
     }
+    fn interpreter(&self) -> Option<&InterpreterPass> {
+        Some(self)
+    }
     fn optimize_expr(&self, expr: AstExpr, ctx: &SharedScopedContext) -> Result<AstExpr> {
         let value = self.interpret_expr_no_resolve(&expr, ctx)?;
         Ok(AstExpr::value(value))
@@ -581,7 +1185,7 @@ impl OptimizePass for InterpreterPass {
                     Ok(ControlFlow::Continue)
                 }
             }
-            _ => bail!("Failed to interpret {:?} => {:?}", expr, value),
+            _ => bail!(expected_bool(&expr, &value)),
         }
     }
     fn evaluate_invoke(
@@ -599,7 +1203,7 @@ impl OptimizePass for InterpreterPass {
     ) -> Result<AstExpr> {
         match func {
             AstValue::Function(func) => self
-                .interpret_expr(&func.body.get(), ctx)
+                .interpret_body(&func.body.get(), ctx)
                 .map(AstExpr::value),
             AstValue::BinOpKind(kind) => self
                 .interpret_invoke_binop(kind.clone(), &invoke.args, ctx)
@@ -610,21 +1214,14 @@ impl OptimizePass for InterpreterPass {
                 self.interpret_invoke_unop(func.clone(), arg, ctx)
                     .map(AstExpr::value)
             }
-            _ => bail!("Could not invoke {:?}", func),
+            _ => bail!(not_invokable(&invoke)),
         }
     }
 
     fn try_evaluate_expr(&self, pat: &AstExpr, ctx: &SharedScopedContext) -> Result<AstExpr> {
-        let value = ctx.try_get_value_from_expr(pat).with_context(|| {
-            format!(
-                "could not find {:?} in context {:?}",
-                pat,
-                ctx.list_values()
-                    .into_iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<_>>()
-            )
-        })?;
+        let value = ctx
+            .try_get_value_from_expr(pat)
+            .ok_or_else(|| Diagnostic::unresolved(pat.describe()).with_node(pat))?;
         Ok(AstExpr::value(value))
     }
 }
@@ -635,7 +1232,7 @@ impl ValueSystem for InterpreterPass {
         let expr = fold.optimize_expr(expr.clone(), &ctx.values)?;
         match expr {
             AstExpr::Value(value) => Ok(*value),
-            _ => bail!("Expected value, got {:?}", expr),
+            _ => bail!(unsupported_node(&expr)),
         }
     }
 }
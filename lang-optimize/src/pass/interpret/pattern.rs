@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+
+use common::*;
+
+use lang_core::ast::{
+    AstExpr, AstValue, ExprRangeLimit, StructKind, ValueList, ValueOption, ValueStructural,
+    ValueVariant,
+};
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::Path;
+use lang_core::ops::compare_literals;
+use lang_core::pat::{Pattern, PatternRange, PatternStructField, PatternVariant};
+
+use crate::pass::interpret::{coerce_untyped_int, unsupported_node};
+use crate::pass::InterpreterPass;
+
+/// The value inside `Some`, `None` for any other value
+fn as_some(value: &AstValue) -> Option<&AstValue> {
+    match value {
+        AstValue::Some(some) => Some(&*some.value),
+        AstValue::Option(ValueOption { value: Some(value) }) => Some(&**value),
+        _ => None,
+    }
+}
+fn is_none(value: &AstValue) -> bool {
+    matches!(
+        value,
+        AstValue::None(_) | AstValue::Option(ValueOption { value: None })
+    )
+}
+/// Whether a path like `Shape::Circle` or `Self::Circle` names the variant of `value`
+fn names_variant(path: &Path, value: &ValueVariant) -> bool {
+    match path.segments.as_slice() {
+        [name] => name == &value.name,
+        [.., ty, name] => name == &value.name && (ty == &value.ty.name || ty.as_str() == "Self"),
+        [] => false,
+    }
+}
+/// The `..` in a tuple or slice pattern, alone or bound like `rest @ ..`
+fn is_rest(pat: &Pattern) -> bool {
+    match pat {
+        Pattern::Rest(_) => true,
+        Pattern::Ident(ident) => ident
+            .subpattern
+            .as_deref()
+            .is_some_and(|x| matches!(x, Pattern::Rest(_))),
+        _ => false,
+    }
+}
+
+impl InterpreterPass {
+    /// Whether `value` matches `pat`, binding the names in the pattern in `ctx`
+    pub fn interpret_pattern(
+        &self,
+        pat: &Pattern,
+        value: &AstValue,
+        ctx: &SharedScopedContext,
+    ) -> Result<bool> {
+        match pat {
+            // `None` names the variant rather than a new binding
+            Pattern::Ident(ident)
+                if ident.ident.as_str() == "None" && ident.subpattern.is_none() =>
+            {
+                Ok(is_none(value))
+            }
+            Pattern::Ident(ident) => {
+                if let Some(pat) = &ident.subpattern {
+                    if !self.interpret_pattern(pat, value, ctx)? {
+                        return Ok(false);
+                    }
+                }
+                // values are copied, so `ref` binds the same way
+                ctx.insert_value(ident.ident.clone(), value.clone());
+                Ok(true)
+            }
+            Pattern::Wildcard(_) => Ok(true),
+            Pattern::Type(pat) => {
+                let value = coerce_untyped_int(value, &pat.ty)?;
+                self.interpret_pattern(&pat.pat, &value, ctx)
+            }
+            Pattern::Or(or) => {
+                for pat in &or.patterns {
+                    if self.interpret_pattern(pat, value, ctx)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Pattern::Lit(lit) => Ok(compare_literals(value, &lit.value)? == Some(Ordering::Equal)),
+            Pattern::Range(range) => self.interpret_range_pattern(range, value, ctx),
+            Pattern::Tuple(pat) => match value {
+                AstValue::Tuple(tuple) => {
+                    self.interpret_elements(&pat.patterns, &tuple.values, ctx)
+                }
+                _ => Ok(false),
+            },
+            Pattern::Slice(pat) => match value {
+                AstValue::List(list) => self.interpret_elements(&pat.patterns, &list.values, ctx),
+                _ => Ok(false),
+            },
+            Pattern::TupleStruct(pat) if pat.name.to_string() == "Some" => {
+                let [pat] = pat.patterns.as_slice() else {
+                    bail!("Some takes exactly one pattern")
+                };
+                match as_some(value) {
+                    Some(value) => self.interpret_pattern(pat, value, ctx),
+                    None => Ok(false),
+                }
+            }
+            Pattern::TupleStruct(pat) => {
+                let path = pat.name.to_path();
+                match value {
+                    AstValue::Struct(value)
+                        if value.ty.kind == StructKind::Tuple && path.last() == &value.ty.name =>
+                    {
+                        let values: Vec<_> = value
+                            .structural
+                            .fields
+                            .iter()
+                            .map(|x| x.value.clone())
+                            .collect();
+                        self.interpret_elements(&pat.patterns, &values, ctx)
+                    }
+                    AstValue::Variant(value) if names_variant(&path, value) => {
+                        match &*value.value {
+                            AstValue::Tuple(tuple) => {
+                                self.interpret_elements(&pat.patterns, &tuple.values, ctx)
+                            }
+                            _ => Ok(false),
+                        }
+                    }
+                    _ => Ok(false),
+                }
+            }
+            Pattern::Struct(pat) => {
+                let path = pat.name.to_path();
+                match value {
+                    AstValue::Struct(value) if path.last() == &value.ty.name => {
+                        self.interpret_fields(&pat.fields, &value.structural, ctx)
+                    }
+                    AstValue::Variant(value) if names_variant(&path, value) => {
+                        match &*value.value {
+                            AstValue::Structural(fields) => {
+                                self.interpret_fields(&pat.fields, fields, ctx)
+                            }
+                            _ => Ok(false),
+                        }
+                    }
+                    _ => Ok(false),
+                }
+            }
+            Pattern::Variant(pat) => self.interpret_variant_pattern(pat, value, ctx),
+            Pattern::Structural(pat) => match value {
+                AstValue::Structural(value) => self.interpret_fields(&pat.fields, value, ctx),
+                _ => Ok(false),
+            },
+            _ => bail!(unsupported_node(pat)),
+        }
+    }
+    /// Binds `pat` to `value` where the pattern must match, as in `let` and `for`
+    pub fn interpret_irrefutable(
+        &self,
+        pat: &Pattern,
+        value: &AstValue,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        if !self.interpret_pattern(pat, value, ctx)? {
+            bail!(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                format!("Pattern does not match {}", value)
+            )
+            .with_node(pat)
+            .with_label(format!("`{}` is not covered", value)))
+        }
+        Ok(())
+    }
+    /// The elements of a tuple or slice, where a `..` takes those the other patterns leave out
+    fn interpret_elements(
+        &self,
+        patterns: &[Pattern],
+        values: &[AstValue],
+        ctx: &SharedScopedContext,
+    ) -> Result<bool> {
+        let Some(rest) = patterns.iter().position(is_rest) else {
+            if patterns.len() != values.len() {
+                return Ok(false);
+            }
+            for (pat, value) in patterns.iter().zip(values) {
+                if !self.interpret_pattern(pat, value, ctx)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        };
+        let after = patterns.len() - rest - 1;
+        if values.len() < rest + after {
+            return Ok(false);
+        }
+        let (prefix, remaining) = values.split_at(rest);
+        let (middle, suffix) = remaining.split_at(remaining.len() - after);
+        for (pat, value) in patterns[..rest].iter().zip(prefix) {
+            if !self.interpret_pattern(pat, value, ctx)? {
+                return Ok(false);
+            }
+        }
+        for (pat, value) in patterns[rest + 1..].iter().zip(suffix) {
+            if !self.interpret_pattern(pat, value, ctx)? {
+                return Ok(false);
+            }
+        }
+        if let Pattern::Ident(ident) = &patterns[rest] {
+            let middle = AstValue::List(ValueList::new(middle.to_vec()));
+            ctx.insert_value(ident.ident.clone(), middle);
+        }
+        Ok(true)
+    }
+    fn interpret_fields(
+        &self,
+        fields: &[PatternStructField],
+        value: &ValueStructural,
+        ctx: &SharedScopedContext,
+    ) -> Result<bool> {
+        for field in fields {
+            let found = value
+                .get_field(&field.name)
+                .with_context(|| format!("No field {} in {}", field.name, value))?;
+            match &field.rename {
+                Some(pat) => {
+                    if !self.interpret_pattern(pat, &found.value, ctx)? {
+                        return Ok(false);
+                    }
+                }
+                None => ctx.insert_value(field.name.clone(), found.value.clone()),
+            }
+        }
+        Ok(true)
+    }
+    /// A path like `State::Idle`, matching a unit variant or the value of a constant
+    fn interpret_variant_pattern(
+        &self,
+        pat: &PatternVariant,
+        value: &AstValue,
+        ctx: &SharedScopedContext,
+    ) -> Result<bool> {
+        let (AstExpr::Locator(locator), None) = (&pat.name, &pat.pattern) else {
+            bail!(unsupported_node(&Pattern::Variant(pat.clone())))
+        };
+        if let AstValue::Variant(value) = value {
+            return Ok(names_variant(&locator.to_path(), value) && value.value.is_unit());
+        }
+        let expected = self.interpret_expr(&AstExpr::Locator(locator.clone()), ctx)?;
+        Ok(&expected == value)
+    }
+    fn interpret_range_pattern(
+        &self,
+        range: &PatternRange,
+        value: &AstValue,
+        ctx: &SharedScopedContext,
+    ) -> Result<bool> {
+        if let Some(start) = &range.start {
+            let start = self.interpret_expr(start, ctx)?;
+            if !compare_literals(value, &start)?.is_some_and(Ordering::is_ge) {
+                return Ok(false);
+            }
+        }
+        if let Some(end) = &range.end {
+            let end = self.interpret_expr(end, ctx)?;
+            let ordering = compare_literals(value, &end)?;
+            let below = match range.limit {
+                ExprRangeLimit::Inclusive => ordering.is_some_and(Ordering::is_le),
+                ExprRangeLimit::Exclusive => ordering.is_some_and(Ordering::is_lt),
+            };
+            if !below {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
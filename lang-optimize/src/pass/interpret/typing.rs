@@ -1,78 +1,51 @@
-use common::{bail, ensure, ContextCompat, Error, Result};
+use common::{bail, ContextCompat, Error, Result};
 use itertools::Itertools;
 
 use lang_core::ast::{AstExpr, Visibility};
 use lang_core::ast::{
-    AstType, AstValue, DecimalType, ExprInvokeTarget, ImplTraits, StructuralField, TypeBounds,
-    TypeFunction, TypeInt, TypePrimitive, TypeStruct, TypeStructural, TypeType, ValueFunction,
+    AstType, AstValue, EnumTypeVariant, ImplTraits, StructKind, StructuralField, TypeBounds,
+    TypeEnum, TypeFunction, TypeInt, TypePrimitive, TypeStruct, TypeStructural, ValueFunction,
 };
 use lang_core::context::SharedScopedContext;
 use lang_core::ctx::{Context, TypeSystem};
-use lang_core::id::{Ident, Locator};
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator, ParameterPath, ParameterPathSegment};
+use lang_core::ops::BinOpKind;
+use lang_core::span::SourceNode;
 use lang_core::utils::conv::TryConv;
 
-use crate::pass::{FoldOptimizer, InterpreterPass};
+use crate::pass::{type_name, FoldOptimizer, InterpreterPass};
+use std::sync::atomic::Ordering;
 
 impl InterpreterPass {
     pub fn type_check_value(&self, lit: &AstValue, ty: &AstType) -> Result<()> {
-        match lit {
-            AstValue::Int(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::Int(_))),
-                    "Expected i64, got {:?}",
-                    lit
-                )
-            }
-            AstValue::Bool(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::Bool)),
-                    "Expected bool, got {:?}",
-                    lit
-                )
-            }
-            AstValue::Decimal(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::Decimal(_))),
-                    "Expected f64, got {:?}",
-                    lit
-                )
+        let matches = match (lit, ty) {
+            // literal types, like `1`, only contain their own value
+            (_, AstType::Value(expected)) => lit == expected.value.as_ref(),
+            (AstValue::Int(i), AstType::Primitive(TypePrimitive::Int(int))) => {
+                i.ty.is_none_or(|x| x == *int) && int.contains(i.value)
             }
-            AstValue::Char(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::Char)),
-                    "Expected char, got {:?}",
-                    lit
-                )
+            (AstValue::Int(_), _) => false,
+            (AstValue::BigInt(_), _) => {
+                matches!(ty, AstType::Primitive(TypePrimitive::Int(TypeInt::BigInt)))
             }
-            AstValue::String(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::String)),
-                    "Expected string, got {:?}",
-                    lit
-                )
+            (AstValue::Bool(_), _) => matches!(ty, AstType::Primitive(TypePrimitive::Bool)),
+            (AstValue::Decimal(_), _) => {
+                matches!(ty, AstType::Primitive(TypePrimitive::Decimal(_)))
             }
-            AstValue::List(_) => {
-                ensure!(
-                    matches!(ty, AstType::Primitive(TypePrimitive::List)),
-                    "Expected list, got {:?}",
-                    lit
-                )
-            }
-            AstValue::Unit(_) => {
-                ensure!(
-                    matches!(ty, AstType::Unit(_)),
-                    "Expected unit, got {:?}",
-                    lit
-                )
-            }
-            AstValue::Type(_) => {
-                ensure!(
-                    matches!(ty, AstType::Type(_)),
-                    "Expected type, got {:?}",
-                    lit
-                )
-            }
-            _ => {}
+            (AstValue::Char(_), _) => matches!(ty, AstType::Primitive(TypePrimitive::Char)),
+            (AstValue::String(_), _) => matches!(ty, AstType::Primitive(TypePrimitive::String)),
+            (AstValue::List(_), _) => matches!(ty, AstType::Primitive(TypePrimitive::List)),
+            (AstValue::Unit(_), _) => matches!(ty, AstType::Unit(_)),
+            (AstValue::Type(_), _) => matches!(ty, AstType::Type(_)),
+            _ => true,
+        };
+        if !matches {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Mismatched types: expected `{}`", type_name(ty))
+            )
+            .with_label(format!("expected `{}`, found `{}`", type_name(ty), lit)));
         }
         Ok(())
     }
@@ -86,7 +59,7 @@ impl InterpreterPass {
             AstExpr::Locator(n) => {
                 let expr = ctx
                     .get_expr(n.to_path())
-                    .with_context(|| format!("Could not find {:?} in context", n))?;
+                    .ok_or_else(|| Diagnostic::unresolved(n).with_node(expr))?;
                 return self.type_check_expr_against_value(&expr, type_value, ctx);
             }
 
@@ -96,11 +69,23 @@ impl InterpreterPass {
         Ok(())
     }
 
+    /// Evaluates `expr` in type position, where a unit struct names its type rather than its value
+    pub fn interpret_type_expr(
+        &self,
+        expr: &AstExpr,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        match self.interpret_expr(expr, ctx)? {
+            AstValue::Struct(value) if value.ty.kind == StructKind::Unit => {
+                Ok(AstType::Struct(value.ty))
+            }
+            value => value.try_conv(),
+        }
+    }
     pub fn evaluate_type_value(&self, ty: &AstType, ctx: &SharedScopedContext) -> Result<AstType> {
         match ty {
             AstType::Expr(expr) => {
-                let value = self.interpret_expr(expr, ctx)?;
-                let ty = value.try_conv()?;
+                let ty = self.interpret_type_expr(expr, ctx)?;
                 return Ok(ty);
             }
             AstType::Struct(n) => {
@@ -118,6 +103,7 @@ impl InterpreterPass {
                 return Ok(AstType::Struct(TypeStruct {
                     name: n.name.clone(),
                     fields,
+                    kind: n.kind.clone(),
                 }));
             }
             AstType::Structural(n) => {
@@ -159,11 +145,361 @@ impl InterpreterPass {
                     .into(),
                 ));
             }
+            AstType::TypeOf(t) => {
+                let context = Context {
+                    values: ctx.clone(),
+                    ..Context::new()
+                };
+                return self.get_ty_from_expr(&context, &t.expr);
+            }
             AstType::TypeBounds(b) => return self.evaluate_type_bounds(b, ctx),
             AstType::ImplTraits(t) => return self.evaluate_impl_traits(t, ctx),
             _ => Ok(ty.clone()),
         }
     }
+    /// `Foo<T, N>`: an instance of the type function `Foo`, or else the generic type `Foo`
+    /// with its args evaluated, like `Vec<i64>` for `Vec<T>`
+    pub fn interpret_parameter_path(
+        &self,
+        path: &ParameterPath,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let segments: Vec<_> = path
+            .segments
+            .iter()
+            .map(|x| {
+                let args: Vec<_> = x
+                    .args
+                    .iter()
+                    .map(|x| self.interpret_type_arg(x, ctx))
+                    .try_collect()?;
+                eyre::Ok((x.ident.clone(), args))
+            })
+            .try_collect()?;
+        if let [(name, args)] = segments.as_slice() {
+            if let Some((func, closure)) = ctx.get_function(name) {
+                if let Some(AstType::Type(_)) = func.ret_ty {
+                    return self.instantiate_type_function(&func, args, &closure);
+                }
+            }
+        }
+        let segments = segments
+            .into_iter()
+            .zip(&path.segments)
+            .map(|((ident, args), segment)| ParameterPathSegment {
+                ident,
+                lifetimes: segment.lifetimes.clone(),
+                args: args
+                    .into_iter()
+                    .map(|x| match x {
+                        AstValue::Type(ty) => ty,
+                        value => AstType::value(value),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Ok(AstType::locator(Locator::parameter_path(ParameterPath {
+            segments,
+        })))
+    }
+    /// A generic arg is a type, or the value of a const param
+    fn interpret_type_arg(&self, arg: &AstType, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let value = match arg {
+            // params like `T` and `N` parse as type paths
+            AstType::Expr(expr) => self.interpret_expr(expr, ctx)?,
+            AstType::Value(value) => self.interpret_value(&value.value, ctx, true)?,
+            _ => AstValue::Type(arg.clone()),
+        };
+        match value {
+            AstValue::Struct(value) if value.ty.kind == StructKind::Unit => {
+                Ok(AstValue::Type(AstType::Struct(value.ty)))
+            }
+            AstValue::Type(ty) => self.evaluate_type_value(&ty, ctx).map(AstValue::Type),
+            value => Ok(value),
+        }
+    }
+    /// Evaluates the body of a type function with its params bound to `args`, once per `args`
+    fn instantiate_type_function(
+        &self,
+        func: &ValueFunction,
+        args: &[AstValue],
+        ctx: &SharedScopedContext,
+    ) -> Result<AstType> {
+        let name = func.name.clone().context("Type function has no name")?;
+        if args.len() != func.generics_params.len() {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Type {} takes {} generic args, got {}",
+                    name,
+                    func.generics_params.len(),
+                    args.len()
+                )
+            ))
+        }
+        let key = format!("{}::{}<{}>", ctx.path(), name, args.iter().join(", "));
+        if let Some(ty) = self.type_instances.lock().unwrap().get(&key) {
+            return Ok(ty.clone());
+        }
+        let sub = ctx.child(Ident::new("__type__"), Visibility::Private, false);
+        for (param, arg) in func.generics_params.iter().zip(args) {
+            match (&param.const_ty, arg) {
+                (Some(ty), _) => self.type_check_value(arg, ty)?,
+                (None, AstValue::Type(_)) => {}
+                (None, _) => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!(
+                        "Expected a type for {} of {}, got {}",
+                        param.name, name, arg
+                    )
+                )),
+            }
+            sub.insert_value_with_ctx(param.name.clone(), arg.clone());
+        }
+
+        let depth = self.type_depth.fetch_add(1, Ordering::Relaxed);
+        let value = if depth < self.type_depth_limit {
+            self.interpret_expr(&func.body, &sub)
+        } else {
+            Err(Diagnostic::new(
+                ErrorCode::InterpretFailed,
+                format!(
+                    "Type {} is nested more than {} levels deep",
+                    key, self.type_depth_limit
+                ),
+            )
+            .with_help("make sure the recursion of the type function reaches its base case")
+            .into())
+        };
+        self.type_depth.fetch_sub(1, Ordering::Relaxed);
+        let ty = match value? {
+            AstValue::Type(ty) => self.evaluate_type_value(&ty, &sub)?,
+            value => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Type {} evaluates to {}, which is not a type", key, value)
+            )),
+        };
+        self.type_instances.lock().unwrap().insert(key, ty.clone());
+        Ok(ty)
+    }
+    /// `Foo["/api"]`: the type of a field of a struct type, which may have string keys
+    pub fn index_type(&self, ty: &AstType, index: &AstValue) -> Result<AstType> {
+        let name = match index {
+            AstValue::String(s) => Ident::new(s.value.clone()),
+            _ => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Expected a string to index type {}, got {}", ty, index)
+            )),
+        };
+        let fields = match ty {
+            AstType::Struct(s) => &s.fields,
+            AstType::Structural(s) => &s.fields,
+            _ => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Expected a struct type to index with {}, got {}", index, ty)
+            )),
+        };
+        let field = fields.iter().find(|x| x.name == name).ok_or_else(|| {
+            Diagnostic::new(
+                ErrorCode::UnresolvedName,
+                format!("Type {} has no field `{}`", ty, name),
+            )
+        })?;
+        Ok(field.value.clone())
+    }
+    /// `T.a = bool` in a comptime block adds or replaces the field `a` of the struct type `T`
+    pub fn assign_type_field(&self, ty: &mut AstType, name: &Ident, value: AstValue) -> Result<()> {
+        let AstValue::Type(value) = value else {
+            bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Expected a type for field {}, got {}", name, value)
+            ))
+        };
+        let fields = match ty {
+            AstType::Struct(s) => &mut s.fields,
+            AstType::Structural(s) => &mut s.fields,
+            ty => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Cannot add field {} to type {}", name, ty)
+            )
+            .with_help("only struct types have fields")),
+        };
+        match fields.iter_mut().find(|x| &x.name == name) {
+            Some(field) => field.value = value,
+            None => fields.push(StructuralField::new(name.clone(), value)),
+        }
+        Ok(())
+    }
+    /// `A + B` of types: the union of trait bounds, or of the fields of struct types
+    pub fn add_types(&self, types: &[AstType]) -> Result<AstType> {
+        if let Some(bounds) = Self::impl_traits_bounds(types) {
+            return Ok(Self::merge_bounds(&bounds));
+        }
+        let mut fields: Vec<StructuralField> = vec![];
+        for ty in types {
+            for field in Self::struct_fields(BinOpKind::AddTrait, ty)? {
+                if fields.iter().any(|x| x.name == field.name) {
+                    bail!(Diagnostic::new(
+                        ErrorCode::TypeMismatch,
+                        format!("Field {} is defined more than once in {}", field.name, ty)
+                    )
+                    .with_node(ty))
+                }
+                fields.push(field.clone());
+            }
+        }
+        Ok(AstType::Structural(TypeStructural { fields }))
+    }
+    /// `A - B` of types: the trait bounds or fields of `A` that are not in `B`
+    pub fn subtract_types(&self, types: &[AstType]) -> Result<AstType> {
+        let (first, rest) = types.split_first().context("No type to subtract from")?;
+        if let Some(bounds) = Self::impl_traits_bounds(types) {
+            let (first, rest) = bounds.split_first().unwrap();
+            let bounds = first
+                .iter()
+                .filter(|x| !rest.iter().any(|bounds| bounds.contains(*x)))
+                .cloned()
+                .collect();
+            return Ok(AstType::ImplTraits(ImplTraits {
+                bounds: TypeBounds { bounds },
+            }));
+        }
+        let mut fields = Self::struct_fields(BinOpKind::Sub, first)?.to_vec();
+        for ty in rest {
+            for field in Self::struct_fields(BinOpKind::Sub, ty)? {
+                Self::check_field_conflict(&fields, field, ty)?;
+                fields.retain(|x| x.name != field.name);
+            }
+        }
+        Ok(AstType::Structural(TypeStructural { fields }))
+    }
+    /// `A & B` of types: a value of both types, so it has every trait bound and every field
+    pub fn intersect_types(&self, types: &[AstType]) -> Result<AstType> {
+        if let Some(bounds) = Self::impl_traits_bounds(types) {
+            return Ok(Self::merge_bounds(&bounds));
+        }
+        let mut fields: Vec<StructuralField> = vec![];
+        for ty in types {
+            for field in Self::struct_fields(BinOpKind::BitAnd, ty)? {
+                Self::check_field_conflict(&fields, field, ty)?;
+                if !fields.contains(field) {
+                    fields.push(field.clone());
+                }
+            }
+        }
+        Ok(AstType::Structural(TypeStructural { fields }))
+    }
+    /// `A | B` of types: a value of either type, so it only has the trait bounds they share.
+    /// Named structs become the variants of an enum, anonymous ones keep the fields they share
+    pub fn union_types(&self, types: &[AstType]) -> Result<AstType> {
+        if let Some(bounds) = Self::impl_traits_bounds(types) {
+            let (first, rest) = bounds.split_first().unwrap();
+            let bounds = first
+                .iter()
+                .filter(|x| rest.iter().all(|bounds| bounds.contains(*x)))
+                .cloned()
+                .collect();
+            return Ok(AstType::ImplTraits(ImplTraits {
+                bounds: TypeBounds { bounds },
+            }));
+        }
+        // an anonymous struct has no name to become a variant
+        if types.iter().any(|x| matches!(x, AstType::Structural(_))) {
+            let (first, rest) = types.split_first().context("No type to unite")?;
+            let mut fields = Self::struct_fields(BinOpKind::BitOr, first)?.to_vec();
+            for ty in rest {
+                let ty_fields = Self::struct_fields(BinOpKind::BitOr, ty)?;
+                fields.retain(|x| ty_fields.contains(x));
+            }
+            return Ok(AstType::Structural(TypeStructural { fields }));
+        }
+        let mut variants: Vec<EnumTypeVariant> = vec![];
+        for ty in types {
+            let ty_variants = match ty {
+                AstType::Struct(s) => vec![EnumTypeVariant {
+                    name: s.name.clone(),
+                    value: ty.clone(),
+                }],
+                AstType::Enum(e) => e.variants.clone(),
+                _ => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot apply {} to type {}", BinOpKind::BitOr, ty)
+                )
+                .with_node(ty)
+                .with_help("only named structs, enums or trait bounds can be united")),
+            };
+            for variant in ty_variants {
+                match variants.iter().find(|x| x.name == variant.name) {
+                    Some(x) if x.value == variant.value => {}
+                    Some(x) => bail!(Diagnostic::new(
+                        ErrorCode::FieldConflict,
+                        format!(
+                            "Variant {} is defined as both {} and {}",
+                            variant.name, x.value, variant.value
+                        )
+                    )
+                    .with_node(ty)),
+                    None => variants.push(variant),
+                }
+            }
+        }
+        let name = variants.iter().map(|x| x.name.as_str()).join("Or");
+        Ok(AstType::Enum(TypeEnum {
+            name: Ident::new(name),
+            variants,
+        }))
+    }
+    /// The bounds of each type, if all of them are `impl Traits`
+    fn impl_traits_bounds(types: &[AstType]) -> Option<Vec<&[AstExpr]>> {
+        types
+            .iter()
+            .map(|x| match x {
+                AstType::ImplTraits(impls) => Some(impls.bounds.bounds.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+    fn merge_bounds(bounds: &[&[AstExpr]]) -> AstType {
+        let mut merged: Vec<AstExpr> = vec![];
+        for bound in bounds.iter().copied().flatten() {
+            if !merged.contains(bound) {
+                merged.push(bound.clone());
+            }
+        }
+        AstType::ImplTraits(ImplTraits {
+            bounds: TypeBounds { bounds: merged },
+        })
+    }
+    fn struct_fields(op: BinOpKind, ty: &AstType) -> Result<&[StructuralField]> {
+        match ty {
+            AstType::Struct(s) => Ok(&s.fields),
+            AstType::Structural(s) => Ok(&s.fields),
+            _ => bail!(Diagnostic::new(
+                ErrorCode::TypeMismatch,
+                format!("Cannot apply {} to type {}", op, ty)
+            )
+            .with_node(ty)
+            .with_help("only struct types or trait bounds support type arithmetic")),
+        }
+    }
+    fn check_field_conflict(
+        fields: &[StructuralField],
+        field: &StructuralField,
+        ty: &AstType,
+    ) -> Result<()> {
+        match fields.iter().find(|x| x.name == field.name) {
+            Some(x) if x.value != field.value => bail!(Diagnostic::new(
+                ErrorCode::FieldConflict,
+                format!(
+                    "Field {} is defined as both {} and {}",
+                    field.name, x.value, field.value
+                )
+            )
+            .with_node(ty)
+            .with_label(format!("`{}` conflicts here", field.name))),
+            _ => Ok(()),
+        }
+    }
     pub fn evaluate_impl_traits(
         &self,
         traits: &ImplTraits,
@@ -203,126 +539,11 @@ impl InterpreterPass {
         ty: &AstExpr,
         ctx: &SharedScopedContext,
     ) -> Result<()> {
-        let tv = self.interpret_expr(ty, ctx)?.try_conv()?;
+        let tv = self.interpret_type_expr(ty, ctx)?;
 
         self.type_check_expr_against_value(expr, &tv, ctx)
     }
 
-    pub fn infer_type_call(
-        &self,
-        callee: &AstExpr,
-        params: &[AstExpr],
-        ctx: &SharedScopedContext,
-    ) -> Result<AstType> {
-        match callee {
-            AstExpr::Locator(Locator::Ident(ident)) => match ident.as_str() {
-                "+" | "-" | "*" => {
-                    return self.infer_expr(params.first().context("No param")?, ctx)
-                }
-                "print" => return Ok(AstType::unit()),
-                _ => {}
-            },
-            _ => {}
-        }
-
-        let callee = self.infer_expr(callee, ctx)?;
-        match callee {
-            AstType::Function(f) => {
-                return match f.ret_ty {
-                    Some(t) => Ok(*t),
-                    None => Ok(AstType::unit()),
-                }
-            }
-            _ => {}
-        }
-
-        bail!("Could not infer type call {:?}", callee)
-    }
-    pub fn infer_ident(&self, ident: &Ident, ctx: &SharedScopedContext) -> Result<AstType> {
-        match ident.as_str() {
-            "print" => {
-                return Ok(AstType::Function(
-                    TypeFunction {
-                        params: vec![],
-                        generics_params: vec![],
-                        ret_ty: None,
-                    }
-                    .into(),
-                ))
-            }
-            _ => {}
-        }
-        let expr = ctx
-            .get_expr(ident)
-            .with_context(|| format!("Could not find {:?} in context", ident))?;
-        self.infer_expr(&expr, ctx)
-    }
-    pub fn infer_locator(&self, locator: &Locator, ctx: &SharedScopedContext) -> Result<AstType> {
-        if let Some(ty) = ctx.get_type(locator.to_path()) {
-            return Ok(ty);
-        }
-        match locator {
-            Locator::Ident(ident) => self.infer_ident(ident, ctx),
-            _ => bail!("Could not infer locator {:?}", locator),
-        }
-    }
-    pub fn infer_expr_invoke_target(
-        &self,
-        target: &ExprInvokeTarget,
-        ctx: &SharedScopedContext,
-    ) -> Result<AstType> {
-        match target {
-            ExprInvokeTarget::Function(ident) => self.infer_locator(ident, ctx),
-
-            _ => bail!("Could not infer invoke target {:?}", target),
-        }
-    }
-    pub fn infer_expr(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<AstType> {
-        let ret = match expr {
-            AstExpr::Locator(n) => self.infer_locator(n, ctx)?,
-            AstExpr::Value(l) => match l.as_ref() {
-                AstValue::Int(_) => AstType::Primitive(TypePrimitive::Int(TypeInt::I64)),
-                AstValue::Decimal(_) => {
-                    AstType::Primitive(TypePrimitive::Decimal(DecimalType::F64))
-                }
-                AstValue::Unit(_) => AstType::unit(),
-                AstValue::Bool(_) => AstType::Primitive(TypePrimitive::Bool),
-                AstValue::String(_) => AstType::Primitive(TypePrimitive::String),
-                AstValue::Type(_) => AstType::Type(TypeType {}),
-                AstValue::Char(_) => AstType::Primitive(TypePrimitive::Char),
-                AstValue::List(_) => AstType::Primitive(TypePrimitive::List),
-                _ => bail!("Could not infer type of {:?}", l),
-            },
-            AstExpr::Invoke(invoke) => {
-                let function = self.infer_expr_invoke_target(&invoke.target, ctx)?;
-                match function {
-                    AstType::Function(TypeFunction { ret_ty: None, .. }) => AstType::unit(),
-                    AstType::Function(TypeFunction {
-                        ret_ty: Some(t), ..
-                    }) => *t,
-
-                    _ => bail!("Expected function, got {:?}", function),
-                }
-            }
-            AstExpr::BinOp(op) => {
-                if op.kind.is_ret_bool() {
-                    return Ok(AstType::Primitive(TypePrimitive::Bool));
-                }
-                let lhs = self.infer_expr(&op.lhs, ctx)?;
-                let rhs = self.infer_expr(&op.rhs, ctx)?;
-                ensure!(
-                    lhs == rhs,
-                    "Expected same types, got {:?} and {:?}",
-                    lhs,
-                    rhs
-                );
-                lhs
-            }
-            _ => bail!("Could not infer type of {:?}", expr),
-        };
-        Ok(ret)
-    }
-
     pub fn infer_function(
         &self,
         func: &ValueFunction,
@@ -341,17 +562,18 @@ impl InterpreterPass {
     }
 }
 impl TypeSystem for InterpreterPass {
+    /// The type an expression denotes, like `i64`, or else the type of its value,
+    /// like `i64` for `typeof(1)`
     fn get_ty_from_expr(&self, ctx: &Context, expr: &AstExpr) -> Result<AstType> {
         let fold = FoldOptimizer::new(self.serializer.clone(), Box::new(self.clone()));
 
         let expr = fold.optimize_expr(expr.clone(), &ctx.values)?;
-        match expr {
-            AstExpr::Value(v) => match v.into() {
-                AstValue::Type(t) => return Ok(t),
-                v => bail!("Expected type, got {:?}", v),
-            },
-            _ => bail!("Expected type, got {:?}", expr),
+        if let AstExpr::Value(v) = &expr {
+            if let AstValue::Type(t) = v.as_ref() {
+                return Ok(t.clone());
+            }
         }
+        self.infer_expr(&expr, &ctx.values)
     }
     fn get_ty_from_value(&self, ctx: &Context, value: &AstValue) -> Result<AstType> {
         let fold = FoldOptimizer::new(self.serializer.clone(), Box::new(self.clone()));
@@ -361,9 +583,17 @@ impl TypeSystem for InterpreterPass {
         match value {
             AstExpr::Value(v) => match v.into() {
                 AstValue::Type(t) => return Ok(t),
-                v => bail!("Expected type, got {:?}", v),
+                v => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Expected type, found `{}`", v)
+                )
+                .with_label("expected a type")),
             },
-            _ => bail!("Expected type, got {:?}", value),
+            value => bail!(Diagnostic::new(
+                ErrorCode::InferenceFailed,
+                format!("Could not evaluate {} to a type", value.describe())
+            )
+            .with_node(&value)),
         }
     }
 }
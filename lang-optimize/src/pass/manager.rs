@@ -0,0 +1,224 @@
+use crate::pass::{
+    DeadCodeEliminationPass, FoldOptimizer, InlinePass, LoopUnrollPass, MonomorphizePass,
+    OptimizePass, SpecializePass, TypeChecker,
+};
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Optimization levels, in the spirit of `-O0..-O3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+}
+impl OptLevel {
+    pub fn pipeline(&self) -> &'static str {
+        match self {
+            OptLevel::O0 => "",
+            OptLevel::O1 => "inline,dce",
+            OptLevel::O2 => "specialize,inline,dce",
+            OptLevel::O3 => "specialize,unroll,inline,dce",
+        }
+    }
+    /// Passes enable each other, e.g. inlining exposes constants to specialize, so the
+    /// pipeline runs until nothing changes
+    pub fn max_iterations(&self) -> usize {
+        match self {
+            OptLevel::O0 => 1,
+            OptLevel::O1 | OptLevel::O2 | OptLevel::O3 => 8,
+        }
+    }
+}
+impl std::str::FromStr for OptLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim_start_matches('-') {
+            "O0" | "0" => Ok(OptLevel::O0),
+            "O1" | "1" => Ok(OptLevel::O1),
+            "O2" | "2" => Ok(OptLevel::O2),
+            "O3" | "3" => Ok(OptLevel::O3),
+            _ => bail!("Unknown optimization level: {}", s),
+        }
+    }
+}
+
+/// Creates a pass by its pipeline name
+pub fn load_pass(name: &str, serializer: Arc<dyn AstSerializer>) -> Result<Box<dyn OptimizePass>> {
+    let pass: Box<dyn OptimizePass> = match name {
+        "specialize" => Box::new(SpecializePass::new(serializer)),
+        "inline" => Box::new(InlinePass::new(serializer)),
+        "unroll" => Box::new(LoopUnrollPass::new(serializer)),
+        "monomorphize" => Box::new(MonomorphizePass::new(serializer)),
+        "dce" => Box::new(DeadCodeEliminationPass::new()),
+        _ => bail!("Unknown optimization pass: {}", name),
+    };
+    Ok(pass)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassStats {
+    pub name: String,
+    /// how many times the pass has been run
+    pub runs: usize,
+    /// how many of the runs changed the AST
+    pub changes: usize,
+    pub elapsed: Duration,
+}
+impl Display for PassStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: runs={} changes={} elapsed={:?}",
+            self.name, self.runs, self.changes, self.elapsed
+        )
+    }
+}
+
+/// Runs a named pipeline of passes until the AST stops changing or the iteration limit is hit
+pub struct PassManager {
+    optimizers: Vec<FoldOptimizer>,
+    /// checks the modules and files it outputs, see [`PassManager::with_type_check`]
+    type_checker: Option<TypeChecker>,
+    serializer: Arc<dyn AstSerializer>,
+    max_iterations: usize,
+    iterations: usize,
+    stats: Vec<PassStats>,
+}
+impl PassManager {
+    /// `pipeline` is a comma separated list of pass names, e.g. `specialize,inline,dce`
+    pub fn new(serializer: Arc<dyn AstSerializer>, pipeline: &str) -> Result<Self> {
+        let mut optimizers = vec![];
+        let mut stats = vec![];
+        for name in pipeline
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+        {
+            let pass = load_pass(name, serializer.clone())?;
+            stats.push(PassStats {
+                name: pass.name().to_string(),
+                ..PassStats::default()
+            });
+            optimizers.push(FoldOptimizer::new(serializer.clone(), pass));
+        }
+        Ok(Self {
+            optimizers,
+            type_checker: None,
+            serializer,
+            max_iterations: 1,
+            iterations: 0,
+            stats,
+        })
+    }
+    pub fn with_level(serializer: Arc<dyn AstSerializer>, level: OptLevel) -> Result<Self> {
+        Ok(Self::new(serializer, level.pipeline())?.with_max_iterations(level.max_iterations()))
+    }
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+    /// Type checks the optimized modules and files, so that code rustc would reject is
+    /// reported at the offending expression instead
+    pub fn with_type_check(mut self, type_check: bool) -> Self {
+        self.type_checker = type_check.then(|| TypeChecker::new(self.serializer.clone()));
+        self
+    }
+    /// Instantiates generic items before the other passes run, for backends without
+    /// generics like MIPS
+    pub fn with_monomorphize(mut self, monomorphize: bool) -> Self {
+        let loaded = self.stats.iter().any(|x| x.name == "monomorphize");
+        if monomorphize && !loaded {
+            let pass = MonomorphizePass::new(self.serializer.clone());
+            self.stats.insert(
+                0,
+                PassStats {
+                    name: pass.name().to_string(),
+                    ..PassStats::default()
+                },
+            );
+            self.optimizers.insert(
+                0,
+                FoldOptimizer::new(self.serializer.clone(), Box::new(pass)),
+            );
+        }
+        self
+    }
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+    /// iterations done in the last run
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+    pub fn stats(&self) -> &[PassStats] {
+        &self.stats
+    }
+
+    fn run<T: Clone + PartialEq>(
+        &mut self,
+        mut node: T,
+        f: impl Fn(&FoldOptimizer, T) -> Result<T>,
+    ) -> Result<T> {
+        self.iterations = 0;
+        while self.iterations < self.max_iterations {
+            self.iterations += 1;
+            let mut changed = false;
+            for (optimizer, stats) in self.optimizers.iter().zip(self.stats.iter_mut()) {
+                let start = Instant::now();
+                let new_node = f(optimizer, node.clone())?;
+                stats.elapsed += start.elapsed();
+                stats.runs += 1;
+                if new_node != node {
+                    stats.changes += 1;
+                    changed = true;
+                }
+                node = new_node;
+            }
+            if !changed {
+                break;
+            }
+        }
+        for stats in &self.stats {
+            debug!("{}", stats);
+        }
+        Ok(node)
+    }
+
+    pub fn optimize_expr(&mut self, expr: AstExpr, ctx: &SharedScopedContext) -> Result<AstExpr> {
+        self.run(expr, |opt, expr| opt.optimize_expr(expr, ctx))
+    }
+    pub fn optimize_item(&mut self, item: AstItem, ctx: &SharedScopedContext) -> Result<AstItem> {
+        self.run(item, |opt, item| opt.optimize_item(item, ctx))
+    }
+    pub fn optimize_module(
+        &mut self,
+        module: AstModule,
+        ctx: &SharedScopedContext,
+        with_submodule: bool,
+    ) -> Result<AstModule> {
+        let module = self.run(module, |opt, module| {
+            opt.optimize_module(module, ctx, with_submodule)
+        })?;
+        if let Some(checker) = &self.type_checker {
+            checker.check_module(&module, &SharedScopedContext::new())?;
+        }
+        Ok(module)
+    }
+    pub fn optimize_file(&mut self, file: AstFile, ctx: &SharedScopedContext) -> Result<AstFile> {
+        let file = self.run(file, |opt, file| opt.optimize_file(file, ctx))?;
+        if let Some(checker) = &self.type_checker {
+            checker.check_file(&file, &SharedScopedContext::new())?;
+        }
+        Ok(file)
+    }
+    pub fn optimize_tree(&mut self, node: AstNode, ctx: &SharedScopedContext) -> Result<AstNode> {
+        self.run(node, |opt, node| opt.optimize_tree(node, ctx))
+    }
+}
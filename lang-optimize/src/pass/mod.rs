@@ -1,16 +1,26 @@
+mod dce;
 mod inline;
 mod interpret;
+mod manager;
+mod monomorphize;
 mod optimizer;
 mod specialize;
+mod typeck;
+mod unroll;
 
+pub use dce::*;
 pub use inline::*;
 pub use interpret::*;
+pub use manager::*;
+pub use monomorphize::*;
 pub use optimizer::*;
 pub use specialize::*;
+pub use typeck::*;
+pub use unroll::*;
 
 use common::*;
 use lang_core::ast::AstValue;
-use lang_core::ast::{AstExpr, ControlFlow, ExprInvoke};
+use lang_core::ast::{AstExpr, ControlFlow, ExprBlock, ExprInvoke};
 use lang_core::ast::{AstItem, AstModule};
 use lang_core::context::SharedScopedContext;
 
@@ -29,6 +39,14 @@ pub trait OptimizePass {
         Ok(expr)
     }
 
+    fn optimize_block(&self, block: ExprBlock, ctx: &SharedScopedContext) -> Result<ExprBlock> {
+        Ok(block)
+    }
+
+    /// Called once the items of `module` are registered in `ctx`, before they're optimized
+    fn prescan_module(&self, module: &AstModule, ctx: &SharedScopedContext) -> Result<()> {
+        Ok(())
+    }
     fn optimize_module(&self, module: AstModule, ctx: &SharedScopedContext) -> Result<AstModule> {
         Ok(module)
     }
@@ -50,6 +68,10 @@ pub trait OptimizePass {
     fn evaluate_condition(&self, expr: AstExpr, ctx: &SharedScopedContext) -> Result<ControlFlow> {
         Ok(ControlFlow::Into)
     }
+    /// The interpreter of the pass, so that `const` blocks are evaluated with its settings
+    fn interpreter(&self) -> Option<&InterpreterPass> {
+        None
+    }
 }
 
 pub struct NoopPass;
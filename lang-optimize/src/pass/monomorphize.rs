@@ -0,0 +1,1052 @@
+use crate::pass::typeck::{generic_name, substitute};
+use crate::pass::{InterpreterPass, OptimizePass, UsageCollector, BLOCK_SCOPE};
+use common::*;
+use itertools::Itertools;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator, ParameterPath, ParameterPathSegment};
+use lang_core::pat::Pattern;
+use std::collections::HashMap;
+use std::mem::{replace, take};
+use std::sync::Arc;
+
+/// Instances of a single generic item beyond this are taken for polymorphic recursion,
+/// like `fn foo<T>(x: T) { foo(&x) }`, which would never stop instantiating
+const MAX_INSTANCES: usize = 256;
+
+/// Identifies an instance of a generic item by the item and its type args
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct InstanceKey {
+    name: Ident,
+    type_args: Vec<String>,
+}
+
+/// The generic items declared in a module or block, and their instances so far
+struct GenericScope {
+    ctx: SharedScopedContext,
+    functions: HashMap<Ident, ItemDefFunction>,
+    structs: HashMap<Ident, ItemDefStruct>,
+    /// impls of the generic structs, instantiated along with them
+    impls: HashMap<Ident, Vec<ItemImpl>>,
+    instances: HashMap<InstanceKey, Ident>,
+    /// instances by the name of their original, in the order they were created
+    emitted: Vec<(Ident, AstItem)>,
+    /// instantiated functions and impls in `emitted` whose bodies are yet to be rewritten
+    pending: Vec<(usize, HashMap<Ident, AstType>)>,
+}
+
+fn is_generic(params: &[GenericParam]) -> bool {
+    // const generics are left to the specializer, which folds them as constant args
+    !params.is_empty() && params.iter().all(|x| x.const_ty.is_none())
+}
+fn uninferred(name: &Ident, param: &Ident, node: &AstExpr) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::InferenceFailed,
+        format!("Could not infer type argument `{}` of `{}`", param, name),
+    )
+    .with_node(node)
+    .with_label("cannot infer type")
+    .with_help(format!(
+        "consider specifying the type arguments, like `{}::<..>`",
+        name
+    ))
+}
+
+/// Instantiates generic functions and structs for each set of type args they are used with,
+/// rewriting the uses to the instances and dropping the generic items no longer used.
+///
+/// Type args are taken from turbofish, like `id::<i64>(1)`, or else inferred from the args,
+/// the field values or the expected type. Backends without generics, like MIPS, rely on it.
+pub struct MonomorphizePass {
+    serializer: Arc<dyn AstSerializer>,
+    interpreter: InterpreterPass,
+}
+impl MonomorphizePass {
+    pub fn new(serializer: Arc<dyn AstSerializer>) -> Self {
+        Self {
+            interpreter: InterpreterPass::new(serializer.clone()),
+            serializer,
+        }
+    }
+    pub fn monomorphize_module(
+        &self,
+        mut module: AstModule,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstModule> {
+        let mut monomorphizer = Monomorphizer::new(self);
+        let stmts = module.items.into_iter().map(BlockStmt::item).collect();
+        let stmts = monomorphizer.rewrite_scope(stmts, ctx.clone())?;
+        module.items = stmts
+            .into_iter()
+            .filter_map(|x| match x {
+                BlockStmt::Item(item) => Some(*item),
+                _ => None,
+            })
+            .collect();
+        Ok(module)
+    }
+    pub fn monomorphize_block(
+        &self,
+        mut block: ExprBlock,
+        ctx: &SharedScopedContext,
+    ) -> Result<ExprBlock> {
+        let mut monomorphizer = Monomorphizer::new(self);
+        block.stmts = monomorphizer.rewrite_scope(block.stmts, ctx.clone())?;
+        Ok(block)
+    }
+}
+
+struct Monomorphizer<'a> {
+    pass: &'a MonomorphizePass,
+    scopes: Vec<GenericScope>,
+    /// the type args of the instance whose body is being rewritten
+    subst: HashMap<Ident, AstType>,
+    /// the original and type args of each struct instance, to infer type args from
+    origins: HashMap<Ident, (Ident, Vec<AstType>)>,
+}
+impl<'a> Monomorphizer<'a> {
+    fn new(pass: &'a MonomorphizePass) -> Self {
+        Self {
+            pass,
+            scopes: vec![],
+            subst: HashMap::new(),
+            origins: HashMap::new(),
+        }
+    }
+    fn type_of(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> AstType {
+        self.pass
+            .interpreter
+            .infer_expr(expr, ctx)
+            .unwrap_or_else(|_| AstType::unknown())
+    }
+    fn mangle(&self, name: &Ident, type_args: &[AstType]) -> Result<Ident> {
+        let mut mangled = name.to_string();
+        for arg in type_args {
+            let arg = self
+                .pass
+                .serializer
+                .serialize_type(arg)?
+                .replace('&', " ref ");
+            for word in arg
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|x| !x.is_empty())
+            {
+                mangled.push('_');
+                mangled.push_str(word);
+            }
+        }
+        Ok(Ident::new(mangled))
+    }
+
+    fn lookup_function(&self, name: &Ident) -> Option<(usize, ItemDefFunction)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| scope.functions.get(name).map(|x| (i, x.clone())))
+    }
+    fn lookup_struct(&self, name: &Ident) -> Option<(usize, ItemDefStruct)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, scope)| scope.structs.get(name).map(|x| (i, x.clone())))
+    }
+    /// Binds the generic params in `pattern` to the parts of `actual` they match
+    fn match_type(
+        &self,
+        pattern: &AstType,
+        actual: &AstType,
+        generics: &[Ident],
+        bindings: &mut HashMap<Ident, AstType>,
+    ) {
+        if let Some(name) = generic_name(pattern, generics) {
+            if !matches!(actual, AstType::Unknown(_)) && !bindings.contains_key(name) {
+                bindings.insert(name.clone(), actual.clone());
+            }
+            return;
+        }
+        match (pattern, actual) {
+            (AstType::Reference(p), AstType::Reference(a)) => {
+                self.match_type(&p.ty, &a.ty, generics, bindings)
+            }
+            (AstType::Tuple(p), AstType::Tuple(a)) => {
+                for (p, a) in p.types.iter().zip(&a.types) {
+                    self.match_type(p, a, generics, bindings);
+                }
+            }
+            (AstType::Slice(p), AstType::Slice(a)) => {
+                self.match_type(&p.elem, &a.elem, generics, bindings)
+            }
+            (AstType::Vec(p), AstType::Vec(a)) => self.match_type(&p.ty, &a.ty, generics, bindings),
+            (AstType::Function(p), AstType::Function(a)) => {
+                for (p, a) in p.params.iter().zip(&a.params) {
+                    self.match_type(p, a, generics, bindings);
+                }
+                if let (Some(p), Some(a)) = (&p.ret_ty, &a.ret_ty) {
+                    self.match_type(p, a, generics, bindings);
+                }
+            }
+            // `Pair<T>` against an instance like `Pair_i64`
+            (AstType::Expr(p), AstType::Expr(a)) => {
+                let (AstExpr::Locator(Locator::ParameterPath(path)), AstExpr::Locator(instance)) =
+                    (p.as_ref(), a.as_ref())
+                else {
+                    return;
+                };
+                let Some((origin, args)) = self.origins.get(instance.to_path().last()) else {
+                    return;
+                };
+                let segment = path.last();
+                if &segment.ident == origin {
+                    for (p, a) in segment.args.iter().zip(args) {
+                        self.match_type(p, a, generics, bindings);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    fn bound_args(
+        &self,
+        name: &Ident,
+        params: &[GenericParam],
+        bindings: &HashMap<Ident, AstType>,
+        node: &AstExpr,
+    ) -> Result<Vec<AstType>> {
+        params
+            .iter()
+            .map(|x| match bindings.get(&x.name) {
+                Some(ty) => Ok(ty.clone()),
+                None => bail!(uninferred(name, &x.name, node)),
+            })
+            .try_collect()
+    }
+
+    fn instantiate_function(
+        &mut self,
+        scope: usize,
+        def: &ItemDefFunction,
+        type_args: Vec<AstType>,
+    ) -> Result<Ident> {
+        let key = InstanceKey {
+            name: def.name.clone(),
+            type_args: type_args
+                .iter()
+                .map(|x| self.pass.serializer.serialize_type(x))
+                .try_collect()?,
+        };
+        if let Some(name) = self.scopes[scope].instances.get(&key) {
+            return Ok(name.clone());
+        }
+        self.check_instances(scope, &def.name)?;
+        let name = self.mangle(&def.name, &type_args)?;
+        debug!("Instantiating {} as {}", def.name, name);
+        let subst: HashMap<_, _> = def
+            .sig
+            .generics_params
+            .iter()
+            .map(|x| x.name.clone())
+            .zip(type_args)
+            .collect();
+        let mut instance = def.clone();
+        instance.name = name.clone();
+        instance.sig.name = Some(name.clone());
+        instance.sig.generics_params = vec![];
+        // the predicates only constrain the params that were substituted
+        instance.sig.where_predicates = vec![];
+        for param in &mut instance.sig.params {
+            param.ty = substitute(&param.ty, &subst);
+        }
+        instance.sig.ret_ty = instance.sig.ret_ty.map(|x| substitute(&x, &subst));
+
+        let scope = &mut self.scopes[scope];
+        scope.instances.insert(key, name.clone());
+        scope
+            .ctx
+            .insert_value(name.clone(), AstValue::Function(instance._to_value()));
+        scope.pending.push((scope.emitted.len(), subst));
+        scope
+            .emitted
+            .push((def.name.clone(), AstItem::DefFunction(instance)));
+        Ok(name)
+    }
+    fn instantiate_struct(
+        &mut self,
+        scope: usize,
+        def: &ItemDefStruct,
+        type_args: Vec<AstType>,
+    ) -> Result<Ident> {
+        let key = InstanceKey {
+            name: def.name.clone(),
+            type_args: type_args
+                .iter()
+                .map(|x| self.pass.serializer.serialize_type(x))
+                .try_collect()?,
+        };
+        if let Some(name) = self.scopes[scope].instances.get(&key) {
+            return Ok(name.clone());
+        }
+        self.check_instances(scope, &def.name)?;
+        let name = self.mangle(&def.name, &type_args)?;
+        debug!("Instantiating {} as {}", def.name, name);
+        let subst: HashMap<_, _> = def
+            .generics_params
+            .iter()
+            .map(|x| x.name.clone())
+            .zip(type_args.clone())
+            .collect();
+        // registered before the fields, which may refer to the instance itself
+        self.scopes[scope].instances.insert(key, name.clone());
+        self.origins
+            .insert(name.clone(), (def.name.clone(), type_args.clone()));
+        let saved = replace(&mut self.subst, subst);
+        let fields: Result<Vec<_>> = def
+            .value
+            .fields
+            .iter()
+            .map(|x| {
+                Ok(StructuralField {
+                    name: x.name.clone(),
+                    value: self.rewrite_type(x.value.clone())?,
+                })
+            })
+            .try_collect();
+        self.subst = saved;
+        let value = TypeStruct {
+            name: name.clone(),
+            fields: fields?,
+            kind: def.value.kind.clone(),
+        };
+
+        let generic_scope = &mut self.scopes[scope];
+        generic_scope
+            .ctx
+            .insert_value(name.clone(), AstValue::Type(AstType::Struct(value.clone())));
+        generic_scope.emitted.push((
+            def.name.clone(),
+            AstItem::DefStruct(ItemDefStruct {
+                attrs: def.attrs.clone(),
+                visibility: def.visibility,
+                name: name.clone(),
+                lifetimes: def.lifetimes.clone(),
+                generics_params: vec![],
+                where_predicates: vec![],
+                value,
+            }),
+        ));
+        self.instantiate_impls(scope, def, &name, &type_args);
+        Ok(name)
+    }
+    /// Copies the impls of a generic struct for its instance `name`, like `impl Pair_i64` for
+    /// `impl<T> Pair<T>`. The method bodies are rewritten with the rest of the pending instances
+    fn instantiate_impls(
+        &mut self,
+        scope: usize,
+        def: &ItemDefStruct,
+        name: &Ident,
+        type_args: &[AstType],
+    ) {
+        let impls = self.scopes[scope]
+            .impls
+            .get(&def.name)
+            .cloned()
+            .unwrap_or_default();
+        for impl_ in impls {
+            let generics = impl_
+                .generics_params
+                .iter()
+                .map(|x| x.name.clone())
+                .collect_vec();
+            let mut subst = HashMap::new();
+            if let AstExpr::Locator(Locator::ParameterPath(path)) = &impl_.self_ty {
+                for (pattern, actual) in path.last().args.iter().zip(type_args) {
+                    self.match_type(pattern, actual, &generics, &mut subst);
+                }
+            }
+            let instance = ItemImpl {
+                lifetimes: impl_.lifetimes,
+                generics_params: vec![],
+                trait_ty: impl_.trait_ty,
+                self_ty: AstExpr::ident(name.clone()),
+                where_predicates: vec![],
+                items: impl_.items,
+            };
+            let scope = &mut self.scopes[scope];
+            scope.pending.push((scope.emitted.len(), subst));
+            scope
+                .emitted
+                .push((def.name.clone(), AstItem::Impl(instance)));
+        }
+    }
+    fn check_instances(&self, scope: usize, name: &Ident) -> Result<()> {
+        let count = self.scopes[scope]
+            .emitted
+            .iter()
+            .filter(|(origin, _)| origin == name)
+            .count();
+        if count >= MAX_INSTANCES {
+            bail!(Diagnostic::new(
+                ErrorCode::OptimizeFailed,
+                format!("Too many instances of `{}`", name)
+            )
+            .with_help("type args that grow with each recursive call can't be monomorphized"))
+        }
+        Ok(())
+    }
+
+    /// Rewrites the statements of a module or block, whose items are visible to each other
+    fn rewrite_scope(
+        &mut self,
+        stmts: Vec<BlockStmt>,
+        ctx: SharedScopedContext,
+    ) -> Result<Vec<BlockStmt>> {
+        let mut scope = GenericScope {
+            ctx,
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            impls: HashMap::new(),
+            instances: HashMap::new(),
+            emitted: vec![],
+            pending: vec![],
+        };
+        for stmt in &stmts {
+            let BlockStmt::Item(item) = stmt else {
+                continue;
+            };
+            match item.as_ref() {
+                AstItem::DefFunction(def) if is_generic(&def.sig.generics_params) => {
+                    scope.functions.insert(def.name.clone(), def.clone());
+                }
+                AstItem::DefStruct(def) if is_generic(&def.generics_params) => {
+                    scope.structs.insert(def.name.clone(), def.clone());
+                }
+                AstItem::DefFunction(def) => {
+                    scope
+                        .ctx
+                        .insert_value(def.name.clone(), AstValue::Function(def._to_value()));
+                }
+                AstItem::DefStruct(def) => scope.ctx.insert_value(
+                    def.name.clone(),
+                    AstValue::Type(AstType::Struct(def.value.clone())),
+                ),
+                _ => {}
+            }
+        }
+        for stmt in &stmts {
+            let BlockStmt::Item(item) = stmt else {
+                continue;
+            };
+            let AstItem::Impl(impl_) = item.as_ref() else {
+                continue;
+            };
+            let AstExpr::Locator(locator) = &impl_.self_ty else {
+                continue;
+            };
+            let name = locator.to_path().last().clone();
+            if scope.structs.contains_key(&name) {
+                scope.impls.entry(name).or_default().push(impl_.clone());
+            }
+        }
+        let ctx = scope.ctx.clone();
+        self.scopes.push(scope);
+        let stmts = self.rewrite_stmts(stmts, &ctx);
+        let pending = stmts.and_then(|stmts| self.rewrite_pending().map(|_| stmts));
+        let scope = self.scopes.pop().unwrap();
+        let stmts = pending?;
+
+        // the instances take the place of their originals, which are kept only if still used
+        let is_original = |stmt: &BlockStmt| match stmt {
+            BlockStmt::Item(item) => match item.as_ref() {
+                AstItem::DefFunction(def) => scope.functions.contains_key(&def.name),
+                AstItem::DefStruct(def) => scope.structs.contains_key(&def.name),
+                _ => false,
+            },
+            _ => false,
+        };
+        let mut used = UsageCollector::new();
+        for stmt in stmts.iter().filter(|x| !is_original(x)) {
+            used.collect_stmt(stmt);
+        }
+        for (_, item) in &scope.emitted {
+            used.collect_item(item);
+        }
+        let implemented = stmts
+            .iter()
+            .filter_map(|x| match x {
+                BlockStmt::Item(item) => match item.as_ref() {
+                    AstItem::Impl(impl_) => match &impl_.self_ty {
+                        AstExpr::Locator(locator) => Some(locator.to_path().last().clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect_vec();
+        let mut emitted = scope.emitted;
+        let mut result = vec![];
+        for stmt in stmts {
+            if !is_original(&stmt) {
+                result.push(stmt);
+                continue;
+            }
+            let BlockStmt::Item(item) = &stmt else {
+                unreachable!()
+            };
+            let (name, keep) = match item.as_ref() {
+                AstItem::DefFunction(def) => (def.name.clone(), used.is_used(&def.name)),
+                AstItem::DefStruct(def) => (def.name.clone(), implemented.contains(&def.name)),
+                _ => unreachable!(),
+            };
+            let (instances, rest): (Vec<_>, Vec<_>) =
+                emitted.into_iter().partition(|(origin, _)| origin == &name);
+            emitted = rest;
+            if keep {
+                result.push(stmt);
+            } else {
+                debug!("Removing generic {}", name);
+            }
+            result.extend(instances.into_iter().map(|(_, item)| BlockStmt::item(item)));
+        }
+        Ok(result)
+    }
+    /// Rewrites the bodies of the function and impl instances of the innermost scope
+    fn rewrite_pending(&mut self) -> Result<()> {
+        loop {
+            let scope = self.scopes.last_mut().unwrap();
+            let Some((index, subst)) = scope.pending.pop() else {
+                return Ok(());
+            };
+            let ctx = scope.ctx.clone();
+            let item = replace(&mut scope.emitted[index].1, AstItem::unit());
+            let saved = replace(&mut self.subst, subst);
+            let item = self.rewrite_instance(item, &ctx);
+            self.subst = saved;
+            self.scopes.last_mut().unwrap().emitted[index].1 = item?;
+        }
+    }
+    fn rewrite_instance(&mut self, item: AstItem, ctx: &SharedScopedContext) -> Result<AstItem> {
+        let item = match item {
+            AstItem::DefFunction(def) => AstItem::DefFunction(self.rewrite_function(def, ctx)?),
+            AstItem::Impl(mut impl_) => {
+                impl_.items = impl_
+                    .items
+                    .into_iter()
+                    .map(|x| match x {
+                        AstItem::DefFunction(def) => {
+                            Ok(AstItem::DefFunction(self.rewrite_function(def, ctx)?))
+                        }
+                        item => Ok(item),
+                    })
+                    .try_collect::<_, _, Error>()?;
+                AstItem::Impl(impl_)
+            }
+            _ => unreachable!(),
+        };
+        Ok(item)
+    }
+    fn rewrite_stmts(
+        &mut self,
+        stmts: Vec<BlockStmt>,
+        ctx: &SharedScopedContext,
+    ) -> Result<Vec<BlockStmt>> {
+        stmts
+            .into_iter()
+            .map(|stmt| self.rewrite_stmt(stmt, ctx))
+            .try_collect()
+    }
+    fn rewrite_stmt(&mut self, stmt: BlockStmt, ctx: &SharedScopedContext) -> Result<BlockStmt> {
+        let stmt = match stmt {
+            BlockStmt::Item(item) => BlockStmt::item(self.rewrite_item(*item, ctx)?),
+            BlockStmt::Let(let_) => BlockStmt::Let(self.rewrite_let(let_, ctx)?),
+            BlockStmt::Expr(mut expr) => {
+                expr.expr = self.rewrite_expr(*expr.expr, ctx, None)?.into();
+                BlockStmt::Expr(expr)
+            }
+            stmt => stmt,
+        };
+        Ok(stmt)
+    }
+    fn rewrite_item(&mut self, item: AstItem, ctx: &SharedScopedContext) -> Result<AstItem> {
+        let item = match item {
+            AstItem::DefFunction(def) if is_generic(&def.sig.generics_params) => {
+                AstItem::DefFunction(def)
+            }
+            AstItem::DefFunction(def) => AstItem::DefFunction(self.rewrite_function(def, ctx)?),
+            AstItem::DefStruct(def) if is_generic(&def.generics_params) => AstItem::DefStruct(def),
+            AstItem::DefStruct(mut def) => {
+                for field in &mut def.value.fields {
+                    field.value = self.rewrite_type(field.value.clone())?;
+                }
+                AstItem::DefStruct(def)
+            }
+            AstItem::Impl(mut impl_) => {
+                let generic_self = match &impl_.self_ty {
+                    AstExpr::Locator(locator) => {
+                        self.lookup_struct(locator.to_path().last()).is_some()
+                    }
+                    _ => false,
+                };
+                // methods of generic structs and generic impls are not instantiated yet
+                if !generic_self && !is_generic(&impl_.generics_params) {
+                    impl_.items = impl_
+                        .items
+                        .into_iter()
+                        .map(|x| self.rewrite_item(x, ctx))
+                        .try_collect()?;
+                }
+                AstItem::Impl(impl_)
+            }
+            AstItem::Expr(expr) => AstItem::Expr(self.rewrite_expr(expr, ctx, None)?),
+            // submodules are monomorphized on their own
+            item => item,
+        };
+        Ok(item)
+    }
+    fn rewrite_function(
+        &mut self,
+        mut def: ItemDefFunction,
+        ctx: &SharedScopedContext,
+    ) -> Result<ItemDefFunction> {
+        let sub = ctx.child(Ident::new("__func__"), Visibility::Private, false);
+        for param in &mut def.sig.params {
+            param.ty = self.rewrite_type(param.ty.clone())?;
+            sub.insert_type(param.name.clone(), param.ty.clone());
+        }
+        if let Some(ret_ty) = def.sig.ret_ty.take() {
+            def.sig.ret_ty = Some(self.rewrite_type(ret_ty)?);
+        }
+        let ret_ty = def.sig.ret_ty.clone().unwrap_or(AstType::unit());
+        def.body = self.rewrite_expr(*def.body, &sub, Some(&ret_ty))?.into();
+        Ok(def)
+    }
+    fn rewrite_let(&mut self, mut let_: StmtLet, ctx: &SharedScopedContext) -> Result<StmtLet> {
+        let expected = match &mut let_.pat {
+            Pattern::Type(pat) => {
+                pat.ty = self.rewrite_type(pat.ty.clone())?;
+                Some(pat.ty.clone())
+            }
+            _ => None,
+        };
+        if let Some(init) = let_.init.take() {
+            let_.init = Some(self.rewrite_expr(init, ctx, expected.as_ref())?);
+        }
+        if let Some(diverge) = let_.diverge.take() {
+            let_.diverge = Some(self.rewrite_expr(diverge, ctx, None)?);
+        }
+        let ty = match (expected, &let_.init) {
+            (Some(ty), _) => ty,
+            (None, Some(init)) => self.type_of(init, ctx),
+            (None, None) => AstType::unknown(),
+        };
+        if let Some(name) = let_.pat.as_ident() {
+            ctx.insert_type(name.clone(), ty);
+        } else if let Pattern::Type(pat) = &let_.pat {
+            if let Some(name) = pat.pat.as_ident() {
+                ctx.insert_type(name.clone(), ty);
+            }
+        }
+        Ok(let_)
+    }
+    fn rewrite_type(&mut self, ty: AstType) -> Result<AstType> {
+        let ty = if self.subst.is_empty() {
+            ty
+        } else {
+            substitute(&ty, &self.subst)
+        };
+        let ty = match ty {
+            AstType::Expr(expr) => match *expr {
+                AstExpr::Locator(Locator::ParameterPath(path)) if path.segments.len() == 1 => {
+                    let segment = path.last();
+                    match self.lookup_struct(&segment.ident) {
+                        Some((scope, def)) if segment.args.len() == def.generics_params.len() => {
+                            let args = segment
+                                .args
+                                .iter()
+                                .map(|x| self.rewrite_type(x.clone()))
+                                .try_collect()?;
+                            AstType::ident(self.instantiate_struct(scope, &def, args)?)
+                        }
+                        _ => AstType::locator(Locator::ParameterPath(path)),
+                    }
+                }
+                expr => AstType::Expr(expr.into()),
+            },
+            AstType::Reference(mut r) => {
+                r.ty = self.rewrite_type(*r.ty)?.into();
+                AstType::Reference(r)
+            }
+            AstType::Tuple(t) => AstType::Tuple(TypeTuple {
+                types: t
+                    .types
+                    .into_iter()
+                    .map(|x| self.rewrite_type(x))
+                    .try_collect()?,
+            }),
+            AstType::Slice(s) => AstType::Slice(TypeSlice {
+                elem: self.rewrite_type(*s.elem)?.into(),
+            }),
+            AstType::Vec(v) => AstType::Vec(TypeVec {
+                ty: self.rewrite_type(*v.ty)?.into(),
+            }),
+            AstType::Function(mut f) => {
+                f.params = take(&mut f.params)
+                    .into_iter()
+                    .map(|x| self.rewrite_type(x))
+                    .try_collect()?;
+                if let Some(ret_ty) = f.ret_ty.take() {
+                    f.ret_ty = Some(self.rewrite_type(*ret_ty)?.into());
+                }
+                AstType::Function(f)
+            }
+            ty => ty,
+        };
+        Ok(ty)
+    }
+    fn rewrite_box(
+        &mut self,
+        expr: BExpr,
+        ctx: &SharedScopedContext,
+        expected: Option<&AstType>,
+    ) -> Result<BExpr> {
+        Ok(self.rewrite_expr(*expr, ctx, expected)?.into())
+    }
+    /// Rewrites the uses of generic items in `expr`, which is expected to be of `expected`
+    fn rewrite_expr(
+        &mut self,
+        expr: AstExpr,
+        ctx: &SharedScopedContext,
+        expected: Option<&AstType>,
+    ) -> Result<AstExpr> {
+        let expr = match expr {
+            AstExpr::Locator(Locator::ParameterPath(path)) if path.segments.len() == 1 => {
+                let segment = path.last();
+                match self.lookup_function(&segment.ident) {
+                    Some((scope, def)) if segment.args.len() == def.sig.generics_params.len() => {
+                        let args = segment
+                            .args
+                            .iter()
+                            .map(|x| self.rewrite_type(x.clone()))
+                            .try_collect()?;
+                        AstExpr::ident(self.instantiate_function(scope, &def, args)?)
+                    }
+                    _ => AstExpr::Locator(Locator::ParameterPath(path)),
+                }
+            }
+            AstExpr::Value(value) => match *value {
+                AstValue::Type(ty) => AstExpr::value(AstValue::Type(self.rewrite_type(ty)?)),
+                value => AstExpr::Value(value.into()),
+            },
+            AstExpr::Block(block) => {
+                let sub = ctx.child(Ident::new(BLOCK_SCOPE), Visibility::Private, true);
+                let mut stmts = block.stmts;
+                // the value of the block is what's expected of it
+                let last = match stmts.last() {
+                    Some(BlockStmt::Expr(expr)) if expr.semicolon != Some(true) => stmts.pop(),
+                    _ => None,
+                };
+                let mut stmts = self.rewrite_scope(stmts, sub.clone())?;
+                if let Some(BlockStmt::Expr(mut last)) = last {
+                    last.expr = self.rewrite_box(last.expr, &sub, expected)?;
+                    stmts.push(BlockStmt::Expr(last));
+                }
+                AstExpr::Block(ExprBlock::new_stmts(stmts))
+            }
+            AstExpr::Match(mut match_) => {
+                if let Some(scrutinee) = match_.scrutinee.take() {
+                    match_.scrutinee = Some(self.rewrite_box(scrutinee, ctx, None)?);
+                }
+                for case in &mut match_.cases {
+                    case.cond = self.rewrite_box(case.cond.clone(), ctx, None)?;
+                    case.body = self.rewrite_box(case.body.clone(), ctx, expected)?;
+                }
+                AstExpr::Match(match_)
+            }
+            AstExpr::If(if_) => AstExpr::If(ExprIf {
+                cond: self.rewrite_box(if_.cond, ctx, None)?,
+                then: self.rewrite_box(if_.then, ctx, expected)?,
+                elze: match if_.elze {
+                    Some(elze) => Some(self.rewrite_box(elze, ctx, expected)?),
+                    None => None,
+                },
+            }),
+            AstExpr::Loop(mut loop_) => {
+                loop_.body = self.rewrite_box(loop_.body, ctx, None)?;
+                AstExpr::Loop(loop_)
+            }
+            AstExpr::While(mut while_) => {
+                while_.cond = self.rewrite_box(while_.cond, ctx, None)?;
+                while_.body = self.rewrite_box(while_.body, ctx, None)?;
+                AstExpr::While(while_)
+            }
+            AstExpr::For(mut for_) => {
+                for_.iter = self.rewrite_box(for_.iter, ctx, None)?;
+                for_.body = self.rewrite_box(for_.body, ctx, None)?;
+                AstExpr::For(for_)
+            }
+            AstExpr::Break(mut break_) => {
+                if let Some(value) = break_.value.take() {
+                    break_.value = Some(self.rewrite_box(value, ctx, None)?);
+                }
+                AstExpr::Break(break_)
+            }
+            AstExpr::Return(mut return_) => {
+                if let Some(value) = return_.value.take() {
+                    return_.value = Some(self.rewrite_box(value, ctx, None)?);
+                }
+                AstExpr::Return(return_)
+            }
+            AstExpr::Invoke(invoke) => self.rewrite_invoke(invoke, ctx, expected)?,
+            AstExpr::BinOp(mut op) => {
+                op.lhs = self.rewrite_box(op.lhs, ctx, None)?;
+                op.rhs = self.rewrite_box(op.rhs, ctx, None)?;
+                AstExpr::BinOp(op)
+            }
+            AstExpr::UnOp(mut op) => {
+                op.val = self.rewrite_box(op.val, ctx, None)?;
+                AstExpr::UnOp(op)
+            }
+            AstExpr::Assign(mut assign) => {
+                assign.target = self.rewrite_box(assign.target, ctx, None)?;
+                assign.value = self.rewrite_box(assign.value, ctx, None)?;
+                AstExpr::Assign(assign)
+            }
+            AstExpr::Select(mut select) => {
+                select.obj = self.rewrite_box(select.obj, ctx, None)?;
+                AstExpr::Select(select)
+            }
+            AstExpr::Index(mut index) => {
+                index.obj = self.rewrite_box(index.obj, ctx, None)?;
+                index.index = self.rewrite_box(index.index, ctx, None)?;
+                AstExpr::Index(index)
+            }
+            AstExpr::Cast(mut cast) => {
+                cast.expr = self.rewrite_box(cast.expr, ctx, None)?;
+                cast.ty = self.rewrite_type(cast.ty)?;
+                AstExpr::Cast(cast)
+            }
+            AstExpr::Struct(s) => self.rewrite_struct(s, ctx, expected)?,
+            AstExpr::Reference(mut reference) => {
+                reference.referee = self.rewrite_box(reference.referee, ctx, None)?;
+                AstExpr::Reference(reference)
+            }
+            AstExpr::Dereference(mut deref) => {
+                deref.referee = self.rewrite_box(deref.referee, ctx, None)?;
+                AstExpr::Dereference(deref)
+            }
+            AstExpr::Tuple(mut tuple) => {
+                tuple.values = take(&mut tuple.values)
+                    .into_iter()
+                    .map(|x| self.rewrite_expr(x, ctx, None))
+                    .try_collect()?;
+                AstExpr::Tuple(tuple)
+            }
+            AstExpr::Try(mut try_) => {
+                try_.expr = self.rewrite_box(try_.expr, ctx, None)?;
+                AstExpr::Try(try_)
+            }
+            AstExpr::Let(mut let_) => {
+                let_.expr = self.rewrite_box(let_.expr, ctx, None)?;
+                AstExpr::Let(let_)
+            }
+            AstExpr::Closure(closure) => self.rewrite_closure(closure, ctx)?,
+            AstExpr::Array(mut array) => {
+                array.values = take(&mut array.values)
+                    .into_iter()
+                    .map(|x| self.rewrite_expr(x, ctx, None))
+                    .try_collect()?;
+                AstExpr::Array(array)
+            }
+            AstExpr::ArrayRepeat(mut repeat) => {
+                repeat.value = self.rewrite_box(repeat.value, ctx, None)?;
+                repeat.len = self.rewrite_box(repeat.len, ctx, None)?;
+                AstExpr::ArrayRepeat(repeat)
+            }
+            AstExpr::Paren(mut paren) => {
+                paren.expr = self.rewrite_box(paren.expr, ctx, expected)?;
+                AstExpr::Paren(paren)
+            }
+            AstExpr::Const(mut const_) => {
+                const_.block = self.rewrite_box(const_.block, ctx, expected)?;
+                AstExpr::Const(const_)
+            }
+            AstExpr::Unsafe(mut unsafe_) => {
+                unsafe_.block = self.rewrite_box(unsafe_.block, ctx, expected)?;
+                AstExpr::Unsafe(unsafe_)
+            }
+            AstExpr::Async(mut async_) => {
+                async_.block = self.rewrite_box(async_.block, ctx, None)?;
+                AstExpr::Async(async_)
+            }
+            AstExpr::Item(item) => AstExpr::Item(self.rewrite_item(*item, ctx)?.into()),
+            expr => expr,
+        };
+        Ok(expr)
+    }
+    fn rewrite_invoke(
+        &mut self,
+        mut invoke: ExprInvoke,
+        ctx: &SharedScopedContext,
+        expected: Option<&AstType>,
+    ) -> Result<AstExpr> {
+        invoke.args = take(&mut invoke.args)
+            .into_iter()
+            .map(|x| self.rewrite_expr(x, ctx, None))
+            .try_collect()?;
+        invoke.target = match invoke.target {
+            ExprInvokeTarget::Function(locator) => ExprInvokeTarget::Function(
+                self.rewrite_callee(locator, &invoke.args, ctx, expected)?,
+            ),
+            ExprInvokeTarget::Method(mut select) => {
+                select.obj = self.rewrite_box(select.obj, ctx, None)?;
+                ExprInvokeTarget::Method(select)
+            }
+            ExprInvokeTarget::Type(ty) => ExprInvokeTarget::Type(self.rewrite_type(ty)?),
+            ExprInvokeTarget::Expr(expr) => {
+                ExprInvokeTarget::Expr(self.rewrite_box(expr, ctx, None)?)
+            }
+            target => target,
+        };
+        Ok(AstExpr::Invoke(invoke))
+    }
+    /// Points a call of a generic function at the instance for its type args
+    fn rewrite_callee(
+        &mut self,
+        locator: Locator,
+        args: &[AstExpr],
+        ctx: &SharedScopedContext,
+        expected: Option<&AstType>,
+    ) -> Result<Locator> {
+        let (name, explicit) = match &locator {
+            Locator::Ident(name) => (name.clone(), vec![]),
+            Locator::ParameterPath(path) if path.segments.len() == 1 => {
+                (path.last().ident.clone(), path.last().args.clone())
+            }
+            _ => return Ok(locator),
+        };
+        let Some((scope, def)) = self.lookup_function(&name) else {
+            return Ok(locator);
+        };
+        let params = &def.sig.generics_params;
+        let type_args = if explicit.len() == params.len() {
+            explicit
+                .into_iter()
+                .map(|x| self.rewrite_type(x))
+                .try_collect()?
+        } else {
+            let generics = params.iter().map(|x| x.name.clone()).collect_vec();
+            let mut bindings = HashMap::new();
+            for (param, arg) in def.sig.params.iter().zip(args) {
+                let ty = self.type_of(arg, ctx);
+                self.match_type(&param.ty, &ty, &generics, &mut bindings);
+            }
+            if let (Some(ret_ty), Some(expected)) = (&def.sig.ret_ty, expected) {
+                self.match_type(ret_ty, expected, &generics, &mut bindings);
+            }
+            let node = AstExpr::Invoke(ExprInvoke {
+                target: ExprInvokeTarget::Function(locator),
+                args: args.to_vec(),
+            });
+            self.bound_args(&name, params, &bindings, &node)?
+        };
+        let name = self.instantiate_function(scope, &def, type_args)?;
+        Ok(Locator::Ident(name))
+    }
+    fn rewrite_struct(
+        &mut self,
+        mut s: ExprStruct,
+        ctx: &SharedScopedContext,
+        expected: Option<&AstType>,
+    ) -> Result<AstExpr> {
+        for field in &mut s.fields {
+            if let Some(value) = field.value.take() {
+                field.value = Some(self.rewrite_expr(value, ctx, None)?);
+            }
+        }
+        let (name, explicit) = match s.name.as_ref() {
+            AstExpr::Locator(Locator::Ident(name)) => (name.clone(), vec![]),
+            AstExpr::Locator(Locator::ParameterPath(path)) if path.segments.len() == 1 => {
+                (path.last().ident.clone(), path.last().args.clone())
+            }
+            _ => return Ok(AstExpr::Struct(s)),
+        };
+        let Some((scope, def)) = self.lookup_struct(&name) else {
+            return Ok(AstExpr::Struct(s));
+        };
+        let params = &def.generics_params;
+        let type_args = if explicit.len() == params.len() {
+            explicit
+                .into_iter()
+                .map(|x| self.rewrite_type(x))
+                .try_collect()?
+        } else {
+            let generics = params.iter().map(|x| x.name.clone()).collect_vec();
+            let mut bindings = HashMap::new();
+            for field in &s.fields {
+                let Some(declared) = def.value.fields.iter().find(|x| x.name == field.name) else {
+                    continue;
+                };
+                let value = match &field.value {
+                    Some(value) => value.clone(),
+                    None => AstExpr::ident(field.name.clone()),
+                };
+                let ty = self.type_of(&value, ctx);
+                self.match_type(&declared.value, &ty, &generics, &mut bindings);
+            }
+            if let Some(expected) = expected {
+                let pattern = AstType::locator(Locator::ParameterPath(ParameterPath {
+                    segments: vec![ParameterPathSegment::new(
+                        name.clone(),
+                        params
+                            .iter()
+                            .map(|x| AstType::ident(x.name.clone()))
+                            .collect(),
+                    )],
+                }));
+                self.match_type(&pattern, expected, &generics, &mut bindings);
+            }
+            self.bound_args(&name, params, &bindings, &AstExpr::Struct(s.clone()))?
+        };
+        let instance = self.instantiate_struct(scope, &def, type_args)?;
+        s.name = AstExpr::ident(instance).into();
+        Ok(AstExpr::Struct(s))
+    }
+    fn rewrite_closure(
+        &mut self,
+        mut closure: ExprClosure,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstExpr> {
+        let sub = ctx.child(Ident::new("__closure__"), Visibility::Private, true);
+        for param in &mut closure.params {
+            if let Pattern::Type(pat) = param {
+                pat.ty = self.rewrite_type(pat.ty.clone())?;
+                if let Some(name) = pat.pat.as_ident() {
+                    sub.insert_type(name.clone(), pat.ty.clone());
+                }
+            }
+        }
+        if let Some(ret_ty) = closure.ret_ty.take() {
+            closure.ret_ty = Some(self.rewrite_type(*ret_ty)?.into());
+        }
+        let ret_ty = closure.ret_ty.as_deref().cloned();
+        closure.body = self.rewrite_box(closure.body, &sub, ret_ty.as_ref())?;
+        Ok(AstExpr::Closure(closure))
+    }
+}
+
+impl OptimizePass for MonomorphizePass {
+    fn name(&self) -> &str {
+        "monomorphize"
+    }
+    fn interpreter(&self) -> Option<&InterpreterPass> {
+        Some(&self.interpreter)
+    }
+    fn optimize_block(&self, block: ExprBlock, ctx: &SharedScopedContext) -> Result<ExprBlock> {
+        self.monomorphize_block(block, ctx)
+    }
+    fn optimize_module(&self, module: AstModule, ctx: &SharedScopedContext) -> Result<AstModule> {
+        self.monomorphize_module(module, ctx)
+    }
+}
@@ -1,23 +1,16 @@
-use crate::pass::{InlinePass, OptimizePass, SpecializePass};
+use crate::pass::{InterpreterPass, OptimizePass};
 use common::*;
 use itertools::Itertools;
 use lang_core::ast::*;
-use lang_core::context::SharedScopedContext;
-use lang_core::id::Ident;
+use lang_core::context::{impl_key, MethodLookup, SharedScopedContext};
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator, Path};
+use lang_core::span::SourceNode;
 use std::mem::take;
 use std::sync::Arc;
 
-pub fn load_optimizers(serializer: Arc<dyn AstSerializer>) -> Vec<FoldOptimizer> {
-    let optimizers: Vec<Box<dyn OptimizePass>> = vec![
-        Box::new(SpecializePass::new(serializer.clone())),
-        Box::new(InlinePass::new(serializer.clone())),
-    ];
-
-    optimizers
-        .into_iter()
-        .map(|x| FoldOptimizer::new(serializer.clone(), x))
-        .collect()
-}
+/// name of the context created for each block
+pub const BLOCK_SCOPE: &str = "__block__";
 
 pub struct FoldOptimizer {
     serializer: Arc<dyn AstSerializer>,
@@ -35,23 +28,47 @@ impl FoldOptimizer {
     ) -> Result<AstExpr> {
         let mut func;
         let mut closure_context = None;
-        match &invoke.target {
+        match invoke.target.clone() {
             ExprInvokeTarget::Function(id) => {
+                func = ctx.get_expr_with_ctx(id.to_path()).ok_or_else(|| {
+                    Diagnostic::unresolved(&id).with_node(&AstExpr::Locator(id.clone()))
+                })?;
+            }
+            ExprInvokeTarget::Method(select) => {
+                let obj = self.optimize_expr(select.obj.get(), ctx)?;
+                let Some((path, found)) = self.resolve_method(&obj, &select.field, ctx) else {
+                    warn!("Couldn't resolve method {} of {}", select.field, obj);
+                    invoke.target = ExprInvokeTarget::Method(ExprSelect {
+                        obj: obj.into(),
+                        ..select
+                    });
+                    return Ok(AstExpr::Invoke(invoke.into()));
+                };
                 func = ctx
-                    .get_expr_with_ctx(id.to_path())
-                    .with_context(|| format!("Couldn't find {}", id))?;
+                    .get_expr_with_ctx(path.clone())
+                    .ok_or_else(|| Diagnostic::unresolved(&path))?;
+                // x.foo(a) => X::foo(&x, a), the receiver is passed the way `foo` takes it
+                invoke.target = ExprInvokeTarget::Function(Locator::path(path));
+                invoke.args.insert(0, adjust_receiver(obj, &found));
             }
-            ExprInvokeTarget::Method(_) => {
-                todo!()
+            ExprInvokeTarget::Type(AstType::Expr(expr)) => {
+                func = self.optimize_expr(expr.get(), ctx)?;
             }
-            ExprInvokeTarget::Type(_) => {
-                todo!()
+            ExprInvokeTarget::Type(ty) => {
+                // constructing a type, nothing to specialize but the arguments
+                debug!("Invoking type {}", ty);
+                invoke.args = invoke
+                    .args
+                    .into_iter()
+                    .map(|x| self.optimize_expr(x, ctx))
+                    .try_collect()?;
+                return Ok(AstExpr::Invoke(invoke.into()));
             }
-            ExprInvokeTarget::BinOp(_) => {
-                todo!()
+            ExprInvokeTarget::BinOp(kind) => {
+                func = AstExpr::value(AstValue::BinOpKind(kind));
             }
             ExprInvokeTarget::Closure(v) => {
-                func = AstExpr::value(v.clone().into());
+                func = AstExpr::value(v.into());
             }
             ExprInvokeTarget::Expr(expr) => {
                 func = self.optimize_expr(expr.get(), ctx)?;
@@ -62,6 +79,15 @@ impl FoldOptimizer {
             closure_context = Some(f.ctx.clone());
             func = f.expr.get();
         }
+        if let AstExpr::Value(value) = &mut func {
+            if let AstValue::Function(f) = value.as_mut() {
+                // the receiver is passed as the first argument
+                if f.sig.receiver.take().is_some() {
+                    let param = FunctionParam::new("self".into(), AstType::any());
+                    f.sig.params.insert(0, param);
+                }
+            }
+        }
 
         let args = take(&mut invoke.args);
         for arg in args {
@@ -125,8 +151,58 @@ impl FoldOptimizer {
                 }
             }
             ControlFlow::Continue => Ok(AstExpr::Invoke(invoke.into())),
-            _ => bail!("Cannot handle control flow {:?}", control),
+            _ => bail!(Diagnostic::new(
+                ErrorCode::OptimizeFailed,
+                format!("Cannot handle control flow {:?}", control)
+            )
+            .with_node(&AstExpr::Invoke(invoke.into()))
+            .with_label(format!("{} pass returned {:?}", self.pass.name(), control))),
+        }
+    }
+
+    /// Finds the type that an object belongs to, if it's known at compile time
+    fn resolve_type_name(&self, obj: &AstExpr, ctx: &SharedScopedContext) -> Option<Ident> {
+        match obj {
+            AstExpr::Value(value) => match value.as_ref() {
+                AstValue::Expr(expr) => self.resolve_type_name(expr, ctx),
+                value => impl_key(&value.nominal_type()?),
+            },
+            AstExpr::Struct(s) => match s.name.as_ref() {
+                AstExpr::Locator(locator) => Some(locator.to_path().last().clone()),
+                _ => None,
+            },
+            AstExpr::Locator(locator) => {
+                let value = ctx.get_value(locator.to_path())?;
+                self.resolve_type_name(&AstExpr::value(value), ctx)
+            }
+            AstExpr::Closured(c) => self.resolve_type_name(&c.expr, &c.ctx),
+            AstExpr::Paren(p) => self.resolve_type_name(&p.expr, ctx),
+            AstExpr::Reference(r) => self.resolve_type_name(&r.referee, ctx),
+            _ => None,
+        }
+    }
+    /// Resolves `obj.method` to the path of the method in its impl block
+    pub fn resolve_method(
+        &self,
+        obj: &AstExpr,
+        method: &Ident,
+        ctx: &SharedScopedContext,
+    ) -> Option<(Path, MethodLookup)> {
+        let ty = self.resolve_type_name(obj, ctx)?;
+        // `(&x).foo()` is looked up on `&X`, so that `derefs` counts the `&` to strip
+        let receiver = (0..reference_depth(obj))
+            .fold(AstType::ident(ty.clone()), |ty, _| AstType::reference(ty));
+        let found = ctx.lookup_method(&receiver, method)?;
+        let path = Path::new(vec![ty.clone(), method.clone()]);
+        if ctx.get_value(path.clone()).is_none() {
+            // default methods of traits are not declared in the impl, register them on first use
+            let sub = found
+                .ctx
+                .get_module_recursive(Path::new(vec![ty.clone()]))
+                .unwrap_or_else(|| found.ctx.child(ty, Visibility::Public, false));
+            self.prescan_def_function(&found.func, &sub).ok()?;
         }
+        Some((path, found))
     }
 
     pub fn optimize_expr(&self, mut expr: AstExpr, ctx: &SharedScopedContext) -> Result<AstExpr> {
@@ -136,13 +212,27 @@ impl FoldOptimizer {
         expr = match expr {
             AstExpr::Locator(val) => {
                 info!("Looking for {}", val);
-                ctx.get_expr_with_ctx(val.to_path())
-                    .with_context(|| format!("Couldn't find {}", val))?
+                match ctx.get_expr_with_ctx(val.to_path()) {
+                    Some(expr) => expr,
+                    None => {
+                        bail!(Diagnostic::unresolved(&val).with_node(&AstExpr::Locator(val)))
+                    }
+                }
             }
             AstExpr::Block(x) => self.optimize_block(x, ctx)?,
             AstExpr::Match(x) => self.optimize_match(x, ctx)?,
             AstExpr::If(x) => self.optimize_if(x, ctx)?,
             AstExpr::Invoke(x) => self.optimize_invoke(x, ctx)?,
+            AstExpr::BinOp(x) => self.optimize_binop(x, ctx)?,
+            AstExpr::Select(x) => self.optimize_select(x, ctx)?,
+            // a const block that doesn't fold here is left for rustc to evaluate
+            AstExpr::Const(x) => match self.evaluate_const(&x, ctx) {
+                Ok(value) => AstExpr::value(value),
+                Err(err) => {
+                    debug!("Keeping const block: {:?}", err);
+                    AstExpr::Const(x)
+                }
+            },
             _ => self.pass.optimize_expr(expr, ctx)?,
         };
 
@@ -151,6 +241,71 @@ impl FoldOptimizer {
         Ok(expr)
     }
 
+    /// Optimizes `a + b` like the invoke `+(a, b)`, keeping the infix form if it doesn't fold
+    pub fn optimize_binop(&self, binop: ExprBinOp, ctx: &SharedScopedContext) -> Result<AstExpr> {
+        let invoke = ExprInvoke {
+            target: ExprInvokeTarget::BinOp(binop.kind),
+            args: vec![binop.lhs.get(), binop.rhs.get()],
+        };
+        let expr = match self.optimize_invoke(invoke, ctx)? {
+            AstExpr::Invoke(invoke) => match (invoke.target, invoke.args.as_slice()) {
+                (ExprInvokeTarget::BinOp(kind), [lhs, rhs]) => AstExpr::BinOp(ExprBinOp {
+                    kind,
+                    lhs: lhs.clone().into(),
+                    rhs: rhs.clone().into(),
+                }),
+                (target, _) => AstExpr::Invoke(ExprInvoke {
+                    target,
+                    args: invoke.args,
+                }),
+            },
+            expr => expr,
+        };
+        Ok(expr)
+    }
+
+    /// Projects a field out of a struct known at compile time, e.g. `self.x` in a method
+    pub fn optimize_select(
+        &self,
+        select: ExprSelect,
+        ctx: &SharedScopedContext,
+    ) -> Result<AstExpr> {
+        if let Some(value) = self.project_field(&select.obj, &select.field, ctx) {
+            return Ok(value);
+        }
+        self.pass.optimize_expr(AstExpr::Select(select), ctx)
+    }
+    /// The constant value of `obj.field`, looking through `&` as `&self` receivers do
+    fn project_field(
+        &self,
+        obj: &AstExpr,
+        field: &Ident,
+        ctx: &SharedScopedContext,
+    ) -> Option<AstExpr> {
+        match obj {
+            AstExpr::Locator(locator) => {
+                let value = ctx.get_value(locator.to_path())?;
+                self.project_field(&AstExpr::value(value), field, ctx)
+            }
+            AstExpr::Value(value) => match value.as_ref() {
+                AstValue::Expr(expr) => self.project_field(expr, field, ctx),
+                AstValue::Struct(s) => {
+                    let found = s.structural.fields.iter().find(|x| &x.name == field)?;
+                    Some(AstExpr::value(found.value.clone()))
+                }
+                _ => None,
+            },
+            // only literal fields, the others may refer to the scope of the struct expression
+            AstExpr::Struct(s) => match s.fields.iter().find(|x| &x.name == field)?.value {
+                Some(ref value @ AstExpr::Value(_)) => Some(value.clone()),
+                _ => None,
+            },
+            AstExpr::Reference(r) => self.project_field(&r.referee, field, ctx),
+            AstExpr::Paren(p) => self.project_field(&p.expr, field, ctx),
+            _ => None,
+        }
+    }
+
     pub fn optimize_import(
         &self,
         import: ItemImport,
@@ -174,6 +329,7 @@ impl FoldOptimizer {
             .items
             .iter()
             .try_for_each(|x| self.prescan_item(x, ctx))?;
+        self.pass.prescan_module(&module, ctx)?;
 
         module.items = module
             .items
@@ -189,6 +345,11 @@ impl FoldOptimizer {
         match item {
             AstItem::DefFunction(x) => self.prescan_def_function(x, ctx),
             AstItem::Module(x) => self.prescan_module(x, ctx),
+            AstItem::Impl(x) => self.prescan_impl(x, ctx),
+            AstItem::DefTrait(x) => {
+                ctx.insert_trait(x.clone());
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
@@ -199,6 +360,21 @@ impl FoldOptimizer {
         }
         Ok(())
     }
+    /// Registers methods and associated functions under the path `Type::name`
+    fn prescan_impl(&self, impl_: &ItemImpl, ctx: &SharedScopedContext) -> Result<()> {
+        let Some(name) = impl_key(&AstType::expr(impl_.self_ty.clone())) else {
+            warn!("Skipping impl for {}", impl_.self_ty);
+            return Ok(());
+        };
+        ctx.insert_impl(impl_.clone())?;
+        let sub = ctx
+            .get_module_recursive(Path::new(vec![name.clone()]))
+            .unwrap_or_else(|| ctx.child(name, Visibility::Public, false));
+        for item in &impl_.items {
+            self.prescan_item(item, &sub)?;
+        }
+        Ok(())
+    }
     pub fn optimize_item(&self, mut item: AstItem, ctx: &SharedScopedContext) -> Result<AstItem> {
         let serialized = self.serializer.serialize_item(&item)?;
         debug!("Doing {} for {}", self.pass.name(), serialized);
@@ -258,7 +434,10 @@ impl FoldOptimizer {
             BlockStmt::Any(_) => Ok(stmt),
             BlockStmt::Let(x) => self.optimize_let(x, ctx).map(BlockStmt::Let),
             #[allow(unreachable_patterns)]
-            _ => bail!("Could not optimize {:?}", stmt),
+            _ => bail!(Diagnostic::new(
+                ErrorCode::OptimizeFailed,
+                format!("Could not optimize {:?}", stmt)
+            )),
         }
     }
 
@@ -270,23 +449,29 @@ impl FoldOptimizer {
         }
     }
     pub fn optimize_block(&self, mut b: ExprBlock, ctx: &SharedScopedContext) -> Result<AstExpr> {
-        let ctx = ctx.child(Ident::new("__block__"), Visibility::Private, true);
+        let ctx = ctx.child(Ident::new(BLOCK_SCOPE), Visibility::Private, true);
         b.stmts
             .iter()
             .try_for_each(|x| self.prescan_stmt(x, &ctx))?;
 
-        let stmts: Vec<_> = b
+        let mut stmts: Vec<_> = b
             .stmts
             .into_iter()
             .map(|x| self.optimize_stmt(x, &ctx))
             .try_collect()?;
-        // stmts.retain(|x| !x.is_unit());
+        // items folded away by the pass leave units behind
+        stmts.retain(|x| !x.is_unit());
 
         b.stmts = stmts;
+        let b = self.pass.optimize_block(b, &ctx)?;
 
-        Ok(AstExpr::block(b))
+        Ok(b.into_expr())
     }
     pub fn optimize_match(&self, b: ExprMatch, ctx: &SharedScopedContext) -> Result<AstExpr> {
+        // the guards of pattern cases refer to the bindings of their patterns
+        if b.scrutinee.is_some() {
+            return self.pass.optimize_expr(AstExpr::Match(b), ctx);
+        }
         let mut cases = vec![];
         for case in b.cases {
             let cond: BExpr = self.optimize_expr(case.cond.into(), ctx)?.into();
@@ -297,30 +482,86 @@ impl FoldOptimizer {
                 ControlFlow::Return(_) => break,
                 ControlFlow::Into => {
                     let body: BExpr = self.optimize_expr(case.body.into(), ctx)?.into();
-                    cases.push(ExprMatchCase { cond, body });
+                    cases.push(ExprMatchCase {
+                        pat: None,
+                        cond,
+                        body,
+                    });
                 }
                 ControlFlow::IntoAndBreak(_) => {
                     let body: BExpr = self.optimize_expr(case.body.into(), ctx)?.into();
-                    cases.push(ExprMatchCase { cond, body });
+                    cases.push(ExprMatchCase {
+                        pat: None,
+                        cond,
+                        body,
+                    });
                     break;
                 }
             }
         }
 
-        Ok(AstExpr::Match(ExprMatch { cases }))
+        Ok(AstExpr::Match(ExprMatch {
+            scrutinee: None,
+            cases,
+        }))
+    }
+    /// `const { .. }` folds whatever the pass is, it's an error if it can't be evaluated
+    pub fn evaluate_const(&self, c: &ExprConst, ctx: &SharedScopedContext) -> Result<AstValue> {
+        let default;
+        let interpreter = match self.pass.interpreter() {
+            Some(interpreter) => interpreter,
+            None => {
+                default = InterpreterPass::new(self.serializer.clone());
+                &default
+            }
+        };
+        interpreter.interpret_expr(&c.block, ctx).map_err(|err| {
+            let node = AstExpr::Const(c.clone());
+            err.wrap_err(
+                Diagnostic::new(
+                    ErrorCode::OptimizeFailed,
+                    format!("{} is not known at compile time", node.describe()),
+                )
+                .with_node(&node)
+                .with_label("evaluated at compile time")
+                .with_help("use `if` without `const` for conditions known at runtime"),
+            )
+        })
     }
     pub fn optimize_if(&self, if_: ExprIf, ctx: &SharedScopedContext) -> Result<AstExpr> {
+        // `if const { .. }` keeps only the chosen branch
+        if let AstExpr::Const(c) = if_.cond.as_ref() {
+            let branch = match self.evaluate_const(c, ctx)? {
+                AstValue::Bool(b) if b.value => Some(if_.then),
+                AstValue::Bool(_) => if_.elze,
+                value => bail!(Diagnostic::new(
+                    ErrorCode::TypeMismatch,
+                    format!("Expected bool for `if const`, got {}", value)
+                )
+                .with_node(if_.cond.as_ref())
+                .with_label(format!("expected `bool`, found `{}`", value))),
+            };
+            return match branch {
+                Some(branch) => self.optimize_expr(branch.get(), ctx),
+                None => Ok(AstExpr::unit()),
+            };
+        }
         let mut cases = vec![ExprMatchCase {
+            pat: None,
             cond: if_.cond,
             body: if_.then,
         }];
         if let Some(elze) = if_.elze {
             cases.push(ExprMatchCase {
+                pat: None,
                 cond: AstExpr::Value(AstValue::Bool(ValueBool { value: true }).into()).into(),
                 body: elze,
             });
         }
-        let match_ = ExprMatch { cases };
+        let match_ = ExprMatch {
+            scrutinee: None,
+            cases,
+        };
 
         let match_ = self.optimize_match(match_, ctx)?;
         if let AstExpr::Match(match_) = match_ {
@@ -331,6 +572,11 @@ impl FoldOptimizer {
             if match_.cases.len() >= 1 {
                 let mut iter = match_.cases.into_iter();
                 let first = iter.next().unwrap();
+                if let AstExpr::Value(value) = first.cond.as_ref() {
+                    if let AstValue::Bool(ValueBool { value: true }) = value.as_ref() {
+                        return Ok(first.body.get());
+                    }
+                }
                 let second = iter.next();
                 return Ok(ExprIf {
                     cond: first.cond,
@@ -414,3 +660,39 @@ impl FoldOptimizer {
         }
     }
 }
+
+/// How many `&` an expression takes of its value, like 1 for `&x`
+fn reference_depth(expr: &AstExpr) -> usize {
+    match expr {
+        AstExpr::Reference(r) => 1 + reference_depth(&r.referee),
+        AstExpr::Paren(p) => reference_depth(&p.expr),
+        _ => 0,
+    }
+}
+fn strip_reference(expr: AstExpr) -> AstExpr {
+    match expr {
+        AstExpr::Reference(r) => r.referee.get(),
+        AstExpr::Paren(p) => strip_reference(p.expr.get()),
+        expr => AstExpr::Dereference(ExprDereference {
+            referee: expr.into(),
+        }),
+    }
+}
+/// Passes `obj` as the receiver of the method: `x` for `self`, `&x` for `&self` and
+/// `&mut x` for `&mut self`
+fn adjust_receiver(mut obj: AstExpr, found: &MethodLookup) -> AstExpr {
+    for _ in 0..found.derefs {
+        obj = strip_reference(obj);
+    }
+    if !found.by_ref() {
+        return obj;
+    }
+    let mutable = matches!(
+        found.func.sig.receiver,
+        Some(FunctionParamReceiver::RefMut | FunctionParamReceiver::RefMutStatic)
+    );
+    AstExpr::Reference(ExprReference {
+        referee: obj.into(),
+        mutable: Some(mutable),
+    })
+}
@@ -1,14 +1,56 @@
-use crate::pass::{InterpreterPass, OptimizePass};
+use crate::pass::{
+    has_return, is_inline_never, InterpreterPass, OptimizePass, UsageCollector, BLOCK_SCOPE,
+};
 use common::*;
 use itertools::{zip_eq, Itertools};
 use lang_core::ast::*;
 use lang_core::context::SharedScopedContext;
-use lang_core::id::{Ident, Locator};
+use lang_core::error::{render_report, Diagnostic};
+use lang_core::id::{Ident, Locator, Path};
+use lang_core::span::SourceNode;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+/// Identifies a specialization, so that call sites with the same constant arguments share it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpecializationKey {
+    pub func: String,
+    /// folded constant args, `_` for args only known at runtime
+    pub args: Vec<String>,
+    pub type_args: Vec<String>,
+}
+/// `#[shll::no_specialize]` keeps the calls of a function, like runtime calls
+fn is_no_specialize(func: &ValueFunction) -> bool {
+    let path = Path::new(vec!["shll".into(), "no_specialize".into()]);
+    func.attrs.find_by_path(&path).is_some()
+}
+/// Attributes that hold for a specialized copy as well. The others, like `#[test]`,
+/// `#[no_mangle]` or the `shll` ones, only belong to the original function
+const SPECIALIZED_ATTRS: &[&str] = &["inline", "cold", "must_use", "allow", "warn", "deny", "cfg"];
+fn specialized_attrs(attrs: &[AstAttribute]) -> Vec<AstAttribute> {
+    attrs
+        .iter()
+        .filter(|x| {
+            let path = x.meta.name();
+            path.segments.len() == 1 && SPECIALIZED_ATTRS.contains(&path.last().as_str())
+        })
+        .cloned()
+        .collect()
+}
+struct SpecializedFunction {
+    name: Ident,
+    /// the block or module the function is emitted into
+    scope: SharedScopedContext,
+}
 
 pub struct SpecializePass {
     spec_id: AtomicUsize,
+    cache: Mutex<HashMap<SpecializationKey, SpecializedFunction>>,
+    /// specialized functions waiting to be emitted into their blocks or modules
+    pending: Mutex<Vec<(SharedScopedContext, ItemDefFunction)>>,
+    /// the scopes of the modules being optimized
+    modules: Mutex<Vec<SharedScopedContext>>,
     serializer: Arc<dyn AstSerializer>,
     // TODO: use Context instead of InterpreterPass
     interpreter: InterpreterPass,
@@ -17,6 +59,9 @@ impl SpecializePass {
     pub fn new(serializer: Arc<dyn AstSerializer>) -> Self {
         Self {
             spec_id: AtomicUsize::default(),
+            cache: Default::default(),
+            pending: Default::default(),
+            modules: Default::default(),
             interpreter: InterpreterPass::new(serializer.clone()),
             serializer,
         }
@@ -39,9 +84,9 @@ impl SpecializePass {
         let mut args: Vec<BExpr> = vec![];
         for arg in invoke.args.iter() {
             let x = match arg.get() {
-                AstExpr::Locator(v) => ctx
-                    .get_expr(v.to_path())
-                    .with_context(|| format!("Couldn't find {:?} in context", v))?,
+                AstExpr::Locator(v) => ctx.get_expr(v.to_path()).ok_or_else(|| {
+                    Diagnostic::unresolved(&v).with_node(&AstExpr::Locator(v.clone()))
+                })?,
                 x => x,
             };
             args.push(x.into())
@@ -59,14 +104,31 @@ impl SpecializePass {
         let name = func.name.as_ref().map(|x| x.name.as_str()).unwrap_or("fun");
         let mut new_params: Vec<FunctionParam> = vec![];
         let mut new_args: Vec<AstExpr> = vec![];
+        let mut key_args = vec![];
         for (param, arg) in zip_eq(func.params.iter(), args.iter()) {
             match self.interpreter.interpret_expr(&arg.get(), ctx) {
                 Err(err) => {
-                    warn!("Cannot evaluate arg {} {:?}: {:?}", param.name, arg, err);
+                    warn!(
+                        "Cannot evaluate arg {} {}: {}",
+                        param.name,
+                        arg.get().describe(),
+                        render_report(&err)
+                    );
                     new_args.push(arg.get());
                     new_params.push(param.clone());
+                    key_args.push("_".to_string());
                 }
-                Ok(_) => {}
+                Ok(value) => key_args.push(self.serializer.serialize_value(&value)?),
+            }
+        }
+        // `self` can't be rebound in a free function, the body must have folded it away
+        let receiver = Ident::new("self");
+        if func.params.iter().any(|x| x.name == receiver) {
+            let mut usage = UsageCollector::new();
+            usage.collect_expr(&func.body.get());
+            if usage.is_used(&receiver) || new_params.iter().any(|x| x.name == receiver) {
+                debug!("Keeping method call {}, its receiver is still used", invoke);
+                return Ok(invoke.into());
             }
         }
         if !new_params.is_empty() && new_params.len() == func.params.len() {
@@ -87,11 +149,40 @@ impl SpecializePass {
                 continue;
             }
             let name = name.last().clone();
+            if name == receiver {
+                continue;
+            }
 
             let binding = BlockStmt::Let(StmtLet::new_simple(name, AstExpr::value(value).into()));
             bindings.push(binding);
         }
 
+        let key = SpecializationKey {
+            func: format!(
+                "{:?}: {}",
+                invoke.target,
+                self.serializer.serialize_value_function(func)?
+            ),
+            args: key_args,
+            type_args: func
+                .generics_params
+                .iter()
+                .map(|x| match ctx.get_value(&x.name) {
+                    Some(value) => self.serializer.serialize_value(&value),
+                    None => Ok("_".to_string()),
+                })
+                .try_collect()?,
+        };
+        if !invoke.args.is_empty() {
+            if let Some(name) = self.lookup_specialized(&key, ctx) {
+                debug!("Reusing specialized function {} for {}", name, invoke);
+                return Ok(AstExpr::Invoke(ExprInvoke {
+                    target: ExprInvokeTarget::Function(name.into()),
+                    args: new_args,
+                }));
+            }
+        }
+
         let new_body = AstExpr::block(ExprBlock::new_stmts_expr(bindings, func.body.clone()));
         let new_name = Ident::new(format!(
             "{}_{}",
@@ -119,10 +210,13 @@ impl SpecializePass {
             name: Some(new_name.clone()),
             receiver: None,
             params: new_params.clone(),
+            lifetimes: vec![],
             generics_params: vec![],
+            where_predicates: vec![],
             ret_ty: ret.clone(),
         };
         let new_func = ValueFunction {
+            attrs: specialized_attrs(&func.attrs),
             sig,
             body: new_body.into(),
         };
@@ -139,29 +233,119 @@ impl SpecializePass {
         //     func: Expr::ident(new_name).into(),
         //     args: Default::default(),
         // });
-        if invoke.args.is_empty() {
+        // a `return` in the body would leave the caller instead
+        if invoke.args.is_empty() && !has_return(&new_func.body) && !is_inline_never(func) {
             return Ok(new_func.body.into());
         }
-        let block = ExprBlock::new_stmts_expr(
-            vec![BlockStmt::Item(
-                AstItem::DefFunction(ItemDefFunction {
-                    attrs: vec![],
-                    name: new_name.clone(),
-                    ty: None,
-                    sig: new_func.sig,
-                    body: new_func.body,
-                    visibility: Visibility::Private,
-                })
-                .into(),
-            )],
-            AstExpr::Invoke(ExprInvoke {
-                target: ExprInvokeTarget::Function(new_name.into()),
-                args: Default::default(),
-            }),
-        );
+        let def = ItemDefFunction {
+            attrs: new_func.attrs,
+            name: new_name.clone(),
+            ty: None,
+            sig: new_func.sig,
+            body: new_func.body,
+            visibility: Visibility::Private,
+        };
+        let call = AstExpr::Invoke(ExprInvoke {
+            target: ExprInvokeTarget::Function(new_name.clone().into()),
+            args: new_args,
+        });
+        // the specialized function lives next to the original one, in its block or module
+        if let Some(scope) = self.specialization_scope(ctx) {
+            self.cache.lock().unwrap().insert(
+                key,
+                SpecializedFunction {
+                    name: new_name,
+                    scope: scope.clone(),
+                },
+            );
+            self.pending.lock().unwrap().push((scope, def));
+            return Ok(call);
+        }
+        let block =
+            ExprBlock::new_stmts_expr(vec![BlockStmt::item(AstItem::DefFunction(def))], call);
         Ok(AstExpr::Block(block))
     }
 
+    /// `ctx` is the context of the invocation, whose parent is where the function is defined
+    fn specialization_scope(&self, ctx: &SharedScopedContext) -> Option<SharedScopedContext> {
+        let mut scope = ctx.get_parent()?;
+        // methods are registered in the scope of their type, their specializations go next to
+        // the impl
+        if let Some(parent) = scope.get_parent() {
+            if !parent.get_impls(scope.ident()).is_empty() {
+                scope = parent;
+            }
+        }
+        let is_module = self.modules.lock().unwrap().contains(&scope);
+        if scope.ident().as_str() != BLOCK_SCOPE && !is_module {
+            return None;
+        }
+        Some(scope)
+    }
+    fn lookup_specialized(
+        &self,
+        key: &SpecializationKey,
+        ctx: &SharedScopedContext,
+    ) -> Option<Ident> {
+        let cache = self.cache.lock().unwrap();
+        let specialized = cache.get(key)?;
+        if !specialized.scope.is_ancestor_of(ctx) {
+            return None;
+        }
+        Some(specialized.name.clone())
+    }
+    fn take_pending(&self, ctx: &SharedScopedContext) -> Vec<ItemDefFunction> {
+        let mut pending = self.pending.lock().unwrap();
+        let (defs, rest): (Vec<_>, Vec<_>) = pending.drain(..).partition(|(scope, _)| scope == ctx);
+        *pending = rest;
+        defs.into_iter().map(|(_, def)| def).collect()
+    }
+    /// Emits the specialized functions of this block in front of its statements
+    pub fn emit_specialized(&self, mut block: ExprBlock, ctx: &SharedScopedContext) -> ExprBlock {
+        let defs = self.take_pending(ctx);
+        if defs.is_empty() {
+            return block;
+        }
+        let index = block
+            .stmts
+            .iter()
+            .position(|x| !matches!(x, BlockStmt::Item(_)))
+            .unwrap_or(block.stmts.len());
+        block.stmts.splice(
+            index..index,
+            defs.into_iter()
+                .map(|def| BlockStmt::item(AstItem::DefFunction(def))),
+        );
+        block
+    }
+    /// Emits the specialized functions of this module in front of its expressions
+    pub fn emit_specialized_module(
+        &self,
+        mut module: AstModule,
+        ctx: &SharedScopedContext,
+    ) -> AstModule {
+        {
+            let mut modules = self.modules.lock().unwrap();
+            if let Some(index) = modules.iter().rposition(|x| x == ctx) {
+                modules.remove(index);
+            }
+            // submodules registering their items in the same scope leave them to the outermost
+            if modules.contains(ctx) {
+                return module;
+            }
+        }
+        let defs = self.take_pending(ctx);
+        let index = module
+            .items
+            .iter()
+            .position(|x| matches!(x, AstItem::Expr(_)))
+            .unwrap_or(module.items.len());
+        module
+            .items
+            .splice(index..index, defs.into_iter().map(AstItem::DefFunction));
+        module
+    }
+
     pub fn specialize_invoke_func(
         &self,
         invoke: ExprInvoke,
@@ -174,6 +358,9 @@ impl SpecializePass {
             }
             _ => {}
         }
+        if is_no_specialize(func) {
+            return Ok(invoke.into());
+        }
 
         self.specialize_invoke_details(invoke, func, ctx)
     }
@@ -182,6 +369,9 @@ impl OptimizePass for SpecializePass {
     fn name(&self) -> &str {
         "specialize"
     }
+    fn interpreter(&self) -> Option<&InterpreterPass> {
+        Some(&self.interpreter)
+    }
 
     fn try_evaluate_expr(&self, pat: &AstExpr, ctx: &SharedScopedContext) -> Result<AstExpr> {
         match ctx.try_get_value_from_expr(pat) {
@@ -225,4 +415,14 @@ impl OptimizePass for SpecializePass {
     fn evaluate_condition(&self, expr: AstExpr, ctx: &SharedScopedContext) -> Result<ControlFlow> {
         self.interpreter.evaluate_condition(expr, ctx)
     }
+    fn optimize_block(&self, block: ExprBlock, ctx: &SharedScopedContext) -> Result<ExprBlock> {
+        Ok(self.emit_specialized(block, ctx))
+    }
+    fn prescan_module(&self, _module: &AstModule, ctx: &SharedScopedContext) -> Result<()> {
+        self.modules.lock().unwrap().push(ctx.clone());
+        Ok(())
+    }
+    fn optimize_module(&self, module: AstModule, ctx: &SharedScopedContext) -> Result<AstModule> {
+        Ok(self.emit_specialized_module(module, ctx))
+    }
 }
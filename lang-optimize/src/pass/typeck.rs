@@ -0,0 +1,271 @@
+use crate::pass::InterpreterPass;
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::ctx::{Context, TypeSystem};
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator, ParameterPath, ParameterPathSegment, Path};
+use lang_core::pat::Pattern;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How a type is named in diagnostics: structs and enums by their name, not their body
+pub(crate) fn type_name(ty: &AstType) -> String {
+    match ty {
+        AstType::Struct(s) => s.name.to_string(),
+        AstType::Enum(e) => e.name.to_string(),
+        AstType::Reference(r) => {
+            let mutability = if r.mutability == Some(true) {
+                "mut "
+            } else {
+                ""
+            };
+            format!("&{}{}", mutability, type_name(&r.ty))
+        }
+        _ => ty.to_string(),
+    }
+}
+pub(crate) fn mismatch(expected: &AstType, found: &AstType, node: &AstExpr) -> Diagnostic {
+    let expected = type_name(expected);
+    let found = type_name(found);
+    Diagnostic::new(
+        ErrorCode::TypeMismatch,
+        format!("Expected {}, found {}", expected, found),
+    )
+    .with_node(node)
+    .with_label(format!("expected `{}`, found `{}`", expected, found))
+}
+/// The types matched by each of `patterns` against a tuple of `types`, where a `..` stands
+/// for the elements the other patterns leave out; `None` if the lengths cannot match
+pub(crate) fn tuple_element_types(
+    patterns: &[Pattern],
+    types: Vec<AstType>,
+) -> Option<Vec<AstType>> {
+    let Some(rest) = patterns.iter().position(|x| matches!(x, Pattern::Rest(_))) else {
+        return (patterns.len() == types.len()).then_some(types);
+    };
+    let after = patterns.len() - rest - 1;
+    if types.len() < rest + after {
+        return None;
+    }
+    let mut matched = types[..rest].to_vec();
+    matched.push(AstType::unknown());
+    matched.extend_from_slice(&types[types.len() - after..]);
+    Some(matched)
+}
+pub(crate) fn no_field(ty: &AstType, field: &Ident, node: &AstExpr) -> Diagnostic {
+    Diagnostic::new(
+        ErrorCode::UnresolvedName,
+        format!("No field `{}` on type {}", field, type_name(ty)),
+    )
+    .with_node(node)
+    .with_label("unknown field")
+}
+pub(crate) fn generic_name<'a>(ty: &'a AstType, generics: &[Ident]) -> Option<&'a Ident> {
+    match ty {
+        AstType::Expr(expr) => match expr.as_ref() {
+            AstExpr::Locator(Locator::Ident(ident)) if generics.contains(ident) => Some(ident),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+/// Replaces the generic params in `ty` by the types they were instantiated with
+pub(crate) fn substitute(ty: &AstType, subst: &HashMap<Ident, AstType>) -> AstType {
+    let generics = subst.keys().cloned().collect::<Vec<_>>();
+    if let Some(name) = generic_name(ty, &generics) {
+        return subst[name].clone();
+    }
+    match ty {
+        AstType::Reference(r) => {
+            let mut r = r.clone();
+            r.ty = substitute(&r.ty, subst).into();
+            AstType::Reference(r)
+        }
+        AstType::Tuple(t) => AstType::Tuple(TypeTuple {
+            types: t.types.iter().map(|x| substitute(x, subst)).collect(),
+        }),
+        AstType::Slice(s) => AstType::Slice(TypeSlice {
+            elem: substitute(&s.elem, subst).into(),
+        }),
+        AstType::Vec(v) => AstType::Vec(TypeVec {
+            ty: substitute(&v.ty, subst).into(),
+        }),
+        AstType::Expr(expr) => match expr.as_ref() {
+            AstExpr::Locator(Locator::ParameterPath(path)) => {
+                AstType::locator(Locator::ParameterPath(ParameterPath {
+                    segments: path
+                        .segments
+                        .iter()
+                        .map(|x| {
+                            ParameterPathSegment::new(
+                                x.ident.clone(),
+                                x.args.iter().map(|x| substitute(x, subst)).collect(),
+                            )
+                        })
+                        .collect(),
+                }))
+            }
+            _ => ty.clone(),
+        },
+        _ => ty.clone(),
+    }
+}
+/// Checks that modules are well typed before they are printed back to Rust: function
+/// signatures against their bodies and callers, let bindings, struct literals, field selects,
+/// generic instantiation and references.
+///
+/// The items are declared here, while their bodies are inferred by the unification of
+/// [`InterpreterPass::infer_function_body`]. Types the checker knows nothing of, like those
+/// from `std`, are compared by name. Mismatches are reported as [`ErrorCode::TypeMismatch`]
+/// diagnostics at the offending expression.
+#[derive(Clone)]
+pub struct TypeChecker {
+    interpreter: InterpreterPass,
+}
+impl TypeChecker {
+    pub fn new(serializer: Arc<dyn AstSerializer>) -> Self {
+        Self {
+            interpreter: InterpreterPass::new(serializer),
+        }
+    }
+
+    pub fn check_file(&self, file: &AstFile, ctx: &SharedScopedContext) -> Result<()> {
+        self.check_items(&file.items, ctx)
+    }
+    pub fn check_module(&self, module: &AstModule, ctx: &SharedScopedContext) -> Result<()> {
+        let sub = ctx.child(module.name.clone(), module.visibility, false);
+        self.check_items(&module.items, &sub)
+    }
+    /// Items may refer to each other regardless of their order, so all are declared first
+    pub fn check_items(&self, items: &[AstItem], ctx: &SharedScopedContext) -> Result<()> {
+        for item in items {
+            self.declare_item(item, ctx)?;
+        }
+        for item in items {
+            self.check_item(item, ctx)?;
+        }
+        Ok(())
+    }
+    fn impl_context(
+        &self,
+        impl_: &ItemImpl,
+        ctx: &SharedScopedContext,
+    ) -> Option<SharedScopedContext> {
+        let AstExpr::Locator(locator) = &impl_.self_ty else {
+            return None;
+        };
+        let name = locator.to_path().last().clone();
+        let sub = ctx
+            .get_module_recursive(Path::new(vec![name.clone()]))
+            .unwrap_or_else(|| ctx.child(name, Visibility::Public, false));
+        Some(sub)
+    }
+    fn declare_item(&self, item: &AstItem, ctx: &SharedScopedContext) -> Result<()> {
+        match item {
+            AstItem::DefFunction(def) => {
+                ctx.insert_value_with_ctx(def.name.clone(), AstValue::Function(def._to_value()));
+            }
+            AstItem::DefStruct(_) | AstItem::DefEnum(_) | AstItem::DefType(_) => {
+                self.interpreter.interpret_item(item, ctx)?;
+            }
+            AstItem::DefConst(def) => {
+                let ty = match &def.ty {
+                    Some(ty) => self.resolve_type(ty, ctx)?,
+                    None => AstType::unknown(),
+                };
+                ctx.insert_type(def.name.clone(), ty);
+            }
+            AstItem::DefStatic(def) => {
+                let ty = self.resolve_type(&def.ty, ctx)?;
+                ctx.insert_type(def.name.clone(), ty);
+            }
+            AstItem::Module(module) => {
+                let sub = ctx.child(module.name.clone(), module.visibility, false);
+                for item in &module.items {
+                    self.declare_item(item, &sub)?;
+                }
+            }
+            AstItem::Impl(impl_) => {
+                let Some(sub) = self.impl_context(impl_, ctx) else {
+                    return Ok(());
+                };
+                let self_ty = self.resolve_type(&AstType::expr(impl_.self_ty.clone()), ctx)?;
+                sub.insert_value_with_ctx("Self", AstValue::Type(self_ty));
+                for item in &impl_.items {
+                    self.declare_item(item, &sub)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    fn check_item(&self, item: &AstItem, ctx: &SharedScopedContext) -> Result<()> {
+        match item {
+            AstItem::DefFunction(def) => self.check_function(def, ctx),
+            AstItem::DefConst(def) => {
+                let ty = ctx.get_type(def.name.clone()).unwrap_or(AstType::unknown());
+                self.check_expr(&def.value, &ty, ctx)
+            }
+            AstItem::DefStatic(def) => {
+                let ty = self.resolve_type(&def.ty, ctx)?;
+                self.check_expr(&def.value, &ty, ctx)
+            }
+            AstItem::Module(module) => {
+                let sub = ctx
+                    .get_module_recursive(Path::new(vec![module.name.clone()]))
+                    .with_context(|| format!("Module {} was not declared", module.name))?;
+                for item in &module.items {
+                    self.check_item(item, &sub)?;
+                }
+                Ok(())
+            }
+            AstItem::Impl(impl_) => {
+                let Some(sub) = self.impl_context(impl_, ctx) else {
+                    return Ok(());
+                };
+                for item in &impl_.items {
+                    self.check_item(item, &sub)?;
+                }
+                Ok(())
+            }
+            AstItem::Expr(expr) => self.check_expr(expr, &AstType::unknown(), ctx),
+            _ => Ok(()),
+        }
+    }
+    fn check_function(&self, def: &ItemDefFunction, ctx: &SharedScopedContext) -> Result<()> {
+        self.interpreter
+            .infer_function_body(&def._to_value(), ctx)
+            .map(|_| ())
+    }
+
+    /// Resolves the names in a type to what they were declared as, evaluating type functions
+    pub fn resolve_type(&self, ty: &AstType, ctx: &SharedScopedContext) -> Result<AstType> {
+        self.interpreter.resolve_type(ty, ctx)
+    }
+    /// Checks `expr` against the type it is expected to have, which also types unsuffixed
+    /// literals, like `1` as `u8` in `let a: u8 = 1;`
+    pub fn check_expr(
+        &self,
+        expr: &AstExpr,
+        expected: &AstType,
+        ctx: &SharedScopedContext,
+    ) -> Result<()> {
+        self.interpreter.check_expr(expr, expected, ctx)
+    }
+    pub fn infer_expr(&self, expr: &AstExpr, ctx: &SharedScopedContext) -> Result<AstType> {
+        self.interpreter.infer_expr(expr, ctx)
+    }
+    pub fn infer_value(&self, value: &AstValue, ctx: &SharedScopedContext) -> Result<AstType> {
+        self.infer_expr(&AstExpr::value(value.clone()), ctx)
+    }
+}
+
+impl TypeSystem for TypeChecker {
+    fn get_ty_from_expr(&self, ctx: &Context, expr: &AstExpr) -> Result<AstType> {
+        self.infer_expr(expr, &ctx.values)
+    }
+    fn get_ty_from_value(&self, ctx: &Context, value: &AstValue) -> Result<AstType> {
+        self.infer_value(value, &ctx.values)
+    }
+}
@@ -0,0 +1,470 @@
+use crate::pass::{
+    collect_pattern_idents, is_effect_free_expr, InterpreterPass, OptimizePass, UsageCollector,
+};
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::id::Ident;
+use std::collections::HashMap;
+use std::iter::zip;
+use std::sync::Arc;
+
+/// Values of the bindings known to be constant at the current point of a block
+pub type ConstEnv = HashMap<Ident, AstValue>;
+
+/// The body of a `while`/`loop`, split around its only exit `if cond { break; }`
+struct LoopShape<'a> {
+    label: &'a Option<Ident>,
+    before: Vec<BlockStmt>,
+    exit_cond: &'a AstExpr,
+    /// the value of `exit_cond` that leaves the loop
+    exit_when: bool,
+    after: Vec<BlockStmt>,
+}
+
+/// Unrolls loops whose trip count is known at compile time.
+///
+/// `for` over a range is unrolled when the bounds evaluate to constants, binding the pattern
+/// to each value in a block of its own. `while` and `loop` are unrolled by running them in the
+/// interpreter against the constants of the enclosing block, which requires a pure exit
+/// condition at the top level of the body. Only the statements the condition depends on are
+/// run, the others may use values only known at runtime. Loops with `break`/`continue`
+/// elsewhere, or with more iterations than the limit, are kept as is.
+pub struct LoopUnrollPass {
+    interpreter: InterpreterPass,
+    limit: usize,
+}
+impl LoopUnrollPass {
+    pub const DEFAULT_LIMIT: usize = 16;
+    pub fn new(serializer: Arc<dyn AstSerializer>) -> Self {
+        Self {
+            interpreter: InterpreterPass::new(serializer),
+            limit: Self::DEFAULT_LIMIT,
+        }
+    }
+    /// the maximum number of iterations a loop may be unrolled to
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    fn scratch_context(&self, env: &ConstEnv) -> SharedScopedContext {
+        let ctx = SharedScopedContext::new();
+        for (name, value) in env {
+            ctx.insert_value(name.clone(), value.clone());
+        }
+        ctx
+    }
+    fn is_constant(value: &AstValue) -> bool {
+        match value {
+            AstValue::Int(_)
+            | AstValue::BigInt(_)
+            | AstValue::Bool(_)
+            | AstValue::Decimal(_)
+            | AstValue::Char(_)
+            | AstValue::String(_)
+            | AstValue::Unit(_) => true,
+            AstValue::Tuple(t) => t.values.iter().all(Self::is_constant),
+            AstValue::List(l) => l.values.iter().all(Self::is_constant),
+            _ => false,
+        }
+    }
+    fn bind_let(&self, let_: &StmtLet, env: &mut ConstEnv) {
+        let value = match (&let_.init, let_.pat.as_ident()) {
+            (Some(init), Some(_)) if let_.diverge.is_none() => self
+                .interpreter
+                .interpret_expr(init, &self.scratch_context(env))
+                .ok()
+                .filter(Self::is_constant),
+            _ => None,
+        };
+        match (value, let_.pat.as_ident()) {
+            (Some(value), Some(name)) => {
+                env.insert(name.clone(), value);
+            }
+            _ => {
+                let mut idents = vec![];
+                collect_pattern_idents(&let_.pat, &mut idents);
+                idents.iter().for_each(|x| {
+                    env.remove(x);
+                });
+            }
+        }
+    }
+
+    /// the bindings a statement declares or writes to
+    fn defined_idents(stmt: &BlockStmt) -> Vec<Ident> {
+        match stmt {
+            BlockStmt::Let(let_) => {
+                let mut idents = vec![];
+                collect_pattern_idents(&let_.pat, &mut idents);
+                idents
+            }
+            _ => {
+                let mut usage = UsageCollector::new();
+                usage.collect_stmt(stmt);
+                usage.assigned().cloned().collect()
+            }
+        }
+    }
+    /// Marks the statements the exit condition depends on, directly or through other
+    /// statements, returning what they and the condition use
+    fn condition_slice(stmts: &[&BlockStmt], cond: &AstExpr) -> (Vec<bool>, UsageCollector) {
+        let mut deps = UsageCollector::new();
+        deps.collect_expr(cond);
+        let mut relevant = vec![false; stmts.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, stmt) in stmts.iter().enumerate() {
+                if relevant[i] || !Self::defined_idents(stmt).iter().any(|x| deps.is_used(x)) {
+                    continue;
+                }
+                relevant[i] = true;
+                deps.collect_stmt(stmt);
+                changed = true;
+            }
+        }
+        (relevant, deps)
+    }
+
+    fn body_stmts(body: &AstExpr) -> Vec<BlockStmt> {
+        match body {
+            AstExpr::Block(block) => block.stmts.clone(),
+            _ => ExprBlock::new_expr(body.clone()).stmts,
+        }
+    }
+    fn iteration(stmts: Vec<BlockStmt>) -> BlockStmt {
+        let mut block = ExprBlock::new_stmts(stmts);
+        block.seal();
+        BlockStmt::Expr(BlockStmtExpr::new(AstExpr::block(block)))
+    }
+
+    pub fn unroll_for(&self, for_: &ExprFor, env: &ConstEnv) -> Option<Vec<BlockStmt>> {
+        let AstExpr::Range(range) = &*for_.iter else {
+            return None;
+        };
+        let mut usage = UsageCollector::new();
+        usage.collect_expr(&for_.body);
+        if usage.has_loop_control() {
+            return None;
+        }
+        let ctx = self.scratch_context(env);
+        let range = self.interpreter.interpret_range(range, &ctx).ok()?;
+        let trips = range.trip_count();
+        if trips > self.limit as u128 {
+            debug!(
+                "Not unrolling loop of {} iterations, limit is {}",
+                trips, self.limit
+            );
+            return None;
+        }
+        let body = Self::body_stmts(&for_.body);
+        let stmts = range
+            .values()
+            .map(|value| {
+                let bind = StmtLet::new((*for_.pat).clone(), Some(AstExpr::value(value)), None);
+                let mut stmts = vec![BlockStmt::Let(bind)];
+                stmts.extend(body.iter().cloned());
+                Self::iteration(stmts)
+            })
+            .collect();
+        Some(stmts)
+    }
+
+    fn exit_cond<'a>(stmt: &'a BlockStmt, label: &Option<Ident>) -> Option<&'a AstExpr> {
+        let BlockStmt::Expr(expr) = stmt else {
+            return None;
+        };
+        let AstExpr::If(if_) = &*expr.expr else {
+            return None;
+        };
+        if if_.elze.is_some() {
+            return None;
+        }
+        let then = match &*if_.then {
+            AstExpr::Block(block) if block.stmts.len() == 1 => match &block.stmts[0] {
+                BlockStmt::Expr(expr) => &*expr.expr,
+                _ => return None,
+            },
+            then => then,
+        };
+        match then {
+            AstExpr::Break(ExprBreak {
+                label: target,
+                value: None,
+            }) if target.is_none() || target == label => Some(&if_.cond),
+            _ => None,
+        }
+    }
+    fn loop_shape<'a>(expr: &'a AstExpr) -> Option<LoopShape<'a>> {
+        let shape = match expr {
+            AstExpr::While(while_) => LoopShape {
+                label: &while_.label,
+                before: vec![],
+                exit_cond: &while_.cond,
+                exit_when: false,
+                after: Self::body_stmts(&while_.body),
+            },
+            AstExpr::Loop(loop_) => {
+                let AstExpr::Block(body) = &*loop_.body else {
+                    return None;
+                };
+                let mut exits = body
+                    .stmts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, stmt)| Some((i, Self::exit_cond(stmt, &loop_.label)?)));
+                let (i, exit_cond) = exits.next()?;
+                if exits.next().is_some() {
+                    return None;
+                }
+                LoopShape {
+                    label: &loop_.label,
+                    before: body.stmts[..i].to_vec(),
+                    exit_cond,
+                    exit_when: true,
+                    after: body.stmts[i + 1..].to_vec(),
+                }
+            }
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    /// Unrolls a `while` or `loop`, updating `env` with the state it leaves behind
+    pub fn unroll_conditional(&self, expr: &AstExpr, env: &mut ConstEnv) -> Option<Vec<BlockStmt>> {
+        let shape = Self::loop_shape(expr)?;
+        if !is_effect_free_expr(shape.exit_cond) {
+            return None;
+        }
+        let mut usage = UsageCollector::new();
+        shape.before.iter().for_each(|x| usage.collect_stmt(x));
+        shape.after.iter().for_each(|x| usage.collect_stmt(x));
+        if usage.has_loop_control() {
+            return None;
+        }
+        let mut cond_usage = UsageCollector::new();
+        cond_usage.collect_expr(shape.exit_cond);
+        if env
+            .keys()
+            .any(|x| cond_usage.is_used(x) && usage.is_escaped(x))
+        {
+            return None;
+        }
+
+        // statements the condition doesn't depend on are left to runtime
+        let body: Vec<_> = shape.before.iter().chain(shape.after.iter()).collect();
+        let (relevant, deps) = Self::condition_slice(&body, shape.exit_cond);
+        let (before, after) = body.split_at(shape.before.len());
+        let (relevant_before, relevant_after) = relevant.split_at(shape.before.len());
+        let run = |stmts: &[&BlockStmt], relevant: &[bool], ctx: &SharedScopedContext| {
+            zip(stmts, relevant)
+                .filter(|(_, relevant)| **relevant)
+                .try_for_each(|(stmt, _)| self.interpreter.interpret_stmt(stmt, ctx).map(|_| ()))
+        };
+
+        let ctx = self.scratch_context(env);
+        let mut trips = None;
+        for n in 0..=self.limit {
+            let iter_ctx = ctx.child(Ident::new("__unroll__"), Visibility::Private, true);
+            run(before, relevant_before, &iter_ctx).ok()?;
+            match self
+                .interpreter
+                .interpret_expr(shape.exit_cond, &iter_ctx)
+                .ok()?
+            {
+                AstValue::Bool(b) if b.value == shape.exit_when => {
+                    trips = Some(n);
+                    break;
+                }
+                AstValue::Bool(_) => {}
+                _ => return None,
+            }
+            run(after, relevant_after, &iter_ctx).ok()?;
+        }
+        let Some(trips) = trips else {
+            debug!(
+                "Not unrolling loop {:?}, it runs more than {} iterations",
+                shape.label, self.limit
+            );
+            return None;
+        };
+
+        for name in usage.assigned() {
+            // bindings written by the statements that weren't run are unknown
+            let value = ctx
+                .get_value(name.clone())
+                .filter(|x| deps.is_used(name) && Self::is_constant(x));
+            match value {
+                Some(value) if env.contains_key(name) => {
+                    env.insert(name.clone(), value);
+                }
+                _ => {
+                    env.remove(name);
+                }
+            }
+        }
+        let whole: Vec<_> = shape
+            .before
+            .iter()
+            .chain(shape.after.iter())
+            .cloned()
+            .collect();
+        let mut stmts = vec![Self::iteration(whole); trips];
+        if !shape.before.is_empty() {
+            stmts.push(Self::iteration(shape.before));
+        }
+        Some(stmts)
+    }
+
+    /// Unrolls the loops among the statements of a block, tracking constants from top to bottom
+    pub fn unroll_block(&self, block: ExprBlock) -> ExprBlock {
+        let mut env = ConstEnv::new();
+        let mut stmts = vec![];
+        for stmt in block.stmts {
+            let expr = match &stmt {
+                BlockStmt::Let(let_) => {
+                    // the initializer may write other bindings, like `let y = { i += 1; i };`
+                    let mut usage = UsageCollector::new();
+                    for x in [&let_.init, &let_.diverge].into_iter().flatten() {
+                        usage.collect_expr(x);
+                    }
+                    usage.assigned().for_each(|x| {
+                        env.remove(x);
+                    });
+                    self.bind_let(let_, &mut env);
+                    stmts.push(stmt);
+                    continue;
+                }
+                BlockStmt::Expr(expr) => &*expr.expr,
+                _ => {
+                    stmts.push(stmt);
+                    continue;
+                }
+            };
+            let unrolled = match expr {
+                AstExpr::For(for_) => self.unroll_for(for_, &env),
+                AstExpr::While(_) | AstExpr::Loop(_) => self.unroll_conditional(expr, &mut env),
+                _ => None,
+            };
+            let mut usage = UsageCollector::new();
+            usage.collect_stmt(&stmt);
+            match unrolled {
+                Some(unrolled) => {
+                    if let AstExpr::For(_) = expr {
+                        usage.assigned().for_each(|x| {
+                            env.remove(x);
+                        });
+                    }
+                    stmts.extend(unrolled);
+                }
+                None => {
+                    usage.assigned().for_each(|x| {
+                        env.remove(x);
+                    });
+                    stmts.push(stmt);
+                }
+            }
+        }
+        ExprBlock::new_stmts(stmts)
+    }
+
+    /// recursively unrolls loops in an expression, innermost first
+    pub fn unroll_expr(&self, expr: AstExpr) -> AstExpr {
+        match expr {
+            AstExpr::Block(block) => AstExpr::block(self.unroll_block_recursive(block)),
+            AstExpr::If(mut if_) => {
+                if_.then = self.unroll_expr(*if_.then).into();
+                if_.elze = if_.elze.map(|x| self.unroll_expr(*x).into());
+                AstExpr::If(if_)
+            }
+            AstExpr::Match(mut match_) => {
+                match_.cases = match_
+                    .cases
+                    .into_iter()
+                    .map(|mut case| {
+                        case.body = self.unroll_expr(*case.body).into();
+                        case
+                    })
+                    .collect();
+                AstExpr::Match(match_)
+            }
+            AstExpr::Loop(mut l) => {
+                l.body = self.unroll_expr(*l.body).into();
+                AstExpr::Loop(l)
+            }
+            AstExpr::While(mut w) => {
+                w.body = self.unroll_expr(*w.body).into();
+                AstExpr::While(w)
+            }
+            AstExpr::For(mut f) => {
+                f.body = self.unroll_expr(*f.body).into();
+                AstExpr::For(f)
+            }
+            AstExpr::Closure(mut c) => {
+                c.body = self.unroll_expr(*c.body).into();
+                AstExpr::Closure(c)
+            }
+            AstExpr::Paren(mut p) => {
+                p.expr = self.unroll_expr(*p.expr).into();
+                AstExpr::Paren(p)
+            }
+            _ => expr,
+        }
+    }
+    fn unroll_block_recursive(&self, block: ExprBlock) -> ExprBlock {
+        let stmts = block
+            .stmts
+            .into_iter()
+            .map(|stmt| match stmt {
+                BlockStmt::Expr(mut expr) => {
+                    expr.expr = self.unroll_expr(*expr.expr).into();
+                    BlockStmt::Expr(expr)
+                }
+                BlockStmt::Let(mut let_) => {
+                    let_.init = let_.init.map(|x| self.unroll_expr(x));
+                    BlockStmt::Let(let_)
+                }
+                BlockStmt::Item(item) => BlockStmt::item(self.unroll_item(*item)),
+                _ => stmt,
+            })
+            .collect();
+        self.unroll_block(ExprBlock::new_stmts(stmts))
+    }
+    pub fn unroll_item(&self, item: AstItem) -> AstItem {
+        match item {
+            AstItem::DefFunction(mut func) => {
+                func.body = self.unroll_expr(*func.body).into();
+                AstItem::DefFunction(func)
+            }
+            AstItem::Impl(mut impl_) => {
+                impl_.items = impl_
+                    .items
+                    .into_iter()
+                    .map(|x| self.unroll_item(x))
+                    .collect();
+                AstItem::Impl(impl_)
+            }
+            _ => item,
+        }
+    }
+}
+
+impl OptimizePass for LoopUnrollPass {
+    fn name(&self) -> &str {
+        "unroll"
+    }
+    fn interpreter(&self) -> Option<&InterpreterPass> {
+        Some(&self.interpreter)
+    }
+    fn optimize_item(&self, item: AstItem, _ctx: &SharedScopedContext) -> Result<AstItem> {
+        Ok(self.unroll_item(item))
+    }
+    fn optimize_block(&self, block: ExprBlock, _ctx: &SharedScopedContext) -> Result<ExprBlock> {
+        Ok(self.unroll_block(block))
+    }
+}
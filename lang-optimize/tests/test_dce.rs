@@ -0,0 +1,281 @@
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::ops::IntOverflow;
+use lang_optimize::pass::{DeadCodeEliminationPass, FoldOptimizer, InterpreterPass};
+use rust_lang::printer::RustPrinter;
+use rust_lang::{shll_parse_expr, shll_parse_item};
+use std::sync::Arc;
+
+fn dce_shll_item(item: AstItem) -> Result<AstItem> {
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer =
+        FoldOptimizer::new(serializer.clone(), Box::new(DeadCodeEliminationPass::new()));
+    let ctx = SharedScopedContext::new();
+    optimizer.optimize_item(item, &ctx)
+}
+
+fn dce_shll_expr(expr: AstExpr) -> Result<AstExpr> {
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer =
+        FoldOptimizer::new(serializer.clone(), Box::new(DeadCodeEliminationPass::new()));
+    let ctx = SharedScopedContext::new();
+    optimizer.optimize_expr(expr, &ctx)
+}
+
+#[test]
+fn test_dce_unused_let() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo(a: i64) -> i64 {
+            let b = a == 1;
+            let c = a * 2;
+            let d = print(a);
+            c
+        }
+    };
+    let value = dce_shll_item(code)?;
+    let expected = shll_parse_item! {
+        fn foo(a: i64) -> i64 {
+            let c = a * 2;
+            let d = print(a);
+            c
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_keeps_trapping_ops() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo(a: u8, b: u8) -> u8 {
+            let _ = a / b;
+            let c = a + 1;
+            let d = -(a as i8);
+            let e = a < b && !(a == b);
+            a
+        }
+    };
+    let value = dce_shll_item(code)?;
+    let expected = shll_parse_item! {
+        fn foo(a: u8, b: u8) -> u8 {
+            let _ = a / b;
+            let c = a + 1;
+            let d = -(a as i8);
+            a
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_constant_branch() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo(a: i64) -> i64 {
+            if false {
+                print(a);
+            }
+            if true {
+                a
+            } else {
+                a + 1
+            }
+        }
+    };
+    let value = dce_shll_item(code)?;
+    let expected = shll_parse_item! {
+        fn foo(a: i64) -> i64 {
+            a
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_if_const() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        if const { 1 + 1 == 2 } {
+            1
+        } else {
+            2
+        }
+    };
+    let value = dce_shll_expr(code)?;
+    assert_eq!(value.to_string(), shll_parse_expr!(1).to_string());
+
+    let code = shll_parse_expr! {
+        if const { 1 + 1 == 3 } {
+            1
+        }
+    };
+    let value = dce_shll_expr(code)?;
+    assert_eq!(value.to_string(), AstExpr::unit().to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_if_const_not_comptime() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        if const { FAST_PATH } {
+            1
+        } else {
+            2
+        }
+    };
+    let err = dce_shll_expr(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::OptimizeFailed);
+
+    let code = shll_parse_expr! {
+        if const { 1 } {
+            1
+        }
+    };
+    let err = dce_shll_expr(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    Ok(())
+}
+
+#[test]
+fn test_dce_keeps_runtime_const_block() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let value = dce_shll_expr(shll_parse_expr!(const { 1 + 1 }))?;
+    assert_eq!(value.to_string(), shll_parse_expr!(2).to_string());
+
+    let code = shll_parse_expr!(const { compute() });
+    let value = dce_shll_expr(code.clone())?;
+    assert_eq!(value.to_string(), code.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_const_block_uses_pass_overflow() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let serializer = Arc::new(RustPrinter::new());
+    let pass = InterpreterPass::new(serializer.clone()).with_overflow(IntOverflow::Wrap);
+    let optimizer = FoldOptimizer::new(serializer, Box::new(pass));
+    let code = shll_parse_expr!(const { 255u8 + 1u8 });
+    let value = optimizer.optimize_expr(code, &SharedScopedContext::new())?;
+    assert_eq!(value.to_string(), "0u8");
+    Ok(())
+}
+
+#[test]
+fn test_dce_unreachable_function() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        mod foo {
+            fn used(a: i64) -> i64 {
+                a + 1
+            }
+            fn unused(a: i64) -> i64 {
+                used(a)
+            }
+            pub fn bar() -> i64 {
+                used(1)
+            }
+        }
+    };
+    let value = dce_shll_item(code)?;
+    let expected = shll_parse_item! {
+        mod foo {
+            fn used(a: i64) -> i64 {
+                a + 1
+            }
+            pub fn bar() -> i64 {
+                used(1)
+            }
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_keeps_attributed_functions() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // called by the test harness or through their symbol, not from the module
+    let code = shll_parse_item! {
+        mod foo {
+            fn helper() -> i64 {
+                1
+            }
+            #[test]
+            fn test_helper() {
+                helper();
+            }
+            #[no_mangle]
+            fn exported() -> i64 {
+                2
+            }
+            fn unused() -> i64 {
+                3
+            }
+        }
+    };
+    let value = dce_shll_item(code)?;
+    let expected = shll_parse_item! {
+        mod foo {
+            fn helper() -> i64 {
+                1
+            }
+            #[test]
+            fn test_helper() {
+                helper();
+            }
+            #[no_mangle]
+            fn exported() -> i64 {
+                2
+            }
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_dce_block_items() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {{
+        fn foo() -> i64 {
+            1
+        }
+        fn bar() -> i64 {
+            2
+        }
+        let unused = 3;
+        foo()
+    }};
+    let value = DeadCodeEliminationPass::new().eliminate_expr(code);
+    let expected = shll_parse_expr! {{
+        fn foo() -> i64 {
+            1
+        }
+        foo()
+    }};
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
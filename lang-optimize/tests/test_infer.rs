@@ -0,0 +1,164 @@
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::Ident;
+use lang_optimize::pass::InterpreterPass;
+use pretty_assertions::assert_eq;
+use rust_lang::printer::RustPrinter;
+use rust_lang::shll_parse_expr;
+use std::sync::Arc;
+
+fn infer_block(code: AstExpr) -> Result<(AstType, SharedScopedContext)> {
+    let interpreter = InterpreterPass::new(Arc::new(RustPrinter::new()));
+    let ctx = SharedScopedContext::new();
+    let AstExpr::Block(block) = code else {
+        bail!("Expected a block, got {}", code)
+    };
+    let ty = interpreter.infer_block(&block, &ctx)?;
+    Ok((ty, ctx))
+}
+fn type_of(ctx: &SharedScopedContext, name: &str) -> Result<String> {
+    let ty = ctx
+        .get_type(Ident::new(name))
+        .with_context(|| format!("No type for {}", name))?;
+    Ok(ty.to_string())
+}
+
+#[test]
+fn test_infer_let() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let (ty, ctx) = infer_block(shll_parse_expr! {{
+        let a = 1;
+        let b: u8 = a;
+        let c = 1.0;
+        let d = (a, c > 2.0);
+        a
+    }})?;
+    assert_eq!(ty.to_string(), "u8");
+    assert_eq!(type_of(&ctx, "a")?, "u8");
+    assert_eq!(type_of(&ctx, "b")?, "u8");
+    assert_eq!(type_of(&ctx, "c")?, "f64");
+    assert_eq!(
+        ctx.get_type(Ident::new("d")),
+        Some(AstType::Tuple(TypeTuple {
+            types: vec![
+                AstType::Primitive(TypePrimitive::Int(TypeInt::U8)),
+                AstType::bool(),
+            ]
+        }))
+    );
+
+    // an integer no other type constrains defaults to i64
+    let (ty, ctx) = infer_block(shll_parse_expr! {{
+        let a = 1;
+        let b = a + 2;
+        b
+    }})?;
+    assert_eq!(ty.to_string(), "i64");
+    assert_eq!(type_of(&ctx, "a")?, "i64");
+    Ok(())
+}
+
+#[test]
+fn test_infer_closure() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let (ty, ctx) = infer_block(shll_parse_expr! {{
+        let add = |a, b| a + b;
+        let x = add(1, 2u8);
+        let apply = |f, v: i32| f(v);
+        let y = apply(|v| v > 0, 1);
+        (x, y)
+    }})?;
+    let u8 = AstType::Primitive(TypePrimitive::Int(TypeInt::U8));
+    assert_eq!(
+        ty,
+        AstType::Tuple(TypeTuple {
+            types: vec![u8.clone(), AstType::bool()]
+        })
+    );
+    assert_eq!(
+        ctx.get_type(Ident::new("add")),
+        Some(AstType::Function(TypeFunction {
+            params: vec![u8.clone(), u8.clone()],
+            generics_params: vec![],
+            ret_ty: Some(u8.into()),
+        }))
+    );
+    assert_eq!(type_of(&ctx, "y")?, "bool");
+    Ok(())
+}
+
+#[test]
+fn test_infer_generic_call() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let (ty, ctx) = infer_block(shll_parse_expr! {{
+        fn id<T>(value: T) -> T {
+            value
+        }
+        let a = id(1u8);
+        let b = id(true);
+        let c: i32 = id(2);
+        a
+    }})?;
+    assert_eq!(ty.to_string(), "u8");
+    assert_eq!(type_of(&ctx, "b")?, "bool");
+    assert_eq!(type_of(&ctx, "c")?, "i32");
+    Ok(())
+}
+
+#[test]
+fn test_infer_mismatch() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let err = infer_block(shll_parse_expr! {{
+        let a = 1;
+        let b: bool = a;
+    }})
+    .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    assert_eq!(diagnostic.message, "Expected bool, found i64");
+
+    let err = infer_block(shll_parse_expr! {{
+        let f = |x| x + 1;
+        f(true)
+    }})
+    .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    Ok(())
+}
+
+#[test]
+fn test_infer_literal_range() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // the literal is only known to be a `u8` once `b` is inferred
+    let err = infer_block(shll_parse_expr! {{
+        let a = 300;
+        let b: u8 = a;
+    }})
+    .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::LiteralOutOfRange);
+
+    let err = infer_block(shll_parse_expr! {{
+        let a: u32 = -1;
+    }})
+    .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    Ok(())
+}
@@ -2,8 +2,12 @@ use common::*;
 use lang_core::ast::AstValue;
 use lang_core::ast::*;
 use lang_core::context::SharedScopedContext;
+use lang_core::error::{render_report, Diagnostic, ErrorCode};
+use lang_core::ops::IntOverflow;
 use lang_optimize::interpreter::Interpreter;
+use lang_optimize::pass::InterpreterPass;
 use pretty_assertions::assert_eq;
+use rust_lang::parser::RustParser;
 use rust_lang::printer::RustPrinter;
 use rust_lang::{shll_parse_expr, shll_parse_value};
 use std::sync::Arc;
@@ -13,6 +17,11 @@ fn interpret_shll_expr(expr: AstExpr) -> Result<AstValue> {
     let ctx = SharedScopedContext::new();
     interpreter.interpret_expr(expr, &ctx)
 }
+fn interpret_shll_expr_directly(expr: AstExpr) -> Result<AstValue> {
+    let interpreter = InterpreterPass::new(Arc::new(RustPrinter::new()));
+    let ctx = SharedScopedContext::new();
+    interpreter.interpret_expr(&expr, &ctx)
+}
 
 #[test]
 fn test_eval_arithmetics() -> Result<()> {
@@ -65,3 +74,647 @@ fn test_eval_function_call_with_main() -> Result<()> {
     assert_eq!(value, expected);
     Ok(())
 }
+
+#[test]
+fn test_eval_while_assign() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            let mut i = 0;
+            let mut sum = 0;
+            while i < 5 {
+                i = i + 1;
+                if i == 2 {
+                    continue;
+                }
+                sum = sum + i;
+            }
+            sum
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(13);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_loop_break_value() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            let mut i = 0;
+            let found = 'outer: loop {
+                loop {
+                    i = i + 1;
+                    if i > 3 {
+                        break 'outer i * 10;
+                    }
+                    break;
+                }
+            };
+            found
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(40);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_loop_iteration_limit() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let interpreter =
+        InterpreterPass::new(Arc::new(RustPrinter::new())).with_loop_iteration_limit(100);
+    let codes = [
+        shll_parse_expr! {
+            loop {}
+        },
+        shll_parse_expr! {{
+            let mut i = 0;
+            while i >= 0 {
+                i = i + 1;
+            }
+        }},
+    ];
+    for code in codes {
+        let err = interpreter
+            .interpret_expr(&code, &SharedScopedContext::new())
+            .unwrap_err();
+        let diagnostic = err
+            .downcast_ref::<Diagnostic>()
+            .context("Expected a diagnostic")?;
+        assert_eq!(diagnostic.code, ErrorCode::InterpretFailed);
+    }
+
+    // loops that end within the limit are unaffected
+    let code = shll_parse_expr! {{
+        let mut i = 0;
+        while i < 100 {
+            i = i + 1;
+        }
+        i
+    }};
+    let value = interpreter.interpret_expr(&code, &SharedScopedContext::new())?;
+    assert_eq!(value, shll_parse_value!(100));
+    Ok(())
+}
+
+#[test]
+fn test_eval_match_return() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            fn classify(x: i64) -> i64 {
+                for i in 0..10 {
+                    if i == x {
+                        return i * 2;
+                    }
+                }
+                match x {
+                    y if y > 20 => y - 20,
+                    _ => 0,
+                }
+            }
+            classify(3) + classify(25) * 10 + classify(11) * 100
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(56);
+    assert_eq!(value, expected);
+
+    let code = shll_parse_expr! {
+        {
+            let buf = [7; 3];
+            buf[2]
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(7);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_patterns() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            fn classify(x: i64) -> i64 {
+                match x {
+                    0 | 1 => 1,
+                    2..=9 => 2,
+                    n @ 10..20 => n,
+                    _ => 0,
+                }
+            }
+            fn unwrap_or(o: Option<i64>, d: i64) -> i64 {
+                let Some(v) = o else {
+                    return d;
+                };
+                v
+            }
+            let (a, .., b) = (1, 2, 3, 4);
+            let [first, rest @ ..] = [5, 6, 7];
+            let c = match Some(3) {
+                Some(n) if n > 5 => 0,
+                Some(n) => n,
+                None => 9,
+            };
+            classify(1) + classify(5) * 10 + classify(15) * 100 + classify(42) * 1000
+                + a + b * 10000 + first + rest[1] + c + unwrap_or(None, 100000)
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(141537);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_structs_and_variants() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            struct Pair(i64, i64);
+            struct Marker;
+            impl Marker {
+                fn get(&self) -> i64 {
+                    5
+                }
+            }
+            enum State {
+                Idle,
+                Running(i64),
+                Done { code: i64 },
+            }
+            fn step(state: State) -> State {
+                match state {
+                    State::Idle => State::Running(0),
+                    State::Running(n) if n < 3 => State::Running(n + 1),
+                    State::Running(n) => State::Done { code: n * 10 },
+                    State::Done { .. } => state,
+                }
+            }
+            let mut state = State::Idle;
+            let mut steps = 0;
+            while steps < 6 {
+                state = step(state);
+                steps = steps + 1;
+            }
+            let State::Done { code } = state else {
+                return 0;
+            };
+            let Pair(a, b) = Pair(1, 2);
+            let marker = Marker;
+            code + a * 100 + b * 1000 + marker.get() * 10000
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(52130);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_index_assign() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            let mut xs = [1, 2, 3];
+            xs[1] = xs[0] + xs[2];
+            xs[1]
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(4);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_int_overflow() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr!(200u8 + 100u8);
+    let err = interpret_shll_expr_directly(code.clone()).unwrap_err();
+    assert_eq!(err.to_string(), "attempt to add with overflow");
+
+    let interpreter =
+        InterpreterPass::new(Arc::new(RustPrinter::new())).with_overflow(IntOverflow::Wrap);
+    let value = interpreter.interpret_expr(&code, &SharedScopedContext::new())?;
+    let expected = shll_parse_value!(44u8);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_annotated_literal_type() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            let x: u8 = 255;
+            x + 1
+        }
+    };
+    let err = interpret_shll_expr_directly(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::ArithmeticError);
+    assert_eq!(diagnostic.message, "attempt to add with overflow");
+
+    let code = shll_parse_expr! {
+        {
+            fn double(x: i8) -> i8 {
+                x * 2
+            }
+            double(100)
+        }
+    };
+    let err = interpret_shll_expr_directly(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::ArithmeticError);
+    assert_eq!(diagnostic.message, "attempt to multiply with overflow");
+
+    let code = shll_parse_expr! {
+        {
+            let x: u8 = 256;
+            x
+        }
+    };
+    let err = interpret_shll_expr_directly(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::LiteralOutOfRange);
+    Ok(())
+}
+
+#[test]
+fn test_eval_int_cast() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let cases = [
+        (shll_parse_expr!(300i32 as u8), shll_parse_value!(44u8)),
+        (
+            shll_parse_expr!(-1i32 as u32),
+            shll_parse_value!(4294967295u32),
+        ),
+        (shll_parse_expr!(3.9 as i32), shll_parse_value!(3i32)),
+        (
+            shll_parse_expr!(1e10 as i32),
+            shll_parse_value!(2147483647i32),
+        ),
+        (shll_parse_expr!(97u8 as char), shll_parse_value!('a')),
+    ];
+    for (code, expected) in cases {
+        let value = interpret_shll_expr_directly(code)?;
+        assert_eq!(value, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_eval_big_int() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            let x = 4294967296 as BigInt;
+            x * x * x
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = AstValue::BigInt(ValueBigInt::new(1u128 << 96));
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_div_mod_bit_ops() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let cases = [
+        (shll_parse_expr!(7 / 2), shll_parse_value!(3)),
+        (shll_parse_expr!(-7 % 3), AstValue::int(-1)),
+        (shll_parse_expr!(7.5 / 2.5), shll_parse_value!(3.0)),
+        (shll_parse_expr!(12u8 & 10u8), shll_parse_value!(8u8)),
+        (shll_parse_expr!(12u8 | 10u8), shll_parse_value!(14u8)),
+        (shll_parse_expr!(12u8 ^ 10u8), shll_parse_value!(6u8)),
+        (shll_parse_expr!(true ^ true), shll_parse_value!(false)),
+        (shll_parse_expr!(1u8 << 7), shll_parse_value!(128u8)),
+        (
+            shll_parse_expr!(-16i32 >> 2),
+            AstValue::int_typed(-4, TypeInt::I32),
+        ),
+        (shll_parse_expr!(1 != 2), shll_parse_value!(true)),
+    ];
+    for (code, expected) in cases {
+        let value = interpret_shll_expr_directly(code)?;
+        assert_eq!(value, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_eval_div_by_zero() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let err = interpret_shll_expr_directly(shll_parse_expr!(1 / 0)).unwrap_err();
+    assert_eq!(err.to_string(), "attempt to divide by zero");
+    let err = interpret_shll_expr_directly(shll_parse_expr!(1 % 0)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "attempt to calculate the remainder by zero"
+    );
+    let err = interpret_shll_expr_directly(shll_parse_expr!(1u8 << 8)).unwrap_err();
+    assert_eq!(err.to_string(), "attempt to shift left with overflow");
+    Ok(())
+}
+
+#[test]
+fn test_eval_logical_short_circuit() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let value = interpret_shll_expr_directly(shll_parse_expr!(false && 1 / 0 == 0))?;
+    assert_eq!(value, shll_parse_value!(false));
+    let value = interpret_shll_expr_directly(shll_parse_expr!(true || 1 / 0 == 0))?;
+    assert_eq!(value, shll_parse_value!(true));
+    let value = interpret_shll_expr_directly(shll_parse_expr!(true && 1 < 2))?;
+    assert_eq!(value, shll_parse_value!(true));
+    Ok(())
+}
+
+#[test]
+fn test_eval_error_points_at_source() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = "{\n    let a = 1;\n    (a, a)\n}";
+    let expr = RustParser::new().deserialize_expr(code)?;
+    let err = interpret_shll_expr_directly(expr).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to interpret `(a, a)` at __file__:3:5"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_eval_error_diagnostics() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = "{\n    let a = 255u8;\n    a + 1\n}";
+    let expr = RustParser::new().deserialize_expr(code)?;
+    let err = interpret_shll_expr_directly(expr).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::ArithmeticError);
+    let rendered = render_report(&err);
+    assert!(
+        rendered.contains("shll::interpret::arithmetic"),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("[__file__:3:5]"), "{}", rendered);
+    assert!(rendered.contains("overflows"), "{}", rendered);
+
+    let code = "{\n    let a = 1;\n    a + b\n}";
+    let expr = RustParser::new().deserialize_expr(code)?;
+    let err = interpret_shll_expr_directly(expr).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::UnresolvedName);
+    assert_eq!(diagnostic.message, "cannot find `b` in this scope");
+    assert!(render_report(&err).contains("[__file__:3:9]"));
+
+    let code = "{\n    let a = [1, 2];\n    a[2]\n}";
+    let expr = RustParser::new().deserialize_expr(code)?;
+    let err = interpret_shll_expr_directly(expr).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::InterpretFailed);
+    assert_eq!(diagnostic.message, "Index 2 out of bounds for length 2");
+    assert!(render_report(&err).contains("[__file__:3:5]"));
+    Ok(())
+}
+
+fn interpret_type_expr(code: proc_macro2::TokenStream) -> Result<String> {
+    let printer = RustPrinter::new();
+    let interpreter = InterpreterPass::new(Arc::new(printer));
+    let ctx = SharedScopedContext::new();
+    let ty = RustParser::new().parse_type_expr(code)?;
+    let ty = interpreter.interpret_type(&ty, &ctx)?;
+    Ok(printer.print_type(&ty)?.to_string())
+}
+
+#[test]
+fn test_eval_type_algebra() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let ty = interpret_type_expr(quote::quote!(impl Read + impl Write - impl Write))?;
+    assert_eq!(ty, "impl Read");
+    let ty = interpret_type_expr(quote::quote!(impl Read & impl Write | impl Read))?;
+    assert_eq!(ty, "impl Read");
+    let ty = interpret_type_expr(quote::quote!(
+        struct { a: i64, b: i64 } & struct { b: i64, c: bool }
+    ))?;
+    let expected = quote::quote! { struct { pub a: i64, pub b: i64, pub c: bool } };
+    assert_eq!(ty, expected.to_string());
+    let ty = interpret_type_expr(quote::quote!(
+        struct { a: i64, b: i64 } - struct { b: i64 }
+    ))?;
+    let expected = quote::quote! { struct { pub a: i64 } };
+    assert_eq!(ty, expected.to_string());
+    let ty = interpret_type_expr(quote::quote!(
+        struct Foo { a: i64 } | struct Bar { b: bool }
+    ))?;
+    let expected = quote::quote! { enum FooOrBar { Foo(Foo), Bar(Bar) } };
+    assert_eq!(ty, expected.to_string());
+    let ty = interpret_type_expr(quote::quote!(
+        struct { a: i64, b: i64 } | struct { b: i64, c: bool }
+    ))?;
+    let expected = quote::quote! { struct { pub b: i64 } };
+    assert_eq!(ty, expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_eval_literal_types_and_typeof() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    assert_eq!(interpret_type_expr(quote::quote!(1))?, "1");
+    assert_eq!(interpret_type_expr(quote::quote!(typeof(1)))?, "i64");
+    assert_eq!(interpret_type_expr(quote::quote!(typeof(1u8 + 2u8)))?, "u8");
+    let ty = interpret_type_expr(quote::quote!(
+        struct { "/api": i64, "/user": bool }["/user"]
+    ))?;
+    assert_eq!(ty, "bool");
+
+    let interpreter = InterpreterPass::new(Arc::new(RustPrinter::new()));
+    let one = AstType::value(AstValue::int(1));
+    interpreter.type_check_value(&AstValue::int(1), &one)?;
+    assert!(interpreter
+        .type_check_value(&AstValue::int(2), &one)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_eval_type_algebra_field_conflict() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let err =
+        interpret_type_expr(quote::quote!(struct { a: i64 } & struct { a: bool })).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::FieldConflict);
+    assert_eq!(
+        diagnostic.message,
+        "Field a is defined as both i64 and bool"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_eval_type_function_depth_limit() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let printer = RustPrinter::new();
+    let interpreter = InterpreterPass::new(Arc::new(printer)).with_type_depth_limit(8);
+    let ctx = SharedScopedContext::new();
+    let item: syn::Item = syn::parse_quote! {
+        type Forever<T, const n: usize> = Type<{ Vec::<Forever<T, { n + 1 }>> }>;
+    };
+    let item = RustParser::new().parse_item(item)?;
+    interpreter.interpret_item(&item, &ctx)?;
+    let ty = RustParser::new().parse_type_expr(quote::quote!(Forever<i64, 0>))?;
+    let err = interpreter.interpret_type(&ty, &ctx).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::InterpretFailed);
+    assert!(
+        diagnostic
+            .message
+            .contains("nested more than 8 levels deep"),
+        "{}",
+        diagnostic.message
+    );
+    Ok(())
+}
+
+#[test]
+fn test_eval_methods() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {
+        {
+            struct Point {
+                x: i64,
+                y: i64,
+            }
+            trait Area {
+                const SCALE: i64 = 2;
+                fn area(&self) -> i64;
+                fn scaled(&self) -> i64 {
+                    self.area() * Self::SCALE
+                }
+            }
+            impl Point {
+                fn shift(&mut self, dx: i64) {
+                    self.x = self.x + dx;
+                }
+            }
+            impl Area for Point {
+                fn area(&self) -> i64 {
+                    self.x * self.y
+                }
+            }
+            let mut p = Point { x: 1, y: 2 };
+            p.shift(2);
+            p.scaled()
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(12);
+    assert_eq!(value, expected);
+
+    let code = shll_parse_expr! {
+        {
+            trait Double {
+                fn double(self) -> i64;
+            }
+            impl Double for i64 {
+                fn double(self) -> i64 {
+                    self * 2
+                }
+            }
+            3.double()
+        }
+    };
+    let value = interpret_shll_expr_directly(code)?;
+    let expected = shll_parse_value!(6);
+    assert_eq!(value, expected);
+    Ok(())
+}
+
+#[test]
+fn test_eval_method_lookup() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let interpreter = InterpreterPass::new(Arc::new(RustPrinter::new()));
+    let ctx = SharedScopedContext::new();
+    let items = [
+        quote::quote!(
+            struct Point {
+                x: i64,
+            }
+        ),
+        quote::quote!(
+            trait Show {
+                fn show(&self) -> i64 {
+                    0
+                }
+            }
+        ),
+        quote::quote!(
+            impl Show for Point {}
+        ),
+    ];
+    for item in items {
+        let item = RustParser::new().parse_item(syn::parse2(item)?)?;
+        interpreter.interpret_item(&item, &ctx)?;
+    }
+    // `&Point` derefs to `Point`, whose impl takes the default of the trait
+    let ty = AstType::reference(AstType::ident("Point".into()));
+    let found = ctx
+        .lookup_method(&ty, &"show".into())
+        .context("Expected a method")?;
+    assert_eq!(found.derefs, 1);
+    assert!(found.by_ref());
+    assert_eq!(found.trait_name, Some("Show".into()));
+    assert!(ctx.lookup_method(&ty, &"hide".into()).is_none());
+
+    let err = interpret_shll_expr_directly(shll_parse_expr!(1.hide())).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.message, "Could not invoke method hide");
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_optimize::pass::{MonomorphizePass, OptLevel, PassManager};
+use rust_lang::parser::RustParser;
+use rust_lang::printer::RustPrinter;
+use rust_lang::shll_parse_expr;
+use std::sync::Arc;
+
+fn parse_module(code: &str) -> Result<AstModule> {
+    match RustParser::new().deserialize_item(code)? {
+        AstItem::Module(module) => Ok(module),
+        item => bail!("Expected a module, got {}", item),
+    }
+}
+fn monomorphize_block(code: AstExpr) -> Result<AstExpr> {
+    let pass = MonomorphizePass::new(Arc::new(RustPrinter::new()));
+    let ctx = SharedScopedContext::new();
+    let block = pass.monomorphize_block(code.into_block(), &ctx)?;
+    Ok(AstExpr::Block(block))
+}
+
+#[test]
+fn test_monomorphize_module() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let module = parse_module(
+        "mod m {
+    fn id<T>(value: T) -> T {
+        value
+    }
+    struct Pair<T> {
+        a: T,
+        b: T,
+    }
+    fn main() {
+        let x = id(1u8);
+        let y = id::<bool>(true);
+        let p: Pair<i64> = Pair { a: 1, b: 2 };
+        let q = Pair { a: y, b: false };
+    }
+}",
+    )?;
+    let pass = MonomorphizePass::new(Arc::new(RustPrinter::new()));
+    let module = pass.monomorphize_module(module, &SharedScopedContext::new())?;
+    let names = module
+        .items
+        .iter()
+        .filter_map(|x| x.get_ident())
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["id_u8", "id_bool", "Pair_i64", "Pair_bool", "main"]);
+
+    let expected = parse_module(
+        "mod m {
+    fn main() {
+        let x = id_u8(1u8);
+        let y = id_bool(true);
+        let p: Pair_i64 = Pair_i64 { a: 1, b: 2 };
+        let q = Pair_bool { a: y, b: false };
+    }
+}",
+    )?;
+    assert_eq!(
+        module.items.last().unwrap().to_string(),
+        expected.items[0].to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_monomorphize_nested() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // instances used only by other instances are created as well
+    let value = monomorphize_block(shll_parse_expr! {{
+        fn id<T>(x: T) -> T {
+            x
+        }
+        fn twice<T>(x: T) -> T {
+            id(id(x))
+        }
+        twice(1u8)
+    }})?;
+    let expected = shll_parse_expr! {{
+        fn id_u8(x: u8) -> u8 {
+            x
+        }
+        fn twice_u8(x: u8) -> u8 {
+            id_u8(id_u8(x))
+        }
+        twice_u8(1u8)
+    }};
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_monomorphize_uninferred() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let err = monomorphize_block(shll_parse_expr! {{
+        fn none<T>(x: i64) -> i64 {
+            x
+        }
+        none(1)
+    }})
+    .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::InferenceFailed);
+    assert_eq!(
+        diagnostic.message,
+        "Could not infer type argument `T` of `none`"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_monomorphize_pipeline() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let module = parse_module(
+        "mod m {
+    fn id<T>(value: T) -> T {
+        value
+    }
+    fn main() {
+        let x = id(1u8);
+    }
+}",
+    )?;
+    let mut manager = PassManager::with_level(Arc::new(RustPrinter::new()), OptLevel::O0)?
+        .with_monomorphize(true);
+    let stats = manager
+        .stats()
+        .iter()
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(stats, ["monomorphize"]);
+
+    let module = manager.optimize_module(module, &SharedScopedContext::new(), true)?;
+    let names = module
+        .items
+        .iter()
+        .filter_map(|x| x.get_ident())
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"id_u8".to_string()), "{:?}", names);
+    assert!(!names.contains(&"id".to_string()), "{:?}", names);
+    Ok(())
+}
+
+#[test]
+fn test_monomorphize_struct_impl() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let module = parse_module(
+        "mod m {
+    struct Pair<T> {
+        a: T,
+        b: T,
+    }
+    impl<T> Pair<T> {
+        fn first(self) -> T {
+            self.a
+        }
+    }
+    fn main() {
+        let p: Pair<i64> = Pair { a: 1, b: 2 };
+        let x = p.first();
+    }
+}",
+    )?;
+    let pass = MonomorphizePass::new(Arc::new(RustPrinter::new()));
+    let module = pass.monomorphize_module(module, &SharedScopedContext::new())?;
+    let instance = module
+        .items
+        .iter()
+        .find(|x| matches!(x, AstItem::Impl(impl_) if impl_.generics_params.is_empty()))
+        .context("Expected an impl of the instance")?;
+
+    let expected = parse_module(
+        "mod m {
+    impl Pair_i64 {
+        fn first(self) -> i64 {
+            self.a
+        }
+    }
+}",
+    )?;
+    assert_eq!(instance.to_string(), expected.items[0].to_string());
+    Ok(())
+}
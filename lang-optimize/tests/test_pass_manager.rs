@@ -0,0 +1,109 @@
+use common::*;
+use lang_core::ast::register_threadlocal_serializer;
+use lang_core::context::SharedScopedContext;
+use lang_optimize::pass::{OptLevel, PassManager};
+use rust_lang::printer::RustPrinter;
+use rust_lang::shll_parse_expr;
+use std::sync::Arc;
+
+#[test]
+fn test_pass_manager_pipeline() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+
+    let mut manager = PassManager::new(serializer, "specialize, dce")?.with_max_iterations(4);
+    let stats: Vec<_> = manager.stats().iter().map(|x| x.name.clone()).collect();
+    assert_eq!(stats, vec!["specialize", "dce"]);
+
+    let code = shll_parse_expr! {{
+        let a = 1 + 2;
+        3
+    }};
+    let ctx = SharedScopedContext::new();
+    let value = manager.optimize_expr(code, &ctx)?;
+    assert_eq!(value.to_string(), shll_parse_expr!(3).to_string());
+
+    // the second iteration should observe no changes and stop
+    assert_eq!(manager.iterations(), 2);
+    let dce = &manager.stats()[1];
+    assert_eq!(dce.runs, 2);
+    assert_eq!(dce.changes, 1);
+    Ok(())
+}
+
+#[test]
+fn test_pass_manager_level_reaches_fixpoint() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+
+    let mut manager = PassManager::with_level(serializer, OptLevel::O2)?;
+    assert!(manager.max_iterations() > 1);
+    let code = shll_parse_expr! {{
+        let a = 1 + 2;
+        3
+    }};
+    let ctx = SharedScopedContext::new();
+    let value = manager.optimize_expr(code, &ctx)?;
+    assert_eq!(value.to_string(), shll_parse_expr!(3).to_string());
+    // stops once an iteration changes nothing, before the limit
+    assert!(manager.iterations() < manager.max_iterations());
+    Ok(())
+}
+
+#[test]
+fn test_pass_manager_unknown_pass() -> Result<()> {
+    let serializer = Arc::new(RustPrinter::new());
+    assert!(PassManager::new(serializer, "specialize,unknown").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_pass_manager_o0() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+
+    let mut manager = PassManager::with_level(serializer, "O0".parse::<OptLevel>()?)?;
+    assert!(manager.stats().is_empty());
+    let code = shll_parse_expr!(1 + 2);
+    let ctx = SharedScopedContext::new();
+    let value = manager.optimize_expr(code.clone(), &ctx)?;
+    assert_eq!(value, code);
+    Ok(())
+}
+
+#[test]
+fn test_pass_manager_inlines_method() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+
+    let mut manager = PassManager::new(serializer, "specialize, inline")?.with_max_iterations(4);
+    let code = shll_parse_expr! {{
+        trait Double {
+            fn double(&self) -> i64;
+        }
+        impl Double for i64 {
+            fn double(&self) -> i64 {
+                self * 2
+            }
+        }
+        2.double()
+    }};
+    let ctx = SharedScopedContext::new();
+    let value = manager.optimize_expr(code, &ctx)?;
+    let expected = shll_parse_expr!({
+        trait Double {
+            fn double(&self) -> i64;
+        }
+        impl Double for i64 {
+            fn double(&self) -> i64 {
+                self * 2
+            }
+        }
+        fn double_0() -> i64 {
+            4
+        }
+        4
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
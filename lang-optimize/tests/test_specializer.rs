@@ -1,9 +1,9 @@
 use common::*;
 use lang_core::ast::*;
 use lang_core::context::SharedScopedContext;
-use lang_optimize::pass::{FoldOptimizer, SpecializePass};
+use lang_optimize::pass::{FoldOptimizer, NoopPass, SpecializePass};
 use rust_lang::printer::RustPrinter;
-use rust_lang::shll_parse_expr;
+use rust_lang::{shll_parse_expr, shll_parse_item};
 use std::sync::Arc;
 
 fn specialize_shll_expr(mut expr: AstExpr) -> Result<AstExpr> {
@@ -45,14 +45,12 @@ fn test_specialize_function_call() -> Result<()> {
         fn foo(a: i64, b: i64) -> i64 {
             a + b
         }
-        {
-            fn foo_0() -> i64 {
-                let a = 1;
-                let b = 2;
-                3
-            }
-            foo_0()
+        fn foo_0() -> i64 {
+            let a = 1;
+            let b = 2;
+            3
         }
+        foo_0()
     });
     assert_eq!(value.to_string(), expected.to_string());
     Ok(())
@@ -78,15 +76,305 @@ fn test_specialize_function_call_in_main() -> Result<()> {
         fn main() {
             foo(1, 2)
         }
-        {
-            fn foo_0() -> i64 {
-                let a = 1;
-                let b = 2;
-                3
+        fn foo_0() -> i64 {
+            let a = 1;
+            let b = 2;
+            3
+        }
+        foo_0()
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_specialize_function_call_reused() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {{
+        fn foo(a: i64, b: i64) -> i64 {
+            a + b
+        }
+        foo(1, 2);
+        foo(1, 2);
+        foo(2, 2)
+    }};
+    let value = specialize_shll_expr(code)?;
+    let expected = shll_parse_expr!({
+        fn foo(a: i64, b: i64) -> i64 {
+            a + b
+        }
+        fn foo_0() -> i64 {
+            let a = 1;
+            let b = 2;
+            3
+        }
+        fn foo_1() -> i64 {
+            let a = 2;
+            let b = 2;
+            4
+        }
+        foo_0();
+        foo_0();
+        foo_1()
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_specialize_skips_no_specialize() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {{
+        #[shll::no_specialize]
+        fn foo(a: i64, b: i64) -> i64 {
+            a + b
+        }
+        #[inline(never)]
+        fn bar(a: i64) -> i64 {
+            a * 2
+        }
+        foo(1, 2);
+        bar(3)
+    }};
+    let value = specialize_shll_expr(code)?;
+    let expected = shll_parse_expr!({
+        #[shll::no_specialize]
+        fn foo(a: i64, b: i64) -> i64 {
+            a + b
+        }
+        #[inline(never)]
+        fn bar(a: i64) -> i64 {
+            a * 2
+        }
+        #[inline(never)]
+        fn bar_0() -> i64 {
+            let a = 3;
+            6
+        }
+        foo(1, 2);
+        bar_0()
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_specialize_copies_attrs() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // a copy exported under the same symbol wouldn't link
+    let code = shll_parse_expr! {{
+        #[no_mangle]
+        #[inline(never)]
+        #[must_use]
+        fn bar(a: i64) -> i64 {
+            a * 2
+        }
+        bar(3)
+    }};
+    let value = specialize_shll_expr(code)?;
+    let expected = shll_parse_expr!({
+        #[no_mangle]
+        #[inline(never)]
+        #[must_use]
+        fn bar(a: i64) -> i64 {
+            a * 2
+        }
+        #[inline(never)]
+        #[must_use]
+        fn bar_0() -> i64 {
+            let a = 3;
+            6
+        }
+        bar_0()
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_specialize_keeps_call_with_return() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // pasted into `main`, the `return` would leave `main` instead
+    let code = shll_parse_expr! {{
+        fn foo() -> i64 {
+            if flag() {
+                return 1;
+            }
+            2
+        }
+        let a = foo();
+        a + 1
+    }};
+    let value = specialize_shll_expr(code)?;
+    let AstExpr::Block(block) = &value else {
+        bail!("Expected a block, got {}", value)
+    };
+    let init = block
+        .stmts
+        .iter()
+        .find_map(|x| match x {
+            BlockStmt::Let(let_) => let_.init.clone(),
+            _ => None,
+        })
+        .context("Expected a let")?;
+    assert!(matches!(init, AstExpr::Invoke(_)), "{}", value);
+    Ok(())
+}
+#[test]
+fn test_resolve_method_call() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer = FoldOptimizer::new(serializer, Box::new(NoopPass));
+
+    let code = shll_parse_expr! {{
+        struct Foo {
+            x: i64
+        }
+        impl Foo {
+            fn new(x: i64) -> Foo {
+                Foo { x }
+            }
+            fn get(self) -> i64 {
+                self.x
+            }
+        }
+        Foo::new(1);
+        Foo { x: 1 }.get()
+    }};
+    let value = optimizer.optimize_expr(code, &SharedScopedContext::new())?;
+    let expected = shll_parse_expr!({
+        struct Foo {
+            x: i64,
+        }
+        impl Foo {
+            fn new(x: i64) -> Foo {
+                Foo { x }
+            }
+            fn get(self) -> i64 {
+                self.x
+            }
+        }
+        Foo::new(1);
+        Foo::get(Foo { x: 1 })
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_resolve_method_call_by_ref() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer = FoldOptimizer::new(serializer, Box::new(NoopPass));
+
+    let code = shll_parse_expr! {{
+        struct Foo {
+            x: i64
+        }
+        impl Foo {
+            fn get(&self) -> i64 {
+                self.x
+            }
+            fn set(&mut self, x: i64) {
+                self.x = x;
+            }
+            fn take(self) -> i64 {
+                self.x
+            }
+        }
+        Foo { x: 1 }.set(2);
+        (&Foo { x: 1 }).take();
+        Foo { x: 1 }.get()
+    }};
+    let value = optimizer.optimize_expr(code, &SharedScopedContext::new())?;
+    let expected = shll_parse_expr!({
+        struct Foo {
+            x: i64,
+        }
+        impl Foo {
+            fn get(&self) -> i64 {
+                self.x
+            }
+            fn set(&mut self, x: i64) {
+                self.x = x;
+            }
+            fn take(self) -> i64 {
+                self.x
+            }
+        }
+        Foo::set(&mut Foo { x: 1 }, 2);
+        Foo::take(Foo { x: 1 });
+        Foo::get(&Foo { x: 1 })
+    });
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+#[test]
+fn test_specialize_method_call() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_expr! {{
+        trait Double {
+            fn double(&self) -> i64;
+        }
+        impl Double for i64 {
+            fn double(&self) -> i64 {
+                self * 2
             }
-            foo_0()
         }
+        2.double()
+    }};
+    let value = specialize_shll_expr(code)?;
+    let expected = shll_parse_expr!({
+        trait Double {
+            fn double(&self) -> i64;
+        }
+        impl Double for i64 {
+            fn double(&self) -> i64 {
+                self * 2
+            }
+        }
+        fn double_0() -> i64 {
+            4
+        }
+        double_0()
     });
     assert_eq!(value.to_string(), expected.to_string());
     Ok(())
 }
+#[test]
+fn test_specialize_module_function_call() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer = FoldOptimizer::new(
+        serializer.clone(),
+        Box::new(SpecializePass::new(serializer.clone())),
+    );
+
+    let foo = shll_parse_item! {
+        fn foo(a: i64, b: i64) -> i64 {
+            a + b
+        }
+    };
+    let call = AstItem::Expr(shll_parse_expr!(foo(1, 2)));
+    let module = AstModule {
+        name: "m".into(),
+        items: vec![foo.clone(), call.clone(), call],
+        visibility: Visibility::Public,
+    };
+    let value = optimizer.optimize_module(module, &SharedScopedContext::new(), false)?;
+    let specialized = shll_parse_item! {
+        fn foo_0() -> i64 {
+            let a = 1;
+            let b = 2;
+            3
+        }
+    };
+    let call = AstItem::Expr(shll_parse_expr!(foo_0()));
+    let expected = vec![foo, specialized, call.clone(), call];
+    let printer = RustPrinter::new();
+    assert_eq!(
+        printer.print_items_chunk(&value.items)?.to_string(),
+        printer.print_items_chunk(&expected)?.to_string()
+    );
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_core::ctx::{Context, TypeSystem};
+use lang_core::error::{render_report, Diagnostic, ErrorCode};
+use lang_optimize::pass::{PassManager, TypeChecker};
+use rust_lang::parser::RustParser;
+use rust_lang::printer::RustPrinter;
+use rust_lang::shll_parse_expr;
+use std::sync::Arc;
+
+fn parse_module(code: &str) -> Result<AstModule> {
+    match RustParser::new().deserialize_item(code)? {
+        AstItem::Module(module) => Ok(module),
+        item => bail!("Expected a module, got {}", item),
+    }
+}
+fn check_module(code: &str) -> Result<()> {
+    let module = parse_module(code)?;
+    let checker = TypeChecker::new(Arc::new(RustPrinter::new()));
+    checker.check_module(&module, &SharedScopedContext::new())
+}
+fn check_module_err(code: &str) -> Result<(Diagnostic, String)> {
+    let err = match check_module(code) {
+        Ok(()) => bail!("Expected {} to be rejected", code),
+        Err(err) => err,
+    };
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?
+        .clone();
+    Ok((diagnostic, render_report(&err)))
+}
+
+#[test]
+fn test_typeck_well_typed() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = r#"
+mod shapes {
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+    impl Point {
+        fn new(x: i64, y: i64) -> Self {
+            Point { x, y }
+        }
+        fn sum(&self) -> i64 {
+            self.x + self.y
+        }
+        fn shift(&mut self, dx: i64) {
+            self.x = self.x + dx;
+        }
+    }
+    fn id<T>(value: T) -> T {
+        value
+    }
+    fn main() -> i64 {
+        let mut p = Point::new(1, 2);
+        p.shift(3);
+        let r: &Point = &p;
+        let small: u8 = id(1u8);
+        let total: i64 = r.sum() + id(p.y);
+        if total > 10 {
+            total
+        } else {
+            0
+        }
+    }
+}"#;
+    check_module(code)
+}
+
+#[test]
+fn test_typeck_let_mismatch() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = "mod m {\n    fn foo() {\n        let a: bool = 1;\n    }\n}";
+    let (diagnostic, rendered) = check_module_err(code)?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    assert_eq!(diagnostic.message, "Expected bool, found i64");
+    assert!(rendered.contains("[__file__:3:23]"), "{}", rendered);
+
+    let code = "mod m {\n    fn foo() {\n        let a: u8 = 256;\n    }\n}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.code, ErrorCode::LiteralOutOfRange);
+
+    let code = "mod m {\n    fn foo() -> i64 {\n        true\n    }\n}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected i64, found bool");
+    Ok(())
+}
+
+#[test]
+fn test_typeck_return_and_match() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = r#"
+mod m {
+    fn pick(flag: bool, x: u8) -> u8 {
+        if flag {
+            return 0;
+        }
+        match x {
+            y if y > 1 => y,
+            _ => 1,
+        }
+    }
+}"#;
+    check_module(code)?;
+
+    let code = "mod m {\n    fn foo() -> i64 {\n        return true;\n    }\n}";
+    let (diagnostic, rendered) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected i64, found bool");
+    assert!(rendered.contains("[__file__:3:16]"), "{}", rendered);
+
+    let code = "mod m {\n    fn foo(x: i64) -> i64 {\n        match x {\n            _ => false,\n        }\n    }\n}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected i64, found bool");
+    Ok(())
+}
+
+#[test]
+fn test_typeck_struct_mismatch() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = "mod m {
+    struct Point { x: i64, y: i64 }
+    fn foo() -> Point {
+        Point { x: 1, y: true }
+    }
+}";
+    let (diagnostic, rendered) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected i64, found bool");
+    assert!(rendered.contains("[__file__:4:26]"), "{}", rendered);
+
+    let code = "mod m {
+    struct Point { x: i64, y: i64 }
+    fn foo() -> Point {
+        Point { x: 1 }
+    }
+}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    assert_eq!(
+        diagnostic.message,
+        "Missing fields `y` in initializer of Point"
+    );
+
+    let code = "mod m {
+    struct Point { x: i64, y: i64 }
+    fn foo(p: &Point) -> i64 {
+        p.z
+    }
+}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.code, ErrorCode::UnresolvedName);
+    assert_eq!(diagnostic.message, "No field `z` on type Point");
+    Ok(())
+}
+
+#[test]
+fn test_typeck_call_mismatch() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = "mod m {
+    fn add(a: i64, b: i64) -> i64 {
+        a + b
+    }
+    fn foo() -> i64 {
+        add(1)
+    }
+}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(
+        diagnostic.message,
+        "Function takes 2 arguments but 1 were supplied"
+    );
+
+    // the generic param is instantiated with the type of the arg
+    let code = "mod m {
+    fn id<T>(value: T) -> T {
+        value
+    }
+    fn foo() -> bool {
+        id(1u8)
+    }
+}";
+    let (diagnostic, _) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected bool, found u8");
+
+    let code = "mod m {
+    fn bump(x: &mut i64) {}
+    fn foo() {
+        let a = 1;
+        bump(&a);
+    }
+}";
+    let (diagnostic, rendered) = check_module_err(code)?;
+    assert_eq!(diagnostic.message, "Expected &mut i64, found &i64");
+    assert!(rendered.contains("[__file__:5:14]"), "{}", rendered);
+    Ok(())
+}
+
+#[test]
+fn test_typeck_pass_manager() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let serializer = Arc::new(RustPrinter::new());
+
+    let code = "mod m {\n    fn foo() -> bool {\n        1 + 2\n    }\n}";
+    let module = parse_module(code)?;
+    let ctx = SharedScopedContext::new();
+    let mut manager = PassManager::new(serializer.clone(), "")?;
+    manager.optimize_module(module.clone(), &ctx, true)?;
+
+    let mut manager = PassManager::new(serializer, "")?.with_type_check(true);
+    let err = manager.optimize_module(module, &ctx, true).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::TypeMismatch);
+    Ok(())
+}
+
+#[test]
+fn test_typeck_type_system() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let checker = TypeChecker::new(Arc::new(RustPrinter::new()));
+    let ctx = Context::new();
+    ctx.values
+        .insert_type("a", AstType::Primitive(TypePrimitive::Int(TypeInt::U8)));
+    let ty = checker.get_ty_from_expr(&ctx, &shll_parse_expr!(1 + a))?;
+    assert_eq!(ty, AstType::Primitive(TypePrimitive::Int(TypeInt::U8)));
+    let ty = checker.get_ty_from_expr(&ctx, &shll_parse_expr!(&a))?;
+    assert_eq!(ty.to_string(), "&u8");
+    Ok(())
+}
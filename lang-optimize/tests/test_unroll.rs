@@ -0,0 +1,227 @@
+use common::*;
+use lang_core::ast::*;
+use lang_core::context::SharedScopedContext;
+use lang_optimize::pass::{FoldOptimizer, LoopUnrollPass};
+use rust_lang::printer::RustPrinter;
+use rust_lang::shll_parse_item;
+use std::sync::Arc;
+
+fn unroll_shll_item(item: AstItem, limit: usize) -> Result<AstItem> {
+    let serializer = Arc::new(RustPrinter::new());
+    let optimizer = FoldOptimizer::new(
+        serializer.clone(),
+        Box::new(LoopUnrollPass::new(serializer).with_limit(limit)),
+    );
+    let ctx = SharedScopedContext::new();
+    optimizer.optimize_item(item, &ctx)
+}
+
+#[test]
+fn test_unroll_for_range() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo() -> i64 {
+            let n = 3;
+            let mut s = 0;
+            for i in 0..n {
+                s = s + i;
+            }
+            s
+        }
+    };
+    let value = unroll_shll_item(code, LoopUnrollPass::DEFAULT_LIMIT)?;
+    let expected = shll_parse_item! {
+        fn foo() -> i64 {
+            let n = 3;
+            let mut s = 0;
+            {
+                let i = 0;
+                s = s + i;
+            }
+            {
+                let i = 1;
+                s = s + i;
+            }
+            {
+                let i = 2;
+                s = s + i;
+            }
+            s
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_while_and_loop() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo() -> i64 {
+            let mut i = 0;
+            let mut s = 0;
+            while i < 2 {
+                s = s + i;
+                i = i + 1;
+            }
+            loop {
+                i = i + 1;
+                if i == 4 {
+                    break;
+                }
+                s = s + i;
+            }
+            s
+        }
+    };
+    let value = unroll_shll_item(code, LoopUnrollPass::DEFAULT_LIMIT)?;
+    let expected = shll_parse_item! {
+        fn foo() -> i64 {
+            let mut i = 0;
+            let mut s = 0;
+            {
+                s = s + i;
+                i = i + 1;
+            }
+            {
+                s = s + i;
+                i = i + 1;
+            }
+            {
+                i = i + 1;
+                s = s + i;
+            }
+            {
+                i = i + 1;
+            }
+            s
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_keeps_loop_over_limit() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn foo(a: i64) -> i64 {
+            let mut s = 0;
+            for i in 0..3 {
+                s = s + i;
+            }
+            for i in 0..a {
+                s = s + i;
+            }
+            s
+        }
+    };
+    let value = unroll_shll_item(code.clone(), 2)?;
+    assert_eq!(value.to_string(), code.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_while_with_runtime_values() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    let code = shll_parse_item! {
+        fn sum(a: Vec<i64>) -> i64 {
+            let mut acc = 0;
+            let mut i = 0;
+            while i < 3 {
+                acc = acc + a[i];
+                i = i + 1;
+            }
+            acc
+        }
+    };
+    let value = unroll_shll_item(code, LoopUnrollPass::DEFAULT_LIMIT)?;
+    let expected = shll_parse_item! {
+        fn sum(a: Vec<i64>) -> i64 {
+            let mut acc = 0;
+            let mut i = 0;
+            {
+                acc = acc + a[i];
+                i = i + 1;
+            }
+            {
+                acc = acc + a[i];
+                i = i + 1;
+            }
+            {
+                acc = acc + a[i];
+                i = i + 1;
+            }
+            acc
+        }
+    };
+    assert_eq!(value.to_string(), expected.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_keeps_huge_range() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // the trip count is computed, not the values of the range
+    let code = shll_parse_item! {
+        fn foo() -> i64 {
+            let mut s = 0;
+            for i in 0..1000000000000i64 {
+                s = s + i;
+            }
+            s
+        }
+    };
+    let value = unroll_shll_item(code.clone(), LoopUnrollPass::DEFAULT_LIMIT)?;
+    assert_eq!(value.to_string(), code.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_let_assigning_init() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // `i` is 1 after the let, the loop must not run 3 times
+    let code = shll_parse_item! {
+        fn foo() -> i64 {
+            let mut i = 0;
+            let y = {
+                i += 1;
+                i
+            };
+            while i < 3 {
+                i = i + 1;
+            }
+            i + y
+        }
+    };
+    let value = unroll_shll_item(code.clone(), LoopUnrollPass::DEFAULT_LIMIT)?;
+    assert_eq!(value.to_string(), code.to_string());
+    Ok(())
+}
+
+#[test]
+fn test_unroll_let_borrowing_mutably() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+
+    // `i` is written through `r`
+    let code = shll_parse_item! {
+        fn foo() -> i64 {
+            let mut i = 0;
+            let r = &mut i;
+            *r = 5;
+            while i < 3 {
+                i = i + 1;
+            }
+            i
+        }
+    };
+    let value = unroll_shll_item(code.clone(), LoopUnrollPass::DEFAULT_LIMIT)?;
+    assert_eq!(value.to_string(), code.to_string());
+    Ok(())
+}
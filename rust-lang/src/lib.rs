@@ -79,7 +79,3 @@ impl PartialEq for RawTokenSteam {
     }
 }
 impl Eq for RawTokenSteam {}
-#[macro_export]
-macro_rules! t {
-    ($t: tt) => {};
-}
@@ -1,22 +1,29 @@
+use crate::parser;
 use crate::parser::expr::parse_expr;
 use crate::parser::parse_path;
 use eyre::bail;
 use eyre::Result;
+use itertools::Itertools;
 use lang_core::ast::{
     AstAttrMeta, AstAttrMetaList, AstAttrMetaNameValue, AstAttrStyle, AstAttribute,
 };
+use syn::punctuated::Punctuated;
+use syn::Token;
 fn parse_attr_style(s: syn::AttrStyle) -> Result<AstAttrStyle> {
     Ok(match s {
         syn::AttrStyle::Outer => AstAttrStyle::Outer,
         syn::AttrStyle::Inner(_) => AstAttrStyle::Inner,
     })
 }
+/// `#[derive(Debug, Clone)]`, `#[cfg(not(test))]` or `#[inline(always)]`, whose items are
+/// nested metas
 fn parse_attr_meta_list(l: syn::MetaList) -> Result<AstAttrMetaList> {
-    bail!("AstAttrMetaList is not implemented: {:?}", l);
-    // let name = parse_path(l.path)?;
-    // let items = todo!();
-    // Ok(AstAttrMetaList { name, items })
-    // todo!()
+    let Ok(items) = l.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated) else {
+        bail!(parser::unsupported("Attribute meta list", &l));
+    };
+    let name = parse_path(l.path)?;
+    let items = items.into_iter().map(parse_attr_meta).try_collect()?;
+    Ok(AstAttrMetaList { name, items })
 }
 fn parse_attr_meta_name_value(nv: syn::MetaNameValue) -> Result<AstAttrMetaNameValue> {
     let name = parse_path(nv.path)?;
@@ -1,46 +1,65 @@
 use crate::parser::item::parse_item;
 use crate::parser::pat::parse_pat;
-use crate::parser::ty::{parse_member, parse_type};
+use crate::parser::ty::{parse_member, parse_type, parse_type_macro};
 use crate::{parser, RawExpr, RawExprMacro, RawStmtMacro};
 use common::warn;
 use eyre::bail;
 use itertools::Itertools;
 use lang_core::ast::*;
+use lang_core::error::{Diagnostic, ErrorCode};
 use lang_core::id::Ident;
 use lang_core::ops::{BinOpKind, UnOpKind};
 use lang_core::utils::anybox::AnyBox;
-use quote::ToTokens;
+use syn::parse_quote;
+use syn::spanned::Spanned;
 
 pub fn parse_expr(expr: syn::Expr) -> eyre::Result<AstExpr> {
+    let span = expr.span();
     let expr = match expr {
         syn::Expr::Binary(b) => parse_expr_binary(b)?,
         syn::Expr::Unary(u) => parse_unary(u)?.into(),
         syn::Expr::Block(b) if b.label.is_none() => AstExpr::block(parse_block(b.block)?),
+        syn::Expr::Match(m) => AstExpr::Match(parse_expr_match(m)?),
         syn::Expr::Call(c) => AstExpr::Invoke(parse_expr_call(c)?.into()),
         syn::Expr::If(i) => AstExpr::If(parse_expr_if(i)?),
         syn::Expr::Loop(l) => AstExpr::Loop(parse_expr_loop(l)?),
+        syn::Expr::Break(b) => AstExpr::Break(parse_expr_break(b)?),
+        syn::Expr::Continue(c) => AstExpr::Continue(parse_expr_continue(c)?),
+        syn::Expr::Return(r) => AstExpr::Return(parse_expr_return(r)?),
+        syn::Expr::Assign(a) => AstExpr::Assign(parse_expr_assign(a)?),
         syn::Expr::Lit(l) => AstExpr::value(parse_literal(l.lit)?),
+        // types in comptime blocks, like `let mut T = t! { struct {} };`
+        syn::Expr::Macro(m) if m.mac.path == parse_quote!(t) => {
+            AstExpr::value(AstValue::Type(parse_type_macro(&m.mac)?))
+        }
         syn::Expr::Macro(m) => AstExpr::any(RawExprMacro { raw: m }),
         syn::Expr::MethodCall(c) => AstExpr::Invoke(parse_expr_method_call(c)?.into()),
         syn::Expr::Index(i) => AstExpr::Index(parse_expr_index(i)?),
-        syn::Expr::Path(p) => AstExpr::path(parser::parse_path(p.path)?),
+        syn::Expr::Cast(c) => AstExpr::Cast(parse_expr_cast(c)?),
+        syn::Expr::Path(p) => AstExpr::Locator(parser::parse_locator(p.path)?),
         syn::Expr::Reference(r) => AstExpr::Reference(parse_expr_reference(r)?.into()),
         syn::Expr::Tuple(t) if t.elems.is_empty() => AstExpr::unit(),
         syn::Expr::Tuple(t) => AstExpr::Tuple(parse_expr_tuple(t)?),
         syn::Expr::Struct(s) => AstExpr::Struct(parse_expr_struct(s)?.into()),
         syn::Expr::Paren(p) => AstExpr::Paren(parse_expr_paren(p)?),
+        syn::Expr::Const(c) => AstExpr::Const(parse_expr_const(c)?),
+        syn::Expr::Unsafe(u) => AstExpr::Unsafe(parse_expr_unsafe(u)?),
+        syn::Expr::Async(a) => AstExpr::Async(parse_expr_async(a)?),
         syn::Expr::Range(r) => AstExpr::Range(parse_expr_range(r)?),
         syn::Expr::Field(f) => AstExpr::Select(parse_expr_field(f)?.into()),
         syn::Expr::Try(t) => AstExpr::Try(parse_expr_try(t)?),
         syn::Expr::While(w) => AstExpr::While(parse_expr_while(w)?),
+        syn::Expr::ForLoop(f) => AstExpr::For(parse_expr_for(f)?),
         syn::Expr::Let(l) => AstExpr::Let(parse_expr_let(l)?),
         syn::Expr::Closure(c) => AstExpr::Closure(parse_expr_closure(c)?),
         syn::Expr::Array(a) => AstExpr::Array(parse_expr_array(a)?),
+        syn::Expr::Repeat(r) => AstExpr::ArrayRepeat(parse_expr_repeat(r)?),
         raw => {
             warn!("RawExpr {:?}", raw);
             AstExpr::Any(AnyBox::new(RawExpr { raw }))
         } // x => bail!("Expr not supported: {:?}", x),
     };
+    parser::record_span(&expr, span);
     Ok(expr)
 }
 fn parse_expr_array(a: syn::ExprArray) -> eyre::Result<ExprArray> {
@@ -48,6 +67,12 @@ fn parse_expr_array(a: syn::ExprArray) -> eyre::Result<ExprArray> {
         values: a.elems.into_iter().map(parse_expr).try_collect()?,
     })
 }
+fn parse_expr_repeat(r: syn::ExprRepeat) -> eyre::Result<ExprArrayRepeat> {
+    Ok(ExprArrayRepeat {
+        value: parse_expr(*r.expr)?.into(),
+        len: parse_expr(*r.len)?.into(),
+    })
+}
 fn parse_expr_closure(c: syn::ExprClosure) -> eyre::Result<ExprClosure> {
     let movability = c.movability.is_some();
     let params: Vec<_> = c.inputs.into_iter().map(|x| parse_pat(x)).try_collect()?;
@@ -71,10 +96,25 @@ fn parse_expr_let(l: syn::ExprLet) -> eyre::Result<ExprLet> {
 }
 fn parse_expr_while(w: syn::ExprWhile) -> eyre::Result<ExprWhile> {
     Ok(ExprWhile {
+        label: w.label.map(|x| parser::parse_ident(x.name.ident)),
         cond: parse_expr(*w.cond)?.into(),
         body: AstExpr::Block(parse_block(w.body)?).into(),
     })
 }
+fn parse_expr_cast(c: syn::ExprCast) -> eyre::Result<ExprCast> {
+    Ok(ExprCast {
+        expr: parse_expr(*c.expr)?.into(),
+        ty: parse_type(*c.ty)?,
+    })
+}
+fn parse_expr_for(f: syn::ExprForLoop) -> eyre::Result<ExprFor> {
+    Ok(ExprFor {
+        label: f.label.map(|x| parser::parse_ident(x.name.ident)),
+        pat: parse_pat(*f.pat)?.into(),
+        iter: parse_expr(*f.expr)?.into(),
+        body: AstExpr::Block(parse_block(f.body)?).into(),
+    })
+}
 fn parse_expr_try(t: syn::ExprTry) -> eyre::Result<ExprTry> {
     Ok(ExprTry {
         expr: parse_expr(*t.expr)?.into(),
@@ -97,21 +137,52 @@ pub fn parse_field_member(f: syn::Member) -> Ident {
 }
 pub fn parse_literal(lit: syn::Lit) -> eyre::Result<AstValue> {
     Ok(match lit {
-        syn::Lit::Int(i) => AstValue::Int(ValueInt::new(i.base10_parse()?)),
+        syn::Lit::Int(i) => AstValue::Int(parse_lit_int(i)?),
         syn::Lit::Float(i) => AstValue::Decimal(ValueDecimal::new(i.base10_parse()?)),
         syn::Lit::Str(s) => AstValue::String(ValueString::new_ref(s.value())),
         syn::Lit::Bool(b) => AstValue::Bool(ValueBool::new(b.value)),
-        _ => bail!("Lit not supported: {:?}", lit.to_token_stream()),
+        syn::Lit::Char(c) => AstValue::Char(ValueChar::new(c.value())),
+        _ => bail!(parser::unsupported("Literal", &lit)),
     })
 }
 
+fn parse_lit_int(i: syn::LitInt) -> eyre::Result<ValueInt> {
+    let value: i128 = i.base10_parse()?;
+    if i.suffix().is_empty() {
+        return Ok(ValueInt { value, ty: None });
+    }
+    let Some(ty) = TypeInt::from_suffix(i.suffix()) else {
+        bail!(parser::unsupported("Integer suffix", &i))
+    };
+    // the maximum plus one is allowed for a following negation, e.g. `-128i8`
+    let max = ty
+        .max_value()
+        .unwrap()
+        .saturating_add(ty.is_signed() as i128);
+    if value > max {
+        let diagnostic = Diagnostic::new(
+            ErrorCode::LiteralOutOfRange,
+            format!("literal out of range for {}: {}", ty, value),
+        )
+        .with_label(format!("does not fit into `{}`", ty))
+        .with_help(format!(
+            "the range of `{}` is `{}..={}`",
+            ty,
+            ty.min_value().unwrap(),
+            ty.max_value().unwrap()
+        ));
+        bail!(parser::with_tokens(diagnostic, &i))
+    }
+    Ok(ValueInt::new_typed(value, ty))
+}
+
 pub fn parse_unary(u: syn::ExprUnary) -> eyre::Result<ExprUnOp> {
     let expr = parse_expr(*u.expr)?;
     let op = match u.op {
         syn::UnOp::Neg(_) => UnOpKind::Neg,
         syn::UnOp::Not(_) => UnOpKind::Not,
         syn::UnOp::Deref(_) => UnOpKind::Deref,
-        _ => bail!("Unary op not supported: {:?}", u.op),
+        _ => bail!(parser::unsupported("Unary operator", &u.op)),
     };
     Ok(ExprUnOp {
         op,
@@ -226,14 +297,60 @@ pub fn parse_expr_loop(l: syn::ExprLoop) -> eyre::Result<ExprLoop> {
     })
 }
 
+pub fn parse_expr_break(b: syn::ExprBreak) -> eyre::Result<ExprBreak> {
+    Ok(ExprBreak {
+        label: b.label.map(|x| parser::parse_ident(x.ident)),
+        value: b.expr.map(|x| parse_expr(*x)).transpose()?.map(Box::new),
+    })
+}
+
+pub fn parse_expr_continue(c: syn::ExprContinue) -> eyre::Result<ExprContinue> {
+    Ok(ExprContinue {
+        label: c.label.map(|x| parser::parse_ident(x.ident)),
+    })
+}
+
+pub fn parse_expr_return(r: syn::ExprReturn) -> eyre::Result<ExprReturn> {
+    Ok(ExprReturn {
+        value: r.expr.map(|x| parse_expr(*x)).transpose()?.map(Box::new),
+    })
+}
+
+pub fn parse_expr_match(m: syn::ExprMatch) -> eyre::Result<ExprMatch> {
+    let mut cases = vec![];
+    for arm in m.arms {
+        let cond = match arm.guard {
+            Some((_, guard)) => parse_expr(*guard)?,
+            None => AstExpr::value(AstValue::bool(true)),
+        };
+        cases.push(ExprMatchCase {
+            pat: Some(parse_pat(arm.pat)?.into()),
+            cond: cond.into(),
+            body: parse_expr(*arm.body)?.into(),
+        });
+    }
+    Ok(ExprMatch {
+        scrutinee: Some(parse_expr(*m.expr)?.into()),
+        cases,
+    })
+}
+
+pub fn parse_expr_assign(a: syn::ExprAssign) -> eyre::Result<ExprAssign> {
+    Ok(ExprAssign {
+        target: parse_expr(*a.left)?.into(),
+        value: parse_expr(*a.right)?.into(),
+    })
+}
+
 pub fn parse_expr_binary(b: syn::ExprBinary) -> eyre::Result<AstExpr> {
-    let lhs = parse_expr(*b.left)?.into();
+    let lhs: BExpr = parse_expr(*b.left)?.into();
     let rhs = parse_expr(*b.right)?.into();
     let (kind, _flatten) = match b.op {
         syn::BinOp::Add(_) => (BinOpKind::Add, true),
         syn::BinOp::Mul(_) => (BinOpKind::Mul, true),
         syn::BinOp::Sub(_) => (BinOpKind::Sub, false),
         syn::BinOp::Div(_) => (BinOpKind::Div, false),
+        syn::BinOp::Rem(_) => (BinOpKind::Mod, false),
         syn::BinOp::Gt(_) => (BinOpKind::Gt, false),
         syn::BinOp::Ge(_) => (BinOpKind::Ge, false),
         syn::BinOp::Le(_) => (BinOpKind::Le, false),
@@ -245,11 +362,43 @@ pub fn parse_expr_binary(b: syn::ExprBinary) -> eyre::Result<AstExpr> {
         syn::BinOp::BitXor(_) => (BinOpKind::BitXor, true),
         syn::BinOp::Or(_) => (BinOpKind::Or, true),
         syn::BinOp::And(_) => (BinOpKind::And, true),
-        _ => bail!("Op not supported {:?}", b.op),
+        syn::BinOp::Shl(_) => (BinOpKind::Shl, false),
+        syn::BinOp::Shr(_) => (BinOpKind::Shr, false),
+        _ => {
+            // `a += b` is lowered to `a = a + b`, the printer folds it back
+            let Some(kind) = compound_assign_kind(&b.op) else {
+                bail!(parser::unsupported("Binary operator", &b.op))
+            };
+            let value = ExprBinOp {
+                kind,
+                lhs: lhs.clone(),
+                rhs,
+            };
+            return Ok(AstExpr::Assign(ExprAssign {
+                target: lhs,
+                value: AstExpr::BinOp(value).into(),
+            }));
+        }
     };
 
     Ok(ExprBinOp { kind, lhs, rhs }.into())
 }
+fn compound_assign_kind(op: &syn::BinOp) -> Option<BinOpKind> {
+    let kind = match op {
+        syn::BinOp::AddAssign(_) => BinOpKind::Add,
+        syn::BinOp::SubAssign(_) => BinOpKind::Sub,
+        syn::BinOp::MulAssign(_) => BinOpKind::Mul,
+        syn::BinOp::DivAssign(_) => BinOpKind::Div,
+        syn::BinOp::RemAssign(_) => BinOpKind::Mod,
+        syn::BinOp::BitXorAssign(_) => BinOpKind::BitXor,
+        syn::BinOp::BitAndAssign(_) => BinOpKind::BitAnd,
+        syn::BinOp::BitOrAssign(_) => BinOpKind::BitOr,
+        syn::BinOp::ShlAssign(_) => BinOpKind::Shl,
+        syn::BinOp::ShrAssign(_) => BinOpKind::Shr,
+        _ => return None,
+    };
+    Some(kind)
+}
 
 pub fn parse_expr_tuple(t: syn::ExprTuple) -> eyre::Result<ExprTuple> {
     let mut values = vec![];
@@ -283,6 +432,22 @@ pub fn parse_expr_paren(p: syn::ExprParen) -> eyre::Result<ExprParen> {
         expr: parse_expr(*p.expr)?.into(),
     })
 }
+pub fn parse_expr_const(c: syn::ExprConst) -> eyre::Result<ExprConst> {
+    Ok(ExprConst {
+        block: AstExpr::block(parse_block(c.block)?).into(),
+    })
+}
+pub fn parse_expr_unsafe(u: syn::ExprUnsafe) -> eyre::Result<ExprUnsafe> {
+    Ok(ExprUnsafe {
+        block: AstExpr::block(parse_block(u.block)?).into(),
+    })
+}
+pub fn parse_expr_async(a: syn::ExprAsync) -> eyre::Result<ExprAsync> {
+    Ok(ExprAsync {
+        movability: Some(a.capture.is_some()),
+        block: AstExpr::block(parse_block(a.block)?).into(),
+    })
+}
 pub fn parse_expr_range(r: syn::ExprRange) -> eyre::Result<ExprRange> {
     let start = r
         .start
@@ -1,15 +1,16 @@
 use eyre::{bail, ContextCompat};
 use itertools::Itertools;
+use syn::spanned::Spanned;
 use syn::{Fields, FnArg, ReturnType};
 
 use lang_core::ast::*;
-use lang_core::id::Locator;
+use lang_core::id::Ident;
 
 use crate::parser::attr::parse_attrs;
 use crate::parser::expr::parse_expr;
 use crate::parser::pat::parse_pat;
 use crate::parser::ty::{parse_struct_field, parse_type, parse_type_param_bounds};
-use crate::parser::{parse_ident, parse_path, parse_value_fn, parse_vis};
+use crate::parser::{parse_ident, parse_lifetime, parse_value_fn, parse_vis};
 use crate::{parser, RawItemMacro};
 
 fn parse_fn_arg_receiver(r: syn::Receiver) -> eyre::Result<FunctionParamReceiver> {
@@ -24,7 +25,7 @@ fn parse_fn_arg_receiver(r: syn::Receiver) -> eyre::Result<FunctionParamReceiver
         (Some((_, Some(lf))), None) if lf.ident == "static" => Ok(FunctionParamReceiver::RefStatic),
         (None, Some(_)) => Ok(FunctionParamReceiver::MutValue),
         (None, None) => Ok(FunctionParamReceiver::Value),
-        _ => bail!("Does not support receiver {:?}", r),
+        _ => bail!(parser::unsupported("Receiver", &r)),
     }
 }
 pub fn parse_fn_arg(i: FnArg) -> eyre::Result<Option<FunctionParam>> {
@@ -37,19 +38,66 @@ pub fn parse_fn_arg(i: FnArg) -> eyre::Result<Option<FunctionParam>> {
     })
 }
 
-pub fn parse_fn_sig(sig: syn::Signature) -> eyre::Result<FunctionSignature> {
-    let generics_params = sig
-        .generics
+/// Type and const params, the lifetimes are parsed by [`parse_lifetime_params`]
+fn parse_generics_params(generics: syn::Generics) -> eyre::Result<Vec<GenericParam>> {
+    generics
         .params
         .into_iter()
+        .filter(|x| !matches!(x, syn::GenericParam::Lifetime(_)))
         .map(|x| match x {
             syn::GenericParam::Type(t) => Ok(GenericParam {
                 name: parse_ident(t.ident),
                 bounds: parse_type_param_bounds(t.bounds.into_iter().collect())?,
+                const_ty: None,
+            }),
+            syn::GenericParam::Const(c) => Ok(GenericParam {
+                name: parse_ident(c.ident),
+                bounds: TypeBounds::any(),
+                const_ty: Some(parse_type(c.ty)?),
             }),
-            _ => bail!("Does not generic param {:?}", x),
+            _ => bail!(parser::unsupported("Generic param", &x)),
         })
-        .try_collect()?;
+        .try_collect()
+}
+/// Lifetime params without bounds, like `'a` in `fn foo<'a>`
+fn parse_lifetime_params(generics: &syn::Generics) -> eyre::Result<Vec<Ident>> {
+    generics
+        .lifetimes()
+        .map(|x| {
+            if !x.bounds.is_empty() {
+                bail!(parser::unsupported("Lifetime bound", x));
+            }
+            Ok(parse_lifetime(x.lifetime.clone()))
+        })
+        .try_collect()
+}
+fn parse_where_clause(clause: Option<syn::WhereClause>) -> eyre::Result<Vec<WherePredicate>> {
+    let Some(clause) = clause else {
+        return Ok(vec![]);
+    };
+    clause
+        .predicates
+        .into_iter()
+        .map(|x| match x {
+            syn::WherePredicate::Type(t) if t.lifetimes.is_none() => Ok(WherePredicate {
+                ty: parse_type(t.bounded_ty)?,
+                bounds: parse_type_param_bounds(t.bounds.into_iter().collect())?,
+            }),
+            _ => bail!(parser::unsupported("Where predicate", &x)),
+        })
+        .try_collect()
+}
+/// Lifetimes, type and const params and where predicates of an item
+fn parse_generics(
+    mut generics: syn::Generics,
+) -> eyre::Result<(Vec<Ident>, Vec<GenericParam>, Vec<WherePredicate>)> {
+    let lifetimes = parse_lifetime_params(&generics)?;
+    let where_predicates = parse_where_clause(generics.where_clause.take())?;
+    let generics_params = parse_generics_params(generics)?;
+    Ok((lifetimes, generics_params, where_predicates))
+}
+pub fn parse_fn_sig(sig: syn::Signature) -> eyre::Result<FunctionSignature> {
+    let (lifetimes, generics_params, where_predicates) = parse_generics(sig.generics)?;
     let receiver = match sig.inputs.first() {
         Some(FnArg::Receiver(r)) => Some(parse_fn_arg_receiver(r.clone())?),
         _ => None,
@@ -64,7 +112,9 @@ pub fn parse_fn_sig(sig: syn::Signature) -> eyre::Result<FunctionSignature> {
         name: Some(parse_ident(sig.ident)),
         receiver,
         params,
+        lifetimes,
         generics_params,
+        where_predicates,
         ret_ty: match sig.output {
             ReturnType::Default => None,
             ReturnType::Type(_, t) => Some(parse_type(*t)?),
@@ -105,6 +155,11 @@ pub fn parse_use(u: syn::ItemUse) -> eyre::Result<ItemImport> {
 }
 
 pub fn parse_type_struct(s: syn::ItemStruct) -> eyre::Result<TypeStruct> {
+    let kind = match &s.fields {
+        Fields::Named(_) => StructKind::Named,
+        Fields::Unnamed(_) => StructKind::Tuple,
+        Fields::Unit => StructKind::Unit,
+    };
     Ok(TypeStruct {
         name: parse_ident(s.ident),
         fields: s
@@ -113,6 +168,7 @@ pub fn parse_type_struct(s: syn::ItemStruct) -> eyre::Result<TypeStruct> {
             .enumerate()
             .map(|(i, f)| parse_struct_field(i, f))
             .try_collect()?,
+        kind,
     })
 }
 
@@ -135,7 +191,6 @@ fn parse_item_trait(t: syn::ItemTrait) -> eyre::Result<ItemDefTrait> {
 fn parse_impl_item(item: syn::ImplItem) -> eyre::Result<AstItem> {
     match item {
         syn::ImplItem::Fn(m) => {
-            let attrs = parse_attrs(m.attrs.clone())?;
             let func = parse_value_fn(syn::ItemFn {
                 attrs: m.attrs,
                 vis: m.vis.clone(),
@@ -143,7 +198,7 @@ fn parse_impl_item(item: syn::ImplItem) -> eyre::Result<AstItem> {
                 block: Box::new(m.block),
             })?;
             Ok(AstItem::DefFunction(ItemDefFunction {
-                attrs,
+                attrs: func.attrs.clone(),
                 name: func.name.clone().unwrap(),
                 ty: None,
                 sig: func.sig,
@@ -153,10 +208,17 @@ fn parse_impl_item(item: syn::ImplItem) -> eyre::Result<AstItem> {
         }
         syn::ImplItem::Type(t) => Ok(AstItem::DefType(ItemDefType {
             name: parse_ident(t.ident),
+            generics_params: parse_generics_params(t.generics)?,
             value: parse_type(t.ty)?,
             visibility: parse_vis(t.vis),
         })),
-        _ => bail!("Does not support impl item {:?}", item),
+        syn::ImplItem::Const(c) => Ok(AstItem::DefConst(ItemDefConst {
+            name: parse_ident(c.ident),
+            ty: Some(parse_type(c.ty)?),
+            value: parse_expr(c.expr)?.into(),
+            visibility: parse_vis(c.vis),
+        })),
+        _ => bail!(parser::unsupported("Impl item", &item)),
     }
 }
 fn parse_item_static(s: syn::ItemStatic) -> eyre::Result<ItemDefStatic> {
@@ -182,13 +244,13 @@ fn parse_item_const(s: syn::ItemConst) -> eyre::Result<ItemDefConst> {
     })
 }
 fn parse_item_impl(im: syn::ItemImpl) -> eyre::Result<ItemImpl> {
+    let (lifetimes, generics_params, where_predicates) = parse_generics(im.generics)?;
     Ok(ItemImpl {
-        trait_ty: im
-            .trait_
-            .map(|x| parse_path(x.1))
-            .transpose()?
-            .map(Locator::path),
+        lifetimes,
+        generics_params,
+        trait_ty: im.trait_.map(|x| parser::parse_locator(x.1)).transpose()?,
         self_ty: AstExpr::value(parse_type(*im.self_ty.clone())?.into()),
+        where_predicates,
         items: im.items.into_iter().map(parse_impl_item).try_collect()?,
     })
 }
@@ -196,14 +258,28 @@ fn parse_item_impl(im: syn::ItemImpl) -> eyre::Result<ItemImpl> {
 fn parse_item_enum(e: syn::ItemEnum) -> eyre::Result<ItemDefEnum> {
     let visibility = parse_vis(e.vis.clone());
     let ident = parse_ident(e.ident.clone());
+    let (lifetimes, generics_params, where_predicates) = parse_generics(e.generics)?;
     let variants = e
         .variants
         .into_iter()
         .map(|x| {
             let name = parse_ident(x.ident);
             let ty = match x.fields {
-                Fields::Named(_) => bail!("Does not support named fields"),
-                Fields::Unnamed(_) => bail!("Does not support unnamed fields"),
+                Fields::Named(fields) => AstType::Structural(TypeStructural {
+                    fields: fields
+                        .named
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, f)| parse_struct_field(i, f))
+                        .try_collect()?,
+                }),
+                Fields::Unnamed(fields) => AstType::Tuple(TypeTuple {
+                    types: fields
+                        .unnamed
+                        .into_iter()
+                        .map(|f| parse_type(f.ty))
+                        .try_collect()?,
+                }),
                 Fields::Unit => {
                     // be int or string
                     AstType::any()
@@ -213,7 +289,11 @@ fn parse_item_enum(e: syn::ItemEnum) -> eyre::Result<ItemDefEnum> {
         })
         .try_collect()?;
     Ok(ItemDefEnum {
+        attrs: parse_attrs(e.attrs)?,
         name: ident.clone(),
+        lifetimes,
+        generics_params,
+        where_predicates,
         value: TypeEnum {
             name: ident.clone(),
             variants,
@@ -223,10 +303,9 @@ fn parse_item_enum(e: syn::ItemEnum) -> eyre::Result<ItemDefEnum> {
 }
 fn parse_item_fn(f: syn::ItemFn) -> eyre::Result<ItemDefFunction> {
     let visibility = parse_vis(f.vis.clone());
-    let attrs = parse_attrs(f.attrs.clone())?;
     let f = parse_value_fn(f)?;
     let d = ItemDefFunction {
-        attrs,
+        attrs: f.attrs.clone(),
         name: f.name.clone().unwrap(),
         ty: None,
         sig: f.sig,
@@ -236,6 +315,7 @@ fn parse_item_fn(f: syn::ItemFn) -> eyre::Result<ItemDefFunction> {
     Ok(d)
 }
 pub fn parse_item(item: syn::Item) -> eyre::Result<AstItem> {
+    let span = item.span();
     let item = match item {
         syn::Item::Fn(f0) => {
             let f = parse_item_fn(f0)?;
@@ -245,9 +325,16 @@ pub fn parse_item(item: syn::Item) -> eyre::Result<AstItem> {
         syn::Item::Use(u) => AstItem::Import(parse_use(u)?),
         syn::Item::Macro(m) => AstItem::any(RawItemMacro { raw: m }),
         syn::Item::Struct(s) => {
+            let attrs = parse_attrs(s.attrs.clone())?;
+            let (lifetimes, generics_params, where_predicates) =
+                parse_generics(s.generics.clone())?;
             let s = parse_type_struct(s)?;
             AstItem::DefStruct(ItemDefStruct {
+                attrs,
                 name: s.name.clone(),
+                lifetimes,
+                generics_params,
+                where_predicates,
                 value: s,
                 visibility: Visibility::Private,
             })
@@ -261,6 +348,7 @@ pub fn parse_item(item: syn::Item) -> eyre::Result<AstItem> {
             let ty = parse_type(*t.ty)?;
             AstItem::DefType(ItemDefType {
                 name: parse_ident(t.ident),
+                generics_params: parse_generics_params(t.generics)?,
                 value: ty,
                 visibility,
             })
@@ -279,8 +367,9 @@ pub fn parse_item(item: syn::Item) -> eyre::Result<AstItem> {
             let s = parse_item_static(s)?;
             AstItem::DefStatic(s)
         }
-        _ => bail!("Does not support item yet: {:?}", item),
+        _ => bail!(parser::unsupported("Item", &item)),
     };
+    parser::record_span(&item, span);
     Ok(item)
 }
 
@@ -291,6 +380,23 @@ pub fn parse_impl_trait(im: syn::TypeImplTrait) -> eyre::Result<ImplTraits> {
 }
 pub fn parse_trait_item(f: syn::TraitItem) -> eyre::Result<AstItem> {
     match f {
+        // a default method
+        syn::TraitItem::Fn(f) if f.default.is_some() => {
+            let func = parse_value_fn(syn::ItemFn {
+                attrs: f.attrs,
+                vis: syn::Visibility::Inherited,
+                sig: f.sig,
+                block: Box::new(f.default.unwrap()),
+            })?;
+            Ok(AstItem::DefFunction(ItemDefFunction {
+                attrs: func.attrs.clone(),
+                name: func.name.clone().unwrap(),
+                ty: None,
+                sig: func.sig,
+                body: func.body,
+                visibility: Visibility::Inherited,
+            }))
+        }
         syn::TraitItem::Fn(f) => {
             let name = parse_ident(f.sig.ident.clone());
             Ok(ItemDeclFunction {
@@ -307,8 +413,16 @@ pub fn parse_trait_item(f: syn::TraitItem) -> eyre::Result<AstItem> {
         syn::TraitItem::Const(c) => {
             let name = parse_ident(c.ident);
             let ty = parse_type(c.ty)?;
-            Ok(ItemDeclConst { name, ty }.into())
+            match c.default {
+                Some((_, value)) => Ok(AstItem::DefConst(ItemDefConst {
+                    name,
+                    ty: Some(ty),
+                    value: parse_expr(value)?.into(),
+                    visibility: Visibility::Inherited,
+                })),
+                None => Ok(ItemDeclConst { name, ty }.into()),
+            }
         }
-        _ => bail!("Does not support trait item {:?}", f),
+        _ => bail!(parser::unsupported("Trait item", &f)),
     }
 }
@@ -11,12 +11,80 @@ use crate::parser::item::parse_fn_sig;
 use common::*;
 use itertools::Itertools;
 use lang_core::ast::*;
+use lang_core::error::{Diagnostic, ErrorCode};
 use lang_core::id::{Ident, Locator, ParameterPath, ParameterPathSegment, Path};
+use lang_core::span::{with_threadlocal_source_map, FileId, SourceNode, Span};
 
+use quote::ToTokens;
+use std::cell::Cell;
 use std::path::PathBuf;
 use syn::parse_str;
+use syn::spanned::Spanned;
 use syn_inline_mod::InlinerBuilder;
 
+thread_local! {
+    /// 0 while parsing tokens without a source file
+    static CURRENT_FILE: Cell<FileId> = const { Cell::new(0) };
+    static PARSE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+/// Runs a parse, clearing the source map first unless it's nested in another parse
+fn parse_session<T>(f: impl FnOnce() -> T) -> T {
+    let depth = PARSE_DEPTH.get();
+    if depth == 0 {
+        with_threadlocal_source_map(|map| map.clear());
+    }
+    PARSE_DEPTH.set(depth + 1);
+    let ret = f();
+    PARSE_DEPTH.set(depth);
+    ret
+}
+/// Parses with `source` registered in the source map, so that nodes record spans into it
+fn with_source_file<T>(path: PathBuf, source: &str, f: impl FnOnce() -> T) -> T {
+    parse_session(|| {
+        let file = with_threadlocal_source_map(|map| map.add_file(path, source.to_owned()));
+        let previous = CURRENT_FILE.replace(file);
+        let ret = f();
+        CURRENT_FILE.set(previous);
+        ret
+    })
+}
+/// `span` in the current source file if it comes from there, or the tokens themselves
+/// when parsing without a source file
+fn source_span(span: proc_macro2::Span) -> Option<Span> {
+    let file = CURRENT_FILE.get();
+    if file == 0 {
+        return Some(with_threadlocal_source_map(|map| map.add_tokens(span)));
+    }
+    let range = span.byte_range();
+    let span_in_file = Span::new(file, range.start as u32, range.end as u32);
+    with_threadlocal_source_map(|map| {
+        // tokens of modules inlined from other files have offsets into those files
+        let text = map.get_file(file)?.text(span_in_file)?;
+        (Some(text) == span.source_text().as_deref()).then_some(span_in_file)
+    })
+}
+fn record_span(node: &impl SourceNode, span: proc_macro2::Span) {
+    if let Some(span) = source_span(span) {
+        with_threadlocal_source_map(|map| map.record(node, span));
+    }
+}
+/// Points `diagnostic` at the tokens of `node`, in the current source file if they come from
+/// there. Proc macros find the tokens again with [Diagnostic::tokens]
+fn with_tokens(diagnostic: Diagnostic, node: &impl Spanned) -> Diagnostic {
+    match source_span(node.span()) {
+        Some(span) => diagnostic.with_span(span),
+        None => diagnostic,
+    }
+}
+/// Syntax the parser does not handle yet
+fn unsupported(what: &str, node: &impl ToTokens) -> Diagnostic {
+    let diagnostic = Diagnostic::new(
+        ErrorCode::UnsupportedSyntax,
+        format!("{} not supported: {}", what, node.to_token_stream()),
+    )
+    .with_label(format!("unsupported {}", what.to_lowercase()));
+    with_tokens(diagnostic, node)
+}
 pub fn parse_ident(i: syn::Ident) -> Ident {
     Ident::new(i.to_string())
 }
@@ -26,39 +94,50 @@ pub fn parse_path(p: syn::Path) -> Result<Path> {
             .segments
             .into_iter()
             .map(|x| {
-                let ident = parse_ident(x.ident);
-                ensure!(
-                    x.arguments.is_none(),
-                    "Does not support path arguments: {:?}",
-                    x.arguments
-                );
-                Ok(ident)
+                if !x.arguments.is_none() {
+                    bail!(unsupported("Path arguments", &x.arguments));
+                }
+                Ok(parse_ident(x.ident))
             })
             .try_collect()?,
     })
 }
+/// `'a` as `a`, like labels
+pub fn parse_lifetime(l: syn::Lifetime) -> Ident {
+    parse_ident(l.ident)
+}
 pub fn parse_parameter_path(p: syn::Path) -> Result<ParameterPath> {
     Ok(ParameterPath {
         segments: p
             .segments
             .into_iter()
             .map(|x| {
-                let args = match x.arguments {
+                let mut lifetimes = vec![];
+                let mut args = vec![];
+                match x.arguments {
+                    syn::PathArguments::None => {}
                     syn::PathArguments::AngleBracketed(a) => {
-                        a.args
-                            .into_iter()
-                            .map(|x| match x {
-                                syn::GenericArgument::Type(t) => ty::parse_type(t),
-                                syn::GenericArgument::Const(c) => expr::parse_expr(c)
-                                    .map(|x| AstType::value(AstValue::expr(x.get()))),
-                                _ => bail!("Does not support path arguments: {:?}", x),
-                            })
-                            .try_collect()?
+                        for arg in a.args {
+                            match arg {
+                                syn::GenericArgument::Lifetime(l) => {
+                                    lifetimes.push(parse_lifetime(l))
+                                }
+                                syn::GenericArgument::Type(t) => args.push(ty::parse_type(t)?),
+                                syn::GenericArgument::Const(c) => args.push(AstType::value(
+                                    AstValue::expr(expr::parse_expr(c)?.get()),
+                                )),
+                                _ => bail!(unsupported("Generic argument", &arg)),
+                            }
+                        }
                     }
-                    _ => bail!("Does not support path arguments: {:?}", x),
+                    _ => bail!(unsupported("Path arguments", &x.arguments)),
                 };
                 let ident = parse_ident(x.ident);
-                Ok(ParameterPathSegment { ident, args })
+                Ok(ParameterPathSegment {
+                    ident,
+                    lifetimes,
+                    args,
+                })
             })
             .try_collect()?,
     })
@@ -83,15 +162,12 @@ pub fn parse_file(path: PathBuf, file: syn::File) -> Result<AstFile> {
     Ok(AstFile { path, items })
 }
 pub fn parse_module(m: syn::ItemMod) -> Result<AstModule> {
+    let Some((_, items)) = m.content else {
+        bail!(unsupported("Module without body", &m.ident))
+    };
     Ok(AstModule {
         name: parse_ident(m.ident),
-        items: m
-            .content
-            .unwrap()
-            .1
-            .into_iter()
-            .map(item::parse_item)
-            .try_collect()?,
+        items: items.into_iter().map(item::parse_item).try_collect()?,
         visibility: parse_vis(m.vis),
     })
 }
@@ -99,6 +175,7 @@ pub fn parse_value_fn(f: syn::ItemFn) -> Result<ValueFunction> {
     let sig = parse_fn_sig(f.sig)?;
     let body = parse_block(*f.block)?;
     Ok(ValueFunction {
+        attrs: parse_attrs(f.attrs)?,
         sig,
         body: AstExpr::block(body).into(),
     })
@@ -130,54 +207,64 @@ impl RustParser {
             path.display(),
             errors_str
         );
-        let file = self.parse_file_content(path, outputs)?;
+        let source =
+            std::fs::read_to_string(&path).with_context(|| format!("path: {}", path.display()))?;
+        let file = with_source_file(path.clone(), &source, || {
+            self.parse_file_content(path, outputs)
+        })?;
         Ok(file)
     }
     pub fn parse_value(&self, code: syn::Expr) -> Result<AstValue> {
-        expr::parse_expr(code).map(|x| AstValue::expr(x.get()))
+        parse_session(|| expr::parse_expr(code).map(|x| AstValue::expr(x.get())))
     }
     pub fn parse_expr(&self, code: syn::Expr) -> Result<AstExpr> {
-        expr::parse_expr(code).map(|x| x.get())
+        parse_session(|| expr::parse_expr(code).map(|x| x.get()))
     }
     pub fn parse_item(&self, code: syn::Item) -> Result<AstItem> {
-        item::parse_item(code)
+        parse_session(|| item::parse_item(code))
     }
     pub fn parse_items(&self, code: Vec<syn::Item>) -> Result<Vec<AstItem>> {
-        code.into_iter().map(|x| self.parse_item(x)).try_collect()
+        parse_session(|| code.into_iter().map(|x| self.parse_item(x)).try_collect())
     }
     pub fn parse_file_content(&self, path: PathBuf, code: syn::File) -> Result<AstFile> {
-        parse_file(path, code)
+        parse_session(|| parse_file(path, code))
     }
     pub fn parse_module(&self, code: syn::ItemMod) -> Result<AstModule> {
-        parse_module(code)
+        parse_session(|| parse_module(code))
     }
     pub fn parse_type(&self, code: syn::Type) -> Result<AstType> {
-        ty::parse_type(code)
+        parse_session(|| ty::parse_type(code))
+    }
+    /// Parses type arithmetic, the body of `t! { }`
+    pub fn parse_type_expr(&self, code: proc_macro2::TokenStream) -> Result<AstType> {
+        parse_session(|| ty::parse_type_expr(code))
     }
 }
 
 impl AstDeserializer for RustParser {
     fn deserialize_node(&self, code: &str) -> Result<AstNode> {
-        let code: syn::File = parse_str(code)?;
         let path = PathBuf::from("__file__");
-        self.parse_file_content(path, code).map(AstNode::File)
+        let file: syn::File = parse_str(code)?;
+        with_source_file(path.clone(), code, || {
+            self.parse_file_content(path, file).map(AstNode::File)
+        })
     }
 
     fn deserialize_expr(&self, code: &str) -> Result<AstExpr> {
-        let code: syn::Expr = parse_str(code)?;
-        self.parse_expr(code)
+        let expr: syn::Expr = parse_str(code)?;
+        with_source_file(PathBuf::from("__file__"), code, || self.parse_expr(expr))
     }
 
     fn deserialize_item(&self, code: &str) -> Result<AstItem> {
-        let code: syn::Item = parse_str(code)?;
-        self.parse_item(code)
+        let item: syn::Item = parse_str(code)?;
+        with_source_file(PathBuf::from("__file__"), code, || self.parse_item(item))
     }
 
     fn deserialize_file_load(&self, path: &std::path::Path) -> Result<AstFile> {
         self.parse_file_recursively(path.to_owned())
     }
     fn deserialize_type(&self, code: &str) -> Result<AstType> {
-        let code: syn::Type = parse_str(code)?;
-        self.parse_type(code)
+        let ty: syn::Type = parse_str(code)?;
+        with_source_file(PathBuf::from("__file__"), code, || self.parse_type(ty))
     }
 }
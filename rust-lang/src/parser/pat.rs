@@ -1,32 +1,103 @@
-use crate::parser::{parse_ident, parse_locator, ty};
+use crate::parser::expr::{parse_expr_range, parse_literal};
+use crate::parser::{self, parse_ident, parse_locator, ty};
 use eyre::bail;
 use itertools::Itertools;
+use lang_core::ast::AstExpr;
 use lang_core::pat::{
-    Pattern, PatternIdent, PatternTuple, PatternTupleStruct, PatternType, PatternWildcard,
+    Pattern, PatternIdent, PatternLit, PatternOr, PatternRange, PatternRest, PatternSlice,
+    PatternStruct, PatternStructField, PatternTuple, PatternTupleStruct, PatternType,
+    PatternVariant, PatternWildcard,
 };
-use quote::ToTokens;
+use syn::spanned::Spanned;
 
 pub fn parse_pat_ident(i: syn::PatIdent) -> eyre::Result<PatternIdent> {
     Ok(PatternIdent {
         ident: parse_ident(i.ident),
         mutability: Some(i.mutability.is_some()),
+        by_ref: i.by_ref.is_some(),
+        subpattern: i
+            .subpat
+            .map(|(_, pat)| parse_pat(*pat))
+            .transpose()?
+            .map(|x| x.into()),
+    })
+}
+fn parse_pat_struct(s: syn::PatStruct) -> eyre::Result<PatternStruct> {
+    let name = parse_locator(s.path)?;
+    let fields = s
+        .fields
+        .into_iter()
+        .map(|field| {
+            let syn::Member::Named(name) = &field.member else {
+                bail!(parser::unsupported("Struct pattern field", &field))
+            };
+            let name = parse_ident(name.clone());
+            let rename = parse_pat(*field.pat)?;
+            // the shorthand `Point { x }` binds the field to its own name
+            let shorthand = matches!(&rename, Pattern::Ident(ident)
+                if ident.ident == name
+                    && !ident.by_ref
+                    && ident.subpattern.is_none()
+                    && ident.mutability != Some(true));
+            Ok(PatternStructField {
+                name,
+                rename: if field.colon_token.is_none() && shorthand {
+                    None
+                } else {
+                    Some(rename.into())
+                },
+            })
+        })
+        .try_collect()?;
+    Ok(PatternStruct {
+        name,
+        fields,
+        has_rest: s.rest.is_some(),
     })
 }
 pub fn parse_pat(p: syn::Pat) -> eyre::Result<Pattern> {
-    Ok(match p {
+    let span = p.span();
+    let pat = match p {
         syn::Pat::Ident(ident) => parse_pat_ident(ident)?.into(),
         syn::Pat::Wild(_) => Pattern::Wildcard(PatternWildcard {}),
+        syn::Pat::Rest(_) => Pattern::Rest(PatternRest {}),
         syn::Pat::TupleStruct(t) => Pattern::TupleStruct(PatternTupleStruct {
             name: parse_locator(t.path)?,
             patterns: t.elems.into_iter().map(parse_pat).try_collect()?,
         }),
+        syn::Pat::Struct(s) => Pattern::Struct(parse_pat_struct(s)?),
+        syn::Pat::Path(p) => Pattern::Variant(PatternVariant {
+            name: AstExpr::Locator(parse_locator(p.path)?),
+            pattern: None,
+        }),
         syn::Pat::Tuple(t) => Pattern::Tuple(PatternTuple {
             patterns: t.elems.into_iter().map(parse_pat).try_collect()?,
         }),
+        syn::Pat::Slice(s) => Pattern::Slice(PatternSlice {
+            patterns: s.elems.into_iter().map(parse_pat).try_collect()?,
+        }),
+        syn::Pat::Or(o) => Pattern::Or(PatternOr {
+            patterns: o.cases.into_iter().map(parse_pat).try_collect()?,
+        }),
+        syn::Pat::Lit(l) => Pattern::Lit(PatternLit {
+            value: parse_literal(l.lit)?,
+        }),
+        syn::Pat::Range(r) => {
+            let range = parse_expr_range(r)?;
+            Pattern::Range(PatternRange {
+                start: range.start,
+                limit: range.limit,
+                end: range.end,
+            })
+        }
+        // the inner pattern keeps its own span
+        syn::Pat::Paren(p) => return parse_pat(*p.pat),
         syn::Pat::Type(p) => Pattern::Type(PatternType {
             pat: parse_pat(*p.pat)?.into(),
             ty: ty::parse_type(*p.ty)?,
         }),
-        _ => bail!("Pattern not supported {}: {:?}", p.to_token_stream(), p),
-    })
+        _ => bail!(parser::unsupported("Pattern", &p)),
+    };
+    parser::record_span(&pat, span);
+    Ok(pat)
 }
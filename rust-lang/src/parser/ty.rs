@@ -2,20 +2,23 @@ use eyre::{bail, Context};
 use itertools::Itertools;
 use quote::ToTokens;
 use syn::parse::ParseStream;
-use syn::{parse_quote, FieldsNamed, Token};
+use syn::spanned::Spanned;
+use syn::{parse_quote, Token};
 
 use lang_core::ast::{
-    AstExpr, AstType, DecimalType, ExprBinOp, StructuralField, TypeBounds, TypeFunction, TypeInt,
-    TypePrimitive, TypeReference, TypeSlice, TypeStruct, TypeStructural,
+    AstExpr, AstType, DecimalType, ExprBinOp, ExprIndex, ImplTraits, StructuralField, TypeBounds,
+    TypeFunction, TypeInt, TypeOf, TypePrimitive, TypeReference, TypeSlice, TypeStruct,
+    TypeStructural,
 };
-use lang_core::id::{Ident, Path};
+use lang_core::id::Ident;
 use lang_core::ops::BinOpKind;
 
 use crate::parser;
 use crate::parser::item::parse_impl_trait;
-use crate::parser::{item, parse_path};
+use crate::parser::{expr, item};
 
 pub fn parse_type(t: syn::Type) -> eyre::Result<AstType> {
+    let span = t.span();
     let t = match t {
         syn::Type::BareFn(f) => AstType::Function(
             TypeFunction {
@@ -34,41 +37,66 @@ pub fn parse_type(t: syn::Type) -> eyre::Result<AstType> {
             .into(),
         )
         .into(),
-        syn::Type::Path(p) => {
-            let s = p.path.to_token_stream().to_string();
-            fn int(ty: TypeInt) -> AstType {
-                AstType::Primitive(TypePrimitive::Int(ty))
-            }
-            fn float(ty: DecimalType) -> AstType {
-                AstType::Primitive(TypePrimitive::Decimal(ty))
-            }
-
-            match s.as_str() {
-                "i64" => int(TypeInt::I64),
-                "i32" => int(TypeInt::I32),
-                "i16" => int(TypeInt::I16),
-                "i8" => int(TypeInt::I8),
-                "u64" => int(TypeInt::U64),
-                "u32" => int(TypeInt::U32),
-                "u16" => int(TypeInt::U16),
-                "u8" => int(TypeInt::U8),
-                "f64" => float(DecimalType::F64),
-                "f32" => float(DecimalType::F32),
-                _ => AstType::locator(parser::parse_locator(p.path)?),
-            }
-        }
+        syn::Type::Path(p) => match parse_type_block(&p) {
+            Some(block) => AstType::expr(expr::parse_expr(syn::Expr::Block(block))?),
+            None => parse_type_path(p)?,
+        },
         syn::Type::ImplTrait(im) => AstType::ImplTraits(parse_impl_trait(im)?),
         syn::Type::Tuple(t) if t.elems.is_empty() => AstType::unit().into(),
         syn::Type::Slice(s) => parse_type_slice(s)?,
         // types like t!{ }
-        syn::Type::Macro(m) if m.mac.path == parse_quote!(t) => {
-            AstType::expr(parse_custom_type_expr(m)?)
-        }
+        syn::Type::Macro(m) if m.mac.path == parse_quote!(t) => parse_type_macro(&m.mac)?,
         syn::Type::Reference(r) => AstType::Reference(parse_type_reference(r)?.into()),
-        t => bail!("Type not supported {:?}", t),
+        t => bail!(parser::unsupported("Type", &t)),
     };
+    parser::record_span(&t, span);
     Ok(t)
 }
+fn parse_type_path(p: syn::TypePath) -> eyre::Result<AstType> {
+    let s = p.path.to_token_stream().to_string();
+    fn int(ty: TypeInt) -> AstType {
+        AstType::Primitive(TypePrimitive::Int(ty))
+    }
+    fn float(ty: DecimalType) -> AstType {
+        AstType::Primitive(TypePrimitive::Decimal(ty))
+    }
+
+    Ok(match s.as_str() {
+        "i128" => int(TypeInt::I128),
+        "u128" => int(TypeInt::U128),
+        "i64" => int(TypeInt::I64),
+        "i32" => int(TypeInt::I32),
+        "i16" => int(TypeInt::I16),
+        "i8" => int(TypeInt::I8),
+        "u64" => int(TypeInt::U64),
+        "u32" => int(TypeInt::U32),
+        "u16" => int(TypeInt::U16),
+        "u8" => int(TypeInt::U8),
+        "isize" => int(TypeInt::ISize),
+        "usize" => int(TypeInt::USize),
+        "BigInt" | "num_bigint :: BigInt" => int(TypeInt::BigInt),
+        "f64" => float(DecimalType::F64),
+        "f32" => float(DecimalType::F32),
+        "bool" => AstType::Primitive(TypePrimitive::Bool),
+        "char" => AstType::Primitive(TypePrimitive::Char),
+        _ => AstType::locator(parser::parse_locator(p.path)?),
+    })
+}
+/// The block of `Type<{ .. }>`, which is evaluated to a type at compile time
+fn parse_type_block(p: &syn::TypePath) -> Option<syn::ExprBlock> {
+    let [segment] = p.path.segments.iter().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.iter().collect::<Vec<_>>()[..] {
+        [syn::GenericArgument::Const(syn::Expr::Block(block))] if segment.ident == "Type" => {
+            Some(block.clone())
+        }
+        _ => None,
+    }
+}
 fn parse_type_slice(s: syn::TypeSlice) -> eyre::Result<AstType> {
     Ok(AstType::Slice(TypeSlice {
         elem: parse_type(*s.elem)?.into(),
@@ -78,17 +106,15 @@ fn parse_type_reference(r: syn::TypeReference) -> eyre::Result<TypeReference> {
     Ok(TypeReference {
         ty: Box::new(parse_type(*r.elem)?),
         mutability: r.mutability.map(|_| true),
-        lifetime: r.lifetime.map(|x| parser::parse_ident(x.ident)),
+        lifetime: r.lifetime.map(parser::parse_lifetime),
     })
 }
 
 pub fn parse_type_param_bound(b: syn::TypeParamBound) -> eyre::Result<AstExpr> {
     match b {
-        syn::TypeParamBound::Trait(t) => {
-            let path = parse_path(t.path)?;
-            Ok(AstExpr::path(path))
-        }
-        _ => bail!("Does not support lifetimes {:?}", b),
+        // generic traits, like `Into<String>`
+        syn::TypeParamBound::Trait(t) => Ok(AstExpr::Locator(parser::parse_locator(t.path)?)),
+        _ => bail!(parser::unsupported("Lifetime bound", &b)),
     }
 }
 
@@ -101,7 +127,8 @@ pub fn parse_type_param_bounds(bs: Vec<syn::TypeParamBound>) -> eyre::Result<Typ
 pub fn parse_member(mem: syn::Member) -> eyre::Result<Ident> {
     Ok(match mem {
         syn::Member::Named(n) => parser::parse_ident(n),
-        syn::Member::Unnamed(_) => bail!("Does not support unnamed field yet {:?}", mem),
+        // tuple struct fields are named by their index
+        syn::Member::Unnamed(index) => Ident::new(index.index.to_string()),
     })
 }
 
@@ -120,24 +147,33 @@ struct StructuralTypeParser(TypeStructural);
 impl syn::parse::Parse for StructuralTypeParser {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         input.parse::<Token![struct]>()?;
+        let content;
+        syn::braced!(content in input);
+        let mut fields = vec![];
+        while !content.is_empty() {
+            content.parse::<syn::Visibility>()?;
+            // type dicts have string keys, like `struct { "/api": EndpointApi }`
+            let name = if content.peek(syn::LitStr) {
+                Ident::new(content.parse::<syn::LitStr>()?.value())
+            } else {
+                parser::parse_ident(content.parse()?)
+            };
+            content.parse::<Token![:]>()?;
+            let ty: syn::Type = content.parse()?;
+            let value = parse_type(ty).map_err(|err| content.error(err))?;
+            fields.push(StructuralField { name, value });
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
 
-        let fields: FieldsNamed = input.parse()?;
-
-        Ok(StructuralTypeParser(TypeStructural {
-            fields: fields
-                .named
-                .into_iter()
-                .enumerate()
-                .map(|(i, f)| parse_struct_field(i, f))
-                .try_collect()
-                .map_err(|err| input.error(err))?,
-        }))
+        Ok(StructuralTypeParser(TypeStructural { fields }))
     }
 }
 enum TypeValueParser {
     Structural(TypeStructural),
     Struct(TypeStruct),
-    Path(Path),
+    Path(AstType),
     // Ident(Ident),
 }
 impl Into<AstType> for TypeValueParser {
@@ -153,7 +189,7 @@ impl Into<AstType> for TypeValueParser {
             // })),
             TypeValueParser::Structural(s) => AstType::Structural(s),
             TypeValueParser::Struct(s) => AstType::Struct(s),
-            TypeValueParser::Path(p) => AstType::path(p),
+            TypeValueParser::Path(p) => p,
             // TypeValueParser::Ident(i) => TypeValue::ident(i),
         }
     }
@@ -171,63 +207,135 @@ impl syn::parse::Parse for TypeValueParser {
                     input.parse::<StructuralTypeParser>()?.0,
                 ))
             }
+        } else if input.peek(Token![impl]) {
+            // one bound only, as `+` is type arithmetic here: `impl Read + impl Write`
+            input.parse::<Token![impl]>()?;
+            let bound: syn::TypeParamBound = input.parse()?;
+            let bounds = parse_type_param_bounds(vec![bound]).map_err(|err| input.error(err))?;
+            Ok(TypeValueParser::Path(AstType::ImplTraits(ImplTraits {
+                bounds,
+            })))
+        } else if input.peek(Token![typeof]) {
+            input.parse::<Token![typeof]>()?;
+            let content;
+            syn::parenthesized!(content in input);
+            let expr = expr::parse_expr(content.parse()?).map_err(|err| input.error(err))?;
+            Ok(TypeValueParser::Path(AstType::TypeOf(TypeOf {
+                expr: expr.into(),
+            })))
+        } else if input.peek(syn::Lit) {
+            // literal types, like `1`
+            let lit = input.parse()?;
+            let value = expr::parse_literal(lit).map_err(|err| input.error(err))?;
+            Ok(TypeValueParser::Path(AstType::value(value)))
         } else {
-            let path = input.parse::<syn::Path>()?;
+            // primitives like `i64` are paths too
+            let path = input.parse::<syn::TypePath>()?;
             Ok(TypeValueParser::Path(
-                parse_path(path).map_err(|err| input.error(err))?,
+                parse_type(syn::Type::Path(path)).map_err(|err| input.error(err))?,
             ))
         }
     }
 }
 
 enum TypeExprParser {
-    Add { left: AstExpr, right: AstExpr },
+    BinOp {
+        kind: BinOpKind,
+        left: AstExpr,
+        right: AstExpr,
+    },
+    /// `Foo["/api"]`, a field of a type dict
+    Index {
+        obj: AstExpr,
+        index: AstExpr,
+    },
     Value(AstType),
 }
-impl syn::parse::Parse for TypeExprParser {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut lhs = TypeExprParser::Value(input.parse::<TypeValueParser>()?.into());
+impl TypeExprParser {
+    fn parse_operand(input: ParseStream) -> syn::Result<Self> {
+        let mut operand = TypeExprParser::Value(input.parse::<TypeValueParser>()?.into());
+        while input.peek(syn::token::Bracket) {
+            let content;
+            syn::bracketed!(content in input);
+            let index = expr::parse_expr(content.parse()?).map_err(|err| input.error(err))?;
+            operand = TypeExprParser::Index {
+                obj: operand.into(),
+                index,
+            };
+        }
+        Ok(operand)
+    }
+    /// `+` and `-` bind tighter than `&`, which binds tighter than `|`, like in Rust
+    fn parse_level(input: ParseStream, level: usize) -> syn::Result<Self> {
+        let mut lhs = match level {
+            0 => Self::parse_operand(input)?,
+            _ => Self::parse_level(input, level - 1)?,
+        };
         loop {
-            if input.is_empty() {
-                break;
-            }
-            if input.peek(Token![+]) {
-                input.parse::<Token![+]>()?;
-                let rhs: AstType = input.parse::<TypeValueParser>()?.into();
-                lhs = TypeExprParser::Add {
-                    left: lhs.into(),
-                    right: AstExpr::value(rhs.into()),
-                };
-            // } else if input.peek(Token![-]) {
-            //     input.parse::<Token![-]>()?;
-            //     let rhs: TypeValue = input.parse::<TypeValueParser>()?.into();
-            //     lhs = TypeExprParser::Sub {
-            //         left: lhs.into(),
-            //         right: Expr::value(rhs.into()),
-            //     };
-            } else {
-                return Err(input.error("Expected + or -"));
-            }
+            let kind = match level {
+                0 if input.peek(Token![+]) => {
+                    input.parse::<Token![+]>()?;
+                    BinOpKind::AddTrait
+                }
+                0 if input.peek(Token![-]) => {
+                    input.parse::<Token![-]>()?;
+                    BinOpKind::Sub
+                }
+                1 if input.peek(Token![&]) => {
+                    input.parse::<Token![&]>()?;
+                    BinOpKind::BitAnd
+                }
+                2 if input.peek(Token![|]) => {
+                    input.parse::<Token![|]>()?;
+                    BinOpKind::BitOr
+                }
+                _ => break,
+            };
+            let rhs = match level {
+                0 => Self::parse_operand(input)?,
+                _ => Self::parse_level(input, level - 1)?,
+            };
+            lhs = TypeExprParser::BinOp {
+                kind,
+                left: lhs.into(),
+                right: rhs.into(),
+            };
         }
         Ok(lhs)
     }
 }
+impl syn::parse::Parse for TypeExprParser {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let expr = Self::parse_level(input, 2)?;
+        if !input.is_empty() {
+            return Err(input.error("Expected +, -, & or |"));
+        }
+        Ok(expr)
+    }
+}
 impl Into<AstExpr> for TypeExprParser {
     fn into(self) -> AstExpr {
         match self {
-            TypeExprParser::Add { left, right } => AstExpr::BinOp(ExprBinOp {
+            TypeExprParser::BinOp { kind, left, right } => AstExpr::BinOp(ExprBinOp {
                 lhs: left.into(),
                 rhs: right.into(),
-                kind: BinOpKind::Add,
+                kind,
+            }),
+            TypeExprParser::Index { obj, index } => AstExpr::Index(ExprIndex {
+                obj: obj.into(),
+                index: index.into(),
             }),
-            // TypeExprParser::Sub { .. } => {
-            //     unreachable!()
-            // }
             TypeExprParser::Value(v) => AstExpr::value(v.into()),
         }
     }
 }
-fn parse_custom_type_expr(m: syn::TypeMacro) -> eyre::Result<AstExpr> {
-    let t: TypeExprParser = m.mac.parse_body().with_context(|| format!("{:?}", m))?;
-    Ok(t.into())
+/// `t! { .. }` in type or expression position
+pub fn parse_type_macro(m: &syn::Macro) -> eyre::Result<AstType> {
+    let t: TypeExprParser = m.parse_body().with_context(|| format!("{:?}", m))?;
+    Ok(AstType::expr(t.into()))
+}
+/// Parses the body of `t! { }`
+pub fn parse_type_expr(tokens: proc_macro2::TokenStream) -> eyre::Result<AstType> {
+    let t: TypeExprParser = syn::parse2(tokens)?;
+    Ok(AstType::expr(t.into()))
 }
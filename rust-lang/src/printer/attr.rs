@@ -41,12 +41,14 @@ impl RustPrinter {
         let style = self.print_attr_style(&attr.style)?;
         let meta = self.print_attr_meta(&attr.meta)?;
         Ok(quote! {
-            #[#style(#meta)]
+            # #style [#meta]
         })
     }
     pub fn print_attrs(&self, attrs: &[AstAttribute]) -> Result<TokenStream> {
+        // rustc doesn't know the `shll` tool
         let attrs: Vec<TokenStream> = attrs
             .iter()
+            .filter(|attr| !attr.is_shll())
             .map(|attr| self.print_attr(attr))
             .try_collect()?;
         Ok(quote! { #(#attrs)* })
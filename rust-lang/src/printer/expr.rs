@@ -5,11 +5,13 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 use lang_core::ast::{
-    AstExpr, BlockStmt, ExprArray, ExprAssign, ExprBinOp, ExprBlock, ExprClosure, ExprField,
+    AstExpr, AstValue, BlockStmt, ExprArray, ExprArrayRepeat, ExprAssign, ExprAsync, ExprBinOp,
+    ExprBlock, ExprBreak, ExprCast, ExprClosure, ExprConst, ExprContinue, ExprField, ExprFor,
     ExprIf, ExprIndex, ExprInvoke, ExprInvokeTarget, ExprLet, ExprLoop, ExprMatch, ExprParen,
-    ExprRange, ExprRangeLimit, ExprReference, ExprSelect, ExprSelectType, ExprStruct, ExprTuple,
-    ExprUnOp, ExprWhile, StmtLet,
+    ExprRange, ExprRangeLimit, ExprReference, ExprReturn, ExprSelect, ExprSelectType, ExprStruct,
+    ExprTuple, ExprUnOp, ExprUnsafe, ExprWhile, StmtLet,
 };
+use lang_core::id::Ident;
 use lang_core::ops::{BinOpKind, UnOpKind};
 
 use crate::printer::RustPrinter;
@@ -43,16 +45,25 @@ impl RustPrinter {
             AstExpr::Reference(n) => self.print_ref(n),
             AstExpr::Assign(n) => self.print_assign(n),
             AstExpr::Index(n) => self.print_index(n),
+            AstExpr::Cast(n) => self.print_cast(n),
             AstExpr::Closured(n) => self.print_expr(&n.expr),
             AstExpr::Paren(n) => self.print_paren(n),
+            AstExpr::Const(n) => self.print_const_block(n),
+            AstExpr::Unsafe(n) => self.print_unsafe_block(n),
+            AstExpr::Async(n) => self.print_async_block(n),
             AstExpr::Loop(n) => self.print_loop(n),
             AstExpr::Range(n) => self.print_range(n),
             AstExpr::Tuple(n) => self.print_expr_tuple(n),
             AstExpr::Try(n) => self.print_expr_try(&n.expr),
             AstExpr::While(n) => self.print_while(n),
+            AstExpr::For(n) => self.print_for(n),
+            AstExpr::Break(n) => self.print_break(n),
+            AstExpr::Continue(n) => self.print_continue(n),
+            AstExpr::Return(n) => self.print_return(n),
             AstExpr::Let(n) => self.print_expr_let(n),
             AstExpr::Closure(n) => self.print_expr_closure(n),
             AstExpr::Array(n) => self.print_expr_array(n),
+            AstExpr::ArrayRepeat(n) => self.print_expr_array_repeat(n),
 
             _ => bail!("Unable to serialize {:?}", node),
         }
@@ -105,11 +116,27 @@ impl RustPrinter {
     }
     pub fn print_assign(&self, assign: &ExprAssign) -> Result<TokenStream> {
         let target = self.print_expr(&assign.target)?;
+        // `a = a + b` is how the parser lowers `a += b`
+        if let AstExpr::BinOp(binop) = assign.value.get() {
+            if binop.lhs == assign.target {
+                if let Some(op) = self.print_compound_assign_op(&binop.kind) {
+                    let rhs = self.print_expr(&binop.rhs.get())?;
+                    return Ok(quote!(
+                        #target #op #rhs
+                    ));
+                }
+            }
+        }
         let value = self.print_expr(&assign.value)?;
         Ok(quote!(
-            #target = #value;
+            #target = #value
         ))
     }
+    pub fn print_cast(&self, cast: &ExprCast) -> Result<TokenStream> {
+        let expr = self.print_expr(&cast.expr)?;
+        let ty = self.print_type(&cast.ty)?;
+        Ok(quote!(#expr as #ty))
+    }
     pub fn print_index(&self, index: &ExprIndex) -> Result<TokenStream> {
         let expr = self.print_expr(&index.obj.get())?;
         let index = self.print_expr(&index.index.get())?;
@@ -123,29 +150,90 @@ impl RustPrinter {
             (#expr)
         ))
     }
+    pub fn print_const_block(&self, c: &ExprConst) -> Result<TokenStream> {
+        let block = self.print_expr_no_braces(&c.block)?;
+        Ok(quote!(
+            const { #block }
+        ))
+    }
+    pub fn print_unsafe_block(&self, u: &ExprUnsafe) -> Result<TokenStream> {
+        let block = self.print_expr_no_braces(&u.block)?;
+        Ok(quote!(
+            unsafe { #block }
+        ))
+    }
+    pub fn print_async_block(&self, a: &ExprAsync) -> Result<TokenStream> {
+        let movability = if a.movability == Some(true) {
+            quote!(move)
+        } else {
+            quote!()
+        };
+        let block = self.print_expr_no_braces(&a.block)?;
+        Ok(quote!(
+            async #movability { #block }
+        ))
+    }
 
+    fn print_label(&self, label: &Option<Ident>) -> TokenStream {
+        match label {
+            Some(label) => self.print_lifetime(label),
+            None => quote!(),
+        }
+    }
+    fn print_loop_label(&self, label: &Option<Ident>) -> TokenStream {
+        match label {
+            Some(_) => {
+                let label = self.print_label(label);
+                quote!(#label:)
+            }
+            None => quote!(),
+        }
+    }
     pub fn print_loop(&self, loop_: &ExprLoop) -> Result<TokenStream> {
+        let label = self.print_loop_label(&loop_.label);
         let body = self.print_expr_no_braces(&loop_.body)?;
         Ok(quote!(
-            loop {
+            #label loop {
+                #body
+            }
+        ))
+    }
+    pub fn print_break(&self, break_: &ExprBreak) -> Result<TokenStream> {
+        let label = self.print_label(&break_.label);
+        let value = match &break_.value {
+            Some(value) => self.print_expr(value)?,
+            None => quote!(),
+        };
+        Ok(quote!(break #label #value))
+    }
+    pub fn print_continue(&self, continue_: &ExprContinue) -> Result<TokenStream> {
+        let label = self.print_label(&continue_.label);
+        Ok(quote!(continue #label))
+    }
+    pub fn print_return(&self, return_: &ExprReturn) -> Result<TokenStream> {
+        let value = match &return_.value {
+            Some(value) => self.print_expr(value)?,
+            None => quote!(),
+        };
+        Ok(quote!(return #value))
+    }
+    pub fn print_for(&self, for_: &ExprFor) -> Result<TokenStream> {
+        let label = self.print_loop_label(&for_.label);
+        let pat = self.print_pattern(&for_.pat)?;
+        let iter = self.print_expr(&for_.iter)?;
+        let body = self.print_expr_no_braces(&for_.body)?;
+        Ok(quote!(
+            #label for #pat in #iter {
                 #body
             }
         ))
     }
-    // pub fn print_for_each(&self, for_each: &ExprForEach) -> Result<TokenStream> {
-    //     let name = self.print_ident(&for_each.variable);
-    //     let iter = self.print_expr(&for_each.iterable)?;
-    //     let body = self.print_block(&for_each.body)?;
-    //     Ok(quote!(
-    //         for #name in #iter
-    //             #body
-    //     ))
-    // }
     fn print_while(&self, while_: &ExprWhile) -> Result<TokenStream> {
+        let label = self.print_loop_label(&while_.label);
         let cond = self.print_expr(&while_.cond)?;
         let body = self.print_expr_no_braces(&while_.body)?;
         Ok(quote!(
-            while #cond {
+            #label while #cond {
                 #body
             }
         ))
@@ -164,7 +252,13 @@ impl RustPrinter {
                     with_semicolon = false;
                 } else {
                     match &*expr0.expr {
-                        AstExpr::Block(_) | AstExpr::If(_) => with_semicolon = false,
+                        AstExpr::Block(_)
+                        | AstExpr::If(_)
+                        | AstExpr::Loop(_)
+                        | AstExpr::While(_)
+                        | AstExpr::For(_)
+                        | AstExpr::Match(_)
+                        | AstExpr::Unsafe(_) => with_semicolon = false,
                         _ => with_semicolon = true,
                     }
                 }
@@ -219,6 +313,9 @@ impl RustPrinter {
     }
 
     pub fn print_match(&self, m: &ExprMatch) -> Result<TokenStream> {
+        if let Some(scrutinee) = &m.scrutinee {
+            return self.print_match_pattern(scrutinee, m);
+        }
         let mut ts = vec![];
         for (_i, c) in m.cases.iter().enumerate() {
             let node = &c.cond;
@@ -235,6 +332,33 @@ impl RustPrinter {
         }))
     }
 
+    fn print_match_pattern(&self, scrutinee: &AstExpr, m: &ExprMatch) -> Result<TokenStream> {
+        let scrutinee = self.print_expr(scrutinee)?;
+        let mut arms = vec![];
+        for case in &m.cases {
+            let pat = match &case.pat {
+                Some(pat) => self.print_pattern(pat)?,
+                None => quote!(_),
+            };
+            let guard = match case.cond.as_ref() {
+                AstExpr::Value(value) if matches!(value.as_ref(), AstValue::Bool(b) if b.value) => {
+                    quote!()
+                }
+                cond => {
+                    let cond = self.print_expr(cond)?;
+                    quote!(if #cond)
+                }
+            };
+            let body = self.print_expr_no_braces(&case.body)?;
+            arms.push(quote!(
+                #pat #guard => { #body }
+            ))
+        }
+        Ok(quote!(match #scrutinee {
+            #(#arms)*
+        }))
+    }
+
     pub fn print_invoke(&self, node: &ExprInvoke) -> Result<TokenStream> {
         let fun = self.print_invoke_target(&node.target)?;
         let args: Vec<_> = node
@@ -281,6 +405,8 @@ impl RustPrinter {
                     BinOpKind::BitOr => quote!(#(#args) | *),
                     BinOpKind::BitAnd => quote!(#(#args) & *),
                     BinOpKind::BitXor => quote!(#(#args) ^ *),
+                    BinOpKind::Shl => quote!(#(#args) << *),
+                    BinOpKind::Shr => quote!(#(#args) >> *),
                 };
                 return Ok(ret);
             }
@@ -389,6 +515,22 @@ impl RustPrinter {
             .try_collect()?;
         Ok(quote!((#(#args),*)))
     }
+    fn print_compound_assign_op(&self, op: &BinOpKind) -> Option<TokenStream> {
+        let op = match op {
+            BinOpKind::Add => quote!(+=),
+            BinOpKind::Sub => quote!(-=),
+            BinOpKind::Mul => quote!(*=),
+            BinOpKind::Div => quote!(/=),
+            BinOpKind::Mod => quote!(%=),
+            BinOpKind::BitXor => quote!(^=),
+            BinOpKind::BitAnd => quote!(&=),
+            BinOpKind::BitOr => quote!(|=),
+            BinOpKind::Shl => quote!(<<=),
+            BinOpKind::Shr => quote!(>>=),
+            _ => return None,
+        };
+        Some(op)
+    }
     pub fn print_bin_op_kind(&self, op: &BinOpKind) -> TokenStream {
         match op {
             BinOpKind::Add => quote!(+),
@@ -408,6 +550,8 @@ impl RustPrinter {
             BinOpKind::BitOr => quote!(|),
             BinOpKind::BitAnd => quote!(&),
             BinOpKind::BitXor => quote!(^),
+            BinOpKind::Shl => quote!(<<),
+            BinOpKind::Shr => quote!(>>),
         }
     }
     pub fn print_un_op_kind(&self, op: &UnOpKind) -> TokenStream {
@@ -446,4 +590,9 @@ impl RustPrinter {
             .try_collect()?;
         Ok(quote!([#(#values),*]))
     }
+    fn print_expr_array_repeat(&self, repeat: &ExprArrayRepeat) -> Result<TokenStream> {
+        let value = self.print_expr(&repeat.value)?;
+        let len = self.print_expr(&repeat.len)?;
+        Ok(quote!([#value; #len]))
+    }
 }
@@ -1,8 +1,9 @@
 use eyre::{bail, ContextCompat, Result};
 use itertools::Itertools;
 use lang_core::ast::{
-    AstItem, ItemDefConst, ItemDefFunction, ItemDefStatic, ItemDefStruct, ItemDefTrait,
-    ItemDefType, ItemImpl,
+    AstExpr, AstItem, AstType, ItemDeclConst, ItemDeclFunction, ItemDefConst, ItemDefEnum,
+    ItemDefFunction, ItemDefStatic, ItemDefStruct, ItemDefTrait, ItemDefType, ItemImpl, StructKind,
+    Visibility,
 };
 use proc_macro2::TokenStream;
 use quote::quote;
@@ -20,26 +21,58 @@ impl RustPrinter {
     }
 
     pub fn print_def_struct(&self, def: &ItemDefStruct) -> Result<TokenStream> {
+        let attrs = self.print_attrs(&def.attrs)?;
         let vis = self.print_vis(def.visibility);
         let name = self.print_ident(&def.name);
-        let fields: Vec<_> = def
+        let generics = self.print_generics(&def.lifetimes, &def.generics_params)?;
+        let where_clause = self.print_where_clause(&def.where_predicates)?;
+        let fields = self.print_struct_fields(&def.value)?;
+        if def.value.kind == StructKind::Named {
+            return Ok(quote!(
+                #attrs
+                #vis struct #name #generics #where_clause #fields
+            ));
+        }
+        // the where clause of a tuple struct goes after its fields
+        Ok(quote!(
+            #attrs
+            #vis struct #name #generics #fields #where_clause;
+        ))
+    }
+    pub fn print_def_enum(&self, def: &ItemDefEnum) -> Result<TokenStream> {
+        let attrs = self.print_attrs(&def.attrs)?;
+        let vis = self.print_vis(def.visibility);
+        let name = self.print_ident(&def.name);
+        let generics = self.print_generics(&def.lifetimes, &def.generics_params)?;
+        let where_clause = self.print_where_clause(&def.where_predicates)?;
+        let variants: Vec<_> = def
             .value
-            .fields
+            .variants
             .iter()
-            .map(|x| self.print_field(&x))
+            .map(|x| self.print_variant(x))
             .try_collect()?;
         Ok(quote!(
-            #vis struct #name {
-                #(#fields), *
+            #attrs
+            #vis enum #name #generics #where_clause {
+                #(#variants), *
             }
         ))
     }
     pub fn print_def_type(&self, def: &ItemDefType) -> Result<TokenStream> {
         let vis = self.print_vis(def.visibility);
         let name = self.print_ident(&def.name);
+        let generics = self.print_generics_params(&def.generics_params)?;
+        if let AstType::Expr(expr) = &def.value {
+            if let AstExpr::Block(_) = expr.as_ref() {
+                let block = self.print_expr(expr)?;
+                return Ok(quote!(
+                    #vis type #name #generics = Type<#block>;
+                ));
+            }
+        }
         let ty = self.print_type(&def.value)?;
         return Ok(quote!(
-            #vis type #name = t!{ #ty };
+            #vis type #name #generics = t!{ #ty };
         ));
     }
     pub fn print_def_const(&self, def: &ItemDefConst) -> Result<TokenStream> {
@@ -75,12 +108,30 @@ impl RustPrinter {
     pub fn print_impl(&self, impl_: &ItemImpl) -> Result<TokenStream> {
         let name = self.print_expr(&impl_.self_ty)?;
         let methods = self.print_items_chunk(&impl_.items)?;
+        let trait_ty = match &impl_.trait_ty {
+            Some(trait_ty) => {
+                let trait_ty = self.print_locator(trait_ty)?;
+                quote!(#trait_ty for)
+            }
+            None => quote!(),
+        };
+        let generics = self.print_generics(&impl_.lifetimes, &impl_.generics_params)?;
+        let where_clause = self.print_where_clause(&impl_.where_predicates)?;
         Ok(quote!(
-            impl #name {
+            impl #generics #trait_ty #name #where_clause {
                 #methods
             }
         ))
     }
+    pub fn print_decl_function(&self, decl: &ItemDeclFunction) -> Result<TokenStream> {
+        let sig = self.print_function_sig(&decl.sig, Visibility::Inherited)?;
+        Ok(quote!(#sig;))
+    }
+    pub fn print_decl_const(&self, decl: &ItemDeclConst) -> Result<TokenStream> {
+        let name = self.print_ident(&decl.name);
+        let ty = self.print_type(&decl.ty)?;
+        Ok(quote!(const #name: #ty;))
+    }
     pub fn print_def_function(&self, func: &ItemDefFunction) -> Result<TokenStream> {
         let attrs = self.print_attrs(&func.attrs)?;
         let func = self.print_function(&func.sig, &func.body, func.visibility)?;
@@ -97,11 +148,14 @@ impl RustPrinter {
             AstItem::DefTrait(n) => self.print_def_trait(n),
             AstItem::DefConst(n) => self.print_def_const(n),
             AstItem::DefStatic(n) => self.print_def_static(n),
-            // AstItem::DefEnum(n) => self.print_def_enum(n),
+            AstItem::DefEnum(n) => self.print_def_enum(n),
             AstItem::Impl(n) => self.print_impl(n),
+            AstItem::DeclFunction(n) => self.print_decl_function(n),
+            AstItem::DeclConst(n) => self.print_decl_const(n),
             AstItem::Module(n) => self.print_module(n),
             AstItem::Import(n) => self.print_import(n),
             AstItem::Expr(n) => self.print_expr(n),
+            AstItem::Any(n) => self.print_any(n),
             _ => bail!("Unable to serialize {:?}", item),
         }
     }
@@ -9,7 +9,7 @@ use lang_core::ops::{BuiltinFn, BuiltinFnName};
 use lang_core::pat::{Pattern, PatternIdent};
 use lang_core::utils::anybox::AnyBox;
 
-use crate::{RawExpr, RawExprMacro, RawStmtMacro};
+use crate::{RawExpr, RawExprMacro, RawItemMacro, RawStmtMacro};
 
 mod attr;
 mod expr;
@@ -54,17 +54,34 @@ impl RustPrinter {
             "==" => quote!(==),
             "!=" => quote!(!=),
             "|" => quote!(|),
-            a => format_ident!("{}", a).into_token_stream(),
+            a => match a.parse::<usize>() {
+                // the fields of tuple structs, like `p.0`
+                Ok(index) => syn::Index::from(index).into_token_stream(),
+                Err(_) => format_ident!("{}", a).into_token_stream(),
+            },
         }
     }
     pub fn print_pat_ident(&self, i: &PatternIdent) -> Result<TokenStream> {
+        let ref_ = if i.by_ref { quote!(ref) } else { quote!() };
         let mut_ = if i.mutability.unwrap_or_default() {
             quote!(mut)
         } else {
             quote!()
         };
         let name = self.print_ident(&i.ident);
-        Ok(quote!(#mut_ #name))
+        let subpattern = match i.subpattern.as_deref() {
+            // `x @ (1 | 2)`, as `|` binds looser than `@`
+            Some(pat @ Pattern::Or(_)) => {
+                let pat = self.print_pattern(pat)?;
+                quote!(@ (#pat))
+            }
+            Some(pat) => {
+                let pat = self.print_pattern(pat)?;
+                quote!(@ #pat)
+            }
+            None => quote!(),
+        };
+        Ok(quote!(#ref_ #mut_ #name #subpattern))
     }
     pub fn print_trait_bound(&self, n: &ItemDefTrait) -> Result<TokenStream> {
         let name = self.print_ident(&n.name);
@@ -83,7 +100,11 @@ impl RustPrinter {
                     .iter()
                     .map(|x| self.print_pattern(x))
                     .try_collect()?;
-                Ok(quote!(#(#tuple), *))
+                if tuple.len() == 1 {
+                    let first = &tuple[0];
+                    return Ok(quote!((#first,)));
+                }
+                Ok(quote!((#(#tuple), *)))
             }
             Pattern::TupleStruct(tuple) => {
                 let name = self.print_locator(&tuple.name)?;
@@ -95,7 +116,7 @@ impl RustPrinter {
                 Ok(quote!(#name(#(#tuple), *)))
             }
             Pattern::Struct(stru) => {
-                let name = self.print_ident(&stru.name);
+                let name = self.print_locator(&stru.name)?;
                 let fields: Vec<_> = stru
                     .fields
                     .iter()
@@ -110,6 +131,9 @@ impl RustPrinter {
                         Ok::<_, Error>(rename)
                     })
                     .try_collect()?;
+                if stru.has_rest {
+                    return Ok(quote!(#name { #(#fields,)* .. }));
+                }
                 Ok(quote!(#name { #(#fields), * }))
             }
             Pattern::Structural(stru) => {
@@ -149,7 +173,38 @@ impl RustPrinter {
                 let ty = self.print_type(&type_.ty)?;
                 Ok(quote!(#pattern: #ty))
             }
-            _ => todo!("pattern not implemented: {:?}", pat),
+            Pattern::Wildcard(_) => Ok(quote!(_)),
+            Pattern::Rest(_) => Ok(quote!(..)),
+            Pattern::Or(or) => {
+                let patterns: Vec<_> = or
+                    .patterns
+                    .iter()
+                    .map(|x| self.print_pattern(x))
+                    .try_collect()?;
+                Ok(quote!(#(#patterns) | *))
+            }
+            Pattern::Lit(lit) => self.print_value(&lit.value),
+            Pattern::Range(range) => {
+                let start = range
+                    .start
+                    .as_ref()
+                    .map(|x| self.print_expr(x))
+                    .transpose()?;
+                let end = range.end.as_ref().map(|x| self.print_expr(x)).transpose()?;
+                let dots = match range.limit {
+                    ExprRangeLimit::Inclusive => quote!(..=),
+                    ExprRangeLimit::Exclusive => quote!(..),
+                };
+                Ok(quote!(#start #dots #end))
+            }
+            Pattern::Slice(slice) => {
+                let patterns: Vec<_> = slice
+                    .patterns
+                    .iter()
+                    .map(|x| self.print_pattern(x))
+                    .try_collect()?;
+                Ok(quote!([#(#patterns), *]))
+            }
         }
     }
 
@@ -176,6 +231,22 @@ impl RustPrinter {
         sig: &FunctionSignature,
         body: &AstExpr,
         vis: Visibility,
+    ) -> Result<TokenStream> {
+        let sig = self.print_function_sig(sig, vis)?;
+        let stmts = self.print_expr_no_braces(&body)?;
+        // let attrs = self.print_attrs(&func.attrs)?;
+        return Ok(quote!(
+            // #attrs
+            #sig {
+                #stmts
+            }
+        ));
+    }
+    /// `fn foo<T>(a: T) -> T`, without a body
+    pub fn print_function_sig(
+        &self,
+        sig: &FunctionSignature,
+        vis: Visibility,
     ) -> Result<TokenStream> {
         let name = if let Some(name) = &sig.name {
             self.print_ident(name)
@@ -199,31 +270,64 @@ impl RustPrinter {
             .iter()
             .map(|x| self.print_type(&x.ty))
             .try_collect()?;
-        let stmts = self.print_expr_no_braces(&body)?;
-        let gg;
-        if !sig.generics_params.is_empty() {
-            let gt: Vec<_> = sig
-                .generics_params
-                .iter()
-                .map(|x| self.print_ident(&x.name))
-                .collect();
-            let gb: Vec<_> = sig
-                .generics_params
-                .iter()
-                .map(|x| self.print_type_bounds(&x.bounds))
-                .try_collect()?;
-            gg = quote!(<#(#gt: #gb), *>)
-        } else {
-            gg = quote!();
-        }
+        let gg = self.print_generics(&sig.lifetimes, &sig.generics_params)?;
+        let where_clause = self.print_where_clause(&sig.where_predicates)?;
         let vis = self.print_vis(vis);
-        // let attrs = self.print_attrs(&func.attrs)?;
-        return Ok(quote!(
-            // #attrs
-            #vis fn #name #gg(#receiver #(#param_names: #param_types), *) #ret {
-                #stmts
-            }
-        ));
+        Ok(quote!(
+            #vis fn #name #gg(#receiver #(#param_names: #param_types), *) #ret #where_clause
+        ))
+    }
+    /// `<'a, T: Clone>`, lifetimes first
+    pub fn print_generics(
+        &self,
+        lifetimes: &[Ident],
+        params: &[GenericParam],
+    ) -> Result<TokenStream> {
+        if lifetimes.is_empty() {
+            return self.print_generics_params(params);
+        }
+        let lifetimes = lifetimes.iter().map(|x| self.print_lifetime(x));
+        let params = self.print_generic_param_list(params)?;
+        Ok(quote!(<#(#lifetimes,)* #(#params), *>))
+    }
+    pub fn print_where_clause(&self, predicates: &[WherePredicate]) -> Result<TokenStream> {
+        if predicates.is_empty() {
+            return Ok(quote!());
+        }
+        let predicates: Vec<_> = predicates
+            .iter()
+            .map(|x| {
+                let ty = self.print_type(&x.ty)?;
+                let bounds = self.print_type_bounds(&x.bounds)?;
+                Ok::<_, eyre::Error>(quote!(#ty: #bounds))
+            })
+            .try_collect()?;
+        Ok(quote!(where #(#predicates), *))
+    }
+    pub fn print_generics_params(&self, params: &[GenericParam]) -> Result<TokenStream> {
+        if params.is_empty() {
+            return Ok(quote!());
+        }
+        let params = self.print_generic_param_list(params)?;
+        Ok(quote!(<#(#params), *>))
+    }
+    fn print_generic_param_list(&self, params: &[GenericParam]) -> Result<Vec<TokenStream>> {
+        params
+            .iter()
+            .map(|x| {
+                let name = self.print_ident(&x.name);
+                match &x.const_ty {
+                    Some(ty) => {
+                        let ty = self.print_type(ty)?;
+                        Ok(quote!(const #name: #ty))
+                    }
+                    None => {
+                        let bounds = self.print_type_bounds(&x.bounds)?;
+                        Ok::<_, eyre::Error>(quote!(#name: #bounds))
+                    }
+                }
+            })
+            .try_collect()
     }
     pub fn print_value_function(
         &self,
@@ -300,6 +404,9 @@ impl RustPrinter {
         if let Some(n) = n.downcast_ref::<RawStmtMacro>() {
             return Ok(n.raw.to_token_stream());
         }
+        if let Some(n) = n.downcast_ref::<RawItemMacro>() {
+            return Ok(n.raw.to_token_stream());
+        }
         if let Some(f) = n.downcast_ref::<BuiltinFn>() {
             return self.print_builtin_fn(f);
         }
@@ -322,17 +429,26 @@ impl RustPrinter {
         let segments: Vec<_> = path.segments.iter().map(|x| self.print_ident(x)).collect();
         quote!(#(#segments)::*)
     }
+    /// `a` as `'a`
+    pub fn print_lifetime(&self, lifetime: &Ident) -> TokenStream {
+        let lifetime = syn::Lifetime::new(
+            &format!("'{}", lifetime.as_str()),
+            proc_macro2::Span::call_site(),
+        );
+        quote!(#lifetime)
+    }
     fn print_parameter_path_segment(&self, segment: &ParameterPathSegment) -> Result<TokenStream> {
         let ident = self.print_ident(&segment.ident);
-        if segment.args.is_empty() {
+        if segment.lifetimes.is_empty() && segment.args.is_empty() {
             return Ok(ident);
         }
+        let lifetimes = segment.lifetimes.iter().map(|x| self.print_lifetime(x));
         let args: Vec<_> = segment
             .args
             .iter()
             .map(|x| self.print_type(x))
             .try_collect()?;
-        Ok(quote!(#ident::<#(#args), *>))
+        Ok(quote!(#ident::<#(#lifetimes,)* #(#args), *>))
     }
     pub fn print_parameter_path(&self, path: &ParameterPath) -> Result<TokenStream> {
         let segments: Vec<_> = path
@@ -2,8 +2,8 @@ use crate::printer::RustPrinter;
 use eyre::bail;
 use itertools::Itertools;
 use lang_core::ast::{
-    AstType, DecimalType, ExprInvoke, StructuralField, TypeInt, TypePrimitive, TypeReference,
-    TypeSlice, TypeStruct, TypeStructural,
+    AstType, DecimalType, EnumTypeVariant, ExprInvoke, StructKind, StructuralField, TypeEnum,
+    TypeInt, TypePrimitive, TypeReference, TypeSlice, TypeStruct, TypeStructural,
 };
 use proc_macro2::TokenStream;
 use quote::quote;
@@ -15,6 +15,7 @@ impl RustPrinter {
             AstType::Primitive(p) => self.print_primitive_type(*p)?,
             AstType::Struct(s) => self.print_struct_type(s)?,
             AstType::Structural(s) => self.print_structural_type(s)?,
+            AstType::Enum(e) => self.print_enum_type(e)?,
             AstType::Expr(e) => self.print_expr(e)?,
             AstType::Slice(t) => self.print_type_slice(t)?,
             AstType::ImplTraits(t) => self.print_impl_traits(t)?,
@@ -25,21 +26,28 @@ impl RustPrinter {
             AstType::Unknown(_) => quote!(_),
             AstType::Reference(r) => self.print_type_ref(r)?,
             AstType::Value(v) => self.print_value(&v.value)?,
+            AstType::TypeOf(t) => {
+                let expr = self.print_expr(&t.expr)?;
+                quote!(typeof(#expr))
+            }
             _ => bail!("Not supported {:?}", v),
         };
         Ok(ty)
     }
     fn print_type_ref(&self, reference: &TypeReference) -> eyre::Result<TokenStream> {
         let ty = self.print_type(&reference.ty)?;
+        let lifetime = reference.lifetime.as_ref().map(|x| self.print_lifetime(x));
         if reference.mutability == Some(true) {
-            Ok(quote!(&mut #ty))
+            Ok(quote!(&#lifetime mut #ty))
         } else {
-            Ok(quote!(&#ty))
+            Ok(quote!(&#lifetime #ty))
         }
     }
 
     pub fn print_primitive_type(&self, ty: TypePrimitive) -> eyre::Result<TokenStream> {
         match ty {
+            TypePrimitive::Int(TypeInt::I128) => Ok(quote!(i128)),
+            TypePrimitive::Int(TypeInt::U128) => Ok(quote!(u128)),
             TypePrimitive::Int(TypeInt::I64) => Ok(quote!(i64)),
             TypePrimitive::Int(TypeInt::U64) => Ok(quote!(u64)),
             TypePrimitive::Int(TypeInt::I32) => Ok(quote!(i32)),
@@ -48,6 +56,9 @@ impl RustPrinter {
             TypePrimitive::Int(TypeInt::U16) => Ok(quote!(u16)),
             TypePrimitive::Int(TypeInt::I8) => Ok(quote!(i8)),
             TypePrimitive::Int(TypeInt::U8) => Ok(quote!(u8)),
+            TypePrimitive::Int(TypeInt::ISize) => Ok(quote!(isize)),
+            TypePrimitive::Int(TypeInt::USize) => Ok(quote!(usize)),
+            TypePrimitive::Int(TypeInt::BigInt) => Ok(quote!(num_bigint::BigInt)),
             TypePrimitive::Decimal(DecimalType::F64) => Ok(quote!(f64)),
             TypePrimitive::Decimal(DecimalType::F32) => Ok(quote!(f32)),
             TypePrimitive::Bool => Ok(quote!(bool)),
@@ -59,14 +70,32 @@ impl RustPrinter {
     }
     pub fn print_struct_type(&self, s: &TypeStruct) -> eyre::Result<TokenStream> {
         let name = self.print_ident(&s.name);
-        let fields: Vec<_> = s
-            .fields
-            .iter()
-            .map(|x| self.print_field(&x))
-            .try_collect()?;
-        Ok(quote!(struct #name {
-            #(#fields), *
-        }))
+        let fields = self.print_struct_fields(s)?;
+        Ok(quote!(struct #name #fields))
+    }
+    /// The fields after the name of a struct, `{ pub a: T }`, `(pub T)` or nothing for a unit struct
+    pub fn print_struct_fields(&self, s: &TypeStruct) -> eyre::Result<TokenStream> {
+        match s.kind {
+            StructKind::Named => {
+                let fields: Vec<_> = s
+                    .fields
+                    .iter()
+                    .map(|x| self.print_field(&x))
+                    .try_collect()?;
+                Ok(quote!({
+                    #(#fields), *
+                }))
+            }
+            StructKind::Tuple => {
+                let types: Vec<_> = s
+                    .fields
+                    .iter()
+                    .map(|x| self.print_type(&x.value))
+                    .try_collect()?;
+                Ok(quote!((#(pub #types), *)))
+            }
+            StructKind::Unit => Ok(quote!()),
+        }
     }
 
     pub fn print_field(&self, field: &StructuralField) -> eyre::Result<TokenStream> {
@@ -88,6 +117,49 @@ impl RustPrinter {
         ))
     }
 
+    pub fn print_enum_type(&self, e: &TypeEnum) -> eyre::Result<TokenStream> {
+        let name = self.print_ident(&e.name);
+        let variants: Vec<_> = e
+            .variants
+            .iter()
+            .map(|x| self.print_variant(x))
+            .try_collect()?;
+        Ok(quote!(enum #name {
+            #(#variants), *
+        }))
+    }
+    pub fn print_variant(&self, variant: &EnumTypeVariant) -> eyre::Result<TokenStream> {
+        let name = self.print_ident(&variant.name);
+        let ty = match &variant.value {
+            AstType::Any(_) => return Ok(quote!(#name)),
+            AstType::Tuple(tuple) => {
+                let types: Vec<_> = tuple
+                    .types
+                    .iter()
+                    .map(|x| self.print_type(x))
+                    .try_collect()?;
+                return Ok(quote!(#name(#(#types), *)));
+            }
+            // fields of a variant take no visibility
+            AstType::Structural(s) => {
+                let fields: Vec<_> = s
+                    .fields
+                    .iter()
+                    .map(|x| {
+                        let name = self.print_ident(&x.name);
+                        let ty = self.print_type(&x.value)?;
+                        Ok::<_, eyre::Error>(quote!(#name: #ty))
+                    })
+                    .try_collect()?;
+                return Ok(quote!(#name { #(#fields), * }));
+            }
+            // the struct is defined on its own, the variant refers to it by name
+            AstType::Struct(s) => self.print_ident(&s.name),
+            ty => self.print_type(ty)?,
+        };
+        Ok(quote!(#name(#ty)))
+    }
+
     pub fn print_invoke_type(&self, invoke: &ExprInvoke) -> eyre::Result<TokenStream> {
         let fun = self.print_invoke_target(&invoke.target)?;
         let args: Vec<_> = invoke
@@ -2,8 +2,8 @@ use crate::printer::RustPrinter;
 use eyre::bail;
 use itertools::Itertools;
 use lang_core::ast::{
-    AstExpr, AstValue, ValueBool, ValueChar, ValueDecimal, ValueInt, ValueList, ValueString,
-    ValueStruct, ValueUndefined, ValueUnit,
+    AstExpr, AstValue, StructKind, ValueBigInt, ValueBool, ValueChar, ValueDecimal, ValueInt,
+    ValueList, ValueString, ValueStruct, ValueTuple, ValueUndefined, ValueUnit, ValueVariant,
 };
 use proc_macro2::{Span, TokenStream};
 use quote::quote;
@@ -16,6 +16,7 @@ impl RustPrinter {
         let v = match v {
             AstValue::Function(f) => self.print_func_value(f)?,
             AstValue::Int(i) => self.print_int(i)?,
+            AstValue::BigInt(i) => self.print_big_int(i)?,
             AstValue::Bool(b) => self.print_bool(b)?,
             AstValue::Decimal(d) => self.print_decimal(d)?,
             AstValue::Char(c) => self.print_char(c)?,
@@ -24,6 +25,8 @@ impl RustPrinter {
             AstValue::Unit(u) => self.print_unit(u)?,
             AstValue::Type(t) => self.print_type(t)?,
             AstValue::Struct(s) => self.print_struct_value(s)?,
+            AstValue::Variant(v) => self.print_variant_value(v)?,
+            AstValue::Tuple(t) => self.print_tuple_value(t)?,
             AstValue::Any(n) => self.print_any(n)?,
             AstValue::BinOpKind(op) => self.print_bin_op_kind(op),
             AstValue::Expr(e) => self.print_expr(&e.get())?,
@@ -46,6 +49,19 @@ impl RustPrinter {
     }
     pub fn print_struct_value(&self, s: &ValueStruct) -> eyre::Result<TokenStream> {
         let name = self.print_ident(&s.ty.name);
+        match s.ty.kind {
+            StructKind::Named => {}
+            StructKind::Tuple => {
+                let values: Vec<_> = s
+                    .structural
+                    .fields
+                    .iter()
+                    .map(|x| self.print_value(&x.value))
+                    .try_collect()?;
+                return Ok(quote!(#name(#(#values), *)));
+            }
+            StructKind::Unit => return Ok(quote!(#name)),
+        }
         let kwargs: Vec<_> = s
             .structural
             .fields
@@ -54,11 +70,47 @@ impl RustPrinter {
             .try_collect()?;
         Ok(quote!(#name { #(#kwargs), * }))
     }
+    pub fn print_variant_value(&self, v: &ValueVariant) -> eyre::Result<TokenStream> {
+        let ty = self.print_ident(&v.ty.name);
+        let name = self.print_ident(&v.name);
+        match &*v.value {
+            AstValue::Unit(_) => Ok(quote!(#ty::#name)),
+            AstValue::Tuple(tuple) => {
+                let values: Vec<_> = tuple
+                    .values
+                    .iter()
+                    .map(|x| self.print_value(x))
+                    .try_collect()?;
+                Ok(quote!(#ty::#name(#(#values), *)))
+            }
+            AstValue::Structural(s) => {
+                let kwargs: Vec<_> = s
+                    .fields
+                    .iter()
+                    .map(|x| self.print_field_value(x))
+                    .try_collect()?;
+                Ok(quote!(#ty::#name { #(#kwargs), * }))
+            }
+            value => bail!("Not supported variant payload {:?}", value),
+        }
+    }
+    pub fn print_tuple_value(&self, t: &ValueTuple) -> eyre::Result<TokenStream> {
+        let values: Vec<_> = t.values.iter().map(|x| self.print_value(x)).try_collect()?;
+        if values.len() == 1 {
+            let first = &values[0];
+            return Ok(quote!((#first,)));
+        }
+        Ok(quote!((#(#values), *)))
+    }
 
     pub fn print_int(&self, n: &ValueInt) -> eyre::Result<TokenStream> {
-        let n = syn::LitInt::new(&n.value.to_string(), Span::call_site());
+        let n = syn::LitInt::new(&n.to_string(), Span::call_site());
         Ok(quote!(#n))
     }
+    pub fn print_big_int(&self, n: &ValueBigInt) -> eyre::Result<TokenStream> {
+        let digits = n.value.to_string();
+        Ok(quote!(#digits.parse::<num_bigint::BigInt>().unwrap()))
+    }
     pub fn print_bool(&self, n: &ValueBool) -> eyre::Result<TokenStream> {
         let n = n.value;
         Ok(quote!(#n))
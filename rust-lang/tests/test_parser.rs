@@ -6,7 +6,14 @@ use pretty_assertions::assert_eq;
 use lang_core::ast::*;
 use lang_core::ast::{AstItem, ItemDefFunction, ItemImpl, Visibility};
 use lang_core::ast::{AstType, FunctionParam, FunctionSignature, TypePrimitive};
-use lang_core::id::Locator;
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_core::id::{Ident, Locator};
+use lang_core::ops::BinOpKind;
+use lang_core::pat::{
+    Pattern, PatternIdent, PatternLit, PatternStruct, PatternStructField, PatternWildcard,
+};
+use lang_core::span::{with_threadlocal_source_map, SourceNode};
+use rust_lang::parser::RustParser;
 use rust_lang::printer::RustPrinter;
 use rust_lang::{shll_parse_expr, shll_parse_item};
 
@@ -34,7 +41,9 @@ fn test_parse_fn() -> Result<()> {
                     name: "a".into(),
                     ty: AstType::Primitive(TypePrimitive::i64())
                 }],
+                lifetimes: vec![],
                 generics_params: vec![],
+                where_predicates: vec![],
                 ret_ty: Some(AstType::Primitive(TypePrimitive::i64()))
             },
             body: block.into(),
@@ -143,6 +152,405 @@ fn test_parse_if() -> Result<()> {
     Ok(())
 }
 #[test]
+fn test_parse_if_const() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        if const { true } {
+
+        }
+    };
+    assert_eq!(
+        code,
+        AstExpr::If(ExprIf {
+            cond: AstExpr::Const(ExprConst {
+                block: AstExpr::block(ExprBlock::new_expr(AstExpr::value(AstValue::bool(true))))
+                    .into(),
+            })
+            .into(),
+            then: AstExpr::Block(ExprBlock::new()).into(),
+            elze: None,
+        })
+    );
+    Ok(())
+}
+#[test]
+fn test_parse_match() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        match a {
+            b if b > 1 => b,
+            _ => 0,
+        }
+    };
+    assert_eq!(
+        code,
+        AstExpr::Match(ExprMatch {
+            scrutinee: Some(AstExpr::ident("a".into()).into()),
+            cases: vec![
+                ExprMatchCase {
+                    pat: Some(
+                        Pattern::Ident(PatternIdent {
+                            ident: "b".into(),
+                            mutability: Some(false),
+                            by_ref: false,
+                            subpattern: None,
+                        })
+                        .into()
+                    ),
+                    cond: AstExpr::BinOp(ExprBinOp {
+                        kind: BinOpKind::Gt,
+                        lhs: AstExpr::ident("b".into()).into(),
+                        rhs: AstExpr::value(AstValue::int(1)).into(),
+                    })
+                    .into(),
+                    body: AstExpr::ident("b".into()).into(),
+                },
+                ExprMatchCase {
+                    pat: Some(Pattern::Wildcard(PatternWildcard {}).into()),
+                    cond: AstExpr::value(AstValue::bool(true)).into(),
+                    body: AstExpr::value(AstValue::int(0)).into(),
+                },
+            ],
+        })
+    );
+    Ok(())
+}
+#[test]
+fn test_parse_control_flow_round_trip() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            let mut total = 0;
+            'outer: for i in 0..n {
+                if i == 2 {
+                    continue;
+                }
+                if i > 8 {
+                    break 'outer;
+                }
+                total = total + i as u64;
+            }
+            let buf = [0u8; 4];
+            let len = unsafe { raw_len(buf) };
+            let task = async move { len };
+            match total {
+                x if x > 10 => return x,
+                _ => {}
+            }
+            return;
+        }
+    };
+    let debug = format!("{:?}", code);
+    assert!(!debug.contains("RawExpr"), "{}", debug);
+
+    let printed = code.to_string();
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
+fn test_parse_compound_assign() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        total += i
+    };
+    assert_eq!(
+        code,
+        AstExpr::Assign(ExprAssign {
+            target: AstExpr::ident("total".into()).into(),
+            value: AstExpr::BinOp(ExprBinOp {
+                kind: BinOpKind::Add,
+                lhs: AstExpr::ident("total".into()).into(),
+                rhs: AstExpr::ident("i".into()).into(),
+            })
+            .into(),
+        })
+    );
+
+    let code = shll_parse_expr! {
+        {
+            a -= 1;
+            a *= 2;
+            a /= 3;
+            a %= 4;
+            a ^= b;
+            a &= b;
+            a |= b;
+            a <<= 1;
+            a >>= 1;
+            a = b + 1;
+        }
+    };
+    let printed = code.to_string();
+    for op in ["-=", "*=", "/=", "%=", "^=", "&=", "|=", "<<=", ">>="] {
+        assert!(printed.contains(op), "{}", printed);
+    }
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
+fn test_parse_int_suffixes() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            let a: i128 = 170141183460469231731687303715884105727i128;
+            let b: u128 = 1u128 << 100;
+            a as u128 + b
+        }
+    };
+    let printed = code.to_string();
+    assert!(printed.contains("1u128"), "{}", printed);
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
+fn test_parse_patterns_round_trip() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            let (a, mut b) = pair;
+            let (single,) = one;
+            let Point { x, y: ref mut py, .. } = point;
+            let [first, .., last] = items;
+            let Some(inner) = maybe else {
+                return;
+            };
+            match value {
+                0 | 1 => a,
+                2..=9 => b,
+                n @ 10..20 => n,
+                m @ (30 | 40) => m,
+                Some(ref v) if v > 0 => v,
+                [head, rest @ ..] => head,
+                (_, ..) => 0,
+                'a'..='z' => 1,
+                _ => 2,
+            }
+        }
+    };
+    let debug = format!("{:?}", code);
+    assert!(!debug.contains("RawExpr"), "{}", debug);
+
+    let printed = code.to_string();
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
+fn test_parse_struct_pattern() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            let Point { x, y: 0, .. } = p;
+        }
+    };
+    let AstExpr::Block(block) = code else {
+        bail!("Expected a block, got {}", code)
+    };
+    let Some(BlockStmt::Let(let_)) = block.stmts.first() else {
+        bail!("Expected a let, got {:?}", block.stmts)
+    };
+    assert_eq!(
+        let_.pat,
+        Pattern::Struct(PatternStruct {
+            name: Locator::ident("Point".into()),
+            fields: vec![
+                PatternStructField {
+                    name: "x".into(),
+                    rename: None,
+                },
+                PatternStructField {
+                    name: "y".into(),
+                    rename: Some(
+                        Pattern::Lit(PatternLit {
+                            value: AstValue::int(0),
+                        })
+                        .into()
+                    ),
+                },
+            ],
+            has_rest: true,
+        })
+    );
+    Ok(())
+}
+#[test]
+fn test_parse_tuple_structs_and_variants() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            struct Meters(pub f64);
+            struct Marker;
+            enum Shape {
+                Empty,
+                Circle(f64),
+                Rect { w: f64, h: f64 },
+            }
+            let m = Meters(1.0);
+            let s = Shape::Rect { w: m.0, h: 2.0 };
+            match s {
+                Shape::Empty => 0.0,
+                Shape::Circle(r) => r,
+                Shape::Rect { w, .. } => w,
+            }
+        }
+    };
+    let AstExpr::Block(block) = &code else {
+        bail!("Expected a block, got {}", code)
+    };
+    let Some(BlockStmt::Item(item)) = block.stmts.first() else {
+        bail!("Expected an item, got {:?}", block.stmts)
+    };
+    let AstItem::DefStruct(def) = item.as_ref() else {
+        bail!("Expected a struct, got {:?}", item)
+    };
+    assert_eq!(def.value.kind, StructKind::Tuple);
+    assert_eq!(def.value.fields[0].name.as_str(), "0");
+
+    let printed = code.to_string();
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
+fn test_parse_generic_args_lifetimes_and_where() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_item! {
+        fn index<'a, K, V>(
+            names: &'a Vec<K>,
+            values: std::collections::HashMap<K, V>,
+            key: Cow<'a, str>,
+        ) -> &'a str
+        where
+            K: Clone + Into<String>,
+            Vec<V>: Default,
+        {
+            key
+        }
+    };
+    let AstItem::DefFunction(def) = &code else {
+        bail!("Expected a function, got {:?}", code)
+    };
+    assert_eq!(def.sig.lifetimes, vec![Ident::new("a")]);
+    assert_eq!(def.sig.generics_params.len(), 2);
+    assert_eq!(def.sig.where_predicates.len(), 2);
+    let AstType::Reference(names) = &def.sig.params[0].ty else {
+        bail!("Expected a reference, got {:?}", def.sig.params[0].ty)
+    };
+    assert_eq!(names.lifetime, Some(Ident::new("a")));
+    let AstType::Expr(key) = &def.sig.params[2].ty else {
+        bail!("Expected a path, got {:?}", def.sig.params[2].ty)
+    };
+    let AstExpr::Locator(Locator::ParameterPath(key)) = key.as_ref() else {
+        bail!("Expected a parameter path, got {:?}", key)
+    };
+    assert_eq!(key.last().lifetimes, vec![Ident::new("a")]);
+
+    let printed = code.to_string();
+    let reparsed = RustParser::new().deserialize_item(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+
+    let items = [
+        shll_parse_item! {
+            impl<'a, T: Clone> Wrapper<'a, T> for Holder<'a, T>
+            where
+                T: Default,
+            {
+                fn get(&self) -> T {
+                    self.value.clone()
+                }
+            }
+        },
+        shll_parse_item! {
+            enum Token<'a, T>
+            where
+                T: Copy,
+            {
+                Word(&'a str),
+                Value(T),
+            }
+        },
+        shll_parse_item! {
+            struct Holder<'a, T>
+            where
+                T: Clone,
+            {
+                value: &'a T,
+            }
+        },
+        shll_parse_item! {
+            struct Pair<T, const N: usize>(T, T)
+            where
+                T: Copy;
+        },
+    ];
+    let AstItem::Impl(impl_) = &items[0] else {
+        bail!("Expected an impl, got {:?}", items[0])
+    };
+    assert_eq!(impl_.lifetimes, vec![Ident::new("a")]);
+    assert_eq!(impl_.generics_params.len(), 1);
+    assert_eq!(impl_.where_predicates.len(), 1);
+    let AstItem::DefEnum(def) = &items[1] else {
+        bail!("Expected an enum, got {:?}", items[1])
+    };
+    assert_eq!(def.lifetimes, vec![Ident::new("a")]);
+    assert_eq!(def.generics_params.len(), 1);
+    assert_eq!(def.where_predicates.len(), 1);
+    let AstItem::DefStruct(def) = &items[2] else {
+        bail!("Expected a struct, got {:?}", items[2])
+    };
+    assert_eq!(def.lifetimes, vec![Ident::new("a")]);
+    assert_eq!(def.generics_params.len(), 1);
+    assert_eq!(def.where_predicates.len(), 1);
+    for item in items {
+        let printed = item.to_string();
+        let reparsed = RustParser::new().deserialize_item(&printed)?;
+        assert_eq!(reparsed, item, "{}", printed);
+    }
+    Ok(())
+}
+#[test]
+fn test_parse_attr_meta_lists() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = shll_parse_expr! {
+        {
+            #[derive(Debug, Clone)]
+            #[cfg_attr(feature = "serde", derive(Serialize))]
+            struct Point {
+                x: i64,
+                y: i64,
+            }
+            #[inline(always)]
+            #[cfg(not(test))]
+            fn origin() -> Point {
+                Point { x: 0, y: 0 }
+            }
+            origin()
+        }
+    };
+    let AstExpr::Block(block) = &code else {
+        bail!("Expected a block, got {}", code)
+    };
+    let Some(BlockStmt::Item(item)) = block.stmts.first() else {
+        bail!("Expected an item, got {:?}", block.stmts)
+    };
+    let AstItem::DefStruct(def) = item.as_ref() else {
+        bail!("Expected a struct, got {:?}", item)
+    };
+    assert!(def.attrs.has_list_item("derive", "Clone"));
+    assert!(matches!(
+        def.attrs.find_by_name("cfg_attr"),
+        Some(AstAttrMeta::List(l)) if l.items.len() == 2
+    ));
+
+    let printed = code.to_string();
+    let reparsed = RustParser::new().deserialize_expr(&printed)?;
+    assert_eq!(reparsed, code, "{}", printed);
+    Ok(())
+}
+#[test]
 fn test_parse_block_if() -> Result<()> {
     register_threadlocal_serializer(Arc::new(RustPrinter::new()));
 
@@ -194,8 +602,11 @@ fn test_parse_impl_for() -> Result<()> {
     assert_eq!(
         code,
         AstItem::Impl(ItemImpl {
+            lifetimes: vec![],
+            generics_params: vec![],
             trait_ty: Some(Locator::Ident("Foo".into())),
             self_ty: AstExpr::ident("Bar".into()),
+            where_predicates: vec![],
             items: vec![shll_parse_item! {
                 fn foo(a: i64) -> i64 {
                     a + 1
@@ -241,7 +652,9 @@ fn test_parse_fn_self() -> Result<()> {
                 name: Some("foo".into()),
                 receiver: Some(FunctionParamReceiver::Ref),
                 params: vec![],
+                lifetimes: vec![],
                 generics_params: vec![],
+                where_predicates: vec![],
                 ret_ty: None
             },
             body: AstExpr::Block(ExprBlock::new()).into(),
@@ -262,7 +675,9 @@ fn test_parse_fn_self() -> Result<()> {
                 name: Some("foo".into()),
                 receiver: Some(FunctionParamReceiver::RefStatic),
                 params: vec![],
+                lifetimes: vec![],
                 generics_params: vec![],
+                where_predicates: vec![],
                 ret_ty: None
             },
             body: AstExpr::Block(ExprBlock::new()).into(),
@@ -271,3 +686,78 @@ fn test_parse_fn_self() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn test_parse_records_spans() -> Result<()> {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let code = "fn foo(a: i64) -> i64 {\n    let b = a;\n    bar(b)\n}";
+    let item = RustParser::new().deserialize_item(code)?;
+    assert_eq!(item.location().unwrap().to_string(), "__file__:1:1");
+
+    let AstItem::DefFunction(func) = &item else {
+        bail!("Expected function, got {:?}", item)
+    };
+    let AstExpr::Block(body) = &*func.body else {
+        bail!("Expected block, got {:?}", func.body)
+    };
+    let BlockStmt::Let(stmt) = &body.stmts[0] else {
+        bail!("Expected let, got {:?}", body.stmts[0])
+    };
+    assert_eq!(stmt.pat.location().unwrap().to_string(), "__file__:2:9");
+    let ret = body.last_expr().context("Expected a value")?;
+    assert_eq!(ret.describe(), "`bar(b)` at __file__:3:5");
+
+    // equal nodes at different places have no span, rather than the first one
+    let expr = RustParser::new().deserialize_expr("(a + 1, a + 1, b)")?;
+    let AstExpr::Tuple(tuple) = &expr else {
+        bail!("Expected tuple, got {:?}", expr)
+    };
+    assert_eq!(tuple.values[0].span(), None);
+    assert_eq!(
+        tuple.values[2].location().unwrap().to_string(),
+        "__file__:1:16"
+    );
+    // each parse starts from a clear source map
+    assert_eq!(ret.location(), None);
+
+    // tokens from `parse_quote!` have no source file, their spans are the tokens
+    let expr = shll_parse_expr!(baz(b));
+    assert_eq!(expr.location(), None);
+    let span = expr.span().context("Expected a span")?;
+    assert!(with_threadlocal_source_map(|map| map.get_tokens(span)).is_some());
+    Ok(())
+}
+
+#[test]
+fn test_parse_error_diagnostics() -> Result<()> {
+    let code = "{\n    let a = 300u8;\n    a\n}";
+    let err = RustParser::new().deserialize_expr(code).unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::LiteralOutOfRange);
+    assert_eq!(diagnostic.message, "literal out of range for u8: 300");
+    let rendered = diagnostic.render();
+    assert!(
+        rendered.contains("shll::parse::literal_out_of_range"),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("[__file__:2:13]"), "{}", rendered);
+    assert!(rendered.contains("does not fit into `u8`"), "{}", rendered);
+    assert!(
+        rendered.contains("the range of `u8` is `0..=255`"),
+        "{}",
+        rendered
+    );
+
+    let err = RustParser::new()
+        .deserialize_item("union Foo { a: i64 }")
+        .unwrap_err();
+    let diagnostic = err
+        .downcast_ref::<Diagnostic>()
+        .context("Expected a diagnostic")?;
+    assert_eq!(diagnostic.code, ErrorCode::UnsupportedSyntax);
+    assert!(diagnostic.render().contains("unsupported item"));
+    Ok(())
+}
@@ -1,83 +1,319 @@
 use common::*;
 
+use lang_core::ast::{
+    register_threadlocal_serializer, AstExpr, AstFile, AstItem, AstModule, AstType, AstValue,
+    ItemDefEnum, ItemDefStruct, TypeEnum, TypeStruct,
+};
 use lang_core::context::SharedScopedContext;
-use lang_optimize::pass::{load_optimizers, FoldOptimizer};
+use lang_core::error::{Diagnostic, ErrorCode};
+use lang_optimize::pass::{InterpreterPass, OptLevel, PassManager};
 use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned, ToTokens};
 use rust_lang::parser::RustParser;
 use rust_lang::printer::RustPrinter;
-
-use lang_core::ast::{AstExpr, AstFile, AstModule};
 use std::sync::Arc;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
 
 trait Optimizee {
-    fn optimize(
-        self,
-        optimizer: Vec<FoldOptimizer>,
-        ctx: &SharedScopedContext,
-    ) -> Result<TokenStream>;
+    fn optimize(self, manager: &mut PassManager, ctx: &SharedScopedContext) -> Result<TokenStream>;
 }
 impl Optimizee for AstExpr {
-    fn optimize(
-        mut self,
-        optimizer: Vec<FoldOptimizer>,
-        ctx: &SharedScopedContext,
-    ) -> Result<TokenStream> {
-        for opt in optimizer {
-            self = opt.optimize_expr(self, ctx)?;
-        }
+    fn optimize(self, manager: &mut PassManager, ctx: &SharedScopedContext) -> Result<TokenStream> {
+        let expr = manager.optimize_expr(self, ctx)?;
 
-        let node = RustPrinter::new().print_expr(&self)?;
+        let node = RustPrinter::new().print_expr(&expr)?;
         Ok(node.into())
     }
 }
 impl Optimizee for AstModule {
-    fn optimize(
-        mut self,
-        optimizer: Vec<FoldOptimizer>,
-        ctx: &SharedScopedContext,
-    ) -> Result<TokenStream> {
-        for opt in optimizer {
-            self = opt.optimize_module(self, ctx, true)?;
-        }
+    fn optimize(self, manager: &mut PassManager, ctx: &SharedScopedContext) -> Result<TokenStream> {
+        let module = manager.optimize_module(self, ctx, true)?;
 
-        let node = RustPrinter::new().print_module(&self)?;
+        let node = RustPrinter::new().print_module(&module)?;
         Ok(node.into())
     }
 }
 impl Optimizee for AstFile {
-    fn optimize(
-        mut self,
-        optimizer: Vec<FoldOptimizer>,
-        ctx: &SharedScopedContext,
-    ) -> Result<TokenStream> {
-        for opt in optimizer {
-            self = opt.optimize_file(self, ctx)?;
-        }
-        let node = RustPrinter::new().print_file(&self)?;
+    fn optimize(self, manager: &mut PassManager, ctx: &SharedScopedContext) -> Result<TokenStream> {
+        let file = manager.optimize_file(self, ctx)?;
+        let node = RustPrinter::new().print_file(&file)?;
         Ok(node.into())
     }
 }
-fn specialize_inner(code: impl Optimizee) -> Result<TokenStream> {
+fn specialize_inner(code: impl Optimizee, type_check: bool) -> Result<TokenStream> {
     let ctx = SharedScopedContext::new();
     let formatter = RustPrinter::new();
-    let optimizer = load_optimizers(Arc::new(formatter));
-    let node = code.optimize(optimizer, &ctx)?;
+    // the checker doesn't know std, so it only runs when asked for
+    let mut manager =
+        PassManager::with_level(Arc::new(formatter), OptLevel::O2)?.with_type_check(type_check);
+    let node = code.optimize(&mut manager, &ctx)?;
 
     Ok(node.into())
 }
+
+/// What the macros emit when the input can't be specialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnFailure {
+    /// a `compile_error!` at the offending input
+    Error,
+    /// the input unchanged, with a warning
+    Fallback,
+}
+
+fn error_message(err: &Error, diagnostic: Option<&Diagnostic>) -> String {
+    let mut message = err
+        .chain()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(": ");
+    if let Some(help) = diagnostic.and_then(|x| x.help.as_ref()) {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+    message
+}
+/// Emits `input` with `err` reported at the tokens it was raised for, or at the macro call
+fn report_failure(
+    err: Error,
+    input: proc_macro2::TokenStream,
+    on_failure: OnFailure,
+) -> TokenStream {
+    let diagnostic = err.chain().find_map(|x| x.downcast_ref::<Diagnostic>());
+    let span = diagnostic
+        .and_then(Diagnostic::tokens)
+        .unwrap_or_else(Span::call_site);
+    let message = error_message(&err, diagnostic);
+    let report = match on_failure {
+        // braces make it valid in both item and type position
+        OnFailure::Error => quote_spanned!(span=> compile_error! { #message }),
+        OnFailure::Fallback => {
+            // stable proc macros can't emit warnings, but using a deprecated item does
+            let note = format!("specialization skipped: {}", message);
+            quote_spanned!(span=>
+                const _: () = {
+                    #[deprecated(note = #note)]
+                    const SPECIALIZATION_FAILED: () = ();
+                    SPECIALIZATION_FAILED
+                };
+            )
+        }
+    };
+    quote!(#report #input).into()
+}
+
+/// Specializes the items inside.
+///
+/// Unsupported input is a compile error, unless it starts with `#![fallback]`: then it's kept
+/// unchanged with a warning. `#![type_check]` reports type errors at the input, for code that
+/// only uses items declared inside.
 #[proc_macro]
 pub fn specialize(input: TokenStream) -> TokenStream {
-    let input: syn::File = syn::parse(input.into()).unwrap();
-    let input = RustParser::new()
+    let mut input: syn::File = match syn::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let has_attr = |input: &mut syn::File, name: &str| {
+        let len = input.attrs.len();
+        input.attrs.retain(|x| !x.path().is_ident(name));
+        input.attrs.len() < len
+    };
+    let on_failure = if has_attr(&mut input, "fallback") {
+        OnFailure::Fallback
+    } else {
+        OnFailure::Error
+    };
+    let type_check = has_attr(&mut input, "type_check");
+    let tokens = input.to_token_stream();
+    let result = RustParser::new()
         .parse_file_content("".into(), input)
-        .unwrap();
-    specialize_inner(input).unwrap().into()
+        .and_then(|file| specialize_inner(file, type_check));
+    match result {
+        Ok(output) => output,
+        Err(err) => report_failure(err, tokens, on_failure),
+    }
 }
 
+/// Specializes the module.
+///
+/// Unsupported input is a compile error, unless invoked as `#[specialize_module(fallback)]`:
+/// then the module is kept unchanged with a warning. `#[specialize_module(type_check)]`
+/// reports type errors at the input, for modules that only use items declared inside.
 #[proc_macro_attribute]
-pub fn specialize_module(_attr: TokenStream, input: TokenStream) -> TokenStream {
-    let input: syn::ItemMod = syn::parse(input.into()).unwrap();
-    let input = RustParser::new().parse_module(input).unwrap();
+pub fn specialize_module(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let parser = Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated;
+    let args = match parser.parse(attr) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let mut on_failure = OnFailure::Error;
+    let mut type_check = false;
+    for ident in args {
+        if ident == "fallback" {
+            on_failure = OnFailure::Fallback;
+        } else if ident == "type_check" {
+            type_check = true;
+        } else {
+            let message = format!(
+                "unknown argument `{}`, expected `fallback` or `type_check`",
+                ident
+            );
+            return syn::Error::new(ident.span(), message)
+                .to_compile_error()
+                .into();
+        }
+    }
+    let input: syn::ItemMod = match syn::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let tokens = input.to_token_stream();
+    let result = RustParser::new()
+        .parse_module(input)
+        .and_then(|module| specialize_inner(module, type_check));
+    match result {
+        Ok(output) => output,
+        Err(err) => report_failure(err, tokens, on_failure),
+    }
+}
+fn evaluate_type_expr(code: proc_macro2::TokenStream) -> Result<proc_macro2::TokenStream> {
+    let printer = RustPrinter::new();
+    let interpreter = InterpreterPass::new(Arc::new(printer));
+    let ctx = SharedScopedContext::new();
+    let ty = RustParser::new().parse_type_expr(code)?;
+    let ty = interpreter.interpret_type(&ty, &ctx)?;
+    if let AstType::Struct(_) | AstType::Structural(_) | AstType::Enum(_) = ty {
+        bail!(Diagnostic::new(
+            ErrorCode::UnsupportedSyntax,
+            format!("Type {} has no name", ty)
+        )
+        .with_help("declare it as an alias inside the macro: `t! { type Foo = t! { .. }; }`"))
+    }
+    printer.print_type(&ty)
+}
+fn is_rust_type(ty: &AstType) -> bool {
+    match ty {
+        AstType::Value(_) => false,
+        AstType::Struct(s) => s
+            .fields
+            .iter()
+            .all(|x| syn::parse_str::<syn::Ident>(x.name.as_str()).is_ok()),
+        _ => true,
+    }
+}
+fn evaluate_type_items(items: Vec<syn::Item>) -> Result<proc_macro2::TokenStream> {
+    let parser = RustParser::new();
+    let printer = RustPrinter::new();
+    let interpreter = InterpreterPass::new(Arc::new(printer));
+    let ctx = SharedScopedContext::new();
+    let mut output = vec![];
+    let sources: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let attrs = match item {
+                syn::Item::Type(alias) => alias.attrs.clone(),
+                _ => vec![],
+            };
+            (attrs, item.to_token_stream())
+        })
+        .collect();
+    // parsed at once, so that the spans of all items stay known while interpreting them
+    let parsed = parser.parse_items(items)?;
+    for ((attrs, tokens), item) in sources.into_iter().zip(parsed) {
+        let def = match item {
+            // type functions only exist in the macro, their instances are emitted
+            AstItem::DefType(def) if !def.generics_params.is_empty() => {
+                interpreter.interpret_def_type(&def, &ctx)?;
+                continue;
+            }
+            AstItem::DefType(def) => def,
+            // the types that aliases compute with
+            def @ (AstItem::DefStruct(_) | AstItem::DefEnum(_)) => {
+                interpreter.interpret_item(&def, &ctx)?;
+                output.push(tokens);
+                continue;
+            }
+            _ => {
+                output.push(tokens);
+                continue;
+            }
+        };
+        let ty = interpreter.interpret_type(&def.value, &ctx)?;
+        // the alias becomes a struct or enum of its own name
+        let ty = match ty {
+            AstType::Struct(s) => AstType::Struct(TypeStruct {
+                name: def.name.clone(),
+                fields: s.fields,
+                kind: s.kind,
+            }),
+            AstType::Structural(s) => AstType::Struct(TypeStruct::new(def.name.clone(), s.fields)),
+            AstType::Enum(e) => AstType::Enum(TypeEnum {
+                name: def.name.clone(),
+                variants: e.variants,
+            }),
+            ty => ty,
+        };
+        ctx.insert_value_with_ctx(def.name.clone(), AstValue::Type(ty.clone()));
+        // literal types and type dicts only exist in the macro, for the types computed from them
+        if !is_rust_type(&ty) {
+            continue;
+        }
+        let def = match ty {
+            // the attributes of the alias are emitted as they are
+            AstType::Struct(value) => printer.print_def_struct(&ItemDefStruct {
+                attrs: vec![],
+                visibility: def.visibility,
+                name: def.name,
+                lifetimes: vec![],
+                generics_params: vec![],
+                where_predicates: vec![],
+                value,
+            })?,
+            AstType::Enum(value) => printer.print_def_enum(&ItemDefEnum {
+                attrs: vec![],
+                visibility: def.visibility,
+                name: def.name,
+                lifetimes: vec![],
+                generics_params: vec![],
+                where_predicates: vec![],
+                value,
+            })?,
+            ty => {
+                let vis = printer.print_vis(def.visibility);
+                let name = printer.print_ident(&def.name);
+                let ty = printer.print_type(&ty)?;
+                output.push(quote!(#(#attrs)* #vis type #name = #ty;));
+                continue;
+            }
+        };
+        output.push(quote!(#(#attrs)* #def));
+    }
+    Ok(quote!(#(#output)*))
+}
 
-    specialize_inner(input).unwrap().into()
+/// Type arithmetic, evaluated with `InterpreterPass`.
+///
+/// In type position, `t! { i64 }` expands to the resulting type. A proc macro only sees its
+/// input, so types that refer to others are declared together:
+/// `t! { struct Bar { .. } type Foo = t! { Bar + struct { .. } }; }`
+/// expands aliases of struct types to struct definitions, aliases of unions like `Foo | Bar`
+/// to enums with a variant per struct, and keeps the other aliases.
+///
+/// Literal types like `t! { 1 }` and type dicts like `t! { struct { "/api": Api } }` are kept
+/// for `t! { typeof(..) }` and `t! { Foo["/api"] }` to compute with, but not emitted.
+///
+/// Types can be computed by blocks, `type Foo = Type<{ .. }>;`, and type functions
+/// `type Foo<T, const N: usize> = Type<{ .. }>;` are instantiated where they are used.
+#[proc_macro]
+pub fn t(input: TokenStream) -> TokenStream {
+    register_threadlocal_serializer(Arc::new(RustPrinter::new()));
+    let input = proc_macro2::TokenStream::from(input);
+    let result = match syn::parse2::<syn::File>(input.clone()) {
+        Ok(file) if !file.items.is_empty() => evaluate_type_items(file.items),
+        _ => evaluate_type_expr(input),
+    };
+    match result {
+        Ok(output) => output.into(),
+        Err(err) => report_failure(err, quote!(), OnFailure::Error),
+    }
 }
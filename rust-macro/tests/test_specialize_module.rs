@@ -13,3 +13,102 @@ fn test_specialize_module() {
     }
     s::main();
 }
+
+#[test]
+// the fallback warns through a deprecated item
+#[allow(deprecated)]
+fn test_specialize_module_fallback() {
+    #[rust_macro::specialize_module(fallback)]
+    mod s {
+        pub union Bits {
+            pub int: u32,
+            pub float: f32,
+        }
+        pub fn one() -> u32 {
+            let bits = Bits { float: 1.0 };
+            unsafe { bits.int }
+        }
+    }
+    assert_eq!(s::one(), 1.0f32.to_bits());
+}
+
+#[test]
+fn test_specialize_module_type_checked() {
+    #[rust_macro::specialize_module(type_check)]
+    mod s {
+        pub struct Point {
+            pub x: i64,
+            pub y: i64,
+        }
+        fn norm(p: &Point) -> i64 {
+            p.x * p.x + p.y * p.y
+        }
+        pub fn main() -> i64 {
+            let p = Point { x: 3, y: 4 };
+            norm(&p)
+        }
+    }
+    assert_eq!(s::main(), 25);
+}
+
+#[test]
+fn test_specialize_module_no_specialize() {
+    // rustc would reject the `shll` attributes, they must not make it to the output
+    #[rust_macro::specialize_module]
+    mod s {
+        #[shll::no_specialize]
+        fn add(a: i64, b: i64) -> i64 {
+            a + b
+        }
+        #[inline(never)]
+        #[allow(dead_code)]
+        fn double(a: i64) -> i64 {
+            a * 2
+        }
+        pub fn main() -> i64 {
+            add(1, 2) + double(3)
+        }
+    }
+    assert_eq!(s::main(), 9);
+}
+
+#[test]
+fn test_specialize_module_std_items() {
+    // not type checked by default: the checker doesn't know std, coercions or `Self`
+    #[rust_macro::specialize_module]
+    mod s {
+        use std::cmp::max;
+
+        pub struct Point {
+            pub x: i64,
+            pub y: i64,
+        }
+        impl Point {
+            pub fn origin() -> Self {
+                Self { x: 0, y: 0 }
+            }
+        }
+        fn len(s: &str) -> usize {
+            s.len()
+        }
+        pub fn greet(name: String) -> usize {
+            let s = name + "!";
+            len(&s)
+        }
+        pub fn first(values: &[i32]) -> Option<i32> {
+            if values.is_empty() {
+                None
+            } else {
+                Some(values[0])
+            }
+        }
+        pub fn limit(a: i32) -> i32 {
+            max(a, i32::MAX - 1)
+        }
+    }
+    assert_eq!(s::Point::origin().x, 0);
+    assert_eq!(s::greet("hi".to_string()), 3);
+    assert_eq!(s::first(&[1, 2]), Some(1));
+    assert_eq!(s::first(&[]), None);
+    assert_eq!(s::limit(1), i32::MAX - 1);
+}
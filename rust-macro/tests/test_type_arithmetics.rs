@@ -0,0 +1,105 @@
+use rust_macro::t;
+
+#[test]
+fn test_type_expr() {
+    type Int = t! { i64 };
+    let i: Int = -1;
+    assert_eq!(i, -1i64);
+}
+
+#[test]
+fn test_struct_addition() {
+    mod types {
+        use rust_macro::t;
+        t! {
+            pub type Int = i64;
+            pub type Foo = t! { struct { a: Int, b: Int } };
+            pub struct Bar { pub c: Int, pub d: Int }
+            pub type FooPlusBar = t! { Foo + Bar };
+        }
+    }
+    let foo = types::FooPlusBar {
+        a: 1,
+        b: 2,
+        c: 3,
+        d: 4,
+    };
+    assert_eq!(foo.a + foo.b + foo.c + foo.d, 10);
+    let bar = types::Bar { c: 5, d: 6 };
+    assert_eq!(bar.c + bar.d, 11);
+}
+
+#[test]
+fn test_struct_algebra() {
+    mod types {
+        use rust_macro::t;
+        t! {
+            pub struct Foo { pub a: i64, pub b: i64 }
+            pub struct Bar { pub b: i64, pub c: bool }
+            pub type FooAndBar = t! { Foo & Bar };
+            pub type FooMinusBar = t! { Foo - Bar };
+            pub type FooOrBar = t! { Foo | Bar };
+        }
+    }
+    let both = types::FooAndBar {
+        a: 1,
+        b: 2,
+        c: true,
+    };
+    assert_eq!(both.a + both.b, 3);
+    let foo = types::FooMinusBar { a: 4 };
+    assert_eq!(foo.a, 4);
+    let either = types::FooOrBar::Bar(types::Bar { b: 5, c: false });
+    match either {
+        types::FooOrBar::Foo(foo) => panic!("Expected Bar, got a: {}", foo.a),
+        types::FooOrBar::Bar(bar) => assert_eq!(bar.b, 5),
+    }
+}
+
+#[test]
+fn test_type_function() {
+    mod types {
+        use rust_macro::t;
+        t! {
+            type NestedVec<T, const n: usize> = Type<{
+                if n == 0 {
+                    T
+                } else {
+                    Vec::<NestedVec<T, { n - 1 }>>
+                }
+            }>;
+            pub type Matrix = NestedVec<i64, 2>;
+            pub type Point = Type<{
+                let mut T = t! { struct {} };
+                T.x = i64;
+                T.y = i64;
+                T
+            }>;
+        }
+    }
+    let matrix: types::Matrix = vec![vec![1, 2], vec![3, 4]];
+    assert_eq!(matrix[1][0], 3i64);
+    let point = types::Point { x: 1, y: 2 };
+    assert_eq!(point.x + point.y, 3);
+}
+
+#[test]
+fn test_type_dict() {
+    mod types {
+        use rust_macro::t;
+        t! {
+            pub struct EndpointApi { pub version: u32 }
+            pub struct EndpointUser { pub name: String }
+            type Routes = t! { struct {
+                "/api": EndpointApi,
+                "/user": EndpointUser,
+            } };
+            pub type Api = t! { Routes["/api"] };
+            pub type Count = t! { typeof(1) };
+        }
+    }
+    let api: types::Api = types::EndpointApi { version: 2 };
+    assert_eq!(api.version, 2);
+    let count: types::Count = -1;
+    assert_eq!(count, -1i64);
+}
@@ -0,0 +1,11 @@
+#[rust_macro::specialize_module]
+#[allow(overflowing_literals)]
+mod m {
+    pub fn byte() -> u8 {
+        300u8
+    }
+}
+
+fn main() {
+    let _ = m::byte();
+}